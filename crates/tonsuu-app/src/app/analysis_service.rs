@@ -1,25 +1,40 @@
 //! Analysis Service - Core Use Case for Truck Image Analysis
 //!
 //! This service orchestrates the complete analysis workflow:
-//! 1. Validate input image
-//! 2. Check cache for existing results
-//! 3. Detect license plate (YOLO or API)
-//! 4. Match against registered vehicles
+//! 1. Validate input images (one, or several views of the same load)
+//! 2. Detect license plate (manual > YOLO crop + OCR > API OCR fallback)
+//! 3. Match against registered vehicles (scoped by company, with match quality)
+//! 4. Check cache (image hash + backend/model/pipeline/hints/prompt version)
 //! 5. Call vision module for AI analysis
-//! 6. Calculate weight using domain services
-//! 7. Store results in history
+//! 6. Rescale to the matched vehicle's own bed, check physical plausibility
+//!    and calculate weight using domain services, then apply the per-group
+//!    calibration model if enabled
+//! 7. Store results in history (one entry per load, keyed by the combined image hash)
 //! 8. Return analysis result
 
-use crate::config::Config;
+use crate::config::{load_material_catalog, load_truck_specs, Config, MaterialCatalog};
+use crate::constants::{
+    canonical_material, get_material_spec, get_truck_spec, mix_bulk_density, vehicle_truck_spec,
+    weight_mixed_load,
+};
+use crate::repository::{open_history_store, open_vehicle_store};
 use crate::scanner::validate_image;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
-use tonsuu_store::{Store, VehicleStore};
-use tonsuu_types::{Error, EstimationResult, LoadGrade, RegisteredVehicle, TruckClass};
+use tonsuu_domain::service::{check_plausibility, PlausibilityLimits};
+use tonsuu_domain::{MaterialSpec, TruckSpec};
+use tonsuu_store::{CalibrationModel, Recovery, Store, VehicleStore};
+use tonsuu_types::{
+    truck_classes, Error, EstimationResult, ImageView, KarteInput, LicensePlate, LoadGrade,
+    MaterialBreakdown, PlateSource, RegisteredVehicle, TruckClass, SERIAL_ONLY_SCORE,
+};
+use tonsuu_vision::cache::short_hash;
 use tonsuu_vision::{
-    analyze_image_box_overlay, analyze_image_staged, AnalyzerConfig, Cache, ProgressCallback,
-    StagedAnalysisOptions,
+    analyze_images_box_overlay, analyze_images_staged, calculate_volume_and_tonnage, detect_plate,
+    encode_thumbnail, reconcile_views, Aggregator, AnalyzerConfig, Cache, CacheKeyInputs,
+    FusionMode, PipelineKind, PlateDetectionConfig, PlateDetectionOutcome, PlateReading,
+    ProgressCallback, StagedAnalysisOptions, ViewImage, PROMPT_SPEC_VERSION,
 };
-use std::path::Path;
 
 /// Errors specific to the analysis service
 #[derive(Debug, Error)]
@@ -83,6 +98,15 @@ pub struct AnalysisOptions {
 
     /// Karte JSON (known values; null means estimate)
     pub karte_json: Option<String>,
+
+    /// How several photos of one load are combined
+    pub fusion_mode: FusionMode,
+
+    /// Max graded reference thumbnails attached to staged prompts (0 = off)
+    pub reference_images: usize,
+
+    /// Correct the tonnage with the saved calibration model
+    pub apply_calibration: bool,
 }
 
 impl AnalysisOptions {
@@ -138,6 +162,21 @@ impl AnalysisOptions {
         self.karte_json = Some(karte_json);
         self
     }
+
+    pub fn with_fusion_mode(mut self, fusion_mode: FusionMode) -> Self {
+        self.fusion_mode = fusion_mode;
+        self
+    }
+
+    pub fn with_reference_images(mut self, max: usize) -> Self {
+        self.reference_images = max;
+        self
+    }
+
+    pub fn with_calibration(mut self, enabled: bool) -> Self {
+        self.apply_calibration = enabled;
+        self
+    }
 }
 
 /// Result of the analysis containing estimation and matched vehicle info
@@ -149,6 +188,12 @@ pub struct AnalysisResult {
     /// Matched vehicle (if any)
     pub matched_vehicle: Option<RegisteredVehicle>,
 
+    /// How reliably the plate matched `matched_vehicle`
+    pub match_quality: MatchQuality,
+
+    /// Vehicles sharing the plate's last 4 digits when the match is ambiguous
+    pub ambiguous_candidates: Vec<RegisteredVehicle>,
+
     /// Load grade (if max_capacity known)
     #[allow(dead_code)]
     pub load_grade: Option<LoadGrade>,
@@ -157,13 +202,25 @@ pub struct AnalysisResult {
     #[allow(dead_code)]
     pub load_ratio: Option<f64>,
 
+    /// The P90 tonnage exceeds the matched vehicle's max capacity
+    pub possibly_overloaded: bool,
+
     /// Whether result came from cache
     pub from_cache: bool,
+
+    /// Time spent in local YOLO plate detection (ms)
+    pub plate_yolo_ms: Option<u64>,
+
+    /// Time spent in API plate OCR (ms)
+    pub plate_api_ms: Option<u64>,
+
+    /// Damaged store files that were read by salvaging their entries, for
+    /// the caller to report
+    pub recoveries: Vec<Recovery>,
 }
 
 impl AnalysisResult {
     /// Get the max capacity from matched vehicle
-    #[allow(dead_code)]
     pub fn max_capacity(&self) -> Option<f64> {
         self.matched_vehicle.as_ref().map(|v| v.max_capacity)
     }
@@ -187,135 +244,305 @@ pub fn analyze_truck_image(
     options: &AnalysisOptions,
     progress: Option<ProgressCallback>,
 ) -> std::result::Result<AnalysisResult, AnalysisServiceError> {
-    // Step 1: Validate image
-    validate_image(image_path)?;
+    analyze_truck_images(
+        &[ViewImage::new(image_path, ImageView::Unspecified)],
+        config,
+        options,
+        progress,
+    )
+}
+
+/// Analyze several photos of the same load (e.g. rear, side and top views)
+///
+/// Depending on `options.fusion_mode` the photos are sent to the AI together
+/// or analyzed one by one and reconciled (height from the side view, fill
+/// from the top view). The result is cached and stored in history as one
+/// entry keyed by the combined hash of all images.
+pub fn analyze_truck_images(
+    images: &[ViewImage],
+    config: &Config,
+    options: &AnalysisOptions,
+    progress: Option<ProgressCallback>,
+) -> std::result::Result<AnalysisResult, AnalysisServiceError> {
+    // Step 1: Validate images
+    if images.is_empty() {
+        return Err(AnalysisServiceError::InvalidImage(
+            "No image given".to_string(),
+        ));
+    }
+    for image in images {
+        validate_image(&image.path)?;
+    }
+    let image_paths: Vec<PathBuf> = images.iter().map(|i| i.path.clone()).collect();
+    // The plate is read from the rear photo when there is one
+    let plate_image = images
+        .iter()
+        .find(|i| i.view == ImageView::Rear)
+        .unwrap_or(&images[0]);
 
     // Step 2: Initialize stores and cache
-    let store = Store::open(config.store_dir().map_err(|e| {
-        AnalysisServiceError::StoreError(format!("Failed to open store: {}", e))
-    })?)?;
+    let mut store = open_history_store(config)
+        .map_err(|e| AnalysisServiceError::StoreError(format!("Failed to open store: {}", e)))?;
 
-    let vehicle_store = VehicleStore::open(config.store_dir().map_err(|e| {
+    let vehicle_store = open_vehicle_store(config).map_err(|e| {
         AnalysisServiceError::StoreError(format!("Failed to open vehicle store: {}", e))
-    })?)?;
+    })?;
+
+    let calibration = if options.apply_calibration {
+        let store_dir = config.store_dir().map_err(|e| {
+            AnalysisServiceError::StoreError(format!("Failed to open calibration model: {}", e))
+        })?;
+        Some(CalibrationModel::load(&store_dir)?)
+    } else {
+        None
+    };
 
     let cache = if options.use_cache {
         config
             .cache_dir()
             .ok()
             .and_then(|dir| Cache::new(dir).ok())
+            .map(|cache| cache.with_policy(config.cache_policy()))
     } else {
         None
     };
 
-    // Step 3: Check cache (only if no manual overrides)
-    if options.manual_plate.is_none() && options.truck_class_override.is_none() {
-        if let Some(ref cache) = cache {
-            if let Ok(Some(cached)) = cache.get(image_path) {
-                let matched = cached
-                    .license_plate
-                    .as_ref()
-                    .and_then(|plate| find_vehicle_by_plate(&vehicle_store, plate));
-
-                let (load_grade, load_ratio) = calculate_load_info(&cached, matched.as_ref());
-
-                return Ok(AnalysisResult {
-                    estimation: cached,
-                    matched_vehicle: matched,
-                    load_grade,
-                    load_ratio,
-                    from_cache: true,
-                });
-            }
+    // Photos are preprocessed once here, for every request of this analysis
+    let analyzer_config = analyzer_config_for(config).with_prepared_images(&image_paths);
+
+    // Step 3: Resolve license plate and find matched vehicle
+    if let Some(ref cb) = progress {
+        if options.manual_plate.is_none()
+            && (config.plate_local_enabled || config.plate_local_fallback_api)
+        {
+            cb("ナンバープレートを検出中...");
         }
     }
+    let plate_detection = resolve_plate(
+        &plate_image.path,
+        config,
+        &analyzer_config,
+        &vehicle_store,
+        cache.as_ref(),
+        options,
+    );
+    let plate_reading = plate_detection.reading.as_ref();
 
-    // Step 4: Find matched vehicle
-    let matched_vehicle = find_matched_vehicle(
+    let vehicle_match = match_vehicle(
         &vehicle_store,
-        options.manual_plate.as_deref(),
+        plate_reading.map(|r| r.plate.as_str()),
         options.company_filter.as_deref(),
     );
+    let matched_vehicle = vehicle_match.vehicle.clone();
 
-    // Step 5: Determine truck class
+    // Step 4: Determine truck class (the matched vehicle's class feeds the pipeline)
     let truck_class = options
         .truck_class_override
+        .clone()
         .or_else(|| matched_vehicle.as_ref().map(|v| v.truck_class()));
 
-    // Step 6: Run analysis
-    let analyzer_config = AnalyzerConfig::default()
-        .with_backend(&config.backend)
-        .with_model(config.model.clone())
-        .with_usage_mode(&config.usage_mode);
-
-    let estimation = if options.karte_json.is_some() {
-        // Karte path: use legacy staged analysis (karte is multi-param based)
-        let staged_options = StagedAnalysisOptions {
-            truck_class,
-            ensemble_count: options.ensemble_count.max(1),
-            truck_type_hint: options.truck_type_hint.clone(),
-            material_type: options.material_type.clone(),
-            karte_json: options.karte_json.clone(),
-        };
-
-        analyze_image_staged(
-            image_path,
-            &analyzer_config,
-            &staged_options,
-            &store,
-            progress,
-        )?
-    } else {
-        // Box-overlay pipeline (default, higher accuracy)
-        // Priority: Step 5 resolved truck_class > CLI hint > default "4t"
-        // TruckClass::label() returns "2t"/"4t"/"10t" which match prompt-spec.json truckSpecs keys
-        // Caching is handled by Steps 3 (check) and 8 (store) above, applying to both paths
-        let tc_label = truck_class.map(|tc| tc.label().to_string());
-        let truck_class_str = tc_label.as_deref()
-            .or(options.truck_type_hint.as_deref())
-            .unwrap_or("4t");
-        let material_type_str = options.material_type.as_deref().unwrap_or("As殻");
-        let ensemble_count = options.ensemble_count.max(1) as usize;
-
-        analyze_image_box_overlay(
-            image_path,
-            &analyzer_config,
+    // Box-overlay pipeline inputs
+    // Priority: Step 4 resolved truck_class > CLI hint > default "4t"
+    // TruckClass::label() returns the class id from trucks.toml ("2t", "4t", "10t", ...)
+    let tc_label = truck_class.as_ref().map(|tc| tc.label().to_string());
+    let truck_class_str = tc_label
+        .as_deref()
+        .or(options.truck_type_hint.as_deref())
+        .unwrap_or("4t");
+    // The material hint is mapped to its catalog id; the catalog default applies without one
+    let materials = load_material_catalog()?;
+    let material_hint = options
+        .material_type
+        .as_deref()
+        .map(|m| materials.resolve(m).unwrap_or(m));
+    let material_type_str = material_hint.unwrap_or(materials.default_id.as_str());
+    let material_choices: Vec<String> = materials.ids().iter().map(|id| id.to_string()).collect();
+    let catalog = catalog_fingerprint(materials);
+    let ensemble_count = options.ensemble_count.max(1);
+    // A material mix stated in the karte replaces the AI's breakdown
+    let karte_mix: Option<Vec<MaterialBreakdown>> = options
+        .karte_json
+        .as_deref()
+        .and_then(|json| serde_json::from_str::<KarteInput>(json).ok())
+        .and_then(|karte| karte.material_breakdown);
+
+    // Step 5: Check cache (keyed by image + every input that affects the result)
+    let cache_inputs = build_cache_inputs(
+        &analyzer_config,
+        options,
+        &AnalysisRun {
+            truck_class: truck_class.as_ref(),
+            material_hint,
             truck_class_str,
             material_type_str,
+            catalog: &catalog,
             ensemble_count,
-            progress,
-        )?
+            aggregator: config.ensemble_aggregator(),
+            images,
+            matched_vehicle: matched_vehicle.as_ref(),
+        },
+    );
+
+    if let Some(ref cache) = cache {
+        if let Ok(Some(mut cached)) = cache.get_images(&image_paths, &cache_inputs) {
+            let vehicle_match = if plate_reading.is_none() {
+                match_vehicle(
+                    &vehicle_store,
+                    cached.license_plate.as_deref(),
+                    options.company_filter.as_deref(),
+                )
+            } else {
+                vehicle_match
+            };
+            apply_plate_reading(&mut cached, plate_reading);
+            apply_calibration(&mut cached, calibration.as_ref());
+            apply_prediction_interval(&mut cached, &store);
+
+            let (load_grade, load_ratio) =
+                calculate_load_info(&cached, vehicle_match.vehicle.as_ref());
+            let possibly_overloaded =
+                is_possibly_overloaded(&cached, vehicle_match.vehicle.as_ref());
+
+            return Ok(AnalysisResult {
+                estimation: cached,
+                matched_vehicle: vehicle_match.vehicle,
+                match_quality: vehicle_match.quality,
+                ambiguous_candidates: vehicle_match.candidates,
+                load_grade,
+                load_ratio,
+                possibly_overloaded,
+                from_cache: true,
+                plate_yolo_ms: plate_detection.yolo_ms,
+                plate_api_ms: plate_detection.api_ms,
+                recoveries: store_recoveries([store.recovery(), vehicle_store.recovery()]),
+            });
+        }
+    }
+
+    // Step 6: Run analysis
+    let run_analysis = |images: &[ViewImage], progress: Option<ProgressCallback>| {
+        if options.karte_json.is_some() {
+            // Karte path: use legacy staged analysis (karte is multi-param based)
+            let staged_options = StagedAnalysisOptions {
+                truck_class: truck_class.clone(),
+                ensemble_count,
+                truck_type_hint: options.truck_type_hint.clone(),
+                material_type: material_hint.map(|m| m.to_string()),
+                material_choices: material_choices.clone(),
+                karte_json: options.karte_json.clone(),
+                aggregator: config.ensemble_aggregator(),
+                max_reference_images: options.reference_images,
+            };
+
+            analyze_images_staged(images, &analyzer_config, &staged_options, &store, progress)
+        } else {
+            // Box-overlay pipeline (default, higher accuracy)
+            // Caching is handled by Steps 5 (check) and 8 (store), applying to both paths
+            analyze_images_box_overlay(
+                images,
+                &analyzer_config,
+                truck_class_str,
+                material_type_str,
+                ensemble_count as usize,
+                config.ensemble_aggregator(),
+                progress,
+            )
+        }
     };
 
+    let mut estimation = match options.fusion_mode {
+        FusionMode::PerView if images.len() > 1 => {
+            let mut views = Vec::with_capacity(images.len());
+            for (i, image) in images.iter().enumerate() {
+                if let Some(ref cb) = progress {
+                    cb(&format!(
+                        "画像 {}/{} ({}) を解析中...",
+                        i + 1,
+                        images.len(),
+                        image.view.label()
+                    ));
+                }
+                views.push((image.view, run_analysis(std::slice::from_ref(image), None)?));
+            }
+            reconcile_views(&views)
+        }
+        _ => run_analysis(images, progress)?,
+    };
+
+    estimation.preprocessing = analyzer_config.preprocessing_for(&image_paths);
+    apply_plate_reading(&mut estimation, plate_reading);
+    apply_vehicle_bed(&mut estimation, truck_class_str, matched_vehicle.as_ref());
+    apply_material_mix(&mut estimation, karte_mix.as_deref());
+    apply_plausibility_checks_for_vehicle(
+        &mut estimation,
+        truck_class_str,
+        matched_vehicle.as_ref(),
+    );
+    apply_calibration(&mut estimation, calibration.as_ref());
+    apply_prediction_interval(&mut estimation, &store);
+
     // Step 7: Calculate load info
     let (load_grade, load_ratio) = calculate_load_info(&estimation, matched_vehicle.as_ref());
+    let possibly_overloaded = is_possibly_overloaded(&estimation, matched_vehicle.as_ref());
 
     // Step 8: Cache result
     if let Some(ref cache) = cache {
-        let _ = cache.set(image_path, &estimation);
+        let _ = cache.set_images(&image_paths, &cache_inputs, &estimation);
     }
 
     // Step 9: Save to history
-    let mut store_mut = Store::open(config.store_dir().map_err(|e| {
-        AnalysisServiceError::StoreError(format!("Failed to open store: {}", e))
-    })?)?;
-
-    let _ = store_mut.add_analysis_with_capacity(
-        image_path,
+    let source_images: Vec<(PathBuf, ImageView)> =
+        images.iter().map(|i| (i.path.clone(), i.view)).collect();
+    // The primary view's thumbnail lets graded entries serve as reference images
+    let _ = store.add_analysis_for_images(
+        &source_images,
         estimation.clone(),
         matched_vehicle.as_ref().map(|v| v.max_capacity),
-        None,
+        encode_thumbnail(&images[0].path),
     );
 
     Ok(AnalysisResult {
         estimation,
         matched_vehicle,
+        match_quality: vehicle_match.quality,
+        ambiguous_candidates: vehicle_match.candidates,
         load_grade,
         load_ratio,
+        possibly_overloaded,
         from_cache: false,
+        plate_yolo_ms: plate_detection.yolo_ms,
+        plate_api_ms: plate_detection.api_ms,
+        recoveries: store_recoveries([store.recovery(), vehicle_store.recovery()]),
     })
 }
 
+/// Recoveries of the stores opened for one analysis, one per backup copy
+fn store_recoveries<'a>(
+    recoveries: impl IntoIterator<Item = Option<&'a Recovery>>,
+) -> Vec<Recovery> {
+    let mut unique: Vec<Recovery> = Vec::new();
+    for recovery in recoveries.into_iter().flatten() {
+        if !unique.iter().any(|r| r.backup == recovery.backup) {
+            unique.push(recovery.clone());
+        }
+    }
+    unique
+}
+
+/// AI analyzer settings from the app config
+fn analyzer_config_for(config: &Config) -> AnalyzerConfig {
+    let analyzer_config = AnalyzerConfig::default()
+        .with_backend(&config.backend)
+        .with_model(config.model.clone())
+        .with_usage_mode(&config.usage_mode)
+        .with_max_concurrency(config.max_concurrent_requests);
+    // Without a cache directory the originals are sent
+    match config.image_preprocessor() {
+        Ok(Some(preprocessor)) => analyzer_config.with_preprocessing(preprocessor),
+        _ => analyzer_config,
+    }
+}
+
 /// Simplified version without progress callback
 #[allow(dead_code)]
 pub fn analyze_truck_image_simple(
@@ -335,63 +562,348 @@ pub fn analyze_quick(
     analyze_truck_image(image_path, config, &AnalysisOptions::new(), None)
 }
 
-/// Find vehicle by license plate with fuzzy matching
-fn find_vehicle_by_plate(vehicle_store: &VehicleStore, plate: &str) -> Option<RegisteredVehicle> {
-    // Try exact match first
-    if let Some(vehicle) = vehicle_store.get_by_license_plate(plate) {
-        return Some(vehicle.clone());
+/// How the recognized plate was matched against registered vehicles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchQuality {
+    /// Plate string identical to a registered plate
+    Exact,
+    /// Same plate once parsed (spacing, full-width digits, separators)
+    Normalized,
+    /// Serial and some other parts matched; the rest is missing on one side
+    Partial,
+    /// Only the serial number (last 4 digits) could be compared
+    Last4,
+    /// Several vehicles match equally well; none was chosen
+    Ambiguous,
+    /// No plate, or no registered vehicle matched
+    #[default]
+    None,
+}
+
+impl MatchQuality {
+    /// Japanese label for display
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchQuality::Exact => "完全一致",
+            MatchQuality::Normalized => "表記ゆれ一致",
+            MatchQuality::Partial => "部分一致",
+            MatchQuality::Last4 => "下4桁のみ一致",
+            MatchQuality::Ambiguous => "候補複数",
+            MatchQuality::None => "該当なし",
+        }
     }
 
-    // Try fuzzy match (remove spaces, normalize)
-    let normalized_plate = plate
-        .replace(' ', "")
-        .replace('\u{3000}', "")
-        .replace('-', "");
-    let plate_nums: String = normalized_plate
-        .chars()
-        .filter(|c| c.is_ascii_digit())
+    /// The match is weak enough that an operator should confirm the vehicle
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(self, MatchQuality::Last4 | MatchQuality::Ambiguous)
+    }
+}
+
+/// Outcome of matching a plate against the vehicle store
+#[derive(Debug, Clone, Default)]
+pub struct VehicleMatch {
+    pub vehicle: Option<RegisteredVehicle>,
+    pub quality: MatchQuality,
+    /// Equally good candidates when the match is ambiguous
+    pub candidates: Vec<RegisteredVehicle>,
+}
+
+/// Match a plate against registered vehicles.
+///
+/// When `company_filter` is given, only vehicles whose company contains it
+/// are considered. A verbatim match wins; otherwise plates are compared with
+/// `LicensePlate::match_score` and the best-scoring vehicle is chosen. A tie
+/// between several vehicles (at any quality, e.g. the same plate registered
+/// under two companies) is reported as ambiguous instead of picking one.
+pub fn match_vehicle(
+    vehicle_store: &VehicleStore,
+    plate: Option<&str>,
+    company_filter: Option<&str>,
+) -> VehicleMatch {
+    let Some(plate) = plate else {
+        return VehicleMatch::default();
+    };
+
+    let candidates: Vec<&RegisteredVehicle> = vehicle_store
+        .all_vehicles()
+        .into_iter()
+        .filter(|v| match company_filter {
+            Some(company) => v.company_matches(company),
+            None => true,
+        })
         .collect();
 
-    for vehicle in vehicle_store.all_vehicles() {
-        if let Some(ref vplate) = vehicle.license_plate {
-            let normalized_vplate = vplate
-                .replace(' ', "")
-                .replace('\u{3000}', "")
-                .replace('-', "");
+    // One vehicle at `quality`, or ambiguous when several are equally good
+    let found = |matches: Vec<&RegisteredVehicle>, quality: MatchQuality| match matches.as_slice() {
+        [] => VehicleMatch::default(),
+        [vehicle] => VehicleMatch {
+            vehicle: Some((*vehicle).clone()),
+            quality,
+            candidates: Vec::new(),
+        },
+        _ => VehicleMatch {
+            vehicle: None,
+            quality: MatchQuality::Ambiguous,
+            candidates: matches.into_iter().cloned().collect(),
+        },
+    };
 
-            // Direct normalized match
-            if normalized_plate == normalized_vplate {
-                return Some(vehicle.clone());
-            }
+    let exact: Vec<&RegisteredVehicle> = candidates
+        .iter()
+        .copied()
+        .filter(|v| v.license_plate.as_deref() == Some(plate))
+        .collect();
+    if !exact.is_empty() {
+        return found(exact, MatchQuality::Exact);
+    }
 
-            // Check if last 4 digits match
-            let vplate_nums: String = normalized_vplate
-                .chars()
-                .filter(|c| c.is_ascii_digit())
-                .collect();
-            if plate_nums.len() >= 4 && vplate_nums.len() >= 4 {
-                let plate_last4 = &plate_nums[plate_nums.len() - 4..];
-                let vplate_last4 = &vplate_nums[vplate_nums.len() - 4..];
-                if plate_last4 == vplate_last4 {
-                    return Some(vehicle.clone());
-                }
-            }
+    let Some(parsed) = LicensePlate::parse(plate) else {
+        return VehicleMatch::default();
+    };
+    let scored: Vec<(&RegisteredVehicle, f64)> = candidates
+        .into_iter()
+        .filter_map(|v| {
+            let registered = LicensePlate::parse(v.license_plate.as_deref()?)?;
+            let score = parsed.match_score(&registered);
+            (score > 0.0).then_some((v, score))
+        })
+        .collect();
+
+    let best_score = scored.iter().map(|(_, s)| *s).fold(0.0, f64::max);
+    let best: Vec<&RegisteredVehicle> = scored
+        .into_iter()
+        .filter(|(_, s)| *s >= best_score)
+        .map(|(v, _)| v)
+        .collect();
+    let quality = if best_score >= 1.0 {
+        // Every part compared and equal: the same plate written differently
+        MatchQuality::Normalized
+    } else if best_score > SERIAL_ONLY_SCORE {
+        MatchQuality::Partial
+    } else {
+        MatchQuality::Last4
+    };
+    found(best, quality)
+}
+
+/// Determine the license plate for this image.
+///
+/// A manual plate always wins. Otherwise, when `plate_local_enabled` is set,
+/// YOLO locates the plate and API OCR reads the crop; if that yields nothing
+/// (or local detection is off) and `plate_local_fallback_api` is set, the
+/// whole image is read by API OCR. Detection is skipped when no registered
+/// vehicle has a plate to match. Readings are cached by image hash, so a
+/// repeated analysis of the same photo does not call YOLO or the API again.
+fn resolve_plate(
+    image_path: &Path,
+    config: &Config,
+    analyzer_config: &AnalyzerConfig,
+    vehicle_store: &VehicleStore,
+    cache: Option<&Cache>,
+    options: &AnalysisOptions,
+) -> PlateDetectionOutcome {
+    if let Some(ref plate) = options.manual_plate {
+        return PlateDetectionOutcome {
+            reading: Some(PlateReading {
+                plate: plate.clone(),
+                source: PlateSource::Manual,
+                confidence: None,
+            }),
+            ..Default::default()
+        };
+    }
+
+    if !config.plate_local_enabled && !config.plate_local_fallback_api {
+        return PlateDetectionOutcome::default();
+    }
+
+    let registered_plates: Vec<String> = vehicle_store
+        .all_vehicles()
+        .into_iter()
+        .filter_map(|v| v.license_plate.clone())
+        .collect();
+    if registered_plates.is_empty() {
+        return PlateDetectionOutcome::default();
+    }
+
+    let detection = PlateDetectionConfig {
+        enabled: config.plate_local_enabled,
+        command: config.plate_local_command.clone(),
+        min_conf: config.plate_local_min_conf,
+    };
+
+    let settings = plate_cache_settings(config, analyzer_config, &registered_plates);
+    if let Some(reading) = cache.and_then(|c| c.get_plate(image_path, &settings).ok().flatten()) {
+        return PlateDetectionOutcome {
+            reading,
+            ..Default::default()
+        };
+    }
+
+    let outcome = detect_plate(
+        image_path,
+        &detection,
+        config.plate_local_fallback_api,
+        analyzer_config,
+        &registered_plates,
+        options.verbose,
+    );
+    if let Some(cache) = cache {
+        if !outcome.failed {
+            let _ = cache.set_plate(image_path, &settings, outcome.reading.as_ref());
         }
     }
+    outcome
+}
+
+/// Plate cache key: everything a reading depends on (the OCR prompt lists
+/// the registered plates)
+fn plate_cache_settings(
+    config: &Config,
+    analyzer_config: &AnalyzerConfig,
+    registered_plates: &[String],
+) -> String {
+    format!(
+        "{}|{}|{}|{:?}|{}|{}|{}",
+        analyzer_config.backend_name(),
+        analyzer_config.model.as_deref().unwrap_or(""),
+        config.plate_local_enabled,
+        config.plate_local_command,
+        config.plate_local_min_conf,
+        config.plate_local_fallback_api,
+        registered_plates.join(",")
+    )
+}
+
+/// Record the resolved plate, its source and confidence on the result
+fn apply_plate_reading(estimation: &mut EstimationResult, reading: Option<&PlateReading>) {
+    if let Some(reading) = reading {
+        estimation.license_plate = Some(reading.plate.clone());
+        estimation.plate_source = Some(reading.source);
+        estimation.plate_confidence = reading.confidence;
+    }
+}
 
-    None
+/// What one analysis run is given, as far as it affects the result
+#[derive(Clone, Copy)]
+struct AnalysisRun<'a> {
+    /// Resolved class (override or matched vehicle)
+    truck_class: Option<&'a TruckClass>,
+    /// Material hint resolved through the catalog
+    material_hint: Option<&'a str>,
+    /// Class and material strings sent to the box-overlay pipeline
+    truck_class_str: &'a str,
+    material_type_str: &'a str,
+    /// Material catalog and truck specs in use (see [`catalog_fingerprint`])
+    catalog: &'a str,
+    ensemble_count: u32,
+    aggregator: Aggregator,
+    images: &'a [ViewImage],
+    matched_vehicle: Option<&'a RegisteredVehicle>,
 }
 
-/// Find matched vehicle based on manual plate or detected plate
-fn find_matched_vehicle(
-    vehicle_store: &VehicleStore,
-    manual_plate: Option<&str>,
-    _company_filter: Option<&str>,
-) -> Option<RegisteredVehicle> {
-    if let Some(plate) = manual_plate {
-        return find_vehicle_by_plate(vehicle_store, plate);
+/// Build the cache key inputs for the pipeline that will run.
+///
+/// Each path is keyed by the truck class / material it is actually given:
+/// the staged (karte) path by the resolved class (override or matched
+/// vehicle, else the truck type hint) and the catalog-resolved material hint,
+/// the box-overlay path by the strings sent to the pipeline. The matched
+/// vehicle's bed dimensions are included, since the result is rescaled to them.
+/// Both paths are keyed by the material catalog and truck specs: the staged
+/// prompt lists them, and every result is weighed with the catalog densities
+/// and scaled to the class bed, so editing materials.toml or trucks.toml
+/// changes the key.
+fn build_cache_inputs(
+    analyzer_config: &AnalyzerConfig,
+    options: &AnalysisOptions,
+    run: &AnalysisRun,
+) -> CacheKeyInputs {
+    let AnalysisRun {
+        truck_class,
+        material_hint,
+        truck_class_str,
+        material_type_str,
+        catalog,
+        ensemble_count,
+        aggregator,
+        images,
+        matched_vehicle,
+    } = *run;
+    let (pipeline, truck_class, material_type) = if options.karte_json.is_some() {
+        (
+            PipelineKind::Staged,
+            truck_class
+                .map(|tc| tc.label().to_string())
+                .or_else(|| options.truck_type_hint.clone()),
+            material_hint.map(str::to_string),
+        )
+    } else {
+        (
+            PipelineKind::BoxOverlay,
+            Some(truck_class_str.to_string()),
+            Some(material_type_str.to_string()),
+        )
+    };
+
+    CacheKeyInputs {
+        backend: analyzer_config.backend_name().to_string(),
+        model: analyzer_config.model.clone(),
+        pipeline,
+        truck_class,
+        material_type,
+        karte_hash: options.karte_json.as_deref().map(short_hash),
+        catalog: Some(short_hash(catalog)),
+        prompt_version: PROMPT_SPEC_VERSION.clone(),
+        ensemble_count,
+        aggregator: (ensemble_count > 1).then(|| aggregator.name().to_string()),
+        references: (pipeline == PipelineKind::Staged && options.reference_images > 0)
+            .then_some(options.reference_images),
+        fusion: (images.len() > 1).then(|| {
+            let views: Vec<&str> = images.iter().map(|i| i.view.label()).collect();
+            format!("{}:{}", options.fusion_mode.name(), views.join(","))
+        }),
+        bed: matched_vehicle.and_then(bed_key),
+        preprocess: analyzer_config
+            .preprocess
+            .as_ref()
+            .map(|p| format!("{}/q{}", p.max_long_edge, p.jpeg_quality)),
     }
-    None
+}
+
+/// Material catalog, truck classes and truck specs as loaded, for the cache key
+fn catalog_fingerprint(materials: &MaterialCatalog) -> String {
+    let mut trucks: Vec<_> = load_truck_specs()
+        .map(|loaded| loaded.specs.iter().collect())
+        .unwrap_or_default();
+    trucks.sort_by(|a, b| a.0.cmp(b.0));
+    format!(
+        "{:?}\n{:?}\n{:?}",
+        materials.entries,
+        truck_classes().classes(),
+        trucks
+    )
+}
+
+/// Cache key part for a vehicle's own bed dimensions (e.g. "4.30x2.10x0.60/deep")
+fn bed_key(vehicle: &RegisteredVehicle) -> Option<String> {
+    if !vehicle.has_bed_dimensions() {
+        return None;
+    }
+    let dim = |v: Option<f64>| {
+        v.map(|v| format!("{:.2}", v))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let mut key = format!(
+        "{}x{}x{}",
+        dim(vehicle.bed_length),
+        dim(vehicle.bed_width),
+        dim(vehicle.wall_height())
+    );
+    if let Some(body_type) = vehicle.body_type {
+        key.push('/');
+        key.push_str(body_type.name());
+    }
+    Some(key)
 }
 
 /// Calculate load grade and ratio from estimation and matched vehicle
@@ -408,9 +920,174 @@ fn calculate_load_info(
     }
 }
 
+/// Weigh a mixed load by its material breakdown.
+///
+/// `karte_mix`, the mix stated by the operator, replaces the breakdown the AI
+/// returned. Material names are mapped to their catalog ids first (e.g.
+/// "アスファルト殻" → "As殻"). Call once on a freshly computed estimate.
+pub fn apply_material_mix(
+    estimation: &mut EstimationResult,
+    karte_mix: Option<&[MaterialBreakdown]>,
+) {
+    if let Some(mix) = karte_mix.filter(|mix| !mix.is_empty()) {
+        estimation.material_breakdown = mix.to_vec();
+    }
+    estimation.material_type = canonical_material(&estimation.material_type);
+    for part in &mut estimation.material_breakdown {
+        part.material = canonical_material(&part.material);
+    }
+    weight_mixed_load(estimation);
+}
+
+/// Class spec the volume was computed with: the estimate's truck type, then
+/// `fallback_truck_type` (the class the pipeline ran with).
+///
+/// Classes without a core spec (3t, 8t, 11t+) are analyzed on their core
+/// class's bed, but `calculate_volume_and_tonnage` and the box-overlay
+/// pipeline scale the result to the class's own bed, so the class's own spec
+/// is the base a vehicle's bed is compared with.
+fn class_truck_spec(
+    estimation: &EstimationResult,
+    fallback_truck_type: &str,
+) -> Option<&'static TruckSpec> {
+    get_truck_spec(&estimation.truck_type).or_else(|| get_truck_spec(fallback_truck_type))
+}
+
+/// The matched vehicle's own bed and the ratio of its floor area to the class bed's
+fn vehicle_bed(
+    estimation: &EstimationResult,
+    fallback_truck_type: &str,
+    vehicle: Option<&RegisteredVehicle>,
+) -> Option<(TruckSpec, f64)> {
+    let class_spec = class_truck_spec(estimation, fallback_truck_type)?;
+    let spec = vehicle_truck_spec(vehicle?, class_spec)?;
+    let scale = if class_spec.bed_area() > 0.0 {
+        spec.bed_area() / class_spec.bed_area()
+    } else {
+        1.0
+    };
+    Some((spec, scale))
+}
+
+fn scale_to_bed(estimation: &mut EstimationResult, scale: f64) {
+    estimation.estimated_volume_m3 *= scale;
+    estimation.estimated_tonnage *= scale;
+}
+
+/// Rescale an estimate computed for the class bed to the matched vehicle's
+/// own bed (floor area from its registered length and width).
+///
+/// The AI's height and fill ratios describe the load itself, so the volume
+/// grows with the floor area of the bed. Vehicles without bed dimensions
+/// leave the estimate unchanged. Call once on a freshly computed estimate,
+/// before `apply_material_mix`.
+pub fn apply_vehicle_bed(
+    estimation: &mut EstimationResult,
+    fallback_truck_type: &str,
+    vehicle: Option<&RegisteredVehicle>,
+) {
+    let Some((_, scale)) = vehicle_bed(estimation, fallback_truck_type, vehicle) else {
+        return;
+    };
+    scale_to_bed(estimation, scale);
+    estimation.tonnage_std_dev = estimation.tonnage_std_dev.map(|sd| sd * scale);
+    for sample in &mut estimation.ensemble_samples {
+        sample.estimated_volume_m3 *= scale;
+        sample.estimated_tonnage *= scale;
+    }
+}
+
+/// Check the estimate against the truck spec and material density.
+///
+/// The truck is looked up by the estimate's truck type, then by
+/// `fallback_truck_type` (the class the pipeline ran with). A mixed load is
+/// checked against the bulk density of its mix. Clamped parameters are turned
+/// back into volume and tonnage with the shared formula and the mix.
+pub fn apply_plausibility_checks(estimation: &mut EstimationResult, fallback_truck_type: &str) {
+    apply_plausibility_checks_for_vehicle(estimation, fallback_truck_type, None);
+}
+
+/// `apply_plausibility_checks` against the matched vehicle's own bed when it
+/// has registered dimensions; recomputed values are rescaled to that bed.
+pub fn apply_plausibility_checks_for_vehicle(
+    estimation: &mut EstimationResult,
+    fallback_truck_type: &str,
+    vehicle: Option<&RegisteredVehicle>,
+) {
+    let Some(class_spec) = class_truck_spec(estimation, fallback_truck_type) else {
+        return;
+    };
+    let own_bed = vehicle_bed(estimation, fallback_truck_type, vehicle);
+    let (truck, scale) = match own_bed {
+        Some((ref spec, scale)) => (spec, scale),
+        None => (class_spec, 1.0),
+    };
+    let mixed = (estimation.material_breakdown.len() > 1)
+        .then(|| mix_bulk_density(estimation))
+        .flatten()
+        .map(|density| MaterialSpec {
+            name: "混合積載".to_string(),
+            density,
+            void_ratio: 0.0,
+        });
+    let material = mixed
+        .as_ref()
+        .or_else(|| get_material_spec(&estimation.material_type));
+    check_plausibility(
+        estimation,
+        truck,
+        material,
+        &PlausibilityLimits::default(),
+        |result| {
+            calculate_volume_and_tonnage(result);
+            scale_to_bed(result, scale);
+            weight_mixed_load(result);
+        },
+    );
+}
+
+/// Correct the tonnage with the calibration model, keeping the raw AI value
+/// in `raw_tonnage`.
+///
+/// Always starts from the raw value, so a cached result is re-calibrated with
+/// the current model, and restored to its raw tonnage when `model` is None.
+fn apply_calibration(estimation: &mut EstimationResult, model: Option<&CalibrationModel>) {
+    let raw = estimation
+        .raw_tonnage
+        .take()
+        .unwrap_or(estimation.estimated_tonnage);
+    estimation.estimated_tonnage = raw;
+    if raw <= 0.0 {
+        return;
+    }
+    if let Some(calibrated) =
+        model.and_then(|m| m.calibrate(&estimation.truck_type, &estimation.material_type, raw))
+    {
+        estimation.estimated_tonnage = calibrated;
+        estimation.raw_tonnage = Some(raw);
+    }
+}
+
+/// Set P10/P90 bounds from the ensemble spread and the history's error for this class
+fn apply_prediction_interval(estimation: &mut EstimationResult, store: &Store) {
+    estimation.tonnage_interval = if estimation.estimated_tonnage > 0.0 {
+        Some(store.accuracy_stats().prediction_interval(estimation))
+    } else {
+        None
+    };
+}
+
+fn is_possibly_overloaded(
+    estimation: &EstimationResult,
+    matched_vehicle: Option<&RegisteredVehicle>,
+) -> bool {
+    matched_vehicle.is_some_and(|vehicle| estimation.possibly_overloaded(vehicle.max_capacity))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tonsuu_vision::ImagePreprocessor;
 
     #[test]
     fn test_analysis_options_builder() {
@@ -424,6 +1101,145 @@ mod tests {
         assert!(!options.use_cache);
     }
 
+    #[test]
+    fn test_calibration_keeps_raw_tonnage() {
+        use tonsuu_store::{AccuracySample, CalibrationMethod};
+
+        // 4t/As殻 estimates run 0.5t high
+        let samples: Vec<AccuracySample> = [3.0, 3.5, 4.0, 4.5, 5.0]
+            .iter()
+            .map(|&actual| AccuracySample {
+                estimated: actual + 0.5,
+                actual,
+                truck_type: "4t".to_string(),
+                material_type: "As殻".to_string(),
+                used_references: false,
+            })
+            .collect();
+        let model = CalibrationModel::fit(&samples, CalibrationMethod::Linear);
+
+        let mut estimation = EstimationResult {
+            truck_type: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            estimated_tonnage: 4.2,
+            ..Default::default()
+        };
+        apply_calibration(&mut estimation, Some(&model));
+        assert!((estimation.estimated_tonnage - 3.7).abs() < 1e-9);
+        assert_eq!(estimation.raw_tonnage, Some(4.2));
+
+        // Re-applying starts from the raw value; no model restores it
+        apply_calibration(&mut estimation, Some(&model));
+        assert!((estimation.estimated_tonnage - 3.7).abs() < 1e-9);
+        apply_calibration(&mut estimation, None);
+        assert_eq!(estimation.estimated_tonnage, 4.2);
+        assert_eq!(estimation.raw_tonnage, None);
+    }
+
+    #[test]
+    fn test_material_mix_uses_catalog_ids() {
+        let part = |material: &str, percentage: f64| MaterialBreakdown {
+            material: material.to_string(),
+            percentage,
+            density: 0.0,
+            tonnage: None,
+        };
+        let mut estimation = EstimationResult {
+            material_type: "アスファルト殻".to_string(),
+            estimated_volume_m3: 2.0,
+            estimated_tonnage: 3.5,
+            ..Default::default()
+        };
+        apply_material_mix(
+            &mut estimation,
+            Some(&[part("ASガラ", 70.0), part("CONガラ", 30.0)]),
+        );
+
+        assert_eq!(estimation.material_type, "As殻");
+        let names: Vec<&str> = estimation
+            .material_breakdown
+            .iter()
+            .map(|b| b.material.as_str())
+            .collect();
+        assert_eq!(names, vec!["As殻", "Co殻"]);
+        assert!(estimation
+            .material_breakdown
+            .iter()
+            .all(|b| b.tonnage.is_some()));
+    }
+
+    #[test]
+    fn test_vehicle_bed_rescales_estimate() {
+        let class_spec = get_truck_spec("4t").unwrap();
+        let estimate = || EstimationResult {
+            is_target_detected: true,
+            truck_type: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            height: Some(0.3),
+            estimated_volume_m3: 2.0,
+            estimated_tonnage: 3.0,
+            tonnage_std_dev: Some(0.2),
+            ..Default::default()
+        };
+
+        // A long body: 1.5x the class bed's floor area
+        let long = RegisteredVehicle::new("4t ロング".to_string(), 4.0).with_bed(
+            class_spec.bed_length * 1.5,
+            class_spec.bed_width,
+            class_spec.bed_height,
+        );
+        let mut estimation = estimate();
+        apply_vehicle_bed(&mut estimation, "4t", Some(&long));
+        assert!((estimation.estimated_volume_m3 - 3.0).abs() < 1e-9);
+        assert!((estimation.estimated_tonnage - 4.5).abs() < 1e-9);
+        assert!((estimation.tonnage_std_dev.unwrap() - 0.3).abs() < 1e-9);
+
+        // Without registered dimensions the class spec applies unchanged
+        let mut estimation = estimate();
+        apply_vehicle_bed(
+            &mut estimation,
+            "4t",
+            Some(&RegisteredVehicle::new("4t".to_string(), 4.0)),
+        );
+        assert_eq!(estimation.estimated_tonnage, 3.0);
+        assert!(bed_key(&long).is_some());
+        assert!(bed_key(&RegisteredVehicle::new("4t".to_string(), 4.0)).is_none());
+    }
+
+    #[test]
+    fn test_vehicle_bed_of_class_without_core_spec() {
+        // An 11t+ estimate is computed on the 10t bed and scaled to the 11t+ bed
+        let estimate = |truck_type: &str| {
+            let mut estimation = EstimationResult {
+                is_target_detected: true,
+                truck_type: truck_type.to_string(),
+                material_type: "土砂".to_string(),
+                height: Some(0.5),
+                fill_ratio_l: Some(0.8),
+                fill_ratio_w: Some(0.6),
+                packing_density: Some(0.8),
+                ..Default::default()
+            };
+            calculate_volume_and_tonnage(&mut estimation);
+            estimation
+        };
+        let ten_spec = get_truck_spec("10t").unwrap();
+        let trailer_spec = get_truck_spec("11t+").unwrap();
+        let ten = estimate("10t");
+
+        // The vehicle's bed is compared with the 11t+ bed the estimate is on
+        let vehicle = RegisteredVehicle::new("トレーラー".to_string(), 18.0).with_bed(
+            8.0,
+            trailer_spec.bed_width,
+            trailer_spec.bed_height,
+        );
+        let mut estimation = estimate("11t+");
+        apply_vehicle_bed(&mut estimation, "11t+", Some(&vehicle));
+        let expected = ten.estimated_tonnage * (8.0 * trailer_spec.bed_width)
+            / (ten_spec.bed_length * ten_spec.bed_width);
+        assert!((estimation.estimated_tonnage - expected).abs() < 1e-6);
+    }
+
     #[test]
     fn test_calculate_load_info() {
         let estimation = EstimationResult {
@@ -439,4 +1255,321 @@ mod tests {
         assert!(ratio.is_some());
         assert!((ratio.unwrap() - 0.85).abs() < 0.01);
     }
+
+    #[test]
+    fn test_manual_plate_is_recorded() {
+        let tmp = tempfile::tempdir().unwrap();
+        let options = AnalysisOptions::new().with_manual_plate("熊本 130 ら 1122".to_string());
+        let outcome = resolve_plate(
+            Path::new("unused.jpg"),
+            &Config::default(),
+            &AnalyzerConfig::default(),
+            &VehicleStore::open(tmp.path().to_path_buf()).unwrap(),
+            None,
+            &options,
+        );
+
+        let mut estimation = EstimationResult::default();
+        apply_plate_reading(&mut estimation, outcome.reading.as_ref());
+        assert_eq!(
+            estimation.license_plate.as_deref(),
+            Some("熊本 130 ら 1122")
+        );
+        assert_eq!(estimation.plate_source, Some(PlateSource::Manual));
+        assert_eq!(estimation.plate_confidence, None);
+        assert!(outcome.yolo_ms.is_none() && outcome.api_ms.is_none());
+    }
+
+    #[test]
+    fn test_cached_plate_reading_with_class_override() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let image = dir.join("rear.jpg");
+        std::fs::write(&image, b"rear").unwrap();
+
+        let plate = "熊本 130 ら 1122";
+        let mut vehicles = VehicleStore::open(dir.join("store")).unwrap();
+        vehicles
+            .add_vehicle(vehicle("A号車", plate, "松尾運搬"))
+            .unwrap();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        // Local detection off, API fallback on (the defaults)
+        let config = Config::default();
+        let analyzer_config = AnalyzerConfig::default();
+        let reading = PlateReading {
+            plate: plate.to_string(),
+            source: PlateSource::Api,
+            confidence: Some(0.9),
+        };
+        let settings = plate_cache_settings(&config, &analyzer_config, &[plate.to_string()]);
+        cache.set_plate(&image, &settings, Some(&reading)).unwrap();
+
+        // A class override no longer disables plate reading; the cache avoids the API
+        let options = AnalysisOptions::new().with_truck_class(TruckClass::new("10t"));
+        let outcome = resolve_plate(
+            &image,
+            &config,
+            &analyzer_config,
+            &vehicles,
+            Some(&cache),
+            &options,
+        );
+        assert_eq!(outcome.reading, Some(reading));
+        assert!(outcome.yolo_ms.is_none() && outcome.api_ms.is_none());
+    }
+
+    fn vehicle(name: &str, plate: &str, company: &str) -> RegisteredVehicle {
+        let mut vehicle =
+            RegisteredVehicle::new(name.to_string(), 4.0).with_license_plate(plate.to_string());
+        vehicle.company = Some(company.to_string());
+        vehicle
+    }
+
+    #[test]
+    fn test_match_vehicle_scoped_by_company() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let mut store = VehicleStore::open(dir.to_path_buf()).unwrap();
+        store
+            .add_vehicle(vehicle("A号車", "熊本 130 ら 1122", "松尾運搬"))
+            .unwrap();
+        store
+            .add_vehicle(vehicle("B号車", "福岡 100 あ 11-22", "山田建設"))
+            .unwrap();
+
+        let exact = match_vehicle(&store, Some("熊本 130 ら 1122"), None);
+        assert_eq!(exact.quality, MatchQuality::Exact);
+
+        let normalized = match_vehicle(&store, Some("福岡１００あ１１－２２"), None);
+        assert_eq!(normalized.quality, MatchQuality::Normalized);
+        assert_eq!(normalized.vehicle.unwrap().name, "B号車");
+
+        let partial = match_vehicle(&store, Some("130 ら 1122"), None);
+        assert_eq!(partial.quality, MatchQuality::Partial);
+        assert_eq!(partial.vehicle.unwrap().name, "A号車");
+
+        let ambiguous = match_vehicle(&store, Some("1122"), None);
+        assert_eq!(ambiguous.quality, MatchQuality::Ambiguous);
+        assert!(ambiguous.vehicle.is_none());
+        assert_eq!(ambiguous.candidates.len(), 2);
+
+        let scoped = match_vehicle(&store, Some("1122"), Some("松尾"));
+        assert_eq!(scoped.quality, MatchQuality::Last4);
+        assert_eq!(scoped.vehicle.unwrap().name, "A号車");
+        assert!(scoped.quality.needs_confirmation());
+
+        // Same serial but a conflicting region is not a match
+        let other_company = match_vehicle(&store, Some("熊本 130 ら 1122"), Some("山田"));
+        assert_eq!(other_company.quality, MatchQuality::None);
+
+        assert_eq!(
+            match_vehicle(&store, None, None).quality,
+            MatchQuality::None
+        );
+    }
+
+    #[test]
+    fn test_match_vehicle_reports_tied_exact_plates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let mut store = VehicleStore::open(dir.to_path_buf()).unwrap();
+        store
+            .add_vehicle(vehicle("A号車", "熊本 130 ら 1122", "松尾運搬"))
+            .unwrap();
+        store
+            .add_vehicle(vehicle("C号車", "熊本 130 ら 1122", "山田建設"))
+            .unwrap();
+
+        // The same plate under two companies: neither is picked
+        let exact = match_vehicle(&store, Some("熊本 130 ら 1122"), None);
+        assert_eq!(exact.quality, MatchQuality::Ambiguous);
+        assert!(exact.vehicle.is_none());
+        assert_eq!(exact.candidates.len(), 2);
+
+        let normalized = match_vehicle(&store, Some("熊本１３０ら１１２２"), None);
+        assert_eq!(normalized.quality, MatchQuality::Ambiguous);
+        assert_eq!(normalized.candidates.len(), 2);
+
+        // The company filter resolves the tie
+        let scoped = match_vehicle(&store, Some("熊本 130 ら 1122"), Some("山田"));
+        assert_eq!(scoped.quality, MatchQuality::Exact);
+        assert_eq!(scoped.vehicle.unwrap().name, "C号車");
+    }
+
+    #[test]
+    fn test_cache_inputs_distinguish_settings() {
+        let analyzer_config = AnalyzerConfig::default();
+        let options = AnalysisOptions::new();
+        let single = [ViewImage::new("a.jpg", ImageView::Unspecified)];
+        let run = AnalysisRun {
+            truck_class: None,
+            material_hint: None,
+            truck_class_str: "4t",
+            material_type_str: "As殻",
+            catalog: "catalog",
+            ensemble_count: 1,
+            aggregator: Aggregator::Median,
+            images: &single,
+            matched_vehicle: None,
+        };
+        let key = |options: &AnalysisOptions, run: AnalysisRun| {
+            build_cache_inputs(&analyzer_config, options, &run)
+        };
+
+        let base = key(&options, run);
+        assert_eq!(base.pipeline, PipelineKind::BoxOverlay);
+        let ensemble = AnalysisRun {
+            ensemble_count: 3,
+            ..run
+        };
+        assert_ne!(base, key(&options, ensemble));
+        assert_ne!(
+            base,
+            key(
+                &options,
+                AnalysisRun {
+                    truck_class_str: "10t",
+                    ..run
+                }
+            )
+        );
+
+        // The aggregator only matters (and only changes the key) for ensembles
+        let trimmed = AnalysisRun {
+            aggregator: Aggregator::TrimmedMean,
+            ..run
+        };
+        assert_eq!(base, key(&options, trimmed));
+        assert_ne!(
+            key(&options, ensemble),
+            key(
+                &options,
+                AnalysisRun {
+                    aggregator: Aggregator::TrimmedMean,
+                    ..ensemble
+                }
+            )
+        );
+
+        // Multi-image runs are keyed by fusion mode and views
+        assert!(base.fusion.is_none());
+        let views = [
+            ViewImage::new("a.jpg", ImageView::Rear),
+            ViewImage::new("b.jpg", ImageView::Side),
+        ];
+        let multi = AnalysisRun {
+            images: &views,
+            ..run
+        };
+        let joint = key(&options, multi);
+        assert_eq!(joint.fusion.as_deref(), Some("joint:rear,side"));
+        let per_view = options.clone().with_fusion_mode(FusionMode::PerView);
+        assert_ne!(joint, key(&per_view, multi));
+
+        let karte = options
+            .clone()
+            .with_karte_json("{\"truckType\":\"4t\"}".to_string());
+        let staged = key(&karte, run);
+        assert_eq!(staged.pipeline, PipelineKind::Staged);
+        assert!(staged.karte_hash.is_some());
+
+        // Both pipelines are keyed by the material catalog and truck specs
+        for options in [&options, &karte] {
+            let edited = AnalysisRun {
+                catalog: "edited catalog",
+                ..run
+            };
+            assert_ne!(key(options, run), key(options, edited));
+        }
+
+        // Staged runs are keyed by the class and material they are given
+        let four = TruckClass::new("4t");
+        let staged_4t = key(
+            &karte,
+            AnalysisRun {
+                truck_class: Some(&four),
+                material_hint: Some("As殻"),
+                ..run
+            },
+        );
+        assert_eq!(staged_4t.truck_class.as_deref(), Some("4t"));
+        assert_eq!(staged_4t.material_type.as_deref(), Some("As殻"));
+        let ten = TruckClass::new("10t");
+        let staged_10t = AnalysisRun {
+            truck_class: Some(&ten),
+            material_hint: Some("As殻"),
+            truck_class_str: "10t",
+            ..run
+        };
+        assert_ne!(staged_4t, key(&karte, staged_10t));
+
+        // Preprocessed uploads are keyed by their settings
+        assert!(base.preprocess.is_none());
+        let preprocessed = analyzer_config.clone().with_preprocessing(
+            ImagePreprocessor::new(PathBuf::from("pre")).with_max_long_edge(1200),
+        );
+        let resized = build_cache_inputs(&preprocessed, &options, &run);
+        assert_eq!(resized.preprocess.as_deref(), Some("1200/q85"));
+    }
+
+    #[test]
+    fn test_analysis_uses_sqlite_backend() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let image_path = dir.join("truck.jpg");
+        image::RgbImage::new(8, 8).save(&image_path).unwrap();
+
+        let config = Config {
+            storage_backend: "sqlite".to_string(),
+            store_dir: Some(dir.join("store")),
+            cache_dir: Some(dir.join("cache")),
+            ..Config::default()
+        };
+        let plate = "熊本 130 ら 1122";
+        let registered = vehicle("A号車", plate, "松尾運搬");
+        open_vehicle_store(&config)
+            .unwrap()
+            .add_vehicle(registered.clone())
+            .unwrap();
+
+        // Seed the cache with the key this run computes, so no AI backend is called
+        let images = [ViewImage::new(image_path.clone(), ImageView::Unspecified)];
+        let options = AnalysisOptions::new().with_manual_plate(plate.to_string());
+        let truck_class = registered.truck_class();
+        let inputs = build_cache_inputs(
+            &analyzer_config_for(&config),
+            &options,
+            &AnalysisRun {
+                truck_class: Some(&truck_class),
+                material_hint: None,
+                truck_class_str: truck_class.label(),
+                material_type_str: load_material_catalog().unwrap().default_id.as_str(),
+                catalog: &catalog_fingerprint(load_material_catalog().unwrap()),
+                ensemble_count: 1,
+                aggregator: config.ensemble_aggregator(),
+                images: &images,
+                matched_vehicle: Some(&registered),
+            },
+        );
+        let cached = EstimationResult {
+            estimated_tonnage: 3.2,
+            ..Default::default()
+        };
+        Cache::new(config.cache_dir().unwrap())
+            .unwrap()
+            .set_images(&[image_path.clone()], &inputs, &cached)
+            .unwrap();
+
+        let result = analyze_truck_images(&images, &config, &options, None).unwrap();
+        assert!(result.from_cache);
+        assert_eq!(result.matched_vehicle.map(|v| v.id), Some(registered.id));
+
+        // The vehicle was read from the database; no JSON store was written
+        let store_dir = config.store_dir().unwrap();
+        assert!(store_dir
+            .join(tonsuu_infra::persistence::SQLITE_DB_FILE)
+            .exists());
+        assert!(!store_dir.join("vehicles.json").exists());
+        assert!(!store_dir.join("history.json").exists());
+    }
 }
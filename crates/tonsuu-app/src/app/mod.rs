@@ -12,5 +12,7 @@ pub mod query_service;
 
 // Re-export main types for convenience
 pub use analysis_service::{
-    analyze_truck_image, AnalysisOptions, AnalysisServiceError,
+    analyze_truck_image, analyze_truck_images, apply_material_mix, apply_plausibility_checks,
+    apply_plausibility_checks_for_vehicle, apply_vehicle_bed, match_vehicle,
+    AnalysisOptions, AnalysisServiceError, MatchQuality, VehicleMatch,
 };
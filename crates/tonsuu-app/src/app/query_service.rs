@@ -11,6 +11,7 @@
 #![allow(dead_code)]
 
 use crate::config::Config;
+use crate::repository;
 use std::path::Path;
 use thiserror::Error;
 use tonsuu_store::{AccuracyStats, HistoryEntry, Store, VehicleStore};
@@ -53,7 +54,7 @@ pub fn get_vehicles_by_company(
     Ok(store
         .all_vehicles()
         .into_iter()
-        .filter(|v| v.company.as_ref().map(|c| c.contains(company)).unwrap_or(false))
+        .filter(|v| v.company_matches(company))
         .cloned()
         .collect())
 }
@@ -83,41 +84,8 @@ pub fn get_vehicle_by_plate(
 ) -> std::result::Result<Option<RegisteredVehicle>, QueryServiceError> {
     let store = open_vehicle_store(config)?;
 
-    // Try exact match first
-    if let Some(vehicle) = store.get_by_license_plate(plate) {
-        return Ok(Some(vehicle.clone()));
-    }
-
-    // Try fuzzy match
-    let normalized_plate = plate.replace(' ', "").replace('\u{3000}', "").replace('-', "");
-    let plate_nums: String = normalized_plate.chars().filter(|c| c.is_ascii_digit()).collect();
-
-    for vehicle in store.all_vehicles() {
-        if let Some(ref vplate) = vehicle.license_plate {
-            let normalized_vplate = vplate
-                .replace(' ', "")
-                .replace('\u{3000}', "")
-                .replace('-', "");
-
-            if normalized_plate == normalized_vplate {
-                return Ok(Some(vehicle.clone()));
-            }
-
-            let vplate_nums: String = normalized_vplate
-                .chars()
-                .filter(|c| c.is_ascii_digit())
-                .collect();
-            if plate_nums.len() >= 4 && vplate_nums.len() >= 4 {
-                let plate_last4 = &plate_nums[plate_nums.len() - 4..];
-                let vplate_last4 = &vplate_nums[vplate_nums.len() - 4..];
-                if plate_last4 == vplate_last4 {
-                    return Ok(Some(vehicle.clone()));
-                }
-            }
-        }
-    }
-
-    Ok(None)
+    // Ambiguous matches return None rather than an arbitrary vehicle
+    Ok(super::analysis_service::match_vehicle(&store, Some(plate), None).vehicle)
 }
 
 /// Get total vehicle count
@@ -219,19 +187,13 @@ pub fn get_accuracy_by_material_type(
 // ============================================================================
 
 fn open_vehicle_store(config: &Config) -> std::result::Result<VehicleStore, QueryServiceError> {
-    let store_dir = config.store_dir().map_err(|e| {
-        QueryServiceError::StoreError(format!("Failed to get store directory: {}", e))
-    })?;
-    VehicleStore::open(store_dir).map_err(|e| {
+    repository::open_vehicle_store(config).map_err(|e| {
         QueryServiceError::StoreError(format!("Failed to open vehicle store: {}", e))
     })
 }
 
 fn open_history_store(config: &Config) -> std::result::Result<Store, QueryServiceError> {
-    let store_dir = config.store_dir().map_err(|e| {
-        QueryServiceError::StoreError(format!("Failed to get store directory: {}", e))
-    })?;
-    Store::open(store_dir).map_err(|e| {
+    repository::open_history_store(config).map_err(|e| {
         QueryServiceError::StoreError(format!("Failed to open history store: {}", e))
     })
 }
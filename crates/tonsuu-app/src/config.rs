@@ -5,7 +5,11 @@
 
 use tonsuu_types::OutputFormat;
 use tonsuu_domain::{MaterialSpec, TruckSpec};
-use tonsuu_types::{ConfigError, Result};
+use tonsuu_types::{install_truck_classes, ConfigError, Result, TruckClassDef, TruckClassRegistry};
+use tonsuu_vision::{
+    Aggregator, CachePolicy, ImagePreprocessor, DEFAULT_JPEG_QUALITY, DEFAULT_MAX_CONCURRENCY,
+    DEFAULT_MAX_LONG_EDGE,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -22,6 +26,12 @@ pub struct TruckConfigEntry {
     pub bed_height: f64,
     pub level_volume: f64,
     pub heap_volume: f64,
+    /// Range of registered max capacities (t) classed as this truck
+    #[serde(default)]
+    pub capacity_range: Option<[f64; 2]>,
+    /// Class the box-overlay pipeline analyzes this truck as (default: `id`)
+    #[serde(default)]
+    pub core_class: Option<String>,
     #[serde(default)]
     pub aliases: Vec<String>,
 }
@@ -33,21 +43,23 @@ pub struct TrucksConfig {
 }
 
 /// Material entry in TOML config
-/// Note: Prepared for material specification loading. Currently unused.
-#[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize)]
 pub struct MaterialConfigEntry {
     pub id: String,
     pub name: String,
     pub density: f64,
     pub void_ratio: f64,
+    /// Other spellings (e.g. slip names such as "ASガラ")
+    #[serde(default)]
+    pub aliases: Vec<String>,
 }
 
 /// Materials config file structure
-/// Note: Prepared for material specification loading. Currently unused.
-#[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize)]
 pub struct MaterialsConfig {
+    /// Material assumed when none is given (first entry if omitted)
+    #[serde(default)]
+    pub default: Option<String>,
     pub materials: Vec<MaterialConfigEntry>,
 }
 
@@ -55,19 +67,119 @@ pub struct MaterialsConfig {
 pub struct LoadedTruckSpecs {
     pub specs: HashMap<String, TruckSpec>,
     pub aliases: HashMap<String, String>,
+    /// Truck classes in file order
+    pub classes: Vec<TruckClassDef>,
 }
 
-/// Loaded material specs
-/// Note: Prepared for material specification loading. Currently unused.
-#[allow(dead_code)]
-pub struct LoadedMaterialSpecs {
+/// Material catalog loaded from materials.toml, keyed by material id
+pub struct MaterialCatalog {
+    /// Entries in file order
+    pub entries: Vec<MaterialConfigEntry>,
     pub specs: HashMap<String, MaterialSpec>,
+    pub aliases: HashMap<String, String>,
+    pub default_id: String,
 }
 
+impl MaterialCatalog {
+    /// Build the catalog, rejecting duplicate ids/aliases and an unknown default
+    pub fn from_config(config: MaterialsConfig) -> std::result::Result<Self, String> {
+        let mut specs = HashMap::new();
+        let mut aliases = HashMap::new();
+
+        for entry in &config.materials {
+            let spec = MaterialSpec {
+                name: entry.name.clone(),
+                density: entry.density,
+                void_ratio: entry.void_ratio,
+            };
+            if specs.insert(entry.id.clone(), spec).is_some() {
+                return Err(format!("Duplicate material id: {}", entry.id));
+            }
+        }
+        for entry in &config.materials {
+            for alias in entry.aliases.iter().chain(std::iter::once(&entry.name)) {
+                if alias == &entry.id {
+                    continue;
+                }
+                if specs.contains_key(alias) {
+                    return Err(format!("Alias {} of {} is another material id", alias, entry.id));
+                }
+                if let Some(other) = aliases.insert(alias.clone(), entry.id.clone()) {
+                    if other != entry.id {
+                        return Err(format!("Alias {} is used by both {} and {}", alias, other, entry.id));
+                    }
+                }
+            }
+        }
+
+        let default_id = match config.default {
+            Some(id) if specs.contains_key(&id) => id,
+            Some(id) => return Err(format!("Default material {} is not defined", id)),
+            None => config
+                .materials
+                .first()
+                .map(|m| m.id.clone())
+                .ok_or_else(|| "No materials defined".to_string())?,
+        };
+
+        Ok(Self {
+            entries: config.materials,
+            specs,
+            aliases,
+            default_id,
+        })
+    }
+
+    /// Resolve a material name (id, alias, or either in another letter case) to its id
+    pub fn resolve(&self, name: &str) -> Option<&str> {
+        let trimmed = name.trim();
+        if let Some((id, _)) = self.specs.get_key_value(trimmed) {
+            return Some(id);
+        }
+        if let Some(id) = self.aliases.get(trimmed) {
+            return Some(id);
+        }
+
+        let lower_input = trimmed.to_lowercase();
+        if let Some(id) = self.specs.keys().find(|id| id.to_lowercase() == lower_input) {
+            return Some(id);
+        }
+        self.aliases
+            .iter()
+            .find(|(alias, _)| alias.to_lowercase() == lower_input)
+            .map(|(_, id)| id.as_str())
+    }
+
+    /// Spec of a material given by id or alias
+    pub fn spec(&self, name: &str) -> Option<&MaterialSpec> {
+        self.resolve(name).and_then(|id| self.specs.get(id))
+    }
+
+    /// Material ids in file order
+    pub fn ids(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    /// Parse and validate the contents of a materials.toml
+    pub fn from_toml(content: &str) -> std::result::Result<Self, String> {
+        let config: MaterialsConfig = toml::from_str(content)
+            .map_err(|e| format!("Failed to parse materials.toml: {}", e))?;
+
+        Self::from_config(config).map_err(|e| format!("Invalid materials.toml: {}", e))
+    }
+
+    /// Catalog used when the config directory has no materials.toml
+    pub fn builtin() -> Self {
+        Self::from_toml(DEFAULT_MATERIALS_TOML).expect("built-in materials.toml is invalid")
+    }
+}
+
+/// `config/materials.toml` as shipped, used when the config directory has none
+const DEFAULT_MATERIALS_TOML: &str = include_str!("../../../config/materials.toml");
+
 // Static storage for loaded specs (stores Result to handle errors)
 static LOADED_TRUCK_SPECS: OnceLock<std::result::Result<LoadedTruckSpecs, String>> = OnceLock::new();
-#[allow(dead_code)]
-static LOADED_MATERIAL_SPECS: OnceLock<std::result::Result<LoadedMaterialSpecs, String>> = OnceLock::new();
+static MATERIAL_CATALOG: OnceLock<std::result::Result<MaterialCatalog, String>> = OnceLock::new();
 
 /// Get the config directory path relative to the executable or project root
 fn get_config_dir() -> PathBuf {
@@ -125,6 +237,7 @@ fn load_truck_specs_internal() -> std::result::Result<LoadedTruckSpecs, String>
 
     let config: TrucksConfig = toml::from_str(&content)
         .map_err(|e| format!("Failed to parse trucks.toml: {}", e))?;
+    let classes = TruckClassRegistry::from_toml(&content)?.classes().to_vec();
 
     let mut specs = HashMap::new();
     let mut aliases = HashMap::new();
@@ -146,7 +259,7 @@ fn load_truck_specs_internal() -> std::result::Result<LoadedTruckSpecs, String>
         }
     }
 
-    Ok(LoadedTruckSpecs { specs, aliases })
+    Ok(LoadedTruckSpecs { specs, aliases, classes })
 }
 
 /// Load truck specs from TOML config file
@@ -158,43 +271,38 @@ pub fn load_truck_specs() -> Result<&'static LoadedTruckSpecs> {
     }
 }
 
-/// Internal function to load material specs
-/// Note: Prepared for material specification loading. Currently unused.
-#[allow(dead_code)]
-fn load_material_specs_internal() -> std::result::Result<LoadedMaterialSpecs, String> {
-    let config_path = get_config_dir().join("materials.toml");
-    let content = std::fs::read_to_string(&config_path).map_err(|e| {
-        format!(
-            "Failed to read materials.toml from {}: {}",
-            config_path.display(),
-            e
-        )
-    })?;
-
-    let config: MaterialsConfig = toml::from_str(&content)
-        .map_err(|e| format!("Failed to parse materials.toml: {}", e))?;
-
-    let mut specs = HashMap::new();
-
-    for entry in config.materials {
-        let spec = MaterialSpec {
-            name: entry.name,
-            density: entry.density,
-            void_ratio: entry.void_ratio,
-        };
-        specs.insert(entry.id, spec);
-    }
+/// Make the truck classes from trucks.toml the ones `TruckClass` uses.
+///
+/// Call once at startup, before any truck class is looked up. Returns
+/// `false` if the built-in classes were already in use.
+pub fn install_configured_truck_classes() -> Result<bool> {
+    let loaded = load_truck_specs()?;
+    Ok(install_truck_classes(TruckClassRegistry::new(loaded.classes.clone())))
+}
 
-    Ok(LoadedMaterialSpecs { specs })
+/// Internal function to load the material catalog
+fn load_material_catalog_internal() -> std::result::Result<MaterialCatalog, String> {
+    let config_path = get_config_dir().join("materials.toml");
+    let content = match std::fs::read_to_string(&config_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(MaterialCatalog::builtin()),
+        Err(e) => {
+            return Err(format!(
+                "Failed to read materials.toml from {}: {}",
+                config_path.display(),
+                e
+            ))
+        }
+    };
+    MaterialCatalog::from_toml(&content)
 }
 
-/// Load material specs from TOML config file
-/// Note: Prepared for material specification loading. Currently unused.
-#[allow(dead_code)]
-pub fn load_material_specs() -> Result<&'static LoadedMaterialSpecs> {
-    let result = LOADED_MATERIAL_SPECS.get_or_init(load_material_specs_internal);
+/// Load the material catalog from TOML config file (the shipped one if the
+/// config directory has no materials.toml)
+pub fn load_material_catalog() -> Result<&'static MaterialCatalog> {
+    let result = MATERIAL_CATALOG.get_or_init(load_material_catalog_internal);
     match result {
-        Ok(specs) => Ok(specs),
+        Ok(catalog) => Ok(catalog),
         Err(e) => Err(ConfigError::ParseError(e.clone()).into()),
     }
 }
@@ -218,6 +326,14 @@ pub struct Config {
     #[serde(default)]
     pub cache_dir: Option<PathBuf>,
 
+    /// Expire cache entries older than this many days (None = never)
+    #[serde(default)]
+    pub cache_max_age_days: Option<u32>,
+
+    /// Cap total cache size in MB; least-recently-hit entries are evicted (None = unlimited)
+    #[serde(default)]
+    pub cache_max_size_mb: Option<u64>,
+
     /// Default output format (json, table)
     #[serde(default = "default_output_format")]
     pub output_format: OutputFormat,
@@ -238,13 +354,49 @@ pub struct Config {
     #[serde(default = "default_plate_local_min_conf")]
     pub plate_local_min_conf: f32,
 
-    /// If local detection fails, fall back to API-based stage1
+    /// Read the plate by API OCR when local detection is off or finds nothing
     #[serde(default = "default_true")]
     pub plate_local_fallback_api: bool,
 
     /// Usage mode (time_based_quota, pay_per_use)
     #[serde(default = "default_usage_mode")]
     pub usage_mode: String,
+
+    /// Storage backend for history and registered vehicles (json, sqlite)
+    #[serde(default = "default_storage_backend")]
+    pub storage_backend: String,
+
+    /// Store directory override (history, vehicles, SQLite database)
+    #[serde(default)]
+    pub store_dir: Option<PathBuf>,
+
+    /// How ensemble samples are combined (median, trimmed_mean, confidence_weighted)
+    #[serde(default = "default_ensemble_aggregator")]
+    pub ensemble_aggregator: String,
+
+    /// Concurrent AI requests per backend (shared by ensemble samples and batch workers)
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+
+    /// Graded reference thumbnails attached to staged prompts (0 = off)
+    #[serde(default)]
+    pub reference_images: usize,
+
+    /// Apply the saved calibration model (see `calibrate`) to new estimates
+    #[serde(default = "default_false")]
+    pub apply_calibration: bool,
+
+    /// Upright, downsample and recompress photos before they are uploaded
+    #[serde(default = "default_true")]
+    pub preprocess_enabled: bool,
+
+    /// Longest edge of uploaded photos (px)
+    #[serde(default = "default_preprocess_max_long_edge")]
+    pub preprocess_max_long_edge: u32,
+
+    /// JPEG quality of uploaded photos (1-100)
+    #[serde(default = "default_preprocess_jpeg_quality")]
+    pub preprocess_jpeg_quality: u8,
 }
 
 fn default_backend() -> String {
@@ -275,6 +427,26 @@ fn default_usage_mode() -> String {
     "time_based_quota".to_string()
 }
 
+fn default_storage_backend() -> String {
+    "json".to_string()
+}
+
+fn default_ensemble_aggregator() -> String {
+    "median".to_string()
+}
+
+fn default_max_concurrent_requests() -> usize {
+    DEFAULT_MAX_CONCURRENCY
+}
+
+fn default_preprocess_max_long_edge() -> u32 {
+    DEFAULT_MAX_LONG_EDGE
+}
+
+fn default_preprocess_jpeg_quality() -> u8 {
+    DEFAULT_JPEG_QUALITY
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -282,6 +454,8 @@ impl Default for Config {
             model: None,
             cache_enabled: true,
             cache_dir: None,
+            cache_max_age_days: None,
+            cache_max_size_mb: None,
             output_format: default_output_format(),
             ensemble_count: default_ensemble_count(),
             plate_local_enabled: default_false(),
@@ -289,6 +463,15 @@ impl Default for Config {
             plate_local_min_conf: default_plate_local_min_conf(),
             plate_local_fallback_api: default_true(),
             usage_mode: default_usage_mode(),
+            storage_backend: default_storage_backend(),
+            store_dir: None,
+            ensemble_aggregator: default_ensemble_aggregator(),
+            max_concurrent_requests: default_max_concurrent_requests(),
+            reference_images: 0,
+            apply_calibration: default_false(),
+            preprocess_enabled: default_true(),
+            preprocess_max_long_edge: default_preprocess_max_long_edge(),
+            preprocess_jpeg_quality: default_preprocess_jpeg_quality(),
         }
     }
 }
@@ -319,14 +502,56 @@ impl Config {
         Ok(cache_dir)
     }
 
+    /// Directory for page images extracted from PDF scans
+    pub fn pdf_page_cache_dir(&self) -> Result<PathBuf> {
+        Ok(self.cache_dir()?.join("pdf-pages"))
+    }
+
+    /// Photo preprocessing before upload, cached next to the analysis cache
+    /// (None when disabled)
+    pub fn image_preprocessor(&self) -> Result<Option<ImagePreprocessor>> {
+        if !self.preprocess_enabled {
+            return Ok(None);
+        }
+        Ok(Some(
+            ImagePreprocessor::new(self.cache_dir()?.join("preprocessed"))
+                .with_max_long_edge(self.preprocess_max_long_edge)
+                .with_jpeg_quality(self.preprocess_jpeg_quality),
+        ))
+    }
+
+    /// Get the cache eviction policy
+    pub fn cache_policy(&self) -> CachePolicy {
+        CachePolicy {
+            max_age: self
+                .cache_max_age_days
+                .map(|days| chrono::Duration::days(days as i64)),
+            max_bytes: self.cache_max_size_mb.map(|mb| mb * 1024 * 1024),
+        }
+    }
+
+    /// Get the ensemble aggregator (unknown names fall back to median)
+    pub fn ensemble_aggregator(&self) -> Aggregator {
+        Aggregator::from_name(&self.ensemble_aggregator).unwrap_or_default()
+    }
+
     /// Get the store directory path (for history/feedback data)
     pub fn store_dir(&self) -> Result<PathBuf> {
+        if let Some(ref dir) = self.store_dir {
+            return Ok(dir.clone());
+        }
+
         let data_dir = dirs::data_dir()
             .ok_or_else(|| ConfigError::NotFound)?
             .join("tonsuu-checker");
         Ok(data_dir)
     }
 
+    /// Whether history and vehicles are stored in SQLite instead of JSON files
+    pub fn uses_sqlite(&self) -> bool {
+        self.storage_backend.eq_ignore_ascii_case("sqlite")
+    }
+
     /// Load config from file, or create default
     pub fn load() -> Result<Self> {
         let path = Self::config_path()?;
@@ -374,6 +599,20 @@ impl std::fmt::Display for Config {
                 .map(|p| p.display().to_string())
                 .unwrap_or_else(|_| "(error)".to_string())
         )?;
+        writeln!(
+            f,
+            "Cache max age:  {}",
+            self.cache_max_age_days
+                .map(|d| format!("{} days", d))
+                .unwrap_or_else(|| "(unlimited)".to_string())
+        )?;
+        writeln!(
+            f,
+            "Cache max size: {}",
+            self.cache_max_size_mb
+                .map(|mb| format!("{} MB", mb))
+                .unwrap_or_else(|| "(unlimited)".to_string())
+        )?;
         writeln!(f, "Output format:  {}", self.output_format)?;
         writeln!(f, "Ensemble count: {}", self.ensemble_count)?;
         writeln!(
@@ -399,6 +638,28 @@ impl std::fmt::Display for Config {
             _ => "時間ベース使用量制限",
         };
         writeln!(f, "Usage mode:     {}", usage_mode_display)?;
+        writeln!(f, "Storage:        {}", self.storage_backend)?;
+        writeln!(f, "Aggregator:     {}", self.ensemble_aggregator().name())?;
+        writeln!(f, "Concurrency:    {} requests/backend", self.max_concurrent_requests)?;
+        if self.reference_images > 0 {
+            writeln!(f, "References:     up to {} images", self.reference_images)?;
+        } else {
+            writeln!(f, "References:     off")?;
+        }
+        writeln!(
+            f,
+            "Calibration:    {}",
+            if self.apply_calibration { "enabled" } else { "disabled" }
+        )?;
+        if self.preprocess_enabled {
+            writeln!(
+                f,
+                "Preprocess:     {}px, JPEG q{}",
+                self.preprocess_max_long_edge, self.preprocess_jpeg_quality
+            )?;
+        } else {
+            writeln!(f, "Preprocess:     off")?;
+        }
 
         if let Ok(path) = Self::config_path() {
             writeln!(f)?;
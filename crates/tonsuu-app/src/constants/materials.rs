@@ -1,22 +1,37 @@
-//! Material specifications for weight calculation
-//!
-//! Note: Prepared for material-based weight calculation.
-//! Currently unused but maintained for planned weight calculation feature.
+//! Material catalog lookups (specs, name resolution, default material)
 
-#![allow(dead_code)]
-
-use crate::config::load_material_specs;
+use crate::config::load_material_catalog;
 use tonsuu_domain::MaterialSpec;
 
-/// Get material spec by name
+/// Get material spec by id or alias
 pub fn get_material_spec(material_type: &str) -> Option<&'static MaterialSpec> {
-    let loaded = load_material_specs().ok()?;
-    loaded.specs.get(material_type)
+    load_material_catalog().ok()?.spec(material_type)
+}
+
+/// Map a material name (id, alias such as "ASガラ") to its catalog id
+pub fn resolve_material(name: &str) -> Option<&'static str> {
+    load_material_catalog().ok()?.resolve(name)
+}
+
+/// Catalog id for a name, or the name itself if it is not in the catalog
+pub fn canonical_material(name: &str) -> String {
+    resolve_material(name).unwrap_or(name).to_string()
+}
+
+/// Material ids in catalog order (empty if the catalog cannot be loaded)
+pub fn material_ids() -> Vec<&'static str> {
+    load_material_catalog().map(|c| c.ids()).unwrap_or_default()
+}
+
+/// Material assumed when none is given
+pub fn default_material() -> Option<&'static str> {
+    load_material_catalog().ok().map(|c| c.default_id.as_str())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{MaterialCatalog, MaterialsConfig};
 
     #[test]
     fn test_material_lookup() {
@@ -24,4 +39,48 @@ mod tests {
         assert!(get_material_spec("As殻").is_some());
         assert!(get_material_spec("Co殻").is_some());
     }
+
+    #[test]
+    fn test_slip_names_resolve_to_catalog_ids() {
+        assert_eq!(resolve_material("ASガラ"), Some("As殻"));
+        assert_eq!(resolve_material("asガラ"), Some("As殻"));
+        assert_eq!(resolve_material(" CONガラ "), Some("Co殻"));
+        assert_eq!(resolve_material("アスファルト殻"), Some("As殻"));
+        assert_eq!(canonical_material("不明な材料"), "不明な材料");
+        assert!(material_ids().contains(&"開粒度As殻"));
+        assert_eq!(default_material(), Some("As殻"));
+    }
+
+    #[test]
+    fn test_custom_material_from_config() {
+        let config: MaterialsConfig = toml::from_str(
+            r#"
+            [[materials]]
+            id = "砕石"
+            name = "砕石"
+            density = 2.65
+            void_ratio = 0.40
+            aliases = ["RC-40"]
+            "#,
+        )
+        .unwrap();
+        let catalog = MaterialCatalog::from_config(config).unwrap();
+        assert_eq!(catalog.default_id, "砕石");
+        assert_eq!(catalog.resolve("rc-40"), Some("砕石"));
+        assert_eq!(catalog.spec("RC-40").map(|s| s.density), Some(2.65));
+
+        let unknown_default: MaterialsConfig = toml::from_str(
+            "default = \"As殻\"\n[[materials]]\nid = \"砕石\"\nname = \"砕石\"\ndensity = 2.65\nvoid_ratio = 0.4\n",
+        )
+        .unwrap();
+        assert!(MaterialCatalog::from_config(unknown_default).is_err());
+    }
+
+    #[test]
+    fn test_builtin_catalog() {
+        let catalog = MaterialCatalog::builtin();
+        assert_eq!(catalog.default_id, "As殻");
+        assert_eq!(catalog.resolve("ASガラ"), Some("As殻"));
+        assert!(catalog.ids().contains(&"開粒度As殻"));
+    }
 }
@@ -6,6 +6,10 @@ pub mod truck_specs;
 pub mod weight_calculator;
 
 
-pub use materials::get_material_spec;
-pub use truck_specs::get_truck_spec;
-pub use weight_calculator::{calculate_weight, calculate_weight_explicit};
+pub use materials::{
+    canonical_material, default_material, get_material_spec, material_ids, resolve_material,
+};
+pub use truck_specs::{get_truck_spec, vehicle_truck_spec};
+pub use weight_calculator::{
+    calculate_weight, calculate_weight_explicit, mix_bulk_density, weight_mixed_load,
+};
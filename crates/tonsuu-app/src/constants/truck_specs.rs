@@ -2,6 +2,7 @@
 
 use crate::config::load_truck_specs;
 use tonsuu_domain::TruckSpec;
+use tonsuu_types::RegisteredVehicle;
 
 /// Get truck spec by type name
 pub fn get_truck_spec(truck_type: &str) -> Option<&'static TruckSpec> {
@@ -33,6 +34,24 @@ pub fn get_truck_spec(truck_type: &str) -> Option<&'static TruckSpec> {
     None
 }
 
+/// Spec of a registered vehicle with its own bed dimensions.
+///
+/// Dimensions the vehicle does not have are taken from `class_spec`. Returns
+/// None when no bed dimension is registered, so the class spec applies as is.
+pub fn vehicle_truck_spec(vehicle: &RegisteredVehicle, class_spec: &TruckSpec) -> Option<TruckSpec> {
+    if !vehicle.has_bed_dimensions() {
+        return None;
+    }
+    let mut spec = class_spec.with_bed(
+        &vehicle.name,
+        vehicle.bed_length.unwrap_or(class_spec.bed_length),
+        vehicle.bed_width.unwrap_or(class_spec.bed_width),
+        vehicle.wall_height().unwrap_or(class_spec.bed_height),
+    );
+    spec.max_capacity = vehicle.max_capacity;
+    Some(spec)
+}
+
 /// Get max capacity for a truck type
 #[allow(dead_code)]
 pub fn get_max_capacity(truck_type: &str) -> Option<f64> {
@@ -50,6 +69,22 @@ mod tests {
         assert!(get_truck_spec("増トン").is_some());
     }
 
+    #[test]
+    fn test_vehicle_bed_overrides_class_spec() {
+        let class_spec = get_truck_spec("増トン").unwrap();
+        let plain = RegisteredVehicle::new("増トン 標準".to_string(), 6.5);
+        assert!(vehicle_truck_spec(&plain, class_spec).is_none());
+
+        let mut deep = RegisteredVehicle::new("増トン 深ダンプ".to_string(), 6.5)
+            .with_bed(class_spec.bed_length, class_spec.bed_width, class_spec.bed_height);
+        deep.side_wall_height = Some(class_spec.bed_height * 2.0);
+        let spec = vehicle_truck_spec(&deep, class_spec).unwrap();
+        assert_eq!(spec.max_capacity, 6.5);
+        assert_eq!(spec.bed_height, class_spec.bed_height * 2.0);
+        assert!((spec.heap_volume - class_spec.heap_volume * 2.0).abs() < 1e-9);
+        assert!((spec.bed_area() - class_spec.bed_area()).abs() < 1e-9);
+    }
+
     #[test]
     fn test_4t_spec() {
         let spec = get_truck_spec("4t").unwrap();
@@ -147,6 +182,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_configured_truck_classes() {
+        use tonsuu_types::TruckClassRegistry;
+
+        let loaded = crate::config::load_truck_specs().unwrap();
+        let classes = TruckClassRegistry::new(loaded.classes.clone());
+        assert_eq!(classes.classify(2.8).label(), "3t");
+        assert_eq!(classes.classify(3.0).label(), "3t");
+        assert_eq!(classes.classify(4.8).label(), "4t");
+        assert_eq!(classes.classify(6.5).label(), "増トン");
+        assert_eq!(classes.classify(8.0).label(), "8t");
+        assert_eq!(classes.classify(18.0).label(), "11t+");
+        assert_eq!(classes.resolve("トレーラー").map(|c| c.to_string()), Some("11t+".to_string()));
+        // Every class has a spec
+        for class in classes.classes() {
+            assert!(get_truck_spec(&class.id).is_some(), "{} has no spec", class.id);
+        }
+    }
+
     #[test]
     fn test_invalid_truck_type() {
         // Test that invalid truck types return None
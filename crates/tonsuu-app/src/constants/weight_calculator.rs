@@ -7,13 +7,16 @@
 //! - `domain::service::weight_calculator::calculate_weight_explicit`
 //! - `domain::service::weight_calculator::calculate_weight_from_spec`
 //!
-//! Note: Prepared for material-based weight calculation.
-//! Currently unused but maintained for planned weight calculation feature.
+//! Mixed loads (`EstimationResult::material_breakdown`, from the AI or the
+//! karte) are weighed with the share-weighted bulk density of their materials
+//! by `weight_mixed_load`.
 
 #![allow(dead_code)]
 
 use super::materials::get_material_spec;
 use tonsuu_domain::service::weight_calculator as service;
+use tonsuu_domain::MaterialSpec;
+use tonsuu_types::{EstimationResult, MaterialBreakdown};
 
 // Re-export from domain service for convenience
 pub use service::{calculate_weight_explicit, calculate_weight_from_spec};
@@ -38,6 +41,74 @@ pub fn calculate_weight(volume_m3: f64, material_type: &str) -> Option<f64> {
     })
 }
 
+/// Materials of a breakdown with their volume shares.
+///
+/// Materials missing from `config/materials.toml` use the breakdown's own
+/// density as bulk density. None if a material has neither, or nothing has
+/// a positive share.
+fn resolve_mix(breakdown: &[MaterialBreakdown]) -> Option<Vec<(MaterialSpec, f64)>> {
+    let parts: Vec<(MaterialSpec, f64)> = breakdown
+        .iter()
+        .filter(|b| b.percentage > 0.0)
+        .map(|b| {
+            let spec = get_material_spec(&b.material).cloned().or_else(|| {
+                (b.density > 0.0).then(|| MaterialSpec {
+                    name: b.material.clone(),
+                    density: b.density,
+                    void_ratio: 0.0,
+                })
+            })?;
+            Some((spec, b.percentage))
+        })
+        .collect::<Option<_>>()?;
+    (!parts.is_empty()).then_some(parts)
+}
+
+/// Bulk density (t/m³) of the load's material mix, if it has a usable breakdown
+pub fn mix_bulk_density(result: &EstimationResult) -> Option<f64> {
+    let parts = resolve_mix(&result.material_breakdown)?;
+    let refs: Vec<(&MaterialSpec, f64)> = parts.iter().map(|(spec, share)| (spec, *share)).collect();
+    service::mixed_bulk_density(&refs)
+}
+
+/// Weigh a mixed load by its material breakdown and split the tonnage.
+///
+/// The shared formula weighs the volume as the single `material_type`, so the
+/// tonnage is scaled by mixed / single bulk density (or computed from the
+/// volume if that material is unknown). Each breakdown entry gets its share
+/// of the tonnage and the density from the material spec. Call once, right
+/// after the tonnage was computed from the parameters.
+pub fn weight_mixed_load(result: &mut EstimationResult) {
+    let Some(parts) = resolve_mix(&result.material_breakdown) else {
+        return;
+    };
+    let refs: Vec<(&MaterialSpec, f64)> = parts.iter().map(|(spec, share)| (spec, *share)).collect();
+    let Some(mixed) = service::mixed_bulk_density(&refs) else {
+        return;
+    };
+
+    let single = get_material_spec(&result.material_type)
+        .map(|spec| service::calculate_weight_from_spec(1.0, spec))
+        .filter(|bulk| *bulk > 0.0);
+    result.estimated_tonnage = match single {
+        Some(single) => result.estimated_tonnage * mixed / single,
+        None => result.estimated_volume_m3 * mixed,
+    };
+
+    let total_share: f64 = parts.iter().map(|(_, share)| share).sum();
+    let split = service::split_weight_by_material(result.estimated_tonnage, &refs);
+    result.material_breakdown = parts
+        .iter()
+        .zip(split)
+        .map(|((spec, share), tonnage)| MaterialBreakdown {
+            material: spec.name.clone(),
+            percentage: share / total_share * 100.0,
+            density: spec.density,
+            tonnage: Some(tonnage),
+        })
+        .collect();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,6 +149,40 @@ mod tests {
     // Edge cases - Zero values
     // ==========================================
 
+    #[test]
+    fn test_weight_mixed_load() {
+        // 3.5t computed as As殻; the karte says 70% As殻 / 30% 土砂
+        let mut result = EstimationResult {
+            material_type: "As殻".to_string(),
+            estimated_volume_m3: 2.0,
+            estimated_tonnage: 3.5,
+            material_breakdown: vec![
+                MaterialBreakdown {
+                    material: "As殻".to_string(),
+                    percentage: 70.0,
+                    density: 0.0,
+                    tonnage: None,
+                },
+                MaterialBreakdown {
+                    material: "土砂".to_string(),
+                    percentage: 30.0,
+                    density: 0.0,
+                    tonnage: None,
+                },
+            ],
+            ..Default::default()
+        };
+        weight_mixed_load(&mut result);
+
+        // 2m3 x (0.7 x 1.75 + 0.3 x 1.71)
+        let expected = 2.0 * (0.7 * 1.75 + 0.3 * 1.71);
+        assert!((result.estimated_tonnage - expected).abs() < 1e-9);
+        let split: Vec<f64> = result.material_breakdown.iter().filter_map(|b| b.tonnage).collect();
+        assert!((split[0] - 2.0 * 0.7 * 1.75).abs() < 1e-9);
+        assert!((split[1] - 2.0 * 0.3 * 1.71).abs() < 1e-9);
+        assert_eq!(result.material_breakdown[1].density, 1.8);
+    }
+
     #[test]
     fn test_zero_volume() {
         // Zero volume should return zero weight
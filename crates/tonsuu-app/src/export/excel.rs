@@ -107,10 +107,15 @@ fn write_details_sheet(sheet: &mut Worksheet, results: &BatchResults) -> Result<
         "Material",
         "Volume (m³)",
         "Tonnage (t)",
+        "Tonnage P10 (t)",
+        "Tonnage P90 (t)",
         "Max Capacity (t)",
         "Load %",
+        "Possibly Overloaded",
         "Grade",
         "Confidence",
+        "Material Split",
+        "Warnings",
         "Reasoning",
     ];
 
@@ -154,31 +159,74 @@ fn write_details_sheet(sheet: &mut Worksheet, results: &BatchResults) -> Result<
             .write_number(row, 4, result.estimated_tonnage)
             .map_err(|e| Error::Excel(e.to_string()))?;
 
-        // Max capacity (from truck spec)
-        if let Some(spec) = get_truck_spec(&result.truck_type) {
+        // Tonnage bounds
+        if let Some(interval) = result.tonnage_interval {
             sheet
-                .write_number(row, 5, spec.max_capacity)
+                .write_number(row, 5, interval.p10)
+                .map_err(|e| Error::Excel(e.to_string()))?;
+            sheet
+                .write_number(row, 6, interval.p90)
+                .map_err(|e| Error::Excel(e.to_string()))?;
+        }
+
+        // Max capacity (matched vehicle, else truck spec)
+        let max_capacity = entry
+            .max_capacity
+            .or_else(|| get_truck_spec(&result.truck_type).map(|spec| spec.max_capacity));
+        if let Some(max_capacity) = max_capacity {
+            sheet
+                .write_number(row, 7, max_capacity)
                 .map_err(|e| Error::Excel(e.to_string()))?;
 
             // Load percentage
-            let load_pct = (result.estimated_tonnage / spec.max_capacity) * 100.0;
+            let load_pct = (result.estimated_tonnage / max_capacity) * 100.0;
             sheet
-                .write_number(row, 6, load_pct)
+                .write_number(row, 8, load_pct)
                 .map_err(|e| Error::Excel(e.to_string()))?;
+
+            // Possibly overloaded (P90 above max capacity)
+            if result.possibly_overloaded(max_capacity) {
+                sheet
+                    .write_string(row, 9, "Yes")
+                    .map_err(|e| Error::Excel(e.to_string()))?;
+            }
         }
 
         // Grade
         if let Some(grade) = entry.grade {
             sheet
-                .write_string(row, 7, grade.label())
+                .write_string(row, 10, grade.label())
                 .map_err(|e| Error::Excel(e.to_string()))?;
         }
 
         // Confidence
         sheet
-            .write_number(row, 8, result.confidence_score)
+            .write_number(row, 11, result.confidence_score)
             .map_err(|e| Error::Excel(e.to_string()))?;
 
+        // Per-material tonnage of a mixed load
+        let split: Vec<String> = result
+            .material_breakdown
+            .iter()
+            .filter_map(|b| {
+                b.tonnage
+                    .map(|t| format!("{} {:.2}t ({:.0}%)", b.material, t, b.percentage))
+            })
+            .collect();
+        if !split.is_empty() {
+            sheet
+                .write_string(row, 12, split.join("; "))
+                .map_err(|e| Error::Excel(e.to_string()))?;
+        }
+
+        // Plausibility warnings
+        if !result.warnings.is_empty() {
+            let warnings: Vec<&str> = result.warnings.iter().map(|w| w.message.as_str()).collect();
+            sheet
+                .write_string(row, 13, warnings.join("; "))
+                .map_err(|e| Error::Excel(e.to_string()))?;
+        }
+
         // Reasoning (truncate for Excel)
         let reasoning = if result.reasoning.len() > 200 {
             format!("{}...", &result.reasoning[..200])
@@ -186,7 +234,7 @@ fn write_details_sheet(sheet: &mut Worksheet, results: &BatchResults) -> Result<
             result.reasoning.clone()
         };
         sheet
-            .write_string(row, 9, &reasoning)
+            .write_string(row, 14, &reasoning)
             .map_err(|e| Error::Excel(e.to_string()))?;
     }
 
@@ -201,7 +249,13 @@ fn write_details_sheet(sheet: &mut Worksheet, results: &BatchResults) -> Result<
         .set_column_width(2, 12)
         .map_err(|e| Error::Excel(e.to_string()))?;
     sheet
-        .set_column_width(9, 50)
+        .set_column_width(12, 30)
+        .map_err(|e| Error::Excel(e.to_string()))?;
+    sheet
+        .set_column_width(13, 40)
+        .map_err(|e| Error::Excel(e.to_string()))?;
+    sheet
+        .set_column_width(14, 50)
         .map_err(|e| Error::Excel(e.to_string()))?;
 
     Ok(())
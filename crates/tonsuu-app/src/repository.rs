@@ -2,12 +2,14 @@
 
 use std::path::PathBuf;
 
+use tonsuu_domain::repository::{VehicleMasterRepository, WeighingSlipRepository};
 use tonsuu_infra::persistence::{
-    FileAnalysisHistoryRepository, FileVehicleMasterRepository, FileVehicleRepository,
-    FileWeighingSlipRepository,
+    migrate_json_to_sqlite, FileAnalysisHistoryRepository, FileVehicleMasterRepository,
+    FileVehicleRepository, FileWeighingSlipRepository, SqliteMigrationReport, SqliteStorage,
+    SqliteVehicleMasterRepository, SqliteWeighingSlipRepository, SQLITE_DB_FILE,
 };
 use tonsuu_store::{Store, VehicleStore};
-use tonsuu_types::Result;
+use tonsuu_types::{HistoryEntry, RegisteredVehicle, Result};
 
 use crate::config::Config;
 
@@ -23,14 +25,47 @@ pub fn open_vehicle_repo(config: &Config) -> Result<FileVehicleRepository> {
     FileVehicleRepository::open(store_dir).map_err(Into::into)
 }
 
-/// Open Store for analysis history
+/// Path of the SQLite database in the store directory
+fn sqlite_db_path(config: &Config) -> Result<PathBuf> {
+    Ok(config.store_dir()?.join(SQLITE_DB_FILE))
+}
+
+/// Import history.json and vehicles.json into the SQLite database
+///
+/// Picks up entries added or changed in the JSON files since the last import.
+pub fn migrate_json_store(config: &Config) -> Result<SqliteMigrationReport> {
+    let store_dir = config.store_dir()?;
+    migrate_json_to_sqlite(&store_dir, &store_dir.join(SQLITE_DB_FILE)).map_err(Into::into)
+}
+
+/// Create the SQLite database from the JSON stores on first use
+///
+/// Returns the import report when this call created the database; `None` on
+/// the JSON backend or when the database already exists (later JSON changes
+/// are imported by `migrate_json_store`).
+pub fn prepare_storage(config: &Config) -> Result<Option<SqliteMigrationReport>> {
+    if !config.uses_sqlite() || sqlite_db_path(config)?.exists() {
+        return Ok(None);
+    }
+    migrate_json_store(config).map(Some)
+}
+
+/// Open Store for analysis history on the configured storage backend
 pub fn open_history_store(config: &Config) -> Result<Store> {
+    if config.uses_sqlite() {
+        let storage = SqliteStorage::<HistoryEntry>::open(&sqlite_db_path(config)?)?;
+        return Store::with_storage(Box::new(storage));
+    }
     let store_dir = config.store_dir()?;
     Store::open(store_dir).map_err(Into::into)
 }
 
-/// Open Store for registered vehicles
+/// Open Store for registered vehicles on the configured storage backend
 pub fn open_vehicle_store(config: &Config) -> Result<VehicleStore> {
+    if config.uses_sqlite() {
+        let storage = SqliteStorage::<RegisteredVehicle>::open(&sqlite_db_path(config)?)?;
+        return VehicleStore::with_storage(Box::new(storage));
+    }
     let store_dir = config.store_dir()?;
     VehicleStore::open(store_dir).map_err(Into::into)
 }
@@ -54,3 +89,54 @@ pub fn open_vehicle_master_repo(toml_path: PathBuf) -> Result<FileVehicleMasterR
 pub fn open_weighing_slip_repo(csv_path: PathBuf) -> Result<FileWeighingSlipRepository> {
     FileWeighingSlipRepository::new(csv_path).map_err(Into::into)
 }
+
+/// Open the vehicle master on the configured storage backend
+///
+/// With SQLite the TOML is imported only while the database has no master
+/// yet; later changes are imported by `import_vehicle_master`.
+pub fn open_vehicle_master_repository(
+    config: &Config,
+    toml_path: PathBuf,
+) -> Result<Box<dyn VehicleMasterRepository>> {
+    if !config.uses_sqlite() {
+        return Ok(Box::new(open_vehicle_master_repo(toml_path)?));
+    }
+    let repo = SqliteVehicleMasterRepository::open(&sqlite_db_path(config)?)?;
+    if repo.is_empty()? {
+        repo.import(&open_vehicle_master_repo(toml_path)?.find_all()?)?;
+    }
+    Ok(Box::new(repo))
+}
+
+/// Open weighing slips on the configured storage backend
+///
+/// With SQLite the CSV is imported only while the database has no slips yet,
+/// so lookups by date, site and vehicle use its indexes; later changes are
+/// imported by `import_weighing_slips`.
+pub fn open_weighing_slip_repository(
+    config: &Config,
+    csv_path: PathBuf,
+) -> Result<Box<dyn WeighingSlipRepository>> {
+    if !config.uses_sqlite() {
+        return Ok(Box::new(open_weighing_slip_repo(csv_path)?));
+    }
+    let repo = SqliteWeighingSlipRepository::open(&sqlite_db_path(config)?)?;
+    if repo.is_empty()? {
+        repo.import(&open_weighing_slip_repo(csv_path)?.find_all()?)?;
+    }
+    Ok(Box::new(repo))
+}
+
+/// Import (insert or replace) a vehicle master TOML into the SQLite database
+pub fn import_vehicle_master(config: &Config, toml_path: PathBuf) -> Result<usize> {
+    let vehicles = open_vehicle_master_repo(toml_path)?.find_all()?;
+    let repo = SqliteVehicleMasterRepository::open(&sqlite_db_path(config)?)?;
+    repo.import(&vehicles).map_err(Into::into)
+}
+
+/// Import (insert or replace) a weighing slip CSV into the SQLite database
+pub fn import_weighing_slips(config: &Config, csv_path: PathBuf) -> Result<usize> {
+    let slips = open_weighing_slip_repo(csv_path)?.find_all()?;
+    let repo = SqliteWeighingSlipRepository::open(&sqlite_db_path(config)?)?;
+    repo.import(&slips).map_err(Into::into)
+}
@@ -5,6 +5,7 @@
 
 pub mod vehicles;
 
+use tonsuu_infra::pdf_images::extract_pdf_pages;
 use tonsuu_types::{Error, Result};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
@@ -21,6 +22,24 @@ pub fn is_supported_image(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
+/// Check if a path is a PDF document
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+/// Image files of a scanned document: the page images of a PDF (extracted
+/// into `pdf_cache_dir`, in page order), or the file itself
+pub fn document_images(path: &Path, pdf_cache_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !is_pdf(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let pages = extract_pdf_pages(path, pdf_cache_dir)
+        .map_err(|e| Error::InvalidImageFormat(format!("{}: {}", path.display(), e)))?;
+    Ok(pages.into_iter().map(|p| p.path).collect())
+}
+
 /// Validate an image file exists and is readable
 pub fn validate_image(path: &Path) -> Result<()> {
     if !path.exists() {
@@ -102,5 +121,7 @@ mod tests {
         assert!(is_supported_image(Path::new("test.png")));
         assert!(!is_supported_image(Path::new("test.txt")));
         assert!(!is_supported_image(Path::new("test")));
+        assert!(is_pdf(Path::new("shaken.PDF")));
+        assert!(!is_pdf(Path::new("shaken.jpg")));
     }
 }
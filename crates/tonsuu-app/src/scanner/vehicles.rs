@@ -13,10 +13,12 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+use super::is_pdf;
 use tonsuu_types::{Error, Result};
 
-/// Supported image extensions for scanning (limited to common formats)
-const SCAN_IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+/// Supported image extensions for scanning (limited to common formats; PDF
+/// scans are turned into page images by `document_images`)
+const SCAN_IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "pdf"];
 
 /// Keywords that indicate a 車検証 (vehicle inspection certificate) image
 const SHAKEN_KEYWORDS: &[&str] = &[
@@ -134,6 +136,11 @@ fn is_scan_image(path: &Path) -> bool {
 
 /// Classify an image based on its filename
 fn classify_image_by_name(path: &Path) -> ImageClassification {
+    // A PDF can only be the 車検証 scan
+    if is_pdf(path) {
+        return ImageClassification::Shaken;
+    }
+
     let filename = path
         .file_name()
         .and_then(|n| n.to_str())
@@ -406,10 +413,29 @@ mod tests {
         assert!(is_scan_image(Path::new("test.jpg")));
         assert!(is_scan_image(Path::new("test.JPEG")));
         assert!(is_scan_image(Path::new("test.png")));
+        assert!(is_scan_image(Path::new("車検証.PDF")));
         assert!(!is_scan_image(Path::new("test.gif")));
         assert!(!is_scan_image(Path::new("test.txt")));
     }
 
+    #[test]
+    fn test_pdf_is_only_shaken_candidate() {
+        assert_eq!(
+            classify_image_by_name(Path::new("truck_photo.pdf")),
+            ImageClassification::Shaken
+        );
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        for name in ["a.jpg", "b.pdf", "scan.PDF"] {
+            std::fs::write(dir.join(name), b"data").unwrap();
+        }
+
+        let scan = scan_single_folder(dir).unwrap();
+        assert_eq!(scan.shaken_candidates.len(), 2);
+        assert_eq!(scan.photo_candidates, vec![dir.join("a.jpg")]);
+    }
+
     #[test]
     fn test_vehicle_folder_scan_helpers() {
         let mut scan = VehicleFolderScan::new("test".to_string(), PathBuf::from("/test"));
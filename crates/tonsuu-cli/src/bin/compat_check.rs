@@ -5,6 +5,7 @@ use clap::Parser;
 use tonsuu_app::config::Config;
 use tonsuu_app::repository::{open_history_store_at, open_vehicle_store_at};
 use tonsuu_infra::overload_csv::{load_slips_from_csv, load_vehicles_from_csv};
+use tonsuu_store::schema::{inspect, SchemaReport, StoreKind};
 use serde::Serialize;
 
 #[derive(Parser, Debug)]
@@ -33,6 +34,11 @@ struct Args {
     /// Write pretty JSON summary to file
     #[arg(long)]
     json: Option<PathBuf>,
+
+    /// Only report schema versions of the store files and what loading them
+    /// would migrate (read-only; nothing is written to the store)
+    #[arg(long)]
+    schema: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -43,14 +49,97 @@ struct Summary {
     vehicle_count: usize,
     slips_count: Option<usize>,
     vehicles_master_count: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    schema: Vec<SchemaReport>,
+}
+
+fn print_schema_report(label: &str, report: &SchemaReport) {
+    if !report.exists {
+        println!("[Schema] {}: not found ({})", label, report.file);
+        return;
+    }
+
+    println!(
+        "[Schema] {}: version {} (current {}), {} entries",
+        label,
+        report.version.unwrap_or(0),
+        report.current_version,
+        report.entry_count
+    );
+    if report.newer_than_supported {
+        println!("[Schema]   written by a newer version; it will not be loaded or rewritten");
+    }
+    if report.damaged {
+        println!("[Schema]   file is damaged; only complete entries would be recovered");
+    }
+    for step in &report.steps {
+        println!("[Schema]   v{} -> v{}: {}", step.from, step.to, step.description);
+        for change in &step.changes {
+            println!("[Schema]     - {}", change);
+        }
+    }
+    for entry in &report.unreadable_entries {
+        println!("[Schema]   would drop unreadable entry {}", entry);
+    }
+    if report.is_clean() {
+        println!("[Schema]   up to date");
+    }
+}
+
+/// Schema check mode: inspect the store files and exit.
+///
+/// Exit code 2 if loading any file would drop data or refuse to load.
+fn run_schema_check(store_dir: &std::path::Path, summary: &mut Summary, args: &Args) {
+    for kind in [StoreKind::History, StoreKind::Vehicles, StoreKind::Calibration] {
+        let label = kind.file_name();
+        match inspect(store_dir, kind) {
+            Ok(report) => {
+                print_schema_report(label, &report);
+                summary.schema.push(report);
+            }
+            Err(e) => {
+                eprintln!("[Schema] {}: {}", label, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &args.jsonl {
+        if let Ok(mut file) = std::fs::File::create(path) {
+            for report in &summary.schema {
+                let _ = writeln!(
+                    file,
+                    "{}",
+                    serde_json::json!({ "event": "schema", "report": report })
+                );
+            }
+        } else {
+            eprintln!("[JSONL] failed to write: {}", path.display());
+        }
+    }
+
+    if let Some(path) = &args.json {
+        if let Ok(content) = serde_json::to_string_pretty(summary) {
+            if let Err(e) = std::fs::write(path, content) {
+                eprintln!("[JSON] failed to write: {}", e);
+            }
+        }
+    }
+
+    let data_at_risk = summary.schema.iter().any(|r| {
+        r.newer_than_supported || r.damaged || !r.unreadable_entries.is_empty()
+    });
+    if data_at_risk {
+        std::process::exit(2);
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
-    let config = match args.config {
+    let config = match &args.config {
         Some(path) => {
-            match std::fs::read_to_string(&path) {
+            match std::fs::read_to_string(path) {
                 Ok(content) => serde_json::from_str::<Config>(&content).unwrap_or_default(),
                 Err(e) => {
                     eprintln!("Failed to read config at {}: {}", path.display(), e);
@@ -61,7 +150,7 @@ fn main() {
         None => Config::load().unwrap_or_default(),
     };
 
-    let store_dir = match args.store_dir {
+    let store_dir = match args.store_dir.clone() {
         Some(dir) => dir,
         None => config.store_dir().unwrap_or_else(|_| std::env::temp_dir().join("tonsuu-checker")),
     };
@@ -75,8 +164,14 @@ fn main() {
         vehicle_count: 0,
         slips_count: None,
         vehicles_master_count: None,
+        schema: Vec::new(),
     };
 
+    if args.schema {
+        run_schema_check(&store_dir, &mut summary, &args);
+        return;
+    }
+
     let store = match open_history_store_at(store_dir.clone()) {
         Ok(store) => store,
         Err(e) => {
@@ -84,6 +179,13 @@ fn main() {
             std::process::exit(1);
         }
     };
+    if let Some(r) = store.recovery() {
+        println!(
+            "[Store] recovered {} entries from a damaged file (backup: {})",
+            r.recovered,
+            r.backup.display()
+        );
+    }
     summary.history_count = store.count();
     summary.feedback_count = store.feedback_count();
     println!("[Store] history.json entries: {}", summary.history_count);
@@ -96,6 +198,13 @@ fn main() {
             std::process::exit(1);
         }
     };
+    if let Some(r) = vehicles.recovery() {
+        println!(
+            "[VehicleStore] recovered {} entries from a damaged file (backup: {})",
+            r.recovered,
+            r.backup.display()
+        );
+    }
     summary.vehicle_count = vehicles.count();
     println!("[VehicleStore] vehicles.json entries: {}", summary.vehicle_count);
 
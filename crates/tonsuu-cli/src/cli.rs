@@ -16,7 +16,7 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    /// AI backend to use (gemini, claude, codex)
+    /// AI backend to use (gemini, claude, codex, record:<dir>, replay:<dir>)
     #[arg(long, global = true)]
     pub backend: Option<String>,
 
@@ -39,10 +39,20 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Analyze a single image
+    /// Analyze a single image, or several views of one load
     Analyze {
         /// Path to image file
-        image: PathBuf,
+        image: Option<PathBuf>,
+
+        /// Additional photo of the same load, optionally prefixed with its view
+        /// (rear, side, top), e.g. `--image side:b.jpg`. Repeatable.
+        #[arg(long = "image", short = 'i', value_name = "[VIEW:]PATH")]
+        images: Vec<String>,
+
+        /// How several images are combined: joint (one request with all images)
+        /// or per_view (analyze each view, height from side, fill from top)
+        #[arg(long, default_value = "joint")]
+        fusion: String,
 
         /// Karte JSON string or file path (required). Null fields will be estimated.
         #[arg(long)]
@@ -60,7 +70,7 @@ pub enum Commands {
         #[arg(long, short = 'p')]
         plate: Option<String>,
 
-        /// Skip YOLO plate detection, use class only (2t, 4t, 増トン, 10t)
+        /// Skip YOLO plate detection, use class only (a class id or alias from trucks.toml)
         #[arg(long)]
         skip_yolo_class_only: Option<String>,
 
@@ -75,6 +85,15 @@ pub enum Commands {
         /// Truck class pre-info (deprecated; use --karte)
         #[arg(long, conflicts_with = "karte")]
         truck_class: Option<String>,
+
+        /// Max graded reference images attached to the prompt (0 = off).
+        /// Uses config value if not specified.
+        #[arg(long)]
+        references: Option<usize>,
+
+        /// Report the raw AI tonnage even if calibration is enabled in config
+        #[arg(long)]
+        no_calibration: bool,
     },
 
     /// Batch analyze images in a folder
@@ -123,6 +142,14 @@ pub enum Commands {
         #[arg(long)]
         set_cache: Option<bool>,
 
+        /// Set cache max age in days (0 = unlimited)
+        #[arg(long)]
+        set_cache_max_age: Option<u32>,
+
+        /// Set cache max size in MB (0 = unlimited)
+        #[arg(long)]
+        set_cache_max_size: Option<u64>,
+
         /// Set default output format
         #[arg(long)]
         set_output: Option<OutputFormat>,
@@ -151,6 +178,38 @@ pub enum Commands {
         #[arg(long)]
         set_usage_mode: Option<String>,
 
+        /// Set storage backend for history/vehicles (json, sqlite)
+        #[arg(long)]
+        set_storage_backend: Option<String>,
+
+        /// Set ensemble aggregator (median, trimmed_mean, confidence_weighted)
+        #[arg(long)]
+        set_ensemble_aggregator: Option<String>,
+
+        /// Set max concurrent AI requests per backend (ensemble samples and batch workers share it)
+        #[arg(long)]
+        set_max_concurrency: Option<usize>,
+
+        /// Set max graded reference thumbnails attached to staged prompts (0 = off)
+        #[arg(long)]
+        set_reference_images: Option<usize>,
+
+        /// Apply the saved calibration model to new estimates
+        #[arg(long)]
+        set_calibration: Option<bool>,
+
+        /// Upright, downsample and recompress photos before upload
+        #[arg(long)]
+        set_preprocess: Option<bool>,
+
+        /// Set the longest edge of uploaded photos (px)
+        #[arg(long)]
+        set_preprocess_max_edge: Option<u32>,
+
+        /// Set the JPEG quality of uploaded photos (1-100)
+        #[arg(long)]
+        set_preprocess_quality: Option<u8>,
+
         /// Reset to defaults
         #[arg(long)]
         reset: bool,
@@ -158,6 +217,9 @@ pub enum Commands {
 
     /// Manage cache
     Cache {
+        #[command(subcommand)]
+        action: Option<CacheAction>,
+
         /// Clear all cache
         #[arg(long)]
         clear: bool,
@@ -202,11 +264,30 @@ pub enum Commands {
         #[arg(long)]
         by_material: bool,
 
+        /// Compare analyses run with and without graded reference images
+        #[arg(long)]
+        by_references: bool,
+
         /// Show detailed per-sample breakdown
         #[arg(long)]
         detailed: bool,
     },
 
+    /// Fit a per truck type × material tonnage correction from feedback data
+    Calibrate {
+        /// Fitting method (linear, isotonic)
+        #[arg(long, default_value = "linear")]
+        method: String,
+
+        /// Number of cross-validation folds
+        #[arg(long, default_value = "5")]
+        folds: usize,
+
+        /// Report cross-validated accuracy without saving the model
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Auto-collect vehicles from folder (scan 車検証 PDFs and photos)
     AutoCollect {
         /// Path to folder containing vehicle subfolders
@@ -239,6 +320,44 @@ pub enum Commands {
         dry_run: bool,
     },
 
+    /// Register vehicles or add their bed dimensions from a CSV file (header row
+    /// required: ナンバー, 車両名, 最大積載量, 荷台長, 荷台幅, 荷台高, 側板高, 架装)
+    ImportVehicles {
+        /// Path to vehicle CSV file
+        csv: PathBuf,
+
+        /// Dry run - show the parsed rows without changing the vehicle store
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Import history.json and vehicles.json into the SQLite database, and
+    /// optionally a vehicle master TOML or weighing slip CSV
+    Migrate {
+        /// Vehicle master TOML to import (replaces entries with the same number)
+        #[arg(long)]
+        vehicle_master: Option<PathBuf>,
+
+        /// Weighing slip CSV to import (replaces slips with the same number)
+        #[arg(long)]
+        slips: Option<PathBuf>,
+    },
+
+    /// List vehicles whose 車検証 expires within N days (expired ones included)
+    Expiring {
+        /// Days ahead to look
+        #[arg(long, short = 'd', default_value_t = 30)]
+        days: i64,
+
+        /// Only vehicles whose transport company contains this name
+        #[arg(long, short = 'c')]
+        company: Option<String>,
+
+        /// Output format (json for machine-readable, table for human-readable)
+        #[arg(long, short = 'o')]
+        output: Option<OutputFormat>,
+    },
+
     /// Check AI backend status and rate limits
     Stats,
 
@@ -257,3 +376,21 @@ pub enum Commands {
         output: Option<OutputFormat>,
     },
 }
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Remove expired / least-recently-used entries
+    Prune {
+        /// Remove entries older than this (e.g. 30d, 12h, 2w; default: config)
+        #[arg(long)]
+        older_than: Option<String>,
+
+        /// Evict least-recently-used entries until under this size (e.g. 500MB, 2GB; default: config)
+        #[arg(long)]
+        max_size: Option<String>,
+
+        /// List what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
@@ -1,25 +1,29 @@
 //! Command handlers
 
-use tonsuu_vision::cache::Cache;
-use tonsuu_vision::AnalyzerConfig;
+use tonsuu_vision::cache::{parse_duration, parse_size, Cache, CachePolicy};
+use tonsuu_vision::{read_shaken, Aggregator, AnalyzerConfig, FusionMode, ViewImage};
 use tonsuu_app::app::{self, AnalysisOptions};
 use cli_ai_analyzer::check_gemini_status;
-use crate::cli::{Cli, Commands, OutputFormat};
-use tonsuu_app::config::Config;
-use tonsuu_app::repository::{open_history_store, open_vehicle_store};
-use tonsuu_app::constants::get_truck_spec;
+use crate::cli::{CacheAction, Cli, Commands, OutputFormat};
+use tonsuu_app::config::{install_configured_truck_classes, load_material_catalog, Config};
+use tonsuu_app::repository;
+use tonsuu_app::constants::{canonical_material, get_truck_spec};
 use tonsuu_types::{Error, Result};
 use tonsuu_app::export::export_to_excel;
 use crate::output::output_result;
-use tonsuu_app::scanner::{scan_directory, validate_image};
-use tonsuu_store::{HistoryEntry, VehicleStore};
+use tonsuu_app::scanner::{document_images, scan_directory, validate_image};
+use tonsuu_store::{
+    cross_validate, CalibrationFit, CalibrationMethod, CalibrationModel, ErrorMetrics,
+    HistoryEntry, Recovery, Store, StoreKind, VehicleStore, MIN_CALIBRATION_SAMPLES,
+};
 use tonsuu_domain::service::{check_overloads, generate_overload_report};
 use tonsuu_infra::overload_csv::{load_slips_from_csv, load_vehicles_from_csv};
-use tonsuu_types::{AnalysisEntry, BatchResults, EstimationResult, KarteInput, LoadGrade, RegisteredVehicle, TruckClass};
+use tonsuu_infra::vehicle_csv::{import_vehicle_rows, load_vehicle_csv};
+use tonsuu_types::{AnalysisEntry, BatchResults, EstimationResult, ImageView, KarteInput, LoadGrade, RegisteredVehicle, TruckClass};
 use chrono::Utc;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -32,6 +36,9 @@ struct AnalysisProfiler {
     yolo_ms: Option<u64>,
     api_ms: Option<u64>,
     stage2_ms: Option<u64>,
+    /// Ensemble samples and the sum of their wall times (sequential cost)
+    sample_count: usize,
+    sample_ms_sum: u64,
     cache_hit: bool,
 }
 
@@ -43,18 +50,24 @@ impl AnalysisProfiler {
         }
     }
 
-    #[allow(dead_code)]
-    fn record_yolo(&mut self, start: Instant) {
-        self.yolo_ms = Some(start.elapsed().as_millis() as u64);
+    /// Record plate detection timings measured inside the app layer
+    fn record_plate(&mut self, yolo_ms: Option<u64>, api_ms: Option<u64>) {
+        self.yolo_ms = yolo_ms;
+        self.api_ms = api_ms;
     }
 
-    #[allow(dead_code)]
-    fn record_api(&mut self, start: Instant) {
-        self.api_ms = Some(start.elapsed().as_millis() as u64);
+    /// Record the analysis stage; plate detection time is subtracted
+    fn record_stage2(&mut self, start: Instant) {
+        let elapsed = start.elapsed().as_millis() as u64;
+        let plate_ms = self.yolo_ms.unwrap_or(0) + self.api_ms.unwrap_or(0);
+        self.stage2_ms = Some(elapsed.saturating_sub(plate_ms));
     }
 
-    fn record_stage2(&mut self, start: Instant) {
-        self.stage2_ms = Some(start.elapsed().as_millis() as u64);
+    /// Record per-sample timings of a parallel ensemble run
+    fn record_samples(&mut self, samples: &[tonsuu_types::EnsembleSample]) {
+        let timed: Vec<u64> = samples.iter().filter_map(|s| s.elapsed_ms).collect();
+        self.sample_count = timed.len();
+        self.sample_ms_sum = timed.iter().sum();
     }
 
     fn print_summary(&self) {
@@ -74,7 +87,16 @@ impl AnalysisProfiler {
             breakdown.push(format!("API {:.1}s", ms as f64 / 1000.0));
         }
         if let Some(ms) = self.stage2_ms {
-            breakdown.push(format!("Stage2 {:.1}s", ms as f64 / 1000.0));
+            if self.sample_count > 1 {
+                breakdown.push(format!(
+                    "Stage2 {:.1}s ({} samples, {:.1}s if sequential)",
+                    ms as f64 / 1000.0,
+                    self.sample_count,
+                    self.sample_ms_sum as f64 / 1000.0
+                ));
+            } else {
+                breakdown.push(format!("Stage2 {:.1}s", ms as f64 / 1000.0));
+            }
         }
 
         if breakdown.is_empty() {
@@ -85,57 +107,18 @@ impl AnalysisProfiler {
     }
 }
 
-/// Result from Gemini plate OCR
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-struct PlateOcrResult {
-    plate: Option<String>,
-    confidence: Option<f32>,
-}
-
-/// Build a simple OCR prompt for cropped plate image
-#[allow(dead_code)]
-fn build_plate_ocr_prompt(vehicle_store: &VehicleStore) -> String {
-    let mut prompt = String::from(
-r#"この画像は日本の自動車ナンバープレートです。プレートに書かれている文字を正確に読み取ってください。
-
-【読み取り手順】
-1. 地名（例: 熊本、福岡、東京）
-2. 分類番号3桁（例: 130, 101, 500）
-3. ひらがな1文字（例: ら, あ, さ）
-4. 一連番号4桁（例: 1122, 5678）← ハイフンがある場合は除去して4桁で
-
-【重要】
-- 見えた文字のみを記載すること
-- 推測・創作は禁止
-- 読み取れない部分は「?」で表記
-
-"#);
-
-    // Add registered vehicles for matching hint
-    let vehicles: Vec<_> = vehicle_store.all_vehicles();
-    if !vehicles.is_empty() {
-        prompt.push_str("【登録車両リスト（参考）】以下のナンバーが登録されています:\n");
-        for v in vehicles {
-            if let Some(ref plate) = v.license_plate {
-                prompt.push_str(&format!("- {}\n", plate));
-            }
-        }
-        prompt.push_str("\n読み取った結果がリストにあればそのまま返す。なければ読み取った通りに返す。\n\n");
-    }
-
-    prompt.push_str(r#"以下のJSON形式で回答:
-{"plate": "読み取ったナンバー全体", "confidence": 0.0-1.0}
-
-読み取れない場合: {"plate": null, "confidence": 0.0}"#);
-
-    prompt
-}
-
 /// Execute CLI command
 pub fn execute(cli: Cli) -> Result<()> {
     // Load config
     let mut config = Config::load()?;
+    // Truck classes from trucks.toml (built-in classes if it cannot be read)
+    match install_configured_truck_classes() {
+        Ok(true) => {}
+        Ok(false) => {
+            eprintln!("警告: 車両クラスは既に使用中のため、trucks.toml の設定は反映されません")
+        }
+        Err(e) => eprintln!("警告: 車両クラス設定を読み込めません ({})", e),
+    }
 
     // Override from CLI args
     if let Some(ref backend) = cli.backend {
@@ -148,9 +131,24 @@ pub fn execute(cli: Cli) -> Result<()> {
         config.usage_mode = usage_mode.clone();
     }
 
+    // First run on the SQLite backend: import the JSON stores once
+    match repository::prepare_storage(&config) {
+        Ok(Some(report)) => eprintln!(
+            "JSONストアの内容をSQLiteへ取り込みました (履歴: {}件, 車両: {}件)",
+            report.history_count, report.vehicle_count
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!(
+            "警告: JSONストアをSQLiteへ取り込めません ({}); `migrate` で再実行できます",
+            e
+        ),
+    }
+
     match &cli.command {
         Commands::Analyze {
             image,
+            images,
+            fusion,
             no_cache,
             ensemble,
             plate,
@@ -159,16 +157,32 @@ pub fn execute(cli: Cli) -> Result<()> {
             karte,
             material,
             truck_class,
+            references,
+            no_calibration,
         } => {
             // Use CLI ensemble if specified, otherwise config value
             let ensemble_count = ensemble.unwrap_or(config.ensemble_count);
+            let reference_images = references.unwrap_or(config.reference_images);
+            let apply_calibration = !no_calibration && config.apply_calibration;
             // Cache disabled if: --no-cache OR config.cache_enabled=false
             let use_cache = !no_cache && config.cache_enabled;
             let output_format = cli.format.unwrap_or(config.output_format);
+            let view_images: Vec<ViewImage> = image
+                .iter()
+                .map(|path| ViewImage::new(path.clone(), ImageView::Unspecified))
+                .chain(images.iter().map(|arg| ViewImage::parse(arg)))
+                .collect();
+            let fusion_mode = FusionMode::from_name(fusion).ok_or_else(|| {
+                Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+                    "Unknown fusion mode '{}' (joint, per_view)",
+                    fusion
+                )))
+            })?;
             cmd_analyze(
                 &cli,
                 &config,
-                image.clone(),
+                view_images,
+                fusion_mode,
                 use_cache,
                 ensemble_count,
                 output_format,
@@ -178,6 +192,8 @@ pub fn execute(cli: Cli) -> Result<()> {
                 karte.clone(),
                 material.clone(),
                 truck_class.clone(),
+                reference_images,
+                apply_calibration,
             )
         }
 
@@ -206,6 +222,8 @@ pub fn execute(cli: Cli) -> Result<()> {
             set_backend,
             set_model,
             set_cache,
+            set_cache_max_age,
+            set_cache_max_size,
             set_output,
             set_ensemble,
             set_plate_local,
@@ -213,12 +231,22 @@ pub fn execute(cli: Cli) -> Result<()> {
             set_plate_local_min_conf,
             set_plate_local_fallback,
             set_usage_mode,
+            set_storage_backend,
+            set_ensemble_aggregator,
+            set_max_concurrency,
+            set_reference_images,
+            set_calibration,
+            set_preprocess,
+            set_preprocess_max_edge,
+            set_preprocess_quality,
             reset,
         } => cmd_config(
             *show,
             set_backend.clone(),
             set_model.clone(),
             *set_cache,
+            *set_cache_max_age,
+            *set_cache_max_size,
             *set_output,
             *set_ensemble,
             *set_plate_local,
@@ -226,10 +254,27 @@ pub fn execute(cli: Cli) -> Result<()> {
             *set_plate_local_min_conf,
             *set_plate_local_fallback,
             set_usage_mode.clone(),
+            set_storage_backend.clone(),
+            set_ensemble_aggregator.clone(),
+            *set_max_concurrency,
+            *set_reference_images,
+            *set_calibration,
+            *set_preprocess,
+            *set_preprocess_max_edge,
+            *set_preprocess_quality,
             *reset,
         ),
 
-        Commands::Cache { clear, stats } => cmd_cache(&config, *clear, *stats),
+        Commands::Cache {
+            action: Some(CacheAction::Prune {
+                older_than,
+                max_size,
+                dry_run,
+            }),
+            ..
+        } => cmd_cache_prune(&config, older_than.as_deref(), max_size.as_deref(), *dry_run),
+
+        Commands::Cache { clear, stats, .. } => cmd_cache(&config, *clear, *stats),
 
         Commands::Feedback {
             image,
@@ -245,8 +290,23 @@ pub fn execute(cli: Cli) -> Result<()> {
         Commands::Accuracy {
             by_truck,
             by_material,
+            by_references,
             detailed,
-        } => cmd_accuracy(&config, *by_truck, *by_material, *detailed),
+        } => cmd_accuracy(&config, *by_truck, *by_material, *by_references, *detailed),
+
+        Commands::Calibrate {
+            method,
+            folds,
+            dry_run,
+        } => {
+            let method = CalibrationMethod::from_name(method).ok_or_else(|| {
+                Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+                    "Unknown calibration method '{}' (linear, isotonic)",
+                    method
+                )))
+            })?;
+            cmd_calibrate(&config, method, *folds, *dry_run)
+        }
 
         Commands::AutoCollect {
             folder,
@@ -258,6 +318,21 @@ pub fn execute(cli: Cli) -> Result<()> {
 
         Commands::Import { file, dry_run } => cmd_import(&config, file.clone(), *dry_run),
 
+        Commands::ImportVehicles { csv, dry_run } => {
+            cmd_import_vehicles(&config, csv.clone(), *dry_run)
+        }
+
+        Commands::Migrate {
+            vehicle_master,
+            slips,
+        } => cmd_migrate(&config, vehicle_master.clone(), slips.clone()),
+
+        Commands::Expiring {
+            days,
+            company,
+            output,
+        } => cmd_expiring(&config, *days, company.as_deref(), output.unwrap_or(OutputFormat::Table)),
+
         Commands::Stats => cmd_stats(&cli),
 
         Commands::CheckOverload {
@@ -271,7 +346,8 @@ pub fn execute(cli: Cli) -> Result<()> {
 fn cmd_analyze(
     cli: &Cli,
     config: &Config,
-    image: PathBuf,
+    images: Vec<ViewImage>,
+    fusion_mode: FusionMode,
     use_cache: bool,
     ensemble: u32,
     output_format: OutputFormat,
@@ -281,23 +357,26 @@ fn cmd_analyze(
     karte_arg: Option<String>,
     material_type: Option<String>,
     truck_type_hint: Option<String>,
+    reference_images: usize,
+    apply_calibration: bool,
 ) -> Result<()> {
+    if images.is_empty() {
+        return Err(Error::FileNotFound(
+            "No image given (pass a path or --image [VIEW:]PATH)".to_string(),
+        ));
+    }
+
     // Initialize profiler
     let mut profiler = AnalysisProfiler::new();
 
     // Parse skip_yolo_class_only to get TruckClass
     let truck_class_override: Option<TruckClass> =
         if let Some(ref class_name) = skip_yolo_class_only {
-            let truck_class = match class_name.as_str() {
-                "2t" => TruckClass::TwoTon,
-                "4t" => TruckClass::FourTon,
-                "増トン" => TruckClass::IncreasedTon,
-                "10t" => TruckClass::TenTon,
-                _ => {
-                    eprintln!("警告: 不明なクラス名 '{}' (2t, 4t, 増トン, 10t のいずれかを指定)", class_name);
-                    TruckClass::Unknown
-                }
-            };
+            let truck_class = TruckClass::from_name(class_name).unwrap_or_else(|| {
+                let known: Vec<String> = TruckClass::all().iter().map(|c| c.to_string()).collect();
+                eprintln!("警告: 不明なクラス名 '{}' ({} のいずれかを指定)", class_name, known.join(", "));
+                TruckClass::UNKNOWN
+            });
             Some(truck_class)
         } else {
             None
@@ -312,6 +391,9 @@ fn cmd_analyze(
     let mut options = AnalysisOptions::new()
         .with_cache(use_cache)
         .with_ensemble_count(ensemble)
+        .with_fusion_mode(fusion_mode)
+        .with_reference_images(reference_images)
+        .with_calibration(apply_calibration)
         .with_verbose(cli.verbose);
 
     if let Some(karte) = karte_json {
@@ -331,7 +413,7 @@ fn cmd_analyze(
     }
 
     if let Some(material) = material_type {
-        options = options.with_material_type(material);
+        options = options.with_material_type(resolve_material_arg(&material)?);
     }
 
     if let Some(truck_type) = truck_type_hint {
@@ -346,14 +428,24 @@ fn cmd_analyze(
     };
 
     if cli.verbose {
-        eprintln!("Analyzing image: {}", image.display());
+        for image in &images {
+            eprintln!("Analyzing image: {} ({})", image.path.display(), image.view.label());
+        }
+        if images.len() > 1 {
+            eprintln!("Fusion: {}", fusion_mode.name());
+        }
     }
 
     // Delegate to app layer
     let analysis_start = Instant::now();
-    let result = app::analyze_truck_image(&image, config, &options, progress_cb)
+    let result = app::analyze_truck_images(&images, config, &options, progress_cb)
         .map_err(|e: app::AnalysisServiceError| Error::AnalysisFailed(e.to_string()))?;
+    for recovery in &result.recoveries {
+        warn_recovery(Some(recovery));
+    }
+    profiler.record_plate(result.plate_yolo_ms, result.plate_api_ms);
     profiler.record_stage2(analysis_start);
+    profiler.record_samples(&result.estimation.ensemble_samples);
 
     if result.from_cache {
         profiler.cache_hit = true;
@@ -377,15 +469,31 @@ fn cmd_analyze(
         println!("最大積載量: {}t", vehicle.max_capacity);
         println!("ナンバー:   {}", vehicle.license_plate.as_deref().unwrap_or("-"));
         println!("クラス:     {}", vehicle.truck_class().label());
+        println!("照合:       {}", result.match_quality.label());
+        if result.match_quality.needs_confirmation() {
+            eprintln!("警告: ナンバーの下4桁のみで照合しました。車両を確認してください");
+        }
+    } else if result.match_quality == app::MatchQuality::Ambiguous {
+        eprintln!(
+            "警告: ナンバーが同程度に一致する登録車両が{}台あります。車両を特定できません",
+            result.ambiguous_candidates.len()
+        );
+        for candidate in &result.ambiguous_candidates {
+            eprintln!(
+                "  - {} ({}) {}",
+                candidate.name,
+                candidate.license_plate.as_deref().unwrap_or("-"),
+                candidate.company.as_deref().unwrap_or("")
+            );
+        }
+        if options.company_filter.is_some() {
+            eprintln!("  会社名フィルタを見直すか、--plate でナンバーを指定してください");
+        } else {
+            eprintln!("  --company で運搬会社を指定するか、--plate でナンバーを指定してください");
+        }
     } else if cli.verbose {
         if let Some(ref class_name) = skip_yolo_class_only {
-            let max_cap = match class_name.as_str() {
-                "2t" => 2.0,
-                "4t" => 4.0,
-                "増トン" => 6.5,
-                "10t" => 10.0,
-                _ => 0.0,
-            };
+            let max_cap = get_truck_spec(class_name).map(|s| s.max_capacity).unwrap_or(0.0);
             eprintln!("クラス指定: {} (参照用積載量: {}t、YOLO車両特定スキップ、積載率計算なし)",
                 class_name, max_cap);
         } else {
@@ -400,6 +508,10 @@ fn cmd_analyze(
     output_result(output_format, &result.estimation, output_capacity)?;
     profiler.print_summary();
 
+    if use_cache {
+        enforce_cache_policy(config, cli.verbose);
+    }
+
     Ok(())
 }
 
@@ -411,16 +523,35 @@ fn parse_karte_arg(arg: &str) -> Result<String> {
         arg.to_string()
     };
 
-    let _karte: KarteInput = serde_json::from_str(&raw).map_err(Error::Json)?;
+    let mut karte: KarteInput = serde_json::from_str(&raw).map_err(Error::Json)?;
+    // Material names must be in the catalog; they are stored by catalog id
+    if let Some(ref mut material) = karte.material_type {
+        *material = resolve_material_arg(material)?;
+    }
+    for part in karte.material_breakdown.iter_mut().flatten() {
+        part.material = resolve_material_arg(&part.material)?;
+    }
     // Normalize JSON to ensure valid formatting
-    serde_json::to_string(&_karte).map_err(Error::Json)
+    serde_json::to_string(&karte).map_err(Error::Json)
+}
+
+/// Map a material name or alias to its catalog id, rejecting unknown names
+fn resolve_material_arg(name: &str) -> Result<String> {
+    let catalog = load_material_catalog()?;
+    catalog.resolve(name).map(|id| id.to_string()).ok_or_else(|| {
+        Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+            "Unknown material '{}' ({})",
+            name,
+            catalog.ids().join(", ")
+        )))
+    })
 }
 
 /// Result from a single analysis task
 #[derive(Debug)]
 struct AnalysisTaskResult {
     image_path: PathBuf,
-    result: std::result::Result<EstimationResult, String>,
+    result: std::result::Result<app::AnalysisResult, String>,
 }
 
 fn cmd_batch(
@@ -482,7 +613,8 @@ fn cmd_batch(
         let handle = thread::spawn(move || {
             let batch_options = AnalysisOptions::new()
                 .with_cache(use_cache)
-                .with_ensemble_count(config.ensemble_count);
+                .with_ensemble_count(config.ensemble_count)
+                .with_calibration(config.apply_calibration);
 
             loop {
                 // Get next image to process (lock-free)
@@ -506,7 +638,6 @@ fn cmd_batch(
 
                 // Use app layer (box-overlay pipeline by default)
                 let result = app::analyze_truck_image(image, &config, &batch_options, None)
-                    .map(|r| r.estimation)
                     .map_err(|e| e.to_string());
 
                 // Store result
@@ -532,6 +663,10 @@ fn cmd_batch(
 
     main_pb.finish_with_message("Complete");
 
+    if use_cache {
+        enforce_cache_policy(config, verbose);
+    }
+
     let completed_at = Utc::now();
 
     // Collect results
@@ -544,18 +679,24 @@ fn cmd_batch(
     let mut entries = Vec::new();
     let mut successful = 0;
     let mut failed = 0;
+    // Every worker reopens the stores; report each damaged file once
+    let mut recoveries: Vec<Recovery> = Vec::new();
 
     for task_result in task_results {
         match task_result.result {
-            Ok(result) => {
-                // Calculate grade from truck spec
-                let grade = if let Some(spec) = get_truck_spec(&result.truck_type) {
-                    Some(LoadGrade::from_ratio(
-                        result.estimated_tonnage / spec.max_capacity,
-                    ))
-                } else {
-                    None
-                };
+            Ok(analysis) => {
+                for recovery in &analysis.recoveries {
+                    if !recoveries.iter().any(|r| r.backup == recovery.backup) {
+                        recoveries.push(recovery.clone());
+                    }
+                }
+                let max_capacity = analysis.max_capacity();
+                let result = analysis.estimation;
+
+                // Calculate grade from the matched vehicle, else from truck spec
+                let grade = max_capacity
+                    .or_else(|| get_truck_spec(&result.truck_type).map(|spec| spec.max_capacity))
+                    .map(|cap| LoadGrade::from_ratio(result.estimated_tonnage / cap));
 
                 entries.push(AnalysisEntry {
                     image_path: task_result.image_path.display().to_string(),
@@ -563,6 +704,7 @@ fn cmd_batch(
                     result,
                     grade,
                     actual_tonnage: None,
+                    max_capacity,
                 });
                 successful += 1;
             }
@@ -575,6 +717,10 @@ fn cmd_batch(
         }
     }
 
+    for recovery in &recoveries {
+        warn_recovery(Some(recovery));
+    }
+
     // Sort entries by image path for consistent output
     entries.sort_by(|a, b| a.image_path.cmp(&b.image_path));
 
@@ -647,6 +793,8 @@ fn cmd_config(
     set_backend: Option<String>,
     set_model: Option<String>,
     set_cache: Option<bool>,
+    set_cache_max_age: Option<u32>,
+    set_cache_max_size: Option<u64>,
     set_output: Option<OutputFormat>,
     set_ensemble: Option<u32>,
     set_plate_local: Option<bool>,
@@ -654,6 +802,14 @@ fn cmd_config(
     set_plate_local_min_conf: Option<f32>,
     set_plate_local_fallback: Option<bool>,
     set_usage_mode: Option<String>,
+    set_storage_backend: Option<String>,
+    set_ensemble_aggregator: Option<String>,
+    set_max_concurrency: Option<usize>,
+    set_reference_images: Option<usize>,
+    set_calibration: Option<bool>,
+    set_preprocess: Option<bool>,
+    set_preprocess_max_edge: Option<u32>,
+    set_preprocess_quality: Option<u8>,
     reset: bool,
 ) -> Result<()> {
     if reset {
@@ -682,6 +838,16 @@ fn cmd_config(
         modified = true;
     }
 
+    if let Some(days) = set_cache_max_age {
+        config.cache_max_age_days = if days == 0 { None } else { Some(days) };
+        modified = true;
+    }
+
+    if let Some(mb) = set_cache_max_size {
+        config.cache_max_size_mb = if mb == 0 { None } else { Some(mb) };
+        modified = true;
+    }
+
     if let Some(output_format) = set_output {
         config.output_format = output_format;
         modified = true;
@@ -717,6 +883,59 @@ fn cmd_config(
         modified = true;
     }
 
+    if let Some(storage_backend) = set_storage_backend {
+        let storage_backend = storage_backend.to_lowercase();
+        if storage_backend != "json" && storage_backend != "sqlite" {
+            return Err(Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+                "Unknown storage backend: {} (expected json or sqlite)",
+                storage_backend
+            ))));
+        }
+        config.storage_backend = storage_backend;
+        modified = true;
+    }
+
+    if let Some(name) = set_ensemble_aggregator {
+        let aggregator = Aggregator::from_name(&name).ok_or_else(|| {
+            Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+                "Unknown ensemble aggregator: {} (expected median, trimmed_mean or confidence_weighted)",
+                name
+            )))
+        })?;
+        config.ensemble_aggregator = aggregator.name().to_string();
+        modified = true;
+    }
+
+    if let Some(max_concurrency) = set_max_concurrency {
+        config.max_concurrent_requests = max_concurrency.max(1);
+        modified = true;
+    }
+
+    if let Some(reference_images) = set_reference_images {
+        config.reference_images = reference_images;
+        modified = true;
+    }
+
+    if let Some(calibration) = set_calibration {
+        config.apply_calibration = calibration;
+        modified = true;
+    }
+
+    if let Some(enabled) = set_preprocess {
+        config.preprocess_enabled = enabled;
+        modified = true;
+    }
+
+    if let Some(max_edge) = set_preprocess_max_edge {
+        config.preprocess_max_long_edge = max_edge.max(1);
+        modified = true;
+    }
+
+    if let Some(quality) = set_preprocess_quality {
+        config.preprocess_jpeg_quality = quality.clamp(1, 100);
+        modified = true;
+    }
+
     if modified {
         config.save()?;
         println!("Configuration updated");
@@ -751,6 +970,84 @@ fn cmd_cache(config: &Config, clear: bool, stats: bool) -> Result<()> {
     Ok(())
 }
 
+fn cmd_cache_prune(
+    config: &Config,
+    older_than: Option<&str>,
+    max_size: Option<&str>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut policy = config.cache_policy();
+
+    if let Some(s) = older_than {
+        policy.max_age = Some(parse_duration(s).ok_or_else(|| {
+            Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+                "Invalid --older-than value: {} (e.g. 30d, 12h, 2w)",
+                s
+            )))
+        })?);
+    }
+
+    if let Some(s) = max_size {
+        policy.max_bytes = Some(parse_size(s).ok_or_else(|| {
+            Error::Config(tonsuu_types::ConfigError::ParseError(format!(
+                "Invalid --max-size value: {} (e.g. 500MB, 2GB)",
+                s
+            )))
+        })?);
+    }
+
+    let cache = Cache::new(config.cache_dir()?)?;
+    let report = cache.prune(&policy, dry_run)?;
+    println!("{}", report.display());
+
+    Ok(())
+}
+
+/// Apply the configured size cap after analysis runs (TTL is checked on lookup)
+fn enforce_cache_policy(config: &Config, verbose: bool) {
+    let policy: CachePolicy = config.cache_policy();
+    if !config.cache_enabled || policy.max_bytes.is_none() {
+        return;
+    }
+
+    let report = config
+        .cache_dir()
+        .and_then(Cache::new)
+        .and_then(|cache| cache.prune(&policy, false));
+
+    match report {
+        Ok(report) if verbose && !report.removed.is_empty() => {
+            eprintln!("Cache pruned: {} entries", report.removed.len());
+        }
+        Err(e) if verbose => eprintln!("Cache prune failed: {}", e),
+        _ => {}
+    }
+}
+
+/// Warn that a store file was damaged and only partly read
+fn warn_recovery(recovery: Option<&Recovery>) {
+    if let Some(r) = recovery {
+        eprintln!(
+            "警告: {} が破損しています。{}件を復旧しました (元ファイル: {})",
+            r.path.display(),
+            r.recovered,
+            r.backup.display()
+        );
+    }
+}
+
+fn open_history_store(config: &Config) -> Result<Store> {
+    let store = repository::open_history_store(config)?;
+    warn_recovery(store.recovery());
+    Ok(store)
+}
+
+fn open_vehicle_store(config: &Config) -> Result<VehicleStore> {
+    let store = repository::open_vehicle_store(config)?;
+    warn_recovery(store.recovery());
+    Ok(store)
+}
+
 fn cmd_feedback(
     config: &Config,
     image: PathBuf,
@@ -871,6 +1168,7 @@ fn cmd_accuracy(
     config: &Config,
     by_truck: bool,
     by_material: bool,
+    by_references: bool,
     detailed: bool,
 ) -> Result<()> {
     let store = open_history_store(config)?;
@@ -918,6 +1216,21 @@ fn cmd_accuracy(
         }
     }
 
+    if by_references {
+        println!();
+        println!("By Reference Images");
+        println!("-------------------");
+        let grouped = stats.by_references();
+        let mut keys: Vec<_> = grouped.keys().collect();
+        keys.sort();
+        for key in keys {
+            if let Some(s) = grouped.get(key) {
+                println!();
+                print_accuracy_stats(key, s);
+            }
+        }
+    }
+
     if detailed {
         println!();
         println!("Detailed Samples");
@@ -944,6 +1257,85 @@ fn cmd_accuracy(
     Ok(())
 }
 
+fn cmd_calibrate(
+    config: &Config,
+    method: CalibrationMethod,
+    folds: usize,
+    dry_run: bool,
+) -> Result<()> {
+    let store = open_history_store(config)?;
+    let samples = store.calibration_samples();
+
+    if samples.len() < MIN_CALIBRATION_SAMPLES {
+        println!(
+            "Not enough feedback data ({} samples, need at least {}).",
+            samples.len(),
+            MIN_CALIBRATION_SAMPLES
+        );
+        println!("Use 'tonsuu-checker feedback <image> --actual <tonnage>' to add ground truth.");
+        return Ok(());
+    }
+
+    let report = cross_validate(&samples, method, folds);
+    let model = CalibrationModel::fit(&samples, method);
+
+    println!("Calibration ({}, {}-fold cross-validation)", method.name(), report.folds);
+    println!("==========================================");
+    println!();
+    println!(
+        "{:<24} {:>5} {:>10} {:>10} {:>10} {:>10}",
+        "Group", "n", "MAE", "MAE cal", "RMSE", "RMSE cal"
+    );
+    println!("{}", "-".repeat(74));
+    let print_row = |label: &str, before: &ErrorMetrics, after: &ErrorMetrics| {
+        println!(
+            "{:<24} {:>5} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            label,
+            before.sample_count,
+            before.mae,
+            after.mae,
+            before.rmse,
+            after.rmse
+        );
+    };
+    for group in &report.groups {
+        print_row(&group.label, &group.before, &group.after);
+    }
+    print_row("Overall", &report.before, &report.after);
+
+    println!();
+    println!("Fitted Corrections");
+    println!("------------------");
+    for entry in model.entries() {
+        let fit = match &entry.fit {
+            CalibrationFit::Linear { scale, offset } => {
+                format!("actual = {:.3} x estimated {:+.3} t", scale, offset)
+            }
+            CalibrationFit::Isotonic { points } => format!("{} steps", points.len()),
+        };
+        println!("  {:<28} n={:<4} {}", entry.group_label(), entry.sample_count, fit);
+    }
+
+    if dry_run {
+        println!();
+        println!("Dry run - model not saved.");
+        return Ok(());
+    }
+
+    let store_dir = config.store_dir()?;
+    model.save(&store_dir)?;
+    println!();
+    println!(
+        "Saved calibration model to {}",
+        store_dir.join(StoreKind::Calibration.file_name()).display()
+    );
+    if !config.apply_calibration {
+        println!("Enable it with: tonsuu-checker config --set-calibration true");
+    }
+
+    Ok(())
+}
+
 fn print_accuracy_stats(label: &str, stats: &tonsuu_store::AccuracyStats) {
     println!("{} (n={})", label, stats.sample_count);
     println!("  Mean Error:     {:+.3} t", stats.mean_error);
@@ -964,45 +1356,6 @@ fn truncate(s: &str, max_len: usize) -> String {
     }
 }
 
-/// Find vehicle by license plate with fuzzy matching
-#[allow(dead_code)]
-fn find_vehicle_by_plate<'a>(
-    vehicle_store: &'a tonsuu_store::VehicleStore,
-    plate: &str,
-) -> Option<&'a tonsuu_types::RegisteredVehicle> {
-    // Try exact match first
-    if let Some(vehicle) = vehicle_store.get_by_license_plate(plate) {
-        return Some(vehicle);
-    }
-
-    // Try fuzzy match (remove spaces, normalize)
-    let normalized_plate = plate.replace(' ', "").replace('\u{3000}', "").replace('-', "");
-    let plate_nums: String = normalized_plate.chars().filter(|c| c.is_ascii_digit()).collect();
-
-    for vehicle in vehicle_store.all_vehicles() {
-        if let Some(ref vplate) = vehicle.license_plate {
-            let normalized_vplate = vplate.replace(' ', "").replace('\u{3000}', "").replace('-', "");
-
-            // Direct normalized match
-            if normalized_plate == normalized_vplate {
-                return Some(vehicle);
-            }
-
-            // Check if last 4 digits match
-            let vplate_nums: String = normalized_vplate.chars().filter(|c| c.is_ascii_digit()).collect();
-            if plate_nums.len() >= 4 && vplate_nums.len() >= 4 {
-                let plate_last4 = &plate_nums[plate_nums.len()-4..];
-                let vplate_last4 = &vplate_nums[vplate_nums.len()-4..];
-                if plate_last4 == vplate_last4 {
-                    return Some(vehicle);
-                }
-            }
-        }
-    }
-
-    None
-}
-
 fn cmd_auto_collect(
     cli: &Cli,
     config: &Config,
@@ -1067,7 +1420,10 @@ fn cmd_auto_collect(
     let analyzer_config = AnalyzerConfig::default()
         .with_backend(&config.backend)
         .with_model(config.model.clone())
-        .with_usage_mode(&config.usage_mode);
+        .with_usage_mode(&config.usage_mode)
+        .with_max_concurrency(config.max_concurrent_requests);
+    // Page images of 車検証 PDFs are extracted here
+    let pdf_cache_dir = config.pdf_page_cache_dir()?;
 
     // Progress bar
     let pb = ProgressBar::new(vehicle_folders.len() as u64);
@@ -1087,7 +1443,13 @@ fn cmd_auto_collect(
         for vf in vehicle_folders {
             pb.set_message(truncate(&vf.folder_name, 30));
 
-            match process_vehicle_folder(&vf, &analyzer_config, cli.verbose, company.as_deref()) {
+            match process_vehicle_folder(
+                &vf,
+                &analyzer_config,
+                &pdf_cache_dir,
+                cli.verbose,
+                company.as_deref(),
+            ) {
                 Ok(vehicle) => {
                     if let Err(e) = vehicle_store.add_vehicle(vehicle) {
                         if cli.verbose {
@@ -1117,6 +1479,7 @@ fn cmd_auto_collect(
         let backend = config.backend.clone();
         let model = config.model.clone();
         let usage_mode_str = config.usage_mode.clone();
+        let max_concurrency = config.max_concurrent_requests;
         let verbose = cli.verbose;
         let company_arc = Arc::new(company.clone());
 
@@ -1132,12 +1495,14 @@ fn cmd_auto_collect(
             let usage_mode_for_worker = usage_mode_str.clone();
             let pb = pb.clone();
             let company = Arc::clone(&company_arc);
+            let pdf_cache_dir = pdf_cache_dir.clone();
 
             let handle = thread::spawn(move || {
                 let worker_config = AnalyzerConfig::default()
                     .with_backend(&backend)
                     .with_model(model)
-                    .with_usage_mode(&usage_mode_for_worker);
+                    .with_usage_mode(&usage_mode_for_worker)
+                    .with_max_concurrency(max_concurrency);
 
                 loop {
                     let idx = next_index.fetch_add(1, Ordering::SeqCst);
@@ -1149,7 +1514,13 @@ fn cmd_auto_collect(
                     pb.set_message(truncate(&vf.folder_name, 30));
 
                     let result: std::result::Result<RegisteredVehicle, String> =
-                        process_vehicle_folder(vf, &worker_config, verbose, company.as_deref())
+                        process_vehicle_folder(
+                            vf,
+                            &worker_config,
+                            &pdf_cache_dir,
+                            verbose,
+                            company.as_deref(),
+                        )
                             .map_err(|e| e.to_string());
 
                     {
@@ -1330,69 +1701,32 @@ fn scan_folder_files(folder: &PathBuf) -> (Vec<PathBuf>, Vec<PathBuf>) {
 /// Process a single vehicle folder
 fn process_vehicle_folder(
     vf: &VehicleFolderInfo,
-    _config: &AnalyzerConfig,
+    config: &AnalyzerConfig,
+    pdf_cache_dir: &Path,
     verbose: bool,
     company: Option<&str>,
 ) -> Result<RegisteredVehicle> {
-    use cli_ai_analyzer::{analyze, AnalyzeOptions, Backend};
-
     // Need at least a shaken file for capacity
     if vf.shaken_files.is_empty() {
         return Err(Error::AnalysisFailed("No 車検証 file found".to_string()));
     }
 
-    // Analyze 車検証
-    let shaken_path = &vf.shaken_files[0];
+    // Analyze 車検証 (the first page image of a PDF scan)
+    let shaken_file = &vf.shaken_files[0];
+    let shaken_path = document_images(shaken_file, pdf_cache_dir)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::AnalysisFailed("No 車検証 page image found".to_string()))?;
     if verbose {
-        eprintln!("  Analyzing 車検証: {}", shaken_path.display());
+        eprintln!("  Analyzing 車検証: {}", shaken_file.display());
     }
 
-    let prompt = r#"この画像は日本の自動車検査証（車検証）です。以下の情報を抽出してください。
-
-抽出する項目:
-1. 車名（例: 日野, いすゞ, 三菱ふそう, UD）
-2. 型式（例: プロフィア, ギガ, スーパーグレート）
-3. 最大積載量（kg単位の数値）
-4. 車両番号（ナンバープレート）
-
-以下のJSON形式で回答してください:
-{
-  "vehicleName": "車名 型式",
-  "maxCapacityKg": 10000,
-  "licensePlate": "品川 100 あ 1234"
-}
-
-注意:
-- 最大積載量は必ずkg単位の数値で返してください
-- 読み取れない項目はnullとしてください
-- 車検証でない画像の場合は全てnullとしてください
-"#;
-
-    let options = AnalyzeOptions::default()
-        .with_backend(Backend::Gemini)
-        .json();
-
-    let response = analyze(prompt, &[shaken_path.clone()], options)
-        .map_err(|e| Error::AnalysisFailed(format!("AI error: {}", e)))?;
-
-    // Parse response
-    #[derive(serde::Deserialize)]
-    #[serde(rename_all = "camelCase")]
-    struct ShakenResult {
-        vehicle_name: Option<String>,
-        max_capacity_kg: Option<f64>,
-        license_plate: Option<String>,
+    // QR codes first; the AI reads what they do not carry
+    let shaken = read_shaken(&shaken_path, config)?;
+    if verbose && shaken.from_qr {
+        eprintln!("  Read QR codes: {}", shaken.registration_number.as_deref().unwrap_or("-"));
     }
 
-    let json_str = extract_json_response(&response);
-    let shaken: ShakenResult = serde_json::from_str(&json_str)
-        .map_err(|e| Error::AnalysisFailed(format!("JSON parse error: {}", e)))?;
-
-    let vehicle_name = shaken.vehicle_name.unwrap_or_else(|| vf.folder_name.clone());
-    let max_capacity = shaken.max_capacity_kg
-        .map(|kg| kg / 1000.0)
-        .ok_or_else(|| Error::AnalysisFailed("Could not detect max capacity".to_string()))?;
-
     // Get photo path
     let photo_path = vf.photo_files.first()
         .ok_or_else(|| Error::AnalysisFailed("No photo file found".to_string()))?;
@@ -1400,14 +1734,12 @@ fn process_vehicle_folder(
     // Create thumbnail
     let thumbnail = create_thumbnail_from_path(photo_path);
 
-    // Create vehicle
-    let mut vehicle = RegisteredVehicle::new(vehicle_name, max_capacity)
+    // Create vehicle with every field read from the certificate
+    let mut vehicle = shaken
+        .to_vehicle(&vf.folder_name)
+        .ok_or_else(|| Error::AnalysisFailed("Could not detect max capacity".to_string()))?
         .with_image(photo_path.display().to_string(), thumbnail);
 
-    if let Some(plate) = shaken.license_plate {
-        vehicle = vehicle.with_license_plate(plate);
-    }
-
     if let Some(company_name) = company {
         vehicle.company = Some(company_name.to_string());
     }
@@ -1417,39 +1749,6 @@ fn process_vehicle_folder(
     Ok(vehicle)
 }
 
-/// Extract JSON from AI response
-fn extract_json_response(response: &str) -> String {
-    let response = response.trim();
-
-    if response.starts_with("```json") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(7) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    if response.starts_with("```") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(3) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    if let Some(start) = response.find('{') {
-        if let Some(end) = response.rfind('}') {
-            if start < end {
-                return response[start..=end].to_string();
-            }
-        }
-    }
-
-    response.to_string()
-}
-
 /// Create thumbnail from file path
 fn create_thumbnail_from_path(path: &PathBuf) -> Option<String> {
     use base64::{engine::general_purpose::STANDARD, Engine};
@@ -1578,6 +1877,8 @@ fn cmd_import(config: &Config, file: PathBuf, dry_run: bool) -> Result<()> {
                 is_target_detected: est.is_target_detected,
                 truck_type: est.truck_type.clone(),
                 license_plate: est.license_plate.clone(),
+                plate_source: None,
+                plate_confidence: None,
                 material_type: est.material_type.clone(),
                 height: None,
                 packing_density: None,
@@ -1586,10 +1887,17 @@ fn cmd_import(config: &Config, file: PathBuf, dry_run: bool) -> Result<()> {
                 fill_ratio_z: None,
                 estimated_volume_m3: est.estimated_volume_m3,
                 estimated_tonnage: est.estimated_tonnage,
+                raw_tonnage: None,
                 confidence_score: est.confidence_score,
                 reasoning: est.reasoning.clone(),
                 material_breakdown: Vec::new(),
                 ensemble_count: None,
+                ensemble_samples: Vec::new(),
+                tonnage_std_dev: None,
+                tonnage_interval: None,
+                reference_hashes: Vec::new(),
+                warnings: Vec::new(),
+                preprocessing: None,
             }
         } else {
             // No estimation, create default
@@ -1607,6 +1915,7 @@ fn cmd_import(config: &Config, file: PathBuf, dry_run: bool) -> Result<()> {
             feedback_at: entry.actual_tonnage.map(|_| analyzed_at),
             notes: Some("Imported from TonSuuChecker app backup".to_string()),
             thumbnail_base64: entry.base64_images.first().cloned(),
+            source_images: Vec::new(),
         };
 
         if dry_run {
@@ -1649,6 +1958,153 @@ fn cmd_import(config: &Config, file: PathBuf, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
+/// Register vehicles / set bed dimensions from a CSV file
+fn cmd_import_vehicles(config: &Config, csv_path: PathBuf, dry_run: bool) -> Result<()> {
+    if !csv_path.exists() {
+        return Err(Error::FileNotFound(format!(
+            "CSV file not found: {}",
+            csv_path.display()
+        )));
+    }
+
+    let rows = load_vehicle_csv(&csv_path)
+        .map_err(|e| Error::AnalysisFailed(format!("Failed to load vehicle CSV: {}", e)))?;
+    println!("Loaded {} rows from: {}", rows.len(), csv_path.display());
+
+    if dry_run {
+        let dim = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string());
+        for row in &rows {
+            println!(
+                "  [DRY RUN] {} {} {} bed {}x{}x{} wall {} {}",
+                row.license_plate.as_deref().unwrap_or("-"),
+                row.name.as_deref().unwrap_or("-"),
+                row.max_capacity.map(|c| format!("{:.1}t", c)).unwrap_or_else(|| "-".to_string()),
+                dim(row.bed_length),
+                dim(row.bed_width),
+                dim(row.bed_height),
+                dim(row.side_wall_height),
+                row.body_type.map(|b| b.label()).unwrap_or("-"),
+            );
+        }
+        return Ok(());
+    }
+
+    let mut vehicle_store = open_vehicle_store(config)?;
+    let result = import_vehicle_rows(&mut vehicle_store, &rows);
+    for error in &result.errors {
+        eprintln!("  {}", error);
+    }
+    println!("Import complete:");
+    println!("  Added: {}", result.added);
+    println!("  Updated: {}", result.updated);
+    println!("  Errors: {}", result.errors.len());
+    println!("  Total vehicles in store: {}", vehicle_store.count());
+
+    Ok(())
+}
+
+/// Import the JSON stores, and optionally a vehicle master / slip CSV, into SQLite
+fn cmd_migrate(
+    config: &Config,
+    vehicle_master: Option<PathBuf>,
+    slips: Option<PathBuf>,
+) -> Result<()> {
+    let report = repository::migrate_json_store(config)?;
+    println!("Migration to SQLite complete:");
+    println!("  History entries: {}", report.history_count);
+    println!("  Vehicles: {}", report.vehicle_count);
+
+    if let Some(path) = vehicle_master {
+        let count = repository::import_vehicle_master(config, path)?;
+        println!("  Vehicle master entries: {}", count);
+    }
+    if let Some(path) = slips {
+        let count = repository::import_weighing_slips(config, path)?;
+        println!("  Weighing slips: {}", count);
+    }
+    if !config.uses_sqlite() {
+        println!("Run `config --set-storage-backend sqlite` to use the database.");
+    }
+
+    Ok(())
+}
+
+/// List vehicles whose 車検証 expires within `days` days
+fn cmd_expiring(
+    config: &Config,
+    days: i64,
+    company: Option<&str>,
+    output_format: OutputFormat,
+) -> Result<()> {
+    let vehicle_store = open_vehicle_store(config)?;
+    let today = chrono::Local::now().date_naive();
+    let in_company = |v: &RegisteredVehicle| company.is_none_or(|c| v.company_matches(c));
+
+    let expiring: Vec<_> = vehicle_store
+        .expiring_within(today, days)
+        .into_iter()
+        .filter(|(v, _)| in_company(v))
+        .collect();
+    let no_expiry = vehicle_store
+        .all_vehicles()
+        .into_iter()
+        .filter(|v| in_company(v) && v.inspection_expiry.is_none())
+        .count();
+
+    match output_format {
+        OutputFormat::Json => {
+            let entries: Vec<_> = expiring
+                .iter()
+                .map(|(v, left)| {
+                    serde_json::json!({
+                        "id": v.id,
+                        "name": v.name,
+                        "licensePlate": v.license_plate,
+                        "company": v.company,
+                        "inspectionExpiry": v.inspection_expiry,
+                        "daysLeft": left,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        OutputFormat::Table => {
+            println!("Inspection expiry within {} days (as of {})", days, today);
+            println!("==============================================");
+            if expiring.is_empty() {
+                println!("No vehicles found.");
+            } else {
+                println!(
+                    "{:<20} {:<20} {:<16} {:>10} {:>10}",
+                    "Plate", "Name", "Company", "Expiry", "Days left"
+                );
+                println!("{}", "-".repeat(80));
+                for (v, left) in &expiring {
+                    let left_str = if *left < 0 {
+                        "EXPIRED".to_string()
+                    } else {
+                        left.to_string()
+                    };
+                    println!(
+                        "{:<20} {:<20} {:<16} {:>10} {:>10}",
+                        v.license_plate.as_deref().unwrap_or("-"),
+                        v.name,
+                        v.company.as_deref().unwrap_or("-"),
+                        v.inspection_expiry.map(|d| d.to_string()).unwrap_or_default(),
+                        left_str
+                    );
+                }
+            }
+            if no_expiry > 0 {
+                println!();
+                println!("{} vehicle(s) have no expiry date recorded", no_expiry);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Check AI backend status and rate limits
 fn cmd_stats(cli: &Cli) -> Result<()> {
     let backend = cli.backend.as_deref().unwrap_or("gemini");
@@ -1709,8 +2165,14 @@ fn cmd_check_overload(csv_path: PathBuf, vehicles_path: PathBuf, output_format:
 
     // Load data
     println!("Loading weighing slips from: {}", csv_path.display());
-    let slips = load_slips_from_csv(&csv_path)
+    let mut slips = load_slips_from_csv(&csv_path)
         .map_err(|e| Error::AnalysisFailed(format!("Failed to load slips: {}", e)))?;
+    // Slip material names (e.g. "ASガラ") are mapped to catalog ids
+    for slip in &mut slips {
+        if let Some(ref mut material) = slip.material_type {
+            *material = canonical_material(material);
+        }
+    }
     println!("  Loaded {} slips", slips.len());
 
     println!("Loading vehicle master from: {}", vehicles_path.display());
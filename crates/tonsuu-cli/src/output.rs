@@ -37,6 +37,37 @@ pub fn output_result(output_format: OutputFormat, result: &EstimationResult, max
 
             println!("Volume:          {:.2} m³", result.estimated_volume_m3);
             println!("Tonnage:         {:.2} t", result.estimated_tonnage);
+            if let Some(raw) = result.raw_tonnage {
+                println!("Raw tonnage:     {:.2} t (before calibration)", raw);
+            }
+            let split: Vec<_> = result
+                .material_breakdown
+                .iter()
+                .filter_map(|b| b.tonnage.map(|t| (b, t)))
+                .collect();
+            if !split.is_empty() {
+                println!("Material split:");
+                for (breakdown, tonnage) in split {
+                    println!(
+                        "  {:<14} {:>6.2} t ({:.0}%)",
+                        breakdown.material, tonnage, breakdown.percentage
+                    );
+                }
+            }
+            if let Some(std_dev) = result.tonnage_std_dev {
+                println!("Tonnage spread:  ±{:.2} t (std dev)", std_dev);
+            }
+            if let Some(interval) = result.tonnage_interval {
+                let basis = if interval.history_samples > 0 {
+                    format!("{} ground-truth samples", interval.history_samples)
+                } else {
+                    "default spread, no history".to_string()
+                };
+                println!(
+                    "Tonnage P10-P90: {:.2} - {:.2} t ({})",
+                    interval.p10, interval.p90, basis
+                );
+            }
 
             // Show load ratio if max capacity is known
             if let Some(cap) = max_capacity {
@@ -44,12 +75,65 @@ pub fn output_result(output_format: OutputFormat, result: &EstimationResult, max
                 let grade = LoadGrade::from_ratio(result.estimated_tonnage / cap);
                 println!("Max capacity:    {:.1} t", cap);
                 println!("Load:            {:.1}% ({})", load_pct, grade.label());
+                let overload = result
+                    .tonnage_interval
+                    .filter(|_| result.possibly_overloaded(cap));
+                if let Some(interval) = overload {
+                    println!(
+                        "WARNING:         Possibly overloaded (P90 {:.2} t > {:.1} t)",
+                        interval.p90, cap
+                    );
+                }
             }
 
             println!("Confidence:      {:.0}%", result.confidence_score * 100.0);
+            for warning in &result.warnings {
+                println!("WARNING:         {}", warning.message);
+            }
+            if !result.reference_hashes.is_empty() {
+                let short: Vec<&str> = result
+                    .reference_hashes
+                    .iter()
+                    .map(|h| &h[..h.len().min(8)])
+                    .collect();
+                println!(
+                    "References:      {} images ({})",
+                    short.len(),
+                    short.join(", ")
+                );
+            }
 
             if let Some(ref plate) = result.license_plate {
-                println!("License plate:   {}", plate);
+                match (result.plate_source, result.plate_confidence) {
+                    (Some(source), Some(conf)) => println!(
+                        "License plate:   {} ({}, {:.0}%)",
+                        plate,
+                        source.label(),
+                        conf * 100.0
+                    ),
+                    (Some(source), None) => {
+                        println!("License plate:   {} ({})", plate, source.label())
+                    }
+                    _ => println!("License plate:   {}", plate),
+                }
+            }
+
+            if !result.ensemble_samples.is_empty() {
+                println!("\n--- Ensemble Samples ---");
+                for (i, sample) in result.ensemble_samples.iter().enumerate() {
+                    println!(
+                        "#{:<2} {:>6.2} t  {:>5.2} m³  height {}{}",
+                        i + 1,
+                        sample.estimated_tonnage,
+                        sample.estimated_volume_m3,
+                        sample
+                            .height
+                            .map(|h| format!("{:.2} m", h))
+                            .unwrap_or_else(|| "-".to_string()),
+                        if sample.rejected { "  (rejected outlier)" } else { "" }
+                    );
+                }
+                println!("------------------------");
             }
 
             println!("\nReasoning:");
@@ -11,6 +11,10 @@
 //!   $env:TONSUU_GT_RANK="low"; cargo test -p tonsuu-cli --test ground_truth_test -- --nocapture
 //!   $env:TONSUU_GT_ALL="1"; cargo test -p tonsuu-cli --test ground_truth_test -- --nocapture
 //!   $env:TONSUU_GT_INDEX="1"; $env:TONSUU_GT_HEIGHT_ONLY="1"; cargo test -p tonsuu-cli --test ground_truth_test -- --nocapture
+//!
+//! オフライン実行 (TONSUU_GT_BACKEND は --backend と同じ書式):
+//!   $env:TONSUU_GT_BACKEND="record:tests/fixtures/ai"; $env:TONSUU_GT_ALL="1"; cargo test ...  # 応答を記録
+//!   $env:TONSUU_GT_BACKEND="replay:tests/fixtures/ai"; $env:TONSUU_GT_ALL="1"; cargo test ...  # 記録から再生
 
 use serde::{Deserialize, Serialize};
 use std::env;
@@ -69,7 +73,11 @@ struct HeightRunResult {
 }
 
 fn default_config() -> AnalyzerConfig {
-    AnalyzerConfig::default().with_model(Some("gemini-3-flash-preview".to_string()))
+    let config = AnalyzerConfig::default().with_model(Some("gemini-3-flash-preview".to_string()));
+    match env::var("TONSUU_GT_BACKEND") {
+        Ok(backend) => config.with_backend(&backend),
+        Err(_) => config,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
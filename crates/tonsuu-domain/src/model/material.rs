@@ -3,8 +3,6 @@
 use serde::{Deserialize, Serialize};
 
 /// Material properties
-/// Note: Prepared for material-based weight calculation. Currently unused.
-#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MaterialSpec {
     /// Display name
@@ -20,3 +20,31 @@ pub struct TruckSpec {
     /// Heaped volume in m³
     pub heap_volume: f64,
 }
+
+impl TruckSpec {
+    /// Floor area of the bed in m²
+    pub fn bed_area(&self) -> f64 {
+        self.bed_length * self.bed_width
+    }
+
+    /// This spec with another bed (e.g. a registered vehicle's own).
+    ///
+    /// Level and heaped volumes are scaled by the ratio of the bed box volumes.
+    pub fn with_bed(&self, name: &str, length: f64, width: f64, height: f64) -> TruckSpec {
+        let box_volume = self.bed_area() * self.bed_height;
+        let ratio = if box_volume > 0.0 {
+            length * width * height / box_volume
+        } else {
+            1.0
+        };
+        TruckSpec {
+            name: name.to_string(),
+            max_capacity: self.max_capacity,
+            bed_length: length,
+            bed_width: width,
+            bed_height: height,
+            level_volume: self.level_volume * ratio,
+            heap_volume: self.heap_volume * ratio,
+        }
+    }
+}
@@ -1,8 +1,10 @@
 //! Domain services
 
 pub mod overload_checker;
+pub mod plausibility;
 pub mod weight_calculator;
 
 pub use overload_checker::{
     check_overloads, generate_overload_report, OverloadCheckResult,
 };
+pub use plausibility::{check_plausibility, PlausibilityLimits};
@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use tonsuu_types::LicensePlate;
+
 use crate::model::{VehicleMaster, WeighingSlip};
 
 /// Result of overload check for a single slip
@@ -12,6 +14,9 @@ pub struct OverloadCheckResult {
     pub is_overloaded: bool,
     pub excess_tons: Option<f64>,
     pub load_ratio_percent: Option<f64>,
+    /// Master vehicles tied for the best plate match (then no vehicle is chosen)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ambiguous_candidates: Vec<VehicleMaster>,
 }
 
 pub fn check_overloads(
@@ -21,7 +26,8 @@ pub fn check_overloads(
     slips
         .iter()
         .map(|slip| {
-            let vehicle = find_vehicle_by_plate(&slip.vehicle_number, vehicle_master);
+            let (vehicle, ambiguous_candidates) =
+                find_vehicle_by_plate(&slip.vehicle_number, vehicle_master);
             let (is_overloaded, excess_tons, load_ratio_percent) = match &vehicle {
                 Some(v) => {
                     let excess = slip.weight_tons - v.max_capacity_tons;
@@ -36,48 +42,59 @@ pub fn check_overloads(
                 is_overloaded,
                 excess_tons,
                 load_ratio_percent,
+                ambiguous_candidates,
             }
         })
         .collect()
 }
 
-fn find_vehicle_by_plate(plate: &str, vehicles: &[VehicleMaster]) -> Option<VehicleMaster> {
-    let normalized_plate = normalize_plate(plate);
-    for vehicle in vehicles {
-        if normalize_plate(&vehicle.vehicle_number) == normalized_plate {
-            return Some(vehicle.clone());
-        }
-    }
-    let plate_digits: String = normalized_plate.chars().filter(|c| c.is_ascii_digit()).collect();
-    if plate_digits.len() >= 4 {
-        let plate_last4 = &plate_digits[plate_digits.len() - 4..];
-        for vehicle in vehicles {
-            let v_normalized = normalize_plate(&vehicle.vehicle_number);
-            let v_digits: String = v_normalized.chars().filter(|c| c.is_ascii_digit()).collect();
-            if v_digits.len() >= 4 {
-                let v_last4 = &v_digits[v_digits.len() - 4..];
-                if plate_last4 == v_last4 {
-                    return Some(vehicle.clone());
-                }
-            }
-        }
+/// Best-scoring vehicle for the slip's plate.
+///
+/// A vehicle whose plate matches in every part wins. Otherwise the single
+/// best-scoring vehicle is chosen; when several tie for the best score (e.g.
+/// only the last 4 digits could be compared), none is chosen and the tied
+/// vehicles are returned as candidates.
+fn find_vehicle_by_plate(
+    plate: &str,
+    vehicles: &[VehicleMaster],
+) -> (Option<VehicleMaster>, Vec<VehicleMaster>) {
+    let Some(plate) = LicensePlate::parse(plate) else {
+        return (None, Vec::new());
+    };
+    let scored: Vec<(&VehicleMaster, f64)> = vehicles
+        .iter()
+        .filter_map(|vehicle| {
+            let candidate = LicensePlate::parse(&vehicle.vehicle_number)?;
+            let score = plate.match_score(&candidate);
+            (score > 0.0).then_some((vehicle, score))
+        })
+        .collect();
+
+    if let Some((vehicle, _)) = scored.iter().find(|(_, s)| *s >= 1.0) {
+        return (Some((*vehicle).clone()), Vec::new());
     }
-    None
-}
 
-fn normalize_plate(plate: &str) -> String {
-    plate
-        .replace(' ', "")
-        .replace('\u{3000}', "")
-        .replace('-', "")
-        .replace('ー', "")
-        .to_lowercase()
+    let best_score = scored.iter().map(|(_, s)| *s).fold(0.0, f64::max);
+    let mut best: Vec<VehicleMaster> = scored
+        .into_iter()
+        .filter(|(_, s)| *s >= best_score)
+        .map(|(vehicle, _)| vehicle.clone())
+        .collect();
+    if best.len() == 1 {
+        (best.pop(), Vec::new())
+    } else {
+        (None, best)
+    }
 }
 
 pub fn generate_overload_report(results: &[OverloadCheckResult]) -> String {
     let total = results.len();
     let overloaded_count = results.iter().filter(|r| r.is_overloaded).count();
     let unmatched_count = results.iter().filter(|r| r.vehicle.is_none()).count();
+    let ambiguous_count = results
+        .iter()
+        .filter(|r| !r.ambiguous_candidates.is_empty())
+        .count();
     let matched_count = total - unmatched_count;
 
     let mut report = String::new();
@@ -89,6 +106,9 @@ pub fn generate_overload_report(results: &[OverloadCheckResult]) -> String {
     report.push_str(&format!("  総伝票数 / Total slips:         {}\n", total));
     report.push_str(&format!("  車両照合成功 / Matched:         {}\n", matched_count));
     report.push_str(&format!("  車両未登録 / Unmatched:         {}\n", unmatched_count));
+    if ambiguous_count > 0 {
+        report.push_str(&format!("  (うち候補複数 / Ambiguous:      {})\n", ambiguous_count));
+    }
     report.push_str(&format!("  過積載件数 / Overloaded:        {}\n", overloaded_count));
     if matched_count > 0 {
         let overload_rate = (overloaded_count as f64 / matched_count as f64) * 100.0;
@@ -148,6 +168,14 @@ pub fn generate_overload_report(results: &[OverloadCheckResult]) -> String {
                 truncate_str(&result.slip.vehicle_number, 19),
                 result.slip.weight_tons
             ));
+            if !result.ambiguous_candidates.is_empty() {
+                let candidates: Vec<&str> = result
+                    .ambiguous_candidates
+                    .iter()
+                    .map(|v| v.vehicle_number.as_str())
+                    .collect();
+                report.push_str(&format!("  候補 / Candidates: {}\n", candidates.join(", ")));
+            }
         }
         report.push('\n');
     }
@@ -276,6 +304,66 @@ mod tests {
         assert!(results[0].vehicle.is_some());
     }
 
+    #[test]
+    fn test_plate_match_prefers_best_score() {
+        let slips = vec![WeighingSlip {
+            slip_number: "005".to_string(),
+            vehicle_number: "熊本　１００　あ　１２－３４".to_string(),
+            weight_tons: 8.5,
+            date: None,
+            material_type: None,
+            cumulative_tons: None,
+            delivery_count: None,
+            transport_company: None,
+            site_name: None,
+            max_capacity: None,
+            is_overloaded: false,
+        }];
+        let master = |number: &str, capacity: f64| VehicleMaster {
+            vehicle_number: number.to_string(),
+            max_capacity_tons: capacity,
+            transport_company: "".to_string(),
+            truck_type: None,
+        };
+        // Same serial but a different region must not match
+        let vehicles = vec![master("福岡 100 あ 1234", 4.0), master("熊本 100 あ 1234", 10.0)];
+        let results = check_overloads(&slips, &vehicles);
+        assert_eq!(results[0].vehicle.as_ref().unwrap().max_capacity_tons, 10.0);
+
+        let vehicles = vec![master("1234", 10.0)];
+        assert!(check_overloads(&slips, &vehicles)[0].vehicle.is_some());
+    }
+
+    #[test]
+    fn test_tied_plate_match_is_ambiguous() {
+        let slips = vec![WeighingSlip {
+            slip_number: "006".to_string(),
+            vehicle_number: "1234".to_string(),
+            weight_tons: 8.5,
+            date: None,
+            material_type: None,
+            cumulative_tons: None,
+            delivery_count: None,
+            transport_company: None,
+            site_name: None,
+            max_capacity: None,
+            is_overloaded: false,
+        }];
+        let master = |number: &str, capacity: f64| VehicleMaster {
+            vehicle_number: number.to_string(),
+            max_capacity_tons: capacity,
+            transport_company: "".to_string(),
+            truck_type: None,
+        };
+        // Only the serial can be compared, and it matches both
+        let vehicles = vec![master("熊本 100 あ 1234", 10.0), master("福岡 100 い 1234", 4.0)];
+        let results = check_overloads(&slips, &vehicles);
+        assert!(results[0].vehicle.is_none());
+        assert!(!results[0].is_overloaded);
+        assert_eq!(results[0].ambiguous_candidates.len(), 2);
+        assert!(generate_overload_report(&results).contains("Candidates"));
+    }
+
     #[test]
     fn test_generate_report() {
         let slips = vec![
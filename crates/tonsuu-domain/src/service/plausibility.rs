@@ -0,0 +1,204 @@
+//! Physical plausibility checks on AI-estimated parameters
+//!
+//! The AI can return values no dump truck can hold: a load far taller than the
+//! bed walls, more volume than a heaped bed, or a weight per m³ that does not
+//! match the declared material. Ratios outside 0..=1 and heights above the
+//! bed wall plus a heap allowance are clamped and the volume is recomputed;
+//! volume and density problems that remain are only flagged, since they
+//! depend on several parameters at once.
+
+use tonsuu_types::{EstimationResult, EstimationWarning};
+
+use crate::model::{MaterialSpec, TruckSpec};
+
+/// Tolerances used by `check_plausibility`
+#[derive(Debug, Clone, Copy)]
+pub struct PlausibilityLimits {
+    /// How far a load may rise above the bed wall (m)
+    pub heap_allowance_m: f64,
+    /// Allowed excess over the heaped volume (fraction)
+    pub volume_tolerance: f64,
+    /// Allowed deviation of tonnage/volume from the material's bulk density (fraction)
+    pub density_tolerance: f64,
+}
+
+impl Default for PlausibilityLimits {
+    fn default() -> Self {
+        Self {
+            heap_allowance_m: 0.5,
+            volume_tolerance: 0.15,
+            density_tolerance: 0.35,
+        }
+    }
+}
+
+/// Clamp a ratio into `0..=1`, recording a warning if it was outside
+fn clamp_ratio(value: &mut Option<f64>, field: &str, warnings: &mut Vec<EstimationWarning>) -> bool {
+    let Some(v) = *value else {
+        return false;
+    };
+    let clamped = v.clamp(0.0, 1.0);
+    if clamped == v {
+        return false;
+    }
+    warnings.push(EstimationWarning {
+        field: field.to_string(),
+        message: format!("{} = {:.2} は 0〜1 の範囲外です ({:.2} に補正)", field, v, clamped),
+        clamped_from: Some(v),
+    });
+    *value = Some(clamped);
+    true
+}
+
+/// Check an estimate against the truck's bed dimensions and the material density.
+///
+/// Findings are appended to `result.warnings`. If a parameter was clamped,
+/// `recompute` is called to update volume and tonnage before the volume and
+/// density checks run.
+pub fn check_plausibility<F>(
+    result: &mut EstimationResult,
+    truck: &TruckSpec,
+    material: Option<&MaterialSpec>,
+    limits: &PlausibilityLimits,
+    recompute: F,
+) where
+    F: FnOnce(&mut EstimationResult),
+{
+    if !result.is_target_detected && result.estimated_tonnage <= 0.0 {
+        return;
+    }
+
+    let mut warnings = Vec::new();
+    let mut clamped = false;
+
+    clamped |= clamp_ratio(&mut result.fill_ratio_l, "fillRatioL", &mut warnings);
+    clamped |= clamp_ratio(&mut result.fill_ratio_w, "fillRatioW", &mut warnings);
+    clamped |= clamp_ratio(&mut result.fill_ratio_z, "fillRatioZ", &mut warnings);
+    clamped |= clamp_ratio(&mut result.packing_density, "packingDensity", &mut warnings);
+
+    let max_height = truck.bed_height + limits.heap_allowance_m;
+    if let Some(height) = result.height.filter(|h| *h > max_height) {
+        warnings.push(EstimationWarning {
+            field: "height".to_string(),
+            message: format!(
+                "高さ {:.2}m が荷台高 {:.2}m + 盛り上がり {:.2}m を超えています ({:.2}m に補正)",
+                height, truck.bed_height, limits.heap_allowance_m, max_height
+            ),
+            clamped_from: Some(height),
+        });
+        result.height = Some(max_height);
+        clamped = true;
+    }
+
+    if clamped {
+        recompute(result);
+    }
+
+    let max_volume = truck.heap_volume * (1.0 + limits.volume_tolerance);
+    if result.estimated_volume_m3 > max_volume {
+        warnings.push(EstimationWarning {
+            field: "estimatedVolumeM3".to_string(),
+            message: format!(
+                "体積 {:.2}m³ が{}の山盛り容量 {:.2}m³ を{:.0}%以上超えています",
+                result.estimated_volume_m3,
+                truck.name,
+                truck.heap_volume,
+                limits.volume_tolerance * 100.0
+            ),
+            clamped_from: None,
+        });
+    }
+
+    if let Some(spec) = material {
+        let expected = spec.density * (1.0 - spec.void_ratio);
+        if result.estimated_volume_m3 > 0.0 && expected > 0.0 {
+            let implied = result.estimated_tonnage / result.estimated_volume_m3;
+            if (implied / expected - 1.0).abs() > limits.density_tolerance {
+                warnings.push(EstimationWarning {
+                    field: "estimatedTonnage".to_string(),
+                    message: format!(
+                        "重量/体積 {:.2}t/m³ が{}のかさ密度 {:.2}t/m³ と合いません",
+                        implied, spec.name, expected
+                    ),
+                    clamped_from: None,
+                });
+            }
+        }
+    }
+
+    result.warnings.extend(warnings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truck_4t() -> TruckSpec {
+        TruckSpec {
+            name: "4tダンプ".to_string(),
+            max_capacity: 4.0,
+            bed_length: 3.4,
+            bed_width: 2.06,
+            bed_height: 0.34,
+            level_volume: 2.0,
+            heap_volume: 2.4,
+        }
+    }
+
+    fn as_debris() -> MaterialSpec {
+        MaterialSpec {
+            name: "As殻".to_string(),
+            density: 2.5,
+            void_ratio: 0.30,
+        }
+    }
+
+    fn estimate(height: f64, volume: f64, tonnage: f64) -> EstimationResult {
+        EstimationResult {
+            is_target_detected: true,
+            height: Some(height),
+            fill_ratio_l: Some(0.8),
+            fill_ratio_w: Some(0.7),
+            estimated_volume_m3: volume,
+            estimated_tonnage: tonnage,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plausible_estimate_has_no_warnings() {
+        let mut result = estimate(0.4, 2.0, 3.5);
+        let limits = PlausibilityLimits::default();
+        check_plausibility(&mut result, &truck_4t(), Some(&as_debris()), &limits, |_| {
+            panic!("nothing to recompute")
+        });
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_clamps_height_and_ratios() {
+        // 1.5m tall load with a 4.0 m³ volume; recomputed from the clamped height
+        let mut result = estimate(1.5, 4.0, 7.0);
+        result.fill_ratio_l = Some(1.3);
+        check_plausibility(&mut result, &truck_4t(), None, &PlausibilityLimits::default(), |r| {
+            r.estimated_volume_m3 = 2.2;
+            r.estimated_tonnage = 3.85;
+        });
+        assert!((result.height.unwrap() - 0.84).abs() < 1e-9);
+        assert_eq!(result.fill_ratio_l, Some(1.0));
+        let fields: Vec<&str> = result.warnings.iter().map(|w| w.field.as_str()).collect();
+        assert_eq!(fields, vec!["fillRatioL", "height"]);
+        assert_eq!(result.warnings[1].clamped_from, Some(1.5));
+    }
+
+    #[test]
+    fn test_flags_volume_and_density() {
+        // 3.5 m³ exceeds 2.4 m³ * 1.15; 2.0 t / 3.5 m³ is far below As殻 bulk density
+        let mut result = estimate(0.4, 3.5, 2.0);
+        let limits = PlausibilityLimits::default();
+        check_plausibility(&mut result, &truck_4t(), Some(&as_debris()), &limits, |_| {});
+        let fields: Vec<&str> = result.warnings.iter().map(|w| w.field.as_str()).collect();
+        assert_eq!(fields, vec!["estimatedVolumeM3", "estimatedTonnage"]);
+        assert!(result.warnings.iter().all(|w| w.clamped_from.is_none()));
+    }
+}
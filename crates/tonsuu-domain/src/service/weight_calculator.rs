@@ -12,6 +12,34 @@ pub fn calculate_weight_explicit(volume_m3: f64, density: f64, void_ratio: f64)
     volume_m3 * density * (1.0 - void_ratio)
 }
 
+/// Bulk density (t/m³) of a mixed load: the share-weighted mean of
+/// `density x (1 - void_ratio)`. Shares need not add up to 1.
+pub fn mixed_bulk_density(parts: &[(&MaterialSpec, f64)]) -> Option<f64> {
+    let total_share: f64 = parts.iter().map(|(_, share)| share.max(0.0)).sum();
+    if total_share <= 0.0 {
+        return None;
+    }
+    let weighted: f64 = parts
+        .iter()
+        .map(|(spec, share)| calculate_weight_from_spec(share.max(0.0), spec))
+        .sum();
+    Some(weighted / total_share)
+}
+
+/// Split a load's weight between its materials in proportion to
+/// share x bulk density
+pub fn split_weight_by_material(weight: f64, parts: &[(&MaterialSpec, f64)]) -> Vec<f64> {
+    let masses: Vec<f64> = parts
+        .iter()
+        .map(|(spec, share)| calculate_weight_from_spec(share.max(0.0), spec))
+        .collect();
+    let total: f64 = masses.iter().sum();
+    masses
+        .iter()
+        .map(|mass| if total > 0.0 { weight * mass / total } else { 0.0 })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,6 +106,24 @@ mod tests {
         assert!((weight - 0.0).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn test_mixed_bulk_density() {
+        // 70% As殻 (1.75 t/m³) + 30% 土砂 (1.71 t/m³)
+        let parts = [(&asphalt_debris_spec(), 70.0), (&soil_spec(), 30.0)];
+        let density = mixed_bulk_density(&parts).unwrap();
+        assert!((density - (0.7 * 1.75 + 0.3 * 1.71)).abs() < 1e-9);
+        assert!(mixed_bulk_density(&[(&soil_spec(), 0.0)]).is_none());
+    }
+
+    #[test]
+    fn test_split_weight_by_material() {
+        let parts = [(&asphalt_debris_spec(), 50.0), (&open_graded_asphalt_spec(), 50.0)];
+        let split = split_weight_by_material(4.0, &parts);
+        assert!((split.iter().sum::<f64>() - 4.0).abs() < 1e-9);
+        // As殻 is denser than 開粒度As殻, so it carries more of the weight
+        assert!((split[0] / split[1] - 1.75 / 1.5275).abs() < 1e-9);
+    }
+
     #[test]
     fn test_explicit_matches_from_spec() {
         let spec_weight = calculate_weight_from_spec(2.0, &soil_spec());
@@ -8,13 +8,19 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 use std::time::Instant;
 #[allow(deprecated)]
-use tonsuu_vision::{analyze_image, calculate_volume_and_tonnage, AnalyzerConfig};
+use tonsuu_vision::{
+    analyze_image, calculate_volume_and_tonnage, parse_estimation_with_repair, run_ensemble,
+    send_prompt, with_material_choices_note, with_truck_classes_note, Aggregator, AnalyzerConfig,
+    ViewImage,
+};
+use tonsuu_app::app::{
+    apply_material_mix, apply_plausibility_checks, match_vehicle, MatchQuality, VehicleMatch,
+};
 use tonsuu_app::config::Config;
 use tonsuu_vision::ai::prompts::{build_staged_analysis_prompt, GradedReferenceItem};
-use tonsuu_app::constants::get_truck_spec;
-use tonsuu_store::Store;
-use tonsuu_types::{EstimationResult, TruckClass};
-use cli_ai_analyzer::{analyze, AnalyzeOptions, Backend};
+use tonsuu_app::constants::{get_truck_spec, material_ids};
+use tonsuu_store::{Store, VehicleStore};
+use tonsuu_types::{truck_classes, EstimationResult, ImageView, TruckClass};
 
 /// Status message from analysis thread
 #[derive(Debug, Clone)]
@@ -45,6 +51,8 @@ pub struct AnalyzePanel {
     selected_image: Option<PathBuf>,
     /// Analysis result (if available)
     result: Option<EstimationResult>,
+    /// Registered vehicle matched by the result's license plate
+    vehicle_match: Option<VehicleMatch>,
     /// Error message (if any)
     error: Option<String>,
     /// Whether analysis is in progress
@@ -61,6 +69,8 @@ pub struct AnalyzePanel {
     use_staged_analysis: bool,
     /// Optional max capacity input (for staged analysis)
     max_capacity_input: String,
+    /// Material picked by the operator (None = let the AI decide)
+    selected_material: Option<String>,
 }
 
 impl AnalyzePanel {
@@ -69,6 +79,7 @@ impl AnalyzePanel {
         Self {
             selected_image: None,
             result: None,
+            vehicle_match: None,
             error: None,
             is_analyzing: false,
             status_receiver: None,
@@ -77,6 +88,7 @@ impl AnalyzePanel {
             start_time: None,
             use_staged_analysis: true,  // Default to staged analysis
             max_capacity_input: String::new(),
+            selected_material: None,
         }
     }
 
@@ -89,6 +101,7 @@ impl AnalyzePanel {
         }
         self.selected_image = Some(path);
         self.result = None;
+        self.vehicle_match = None;
         self.error = None;
     }
 
@@ -105,9 +118,9 @@ impl AnalyzePanel {
     }
 
     /// Render the analyze panel UI
-    pub fn ui(&mut self, ui: &mut Ui, config: &Config, store: &mut Store) {
+    pub fn ui(&mut self, ui: &mut Ui, config: &Config, store: &mut Store, vehicle_store: &VehicleStore) {
         // Check for status updates from background thread
-        self.poll_status(ui.ctx(), store);
+        self.poll_status(ui.ctx(), store, vehicle_store);
 
         ui.heading("画像解析");
         ui.add_space(10.0);
@@ -134,7 +147,7 @@ impl AnalyzePanel {
     }
 
     /// Poll for status updates from background analysis thread
-    fn poll_status(&mut self, ctx: &egui::Context, store: &mut Store) {
+    fn poll_status(&mut self, ctx: &egui::Context, store: &mut Store, vehicle_store: &VehicleStore) {
         if let Some(ref receiver) = self.status_receiver {
             // Drain all available messages
             loop {
@@ -175,6 +188,11 @@ impl AnalyzePanel {
                                         self.error = Some(format!("履歴の保存に失敗しました: {}", e));
                                     }
                                 }
+                                self.vehicle_match = Some(match_vehicle(
+                                    vehicle_store,
+                                    result.license_plate.as_deref(),
+                                    None,
+                                ));
                                 self.result = Some(result);
                                 self.is_analyzing = false;
                                 self.status_receiver = None;
@@ -225,6 +243,7 @@ impl AnalyzePanel {
                     self.selected_image = Some(path);
                     // Clear previous results when new image is selected
                     self.result = None;
+                    self.vehicle_match = None;
                     self.error = None;
                 }
             }
@@ -279,6 +298,18 @@ impl AnalyzePanel {
             }
         });
 
+        ui.horizontal(|ui| {
+            ui.label("材料:");
+            egui::ComboBox::from_id_salt("material_select")
+                .selected_text(self.selected_material.as_deref().unwrap_or("自動判定"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.selected_material, None, "自動判定");
+                    for id in material_ids() {
+                        ui.selectable_value(&mut self.selected_material, Some(id.to_string()), id);
+                    }
+                });
+        });
+
         ui.add_space(8.0);
 
         ui.horizontal(|ui| {
@@ -354,6 +385,7 @@ impl AnalyzePanel {
         self.is_analyzing = true;
         self.error = None;
         self.result = None;
+        self.vehicle_match = None;
         self.analyzing_path = Some(image_path.clone());
         self.start_time = Some(Instant::now());
         self.current_status = Some("準備中...".to_string());
@@ -367,7 +399,9 @@ impl AnalyzePanel {
         let backend = config.backend.clone();
         let model = config.model.clone();
         let ensemble_count = config.ensemble_count;
+        let aggregator = config.ensemble_aggregator();
         let use_staged = self.use_staged_analysis;
+        let material = self.selected_material.clone();
 
         // Parse max capacity if provided
         let max_capacity: Option<f64> = self.max_capacity_input.trim()
@@ -380,8 +414,11 @@ impl AnalyzePanel {
             // If we have a max capacity, load graded data for that truck class
             if let Some(cap) = max_capacity {
                 let truck_class = TruckClass::from_capacity(cap);
-                if truck_class != TruckClass::Unknown {
-                    store.select_stock_by_grade(truck_class)
+                if !truck_class.is_unknown() {
+                    // The photo's own weighing must not be one of its references
+                    let own_hash: Vec<String> =
+                        Store::hash_image(&image_path).ok().into_iter().collect();
+                    store.select_stock_by_grade(&truck_class, &own_hash)
                         .iter()
                         .map(|g| GradedReferenceItem {
                             grade_name: g.grade.label().to_string(),
@@ -415,9 +452,11 @@ impl AnalyzePanel {
                     max_capacity,
                     graded_references,
                     ensemble_count,
+                    aggregator,
+                    material,
                 );
             } else {
-                run_simple_analysis(sender, image_path, backend, model);
+                run_simple_analysis(sender, image_path, backend, model, material);
             }
         });
     }
@@ -430,6 +469,7 @@ fn run_simple_analysis(
     image_path: PathBuf,
     backend: String,
     model: Option<String>,
+    material: Option<String>,
 ) {
     let _ = sender.send(AnalysisStatus::BuildingPrompt);
 
@@ -444,7 +484,10 @@ fn run_simple_analysis(
     let _ = sender.send(AnalysisStatus::ParsingResponse);
 
     match result {
-        Ok(estimation) => {
+        Ok(mut estimation) => {
+            apply_selected_material(&mut estimation, material.as_deref());
+            apply_material_mix(&mut estimation, None);
+            apply_plausibility_checks(&mut estimation, "");
             let _ = sender.send(AnalysisStatus::Completed(estimation));
         }
         Err(e) => {
@@ -454,6 +497,7 @@ fn run_simple_analysis(
 }
 
 /// Run staged analysis with graded reference data
+#[allow(clippy::too_many_arguments)]
 fn run_staged_analysis(
     sender: Sender<AnalysisStatus>,
     image_path: PathBuf,
@@ -462,11 +506,12 @@ fn run_staged_analysis(
     max_capacity: Option<f64>,
     graded_references: Vec<GradedReferenceItem>,
     ensemble_count: u32,
+    aggregator: Aggregator,
+    material: Option<String>,
 ) {
     let _ = sender.send(AnalysisStatus::BuildingPrompt);
 
     let target_count = ensemble_count.max(1) as usize;
-    let mut results: Vec<EstimationResult> = Vec::new();
 
     // Notify if we have graded data
     if !graded_references.is_empty() {
@@ -479,171 +524,59 @@ fn run_staged_analysis(
         }
     }
 
-    // Configure backend
-    let ai_backend = match backend.to_lowercase().as_str() {
-        "claude" => Backend::Claude,
-        "codex" => Backend::Codex,
-        _ => Backend::Gemini,
-    };
+    let analyzer_config = AnalyzerConfig::default()
+        .with_backend(&backend)
+        .with_model(model);
+    let images = [ViewImage::new(image_path, ImageView::Unspecified)];
+
+    // Build prompt with graded data, the catalog's material names and the truck classes
+    let materials: Vec<String> = material_ids().iter().map(|id| id.to_string()).collect();
+    let prompt = with_truck_classes_note(
+        &with_material_choices_note(
+            &build_staged_analysis_prompt(max_capacity, &graded_references),
+            &materials,
+        ),
+        truck_classes(),
+    );
+
+    let _ = sender.send(AnalysisStatus::CallingAI { backend: backend.clone() });
 
-    for iteration in 0..target_count {
+    // Samples run concurrently through the backend limiter; invalid JSON gets
+    // one repair re-ask and a sample that still fails is dropped
+    let merged = run_ensemble(target_count, aggregator, |sample| {
         let _ = sender.send(AnalysisStatus::StagedInference {
-            current: iteration + 1,
+            current: sample + 1,
             total: target_count,
         });
-
-        // Build prompt with graded data
-        let prompt = build_staged_analysis_prompt(max_capacity, &graded_references);
-
-        // Configure AI options
-        let mut ai_options = if let Some(ref m) = model {
-            AnalyzeOptions::with_model(m)
-        } else {
-            AnalyzeOptions::default()
-        };
-        ai_options = ai_options.with_backend(ai_backend).json();
-
-        let _ = sender.send(AnalysisStatus::CallingAI { backend: backend.clone() });
-
-        // Call AI
-        match analyze(&prompt, &[image_path.clone()], ai_options) {
-            Ok(response) => {
-                let _ = sender.send(AnalysisStatus::ParsingResponse);
-                match parse_ai_response(&response) {
-                    Ok(result) => {
-                        // After first iteration with no max_capacity, we could
-                        // potentially detect truck class and load graded data
-                        // But since we don't have Store access here, we skip this
-                        // The initial graded_references from main thread is used
-                        results.push(result);
-                    }
-                    Err(e) => {
-                        eprintln!("Inference {} parse error: {}", iteration + 1, e);
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Inference {} error: {}", iteration + 1, e);
-            }
+        let ai_backend = analyzer_config.multi_view_backend(&images, &[], sample);
+        let response = send_prompt(&ai_backend, &prompt)?;
+        let _ = sender.send(AnalysisStatus::ParsingResponse);
+        parse_estimation_with_repair(&response, |repair| send_prompt(&ai_backend, repair))
+    });
+    let mut merged = match merged {
+        Ok(merged) => merged,
+        Err(e) => {
+            let _ = sender.send(AnalysisStatus::Failed(e.to_string()));
+            return;
         }
-    }
-
-    if results.is_empty() {
-        let _ = sender.send(AnalysisStatus::Failed(
-            "All inference attempts failed".to_string()
-        ));
-        return;
-    }
+    };
 
-    // Merge results
     let _ = sender.send(AnalysisStatus::MergingResults);
-    let merged = merge_estimation_results(&results);
+    let fallback_truck_type = max_capacity
+        .map(|cap| TruckClass::from_capacity(cap).to_string())
+        .unwrap_or_default();
+    apply_selected_material(&mut merged, material.as_deref());
+    apply_material_mix(&mut merged, None);
+    apply_plausibility_checks(&mut merged, &fallback_truck_type);
     let _ = sender.send(AnalysisStatus::Completed(merged));
 }
 
-/// Parse AI response into EstimationResult
-fn parse_ai_response(response: &str) -> Result<EstimationResult, String> {
-    let json_str = extract_json_from_response(response);
-    let mut result: EstimationResult = serde_json::from_str(&json_str).map_err(|e| {
-        let truncated: String = response.chars().take(500).collect();
-        format!("Failed to parse AI response: {}. Response: {}", e, truncated)
-    })?;
-
-    // Calculate volume and tonnage if not provided by AI
-    if result.estimated_volume_m3 == 0.0 || result.estimated_tonnage == 0.0 {
-        calculate_volume_and_tonnage(&mut result);
+/// Use the material picked by the operator instead of the AI's guess
+fn apply_selected_material(estimation: &mut EstimationResult, material: Option<&str>) {
+    if let Some(material) = material {
+        estimation.material_type = material.to_string();
+        calculate_volume_and_tonnage(estimation);
     }
-
-    Ok(result)
-}
-
-/// Extract JSON from response (handles markdown code blocks)
-fn extract_json_from_response(response: &str) -> String {
-    let response = response.trim();
-
-    // Check for markdown code block
-    if response.starts_with("```json") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(7) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    // Check for generic code block
-    if response.starts_with("```") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(3) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    // Try to find JSON object directly
-    if let Some(start) = response.find('{') {
-        if let Some(end) = response.rfind('}') {
-            if start < end {
-                return response[start..=end].to_string();
-            }
-        }
-    }
-
-    response.to_string()
-}
-
-/// Merge multiple estimation results (ensemble voting)
-fn merge_estimation_results(results: &[EstimationResult]) -> EstimationResult {
-    use std::collections::HashMap;
-
-    if results.is_empty() {
-        return EstimationResult::default();
-    }
-
-    if results.len() == 1 {
-        return results[0].clone();
-    }
-
-    // Average numeric values
-    let avg_volume: f64 = results.iter().map(|r| r.estimated_volume_m3).sum::<f64>()
-        / results.len() as f64;
-    let avg_tonnage: f64 =
-        results.iter().map(|r| r.estimated_tonnage).sum::<f64>() / results.len() as f64;
-    let avg_confidence: f64 =
-        results.iter().map(|r| r.confidence_score).sum::<f64>() / results.len() as f64;
-
-    // Mode for categorical values
-    fn mode_string(values: Vec<String>) -> String {
-        let mut counts: HashMap<String, usize> = HashMap::new();
-        for v in values.iter() {
-            *counts.entry(v.clone()).or_insert(0) += 1;
-        }
-        counts.into_iter()
-            .max_by_key(|(_, count)| *count)
-            .map(|(value, _)| value)
-            .unwrap_or_default()
-    }
-
-    let truck_type = mode_string(results.iter().map(|r| r.truck_type.clone()).collect());
-    let material_type = mode_string(results.iter().map(|r| r.material_type.clone()).collect());
-
-    // Use first result as base
-    let mut merged = results[0].clone();
-    merged.truck_type = truck_type;
-    merged.material_type = material_type;
-    merged.estimated_volume_m3 = (avg_volume * 100.0).round() / 100.0;
-    merged.estimated_tonnage = (avg_tonnage * 100.0).round() / 100.0;
-    merged.confidence_score = avg_confidence;
-    merged.ensemble_count = Some(results.len() as u32);
-    merged.reasoning = format!(
-        "【統合推論】有効サンプル:{}/{}。{}",
-        results.len(),
-        results.len(),
-        merged.reasoning
-    );
-
-    merged
 }
 
 impl AnalyzePanel {
@@ -682,6 +615,10 @@ impl AnalyzePanel {
                         ui.label(RichText::new("ナンバープレート:").strong());
                         ui.label(plate);
                         ui.end_row();
+
+                        if let Some(ref vehicle_match) = self.vehicle_match {
+                            Self::render_vehicle_match(ui, vehicle_match);
+                        }
                     }
 
                     // Material type
@@ -728,28 +665,58 @@ impl AnalyzePanel {
                     // Ensemble count (if available)
                     if let Some(count) = result.ensemble_count {
                         ui.label(RichText::new("アンサンブル数:").strong());
-                        ui.label(format!("{}", count));
+                        let rejected = result.ensemble_samples.iter().filter(|s| s.rejected).count();
+                        if rejected > 0 {
+                            ui.label(format!("{} (外れ値除外 {})", count, rejected));
+                        } else {
+                            ui.label(format!("{}", count));
+                        }
+                        ui.end_row();
+                    }
+
+                    // Spread of the accepted samples
+                    if let Some(std_dev) = result.tonnage_std_dev {
+                        ui.label(RichText::new("ばらつき:").strong());
+                        ui.label(format!("±{:.2}t (標準偏差)", std_dev));
                         ui.end_row();
                     }
                 });
 
+            // Plausibility warnings
+            if !result.warnings.is_empty() {
+                ui.add_space(10.0);
+                ui.label(RichText::new("警告:").strong().color(Color32::YELLOW));
+                for warning in &result.warnings {
+                    ui.label(
+                        RichText::new(format!("・{}", warning.message)).color(Color32::YELLOW),
+                    );
+                }
+            }
+
             // Material breakdown (if available)
             if !result.material_breakdown.is_empty() {
                 ui.add_space(10.0);
                 ui.label(RichText::new("材料内訳:").strong());
                 egui::Grid::new("material_breakdown_grid")
-                    .num_columns(3)
+                    .num_columns(4)
                     .spacing([15.0, 4.0])
                     .show(ui, |ui| {
                         ui.label(RichText::new("材料").underline());
                         ui.label(RichText::new("割合").underline());
                         ui.label(RichText::new("密度").underline());
+                        ui.label(RichText::new("重量").underline());
                         ui.end_row();
 
                         for breakdown in &result.material_breakdown {
                             ui.label(&breakdown.material);
                             ui.label(format!("{:.1}%", breakdown.percentage));
                             ui.label(format!("{:.2} t/m\u{00B3}", breakdown.density));
+                            ui.label(
+                                breakdown
+                                    .tonnage
+                                    .map(|t| format!("{:.2} t", t))
+                                    .unwrap_or_else(|| "-".to_string()),
+                            );
                             ui.end_row();
                         }
                     });
@@ -778,6 +745,45 @@ impl AnalyzePanel {
         }
     }
 
+    /// Render the registered-vehicle row for the matched plate
+    fn render_vehicle_match(ui: &mut Ui, vehicle_match: &VehicleMatch) {
+        ui.label(RichText::new("登録車両:").strong());
+        let text = match (&vehicle_match.vehicle, vehicle_match.quality) {
+            (Some(vehicle), quality) => {
+                format!("{} ({}t) - {}", vehicle.name, vehicle.max_capacity, quality.label())
+            }
+            (None, MatchQuality::Ambiguous) => format!(
+                "候補{}台: {}",
+                vehicle_match.candidates.len(),
+                vehicle_match
+                    .candidates
+                    .iter()
+                    .map(|v| v.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            (None, quality) => quality.label().to_string(),
+        };
+        let color = match vehicle_match.quality {
+            MatchQuality::Exact | MatchQuality::Normalized | MatchQuality::Partial => {
+                Color32::LIGHT_GREEN
+            }
+            MatchQuality::Last4 | MatchQuality::Ambiguous => Color32::YELLOW,
+            MatchQuality::None => Color32::GRAY,
+        };
+        ui.label(RichText::new(text).color(color));
+        ui.end_row();
+
+        if vehicle_match.quality.needs_confirmation() {
+            ui.label("");
+            ui.label(
+                RichText::new("⚠ ナンバーが完全一致していません。車両を確認してください")
+                    .color(Color32::YELLOW),
+            );
+            ui.end_row();
+        }
+    }
+
     /// Render error messages
     fn render_error(&self, ui: &mut Ui) {
         if let Some(ref error) = self.error {
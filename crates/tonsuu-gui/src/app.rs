@@ -1,8 +1,8 @@
 //! Main application structure with tab navigation
 
 use eframe::egui;
-use tonsuu_app::config::Config;
-use tonsuu_app::repository::{open_history_store, open_vehicle_store};
+use tonsuu_app::config::{install_configured_truck_classes, Config};
+use tonsuu_app::repository::{open_history_store, open_vehicle_store, prepare_storage};
 use tonsuu_store::{Store, VehicleStore};
 
 use crate::analyze_panel::AnalyzePanel;
@@ -95,6 +95,24 @@ impl TonsuuApp {
 
         // Load configuration
         let config = Config::load().unwrap_or_default();
+        // Truck classes from trucks.toml (built-in classes if it cannot be read)
+        match install_configured_truck_classes() {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("車両クラスは既に使用中のため、trucks.toml の設定は反映されません")
+            }
+            Err(e) => eprintln!("車両クラス設定の読み込みに失敗しました: {}", e),
+        }
+
+        // First run on the SQLite backend: import the JSON stores once
+        match prepare_storage(&config) {
+            Ok(Some(report)) => eprintln!(
+                "JSONストアの内容をSQLiteへ取り込みました (履歴: {}件, 車両: {}件)",
+                report.history_count, report.vehicle_count
+            ),
+            Ok(None) => {}
+            Err(e) => eprintln!("JSONストアのSQLiteへの取り込みに失敗しました: {}", e),
+        }
 
         // Open the store via app repository
         let store = open_history_store(&config).unwrap_or_else(|_| {
@@ -109,6 +127,15 @@ impl TonsuuApp {
             VehicleStore::open(fallback_dir).expect("Failed to create fallback vehicle store")
         });
 
+        for recovery in [store.recovery(), vehicle_store.recovery()].into_iter().flatten() {
+            eprintln!(
+                "警告: {} が破損しています。{}件を復旧しました (元ファイル: {})",
+                recovery.path.display(),
+                recovery.recovered,
+                recovery.backup.display()
+            );
+        }
+
         let settings_panel = SettingsPanel::new(&config);
 
         Self {
@@ -171,7 +198,7 @@ impl eframe::App for TonsuuApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             match self.current_tab {
                 Tab::Analyze => {
-                    self.analyze_panel.ui(ui, &self.config, &mut self.store);
+                    self.analyze_panel.ui(ui, &self.config, &mut self.store, &self.vehicle_store);
                 }
                 Tab::Vehicle => {
                     self.vehicle_panel.ui(ui, &mut self.vehicle_store, &self.config);
@@ -63,7 +63,7 @@ impl HistoryPanel {
         &mut self,
         ctx: &egui::Context,
         image_path: &str,
-        thumbnail_base64: Option<&str>,
+        thumbnail_base64: impl FnOnce() -> Option<String>,
     ) -> Option<&TextureHandle> {
         // Check if already loaded (cache hit)
         if self.preview_path.as_deref() == Some(image_path) {
@@ -75,7 +75,7 @@ impl HistoryPanel {
         self.preview_texture = None;
 
         // Try base64 first (faster than filesystem access)
-        if let Some(base64_data) = thumbnail_base64 {
+        if let Some(base64_data) = thumbnail_base64().as_deref() {
             use base64::{engine::general_purpose::STANDARD, Engine};
 
             // Remove data URL prefix if present
@@ -435,7 +435,6 @@ impl HistoryPanel {
             if let Some(entry) = store.get_by_hash(selected_hash) {
                 let filename = truncate_filename(&entry.image_path, 50);
                 let image_path = entry.image_path.clone();
-                let thumbnail_base64 = entry.thumbnail_base64.clone();
                 let estimation = entry.estimation.clone();
 
                 ui.add_space(8.0);
@@ -482,7 +481,9 @@ impl HistoryPanel {
                         ui.add_space(4.0);
 
                         // Load and display image preview
-                        self.load_preview_texture(ctx, &image_path, thumbnail_base64.as_deref());
+                        self.load_preview_texture(ctx, &image_path, || {
+                            store.thumbnail(selected_hash).ok().flatten()
+                        });
 
                         if let Some(ref texture) = self.preview_texture {
                             let preview_size = Self::calc_preview_size(texture, 240.0, 180.0);
@@ -1,13 +1,15 @@
 //! Vehicle management panel for tonsuu-checker GUI
 
 use eframe::egui::{self, Color32, RichText, Ui};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 use tonsuu_app::config::Config;
+use tonsuu_app::scanner::document_images;
+use tonsuu_infra::vehicle_csv::{import_vehicle_rows, load_vehicle_csv};
 use tonsuu_store::VehicleStore;
-use tonsuu_types::{RegisteredVehicle, TruckClass};
-use cli_ai_analyzer::{analyze, AnalyzeOptions, Backend};
+use tonsuu_types::{BodyType, RegisteredVehicle, TruckClass};
+use tonsuu_vision::{read_shaken, AnalyzerConfig};
 
 /// Scanned vehicle folder information
 #[derive(Debug, Clone)]
@@ -63,6 +65,125 @@ pub struct VehicleProcessResult {
     pub capacity: Option<f64>,
 }
 
+/// Bed dimension input fields (empty = use the truck class spec)
+#[derive(Debug, Clone, Default)]
+struct BedForm {
+    length: String,
+    width: String,
+    height: String,
+    side_wall: String,
+    body_type: Option<BodyType>,
+}
+
+impl BedForm {
+    fn from_vehicle(vehicle: &RegisteredVehicle) -> Self {
+        let text = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_default();
+        Self {
+            length: text(vehicle.bed_length),
+            width: text(vehicle.bed_width),
+            height: text(vehicle.bed_height),
+            side_wall: text(vehicle.side_wall_height),
+            body_type: vehicle.body_type,
+        }
+    }
+
+    /// Set the vehicle's bed fields from the inputs
+    fn apply_to(&self, vehicle: &mut RegisteredVehicle) -> Result<(), String> {
+        let parse = |label: &str, text: &str| -> Result<Option<f64>, String> {
+            let text = text.trim();
+            if text.is_empty() {
+                return Ok(None);
+            }
+            match text.parse::<f64>() {
+                Ok(v) if v > 0.0 => Ok(Some(v)),
+                _ => Err(format!("{}が不正です", label)),
+            }
+        };
+        vehicle.bed_length = parse("荷台長", &self.length)?;
+        vehicle.bed_width = parse("荷台幅", &self.width)?;
+        vehicle.bed_height = parse("荷台高", &self.height)?;
+        vehicle.side_wall_height = parse("側板高", &self.side_wall)?;
+        vehicle.body_type = self.body_type;
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &mut Ui, id_salt: &str) {
+        ui.label("荷台寸法:");
+        ui.horizontal(|ui| {
+            for (value, hint) in [
+                (&mut self.length, "長さ"),
+                (&mut self.width, "幅"),
+                (&mut self.height, "高さ"),
+            ] {
+                ui.add(
+                    egui::TextEdit::singleline(value)
+                        .hint_text(hint)
+                        .desired_width(50.0),
+                );
+            }
+            ui.label("m (空欄はクラス標準)");
+        });
+        ui.end_row();
+
+        ui.label("側板高:");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.side_wall)
+                    .hint_text("深ダンプ")
+                    .desired_width(50.0),
+            );
+            ui.label("m");
+            ui.label("架装:");
+            egui::ComboBox::from_id_salt(id_salt)
+                .selected_text(self.body_type.map(|b| b.label()).unwrap_or("-"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.body_type, None, "-");
+                    for body_type in BodyType::ALL {
+                        ui.selectable_value(&mut self.body_type, Some(body_type), body_type.label());
+                    }
+                });
+        });
+        ui.end_row();
+    }
+}
+
+/// 車検証 expiring within this many days is highlighted in the list
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// 車検証 expiry for the vehicle list, colored when it is close or past
+fn expiry_label(vehicle: &RegisteredVehicle) -> RichText {
+    let today = chrono::Local::now().date_naive();
+    match (vehicle.inspection_expiry, vehicle.days_until_inspection_expiry(today)) {
+        (Some(expiry), Some(days)) if days < 0 => {
+            RichText::new(format!("{} (期限切れ)", expiry)).color(Color32::LIGHT_RED)
+        }
+        (Some(expiry), Some(days)) if days <= EXPIRY_WARNING_DAYS => {
+            RichText::new(format!("{} (残り{}日)", expiry, days)).color(Color32::YELLOW)
+        }
+        (Some(expiry), _) => RichText::new(expiry.to_string()),
+        _ => RichText::new("-"),
+    }
+}
+
+/// Bed dimensions for the vehicle list (e.g. "4.30×2.10×0.40 深ダンプ")
+fn bed_summary(vehicle: &RegisteredVehicle) -> String {
+    if !vehicle.has_bed_dimensions() && vehicle.body_type.is_none() {
+        return "-".to_string();
+    }
+    let dim = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "?".to_string());
+    let mut summary = format!(
+        "{}×{}×{}",
+        dim(vehicle.bed_length),
+        dim(vehicle.bed_width),
+        dim(vehicle.wall_height())
+    );
+    if let Some(body_type) = vehicle.body_type {
+        summary.push(' ');
+        summary.push_str(body_type.label());
+    }
+    summary
+}
+
 /// Panel for managing registered vehicles
 pub struct VehiclePanel {
     /// New vehicle form fields
@@ -71,10 +192,12 @@ pub struct VehiclePanel {
     new_plate: String,
     new_notes: String,
     new_image_path: Option<PathBuf>,
+    new_bed: BedForm,
+    /// Vehicle whose bed dimensions are being edited
+    editing_bed: Option<(String, BedForm)>,
     /// Status message
     status_message: Option<(String, bool)>, // (message, is_error)
     /// Selected vehicle ID for details
-    #[allow(dead_code)]
     selected_id: Option<String>,
     /// Folder scan result
     scan_result: Option<FolderScanResult>,
@@ -102,6 +225,8 @@ impl VehiclePanel {
             new_plate: String::new(),
             new_notes: String::new(),
             new_image_path: None,
+            new_bed: BedForm::default(),
+            editing_bed: None,
             status_message: None,
             selected_id: None,
             scan_result: None,
@@ -449,6 +574,13 @@ impl VehiclePanel {
         let Some(ref scan_result) = self.scan_result else {
             return;
         };
+        let pdf_cache_dir = match config.pdf_page_cache_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                self.process_status = Some(format!("キャッシュフォルダを取得できません: {}", e));
+                return;
+            }
+        };
 
         self.is_processing = true;
         self.process_results.clear();
@@ -468,7 +600,7 @@ impl VehiclePanel {
 
         // Spawn processing thread
         thread::spawn(move || {
-            process_vehicle_folders(folders, backend, model, status_tx, vehicle_tx);
+            process_vehicle_folders(folders, backend, model, pdf_cache_dir, status_tx, vehicle_tx);
         });
     }
 
@@ -546,6 +678,9 @@ impl VehiclePanel {
                 });
                 ui.end_row();
 
+                // Bed dimensions (optional; override the class spec)
+                self.new_bed.ui(ui, "new_body_type");
+
                 // Notes
                 ui.label("メモ:");
                 ui.add(
@@ -602,6 +737,11 @@ impl VehiclePanel {
             vehicle.notes = Some(self.new_notes.trim().to_string());
         }
 
+        if let Err(e) = self.new_bed.apply_to(&mut vehicle) {
+            self.status_message = Some((e, true));
+            return;
+        }
+
         match vehicle_store.add_vehicle(vehicle) {
             Ok(_) => {
                 self.status_message = Some(("車両を登録しました".to_string(), false));
@@ -611,6 +751,7 @@ impl VehiclePanel {
                 self.new_plate.clear();
                 self.new_notes.clear();
                 self.new_image_path = None;
+                self.new_bed = BedForm::default();
             }
             Err(e) => {
                 self.status_message = Some((format!("登録エラー: {}", e), true));
@@ -619,7 +760,18 @@ impl VehiclePanel {
     }
 
     fn render_vehicle_list(&mut self, ui: &mut Ui, vehicle_store: &mut VehicleStore) {
-        ui.label(RichText::new("登録済み車両").strong());
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("登録済み車両").strong());
+            if ui
+                .button("CSV取込...")
+                .on_hover_text("ナンバー, 車両名, 最大積載量, 荷台長, 荷台幅, 荷台高, 側板高, 架装 の列を持つCSV")
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new().add_filter("CSV", &["csv"]).pick_file() {
+                    self.import_csv(&path, vehicle_store);
+                }
+            }
+        });
         ui.add_space(5.0);
 
         let vehicles = vehicle_store.all_vehicles();
@@ -636,14 +788,15 @@ impl VehiclePanel {
         ui.label(format!("{}台登録済み", vehicles.len()));
         ui.add_space(5.0);
 
-        // Collect IDs to delete (to avoid borrow issues)
+        // Collect IDs to delete / edit (to avoid borrow issues)
         let mut to_delete: Option<String> = None;
+        let mut to_edit: Option<(String, BedForm)> = None;
 
         egui::ScrollArea::vertical()
             .max_height(300.0)
             .show(ui, |ui| {
                 egui::Grid::new("vehicle_list")
-                    .num_columns(6)
+                    .num_columns(8)
                     .spacing([10.0, 6.0])
                     .striped(true)
                     .show(ui, |ui| {
@@ -652,15 +805,26 @@ impl VehiclePanel {
                         ui.label(RichText::new("積載量").strong());
                         ui.label(RichText::new("クラス").strong());
                         ui.label(RichText::new("ナンバー").strong());
+                        ui.label(RichText::new("荷台").strong());
+                        ui.label(RichText::new("車検満了").strong());
                         ui.label(RichText::new("画像").strong());
                         ui.label("");
                         ui.end_row();
 
                         for vehicle in vehicles {
-                            ui.label(&vehicle.name);
+                            let selected = self.selected_id.as_deref() == Some(vehicle.id.as_str());
+                            if ui
+                                .selectable_label(selected, &vehicle.name)
+                                .on_hover_text("クリックで車検証の詳細を表示")
+                                .clicked()
+                            {
+                                self.selected_id = (!selected).then(|| vehicle.id.clone());
+                            }
                             ui.label(format!("{:.1}t", vehicle.max_capacity));
                             ui.label(vehicle.truck_class().label());
                             ui.label(vehicle.license_plate.as_deref().unwrap_or("-"));
+                            ui.label(bed_summary(vehicle));
+                            ui.label(expiry_label(vehicle));
 
                             // Image indicator
                             if vehicle.image_path.is_some() {
@@ -669,15 +833,26 @@ impl VehiclePanel {
                                 ui.label(RichText::new("✕").color(Color32::LIGHT_RED));
                             }
 
-                            // Delete button
-                            if ui.small_button("削除").clicked() {
-                                to_delete = Some(vehicle.id.clone());
-                            }
+                            ui.horizontal(|ui| {
+                                if ui.small_button("荷台編集").clicked() {
+                                    to_edit = Some((vehicle.id.clone(), BedForm::from_vehicle(vehicle)));
+                                }
+                                // Delete button
+                                if ui.small_button("削除").clicked() {
+                                    to_delete = Some(vehicle.id.clone());
+                                }
+                            });
                             ui.end_row();
                         }
                     });
             });
 
+        if to_edit.is_some() {
+            self.editing_bed = to_edit;
+        }
+        self.render_vehicle_details(ui, vehicle_store);
+        self.render_bed_editor(ui, vehicle_store);
+
         // Process deletion
         if let Some(id) = to_delete {
             match vehicle_store.remove_vehicle(&id) {
@@ -695,6 +870,128 @@ impl VehiclePanel {
     }
 }
 
+impl VehiclePanel {
+    /// 車検証 details of the vehicle selected in the list
+    fn render_vehicle_details(&self, ui: &mut Ui, vehicle_store: &VehicleStore) {
+        let Some(vehicle) = self
+            .selected_id
+            .as_deref()
+            .and_then(|id| vehicle_store.get_vehicle(id))
+        else {
+            return;
+        };
+
+        let text = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+        let tonnes = |v: Option<f64>| text(v.map(|v| format!("{:.2}t", v)));
+        let dimensions = if vehicle.vehicle_length.is_some()
+            || vehicle.vehicle_width.is_some()
+            || vehicle.vehicle_height.is_some()
+        {
+            let m = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "?".to_string());
+            format!(
+                "{}×{}×{} m",
+                m(vehicle.vehicle_length),
+                m(vehicle.vehicle_width),
+                m(vehicle.vehicle_height)
+            )
+        } else {
+            "-".to_string()
+        };
+
+        ui.add_space(8.0);
+        ui.label(RichText::new(format!("車検証: {}", vehicle.name)).strong());
+        egui::Grid::new("vehicle_details")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("型式:");
+                ui.label(text(vehicle.model_code.clone()));
+                ui.end_row();
+
+                ui.label("最大積載量:");
+                ui.label(format!("{:.2}t", vehicle.max_capacity));
+                ui.end_row();
+
+                ui.label("車両重量:");
+                ui.label(tonnes(vehicle.vehicle_weight));
+                ui.end_row();
+
+                ui.label("車両総重量:");
+                ui.label(tonnes(vehicle.gross_vehicle_weight));
+                ui.end_row();
+
+                ui.label("長さ×幅×高さ:");
+                ui.label(dimensions);
+                ui.end_row();
+
+                ui.label("初度登録年月:");
+                ui.label(text(vehicle.first_registration.clone()));
+                ui.end_row();
+
+                ui.label("有効期間満了日:");
+                ui.label(expiry_label(vehicle));
+                ui.end_row();
+
+                ui.label("運送会社:");
+                ui.label(text(vehicle.company.clone()));
+                ui.end_row();
+            });
+    }
+
+    /// Editor for the bed dimensions of the vehicle selected in the list
+    fn render_bed_editor(&mut self, ui: &mut Ui, vehicle_store: &mut VehicleStore) {
+        let Some((id, form)) = self.editing_bed.as_mut() else {
+            return;
+        };
+        let Some(vehicle) = vehicle_store.get_vehicle(id).cloned() else {
+            self.editing_bed = None;
+            return;
+        };
+
+        ui.add_space(8.0);
+        ui.label(RichText::new(format!("荷台寸法の編集: {}", vehicle.name)).strong());
+        egui::Grid::new("edit_bed_form")
+            .num_columns(2)
+            .spacing([10.0, 6.0])
+            .show(ui, |ui| form.ui(ui, "edit_body_type"));
+
+        let (save, cancel) = ui
+            .horizontal(|ui| (ui.button("保存").clicked(), ui.button("キャンセル").clicked()))
+            .inner;
+        if cancel {
+            self.editing_bed = None;
+        } else if save {
+            let mut vehicle = vehicle;
+            if let Err(e) = form.apply_to(&mut vehicle) {
+                self.status_message = Some((e, true));
+                return;
+            }
+            self.status_message = Some(match vehicle_store.update_vehicle(vehicle) {
+                Ok(_) => ("荷台寸法を保存しました".to_string(), false),
+                Err(e) => (format!("保存エラー: {}", e), true),
+            });
+            self.editing_bed = None;
+        }
+    }
+
+    /// Register vehicles / set bed dimensions from a CSV file
+    fn import_csv(&mut self, path: &std::path::Path, vehicle_store: &mut VehicleStore) {
+        let rows = match load_vehicle_csv(path) {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.status_message = Some((format!("CSV読込エラー: {}", e), true));
+                return;
+            }
+        };
+        let result = import_vehicle_rows(vehicle_store, &rows);
+        let mut message = format!("CSV取込: 追加 {}台, 更新 {}台", result.added, result.updated);
+        if !result.errors.is_empty() {
+            message.push_str(&format!(" / エラー {}件 ({})", result.errors.len(), result.errors.join(", ")));
+        }
+        self.status_message = Some((message, !result.errors.is_empty()));
+    }
+}
+
 impl Default for VehiclePanel {
     fn default() -> Self {
         Self::new()
@@ -735,7 +1032,8 @@ fn scan_vehicle_folder(folder_path: &PathBuf) -> (Vec<PathBuf>, Vec<PathBuf>) {
                 .map(|e| e.to_lowercase())
                 .unwrap_or_default();
 
-            if !image_extensions.contains(&extension.as_str()) {
+            let is_image = image_extensions.contains(&extension.as_str());
+            if !is_image && extension != "pdf" {
                 continue;
             }
 
@@ -744,13 +1042,14 @@ fn scan_vehicle_folder(folder_path: &PathBuf) -> (Vec<PathBuf>, Vec<PathBuf>) {
                 .map(|n| n.to_lowercase())
                 .unwrap_or_default();
 
-            // Detect 車検証 images by filename patterns
+            // Detect 車検証 images by filename patterns (PDF scans are only
+            // used as 車検証)
             if filename.contains("車検") || filename.contains("shaken")
                 || filename.contains("certificate") || filename.contains("registration")
                 || filename.contains("検査") || filename.starts_with("cert")
             {
                 shaken_images.push(path);
-            } else {
+            } else if is_image {
                 // All other images are considered photos
                 photo_images.push(path);
             }
@@ -764,42 +1063,12 @@ fn scan_vehicle_folder(folder_path: &PathBuf) -> (Vec<PathBuf>, Vec<PathBuf>) {
     (shaken_images, photo_images)
 }
 
-/// Prompt for extracting vehicle info from 車検証
-const SHAKEN_ANALYSIS_PROMPT: &str = r#"この画像は日本の自動車検査証（車検証）です。以下の情報を抽出してください。
-
-抽出する項目:
-1. 車名（例: 日野, いすゞ, 三菱ふそう, UD）
-2. 型式（例: プロフィア, ギガ, スーパーグレート）
-3. 最大積載量（kg単位の数値）
-4. 車両番号（ナンバープレート）
-
-以下のJSON形式で回答してください:
-{
-  "vehicleName": "車名 型式",
-  "maxCapacityKg": 10000,
-  "licensePlate": "品川 100 あ 1234"
-}
-
-注意:
-- 最大積載量は必ずkg単位の数値で返してください
-- 読み取れない項目はnullとしてください
-- 車検証でない画像の場合は全てnullとしてください
-"#;
-
-/// Result of 車検証 analysis
-#[derive(Debug, Clone, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct ShakenAnalysisResult {
-    vehicle_name: Option<String>,
-    max_capacity_kg: Option<f64>,
-    license_plate: Option<String>,
-}
-
 /// Process vehicle folders in background thread
 fn process_vehicle_folders(
     folders: Vec<ScannedVehicleFolder>,
     backend: String,
     model: Option<String>,
+    pdf_cache_dir: PathBuf,
     status_tx: Sender<ProcessStatus>,
     vehicle_tx: Sender<RegisteredVehicle>,
 ) {
@@ -808,11 +1077,9 @@ fn process_vehicle_folders(
     let mut fail_count = 0;
 
     // Configure AI backend
-    let ai_backend = match backend.to_lowercase().as_str() {
-        "claude" => Backend::Claude,
-        "codex" => Backend::Codex,
-        _ => Backend::Gemini,
-    };
+    let analyzer_config = AnalyzerConfig::default()
+        .with_backend(&backend)
+        .with_model(model);
 
     for (index, folder) in folders.into_iter().enumerate() {
         let current = index + 1;
@@ -823,7 +1090,7 @@ fn process_vehicle_folders(
         });
 
         // Process this folder
-        match process_single_vehicle(&folder, ai_backend, &model, &status_tx) {
+        match process_single_vehicle(&folder, &analyzer_config, &pdf_cache_dir, &status_tx) {
             Ok(vehicle) => {
                 let _ = vehicle_tx.send(vehicle);
                 let _ = status_tx.send(ProcessStatus::VehicleCompleted {
@@ -853,26 +1120,26 @@ fn process_vehicle_folders(
 /// Process a single vehicle folder
 fn process_single_vehicle(
     folder: &ScannedVehicleFolder,
-    backend: Backend,
-    model: &Option<String>,
+    analyzer_config: &AnalyzerConfig,
+    pdf_cache_dir: &Path,
     status_tx: &Sender<ProcessStatus>,
 ) -> Result<RegisteredVehicle, String> {
     let _ = status_tx.send(ProcessStatus::AnalyzingShaken {
         name: folder.folder_name.clone(),
     });
 
-    // Analyze 車検証 if available
-    let (vehicle_name, max_capacity, license_plate) = if !folder.shaken_images.is_empty() {
-        analyze_shaken(&folder.shaken_images[0], backend, model)?
-    } else {
-        // Use folder name as vehicle name, require manual capacity entry
-        (folder.folder_name.clone(), None, None)
-    };
-
-    // Require max capacity for registration
-    let capacity = max_capacity.ok_or_else(|| {
-        "最大積載量を検出できませんでした".to_string()
-    })?;
+    // Analyze 車検証 (required for the max capacity); its QR codes are
+    // decoded first and override the AI reading
+    let shaken_file = folder.shaken_images.first()
+        .ok_or_else(|| "最大積載量を検出できませんでした".to_string())?;
+    // PDF scans are read from their first page image
+    let shaken_path = document_images(shaken_file, pdf_cache_dir)
+        .map_err(|e| format!("PDF読込エラー: {}", e))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "車検証の画像がありません".to_string())?;
+    let shaken = read_shaken(&shaken_path, analyzer_config)
+        .map_err(|e| format!("AI解析エラー: {}", e))?;
 
     // Get vehicle image (first photo)
     let image_path = folder.photo_images.first()
@@ -885,84 +1152,13 @@ fn process_single_vehicle(
     // Create thumbnail
     let thumbnail = create_thumbnail(&image_path.display().to_string());
 
-    // Create vehicle
-    let mut vehicle = RegisteredVehicle::new(vehicle_name, capacity)
+    // Create vehicle with every field read from the certificate
+    let mut vehicle = shaken
+        .to_vehicle(&folder.folder_name)
+        .ok_or_else(|| "最大積載量を検出できませんでした".to_string())?
         .with_image(image_path.display().to_string(), thumbnail);
 
-    if let Some(plate) = license_plate {
-        vehicle = vehicle.with_license_plate(plate);
-    }
-
     vehicle.notes = Some(format!("フォルダから自動登録: {}", folder.folder_name));
 
     Ok(vehicle)
 }
-
-/// Analyze 車検証 image to extract vehicle information
-fn analyze_shaken(
-    image_path: &PathBuf,
-    backend: Backend,
-    model: &Option<String>,
-) -> Result<(String, Option<f64>, Option<String>), String> {
-    // Configure AI options
-    let mut options = if let Some(ref m) = model {
-        AnalyzeOptions::with_model(m)
-    } else {
-        AnalyzeOptions::default()
-    };
-    options = options.with_backend(backend).json();
-
-    // Call AI
-    let response = analyze(SHAKEN_ANALYSIS_PROMPT, &[image_path.clone()], options)
-        .map_err(|e| format!("AI解析エラー: {}", e))?;
-
-    // Parse response
-    let json_str = extract_json_from_response(&response);
-    let result: ShakenAnalysisResult = serde_json::from_str(&json_str)
-        .map_err(|e| format!("JSON解析エラー: {}", e))?;
-
-    // Extract vehicle name
-    let vehicle_name = result.vehicle_name
-        .unwrap_or_else(|| "不明".to_string());
-
-    // Convert kg to tonnes
-    let max_capacity = result.max_capacity_kg.map(|kg| kg / 1000.0);
-
-    Ok((vehicle_name, max_capacity, result.license_plate))
-}
-
-/// Extract JSON from AI response (handles markdown code blocks)
-fn extract_json_from_response(response: &str) -> String {
-    let response = response.trim();
-
-    // Check for markdown code block
-    if response.starts_with("```json") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(7) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    // Check for generic code block
-    if response.starts_with("```") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(3) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    // Try to find JSON object directly
-    if let Some(start) = response.find('{') {
-        if let Some(end) = response.rfind('}') {
-            if start < end {
-                return response[start..=end].to_string();
-            }
-        }
-    }
-
-    response.to_string()
-}
@@ -35,6 +35,13 @@ pub enum CsvLoaderError {
         value: String,
     },
 
+    #[error("Invalid value in row {row}, column {column}: {value}")]
+    InvalidValue {
+        row: usize,
+        column: String,
+        value: String,
+    },
+
     #[error("Missing required column: {0}")]
     MissingColumn(String),
 }
@@ -170,6 +170,8 @@ pub fn convert_to_history_entry(item: &LegacyStockItem) -> HistoryEntry {
         is_target_detected: est.is_target_detected,
         truck_type: est.truck_type,
         license_plate: est.license_plate,
+        plate_source: None,
+        plate_confidence: None,
         material_type: est.material_type,
         height: None,
         packing_density: None,
@@ -178,6 +180,7 @@ pub fn convert_to_history_entry(item: &LegacyStockItem) -> HistoryEntry {
         fill_ratio_z: None,
         estimated_volume_m3: est.estimated_volume_m3.unwrap_or_default(),
         estimated_tonnage: est.estimated_tonnage.unwrap_or_default(),
+        raw_tonnage: None,
         confidence_score: est.confidence_score.unwrap_or_default(),
         reasoning: est.reasoning.unwrap_or_default(),
         material_breakdown: est.material_breakdown.into_iter().map(|mb| {
@@ -185,9 +188,16 @@ pub fn convert_to_history_entry(item: &LegacyStockItem) -> HistoryEntry {
                 material: mb.material,
                 percentage: mb.percentage,
                 density: mb.density,
+                tonnage: None,
             }
         }).collect(),
         ensemble_count: est.ensemble_count,
+        ensemble_samples: Vec::new(),
+        tonnage_std_dev: None,
+        tonnage_interval: None,
+        reference_hashes: Vec::new(),
+        warnings: Vec::new(),
+        preprocessing: None,
     }).unwrap_or_default();
 
     // Create image path placeholder
@@ -203,6 +213,7 @@ pub fn convert_to_history_entry(item: &LegacyStockItem) -> HistoryEntry {
         feedback_at: None,
         notes: item.memo.clone(),
         thumbnail_base64: item.base64_images.first().cloned(),
+        source_images: Vec::new(),
     }
 }
 
@@ -7,5 +7,7 @@ pub mod csv_loader;
 pub mod exif_reader;
 pub mod legacy_importer;
 pub mod overload_csv;
+pub mod pdf_images;
 pub mod persistence;
+pub mod vehicle_csv;
 pub mod vehicle_master_loader;
@@ -0,0 +1,405 @@
+//! Page images embedded in scanned PDFs (車検証 scans)
+//!
+//! Scanners store each page as one image XObject, usually a JPEG or, for
+//! black-and-white scans, a CCITT Group 4 bitmap. The largest image of each
+//! page is written out as a file the image pipeline can read: JPEG streams as
+//! they are, CCITT and uncompressed/Flate bitmaps as PNG. Nothing is
+//! rendered, so pages made only of text or vector drawings yield no image.
+//!
+//! Extracted pages are cached by PDF hash and page number
+//! (`<hash>-p<page>.<ext>`). A manifest `<hash>.json` is written after the
+//! pages, so a PDF seen before is not parsed again.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PdfImageError {
+    #[error("Failed to read file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to parse PDF: {0}")]
+    PdfError(#[from] lopdf::Error),
+
+    #[error("Failed to write page image: {0}")]
+    ImageError(#[from] image::ImageError),
+
+    #[error("Failed to write page manifest: {0}")]
+    ManifestError(#[from] serde_json::Error),
+
+    #[error("No supported page images found in PDF")]
+    NoImages,
+}
+
+/// One extracted page image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PdfPageImage {
+    /// 1-based page number
+    pub page: u32,
+    pub path: PathBuf,
+}
+
+/// Decoded (or passed-through) image of one page
+enum PageImage {
+    Jpeg(Vec<u8>),
+    Gray(image::GrayImage),
+    Rgb(image::RgbImage),
+}
+
+impl PageImage {
+    fn extension(&self) -> &'static str {
+        match self {
+            PageImage::Jpeg(_) => "jpg",
+            PageImage::Gray(_) | PageImage::Rgb(_) => "png",
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), PdfImageError> {
+        match self {
+            PageImage::Jpeg(bytes) => fs::write(path, bytes)?,
+            PageImage::Gray(image) => image.save(path)?,
+            PageImage::Rgb(image) => image.save(path)?,
+        }
+        Ok(())
+    }
+}
+
+/// Extract the page images of a PDF into `cache_dir`, reusing an earlier
+/// extraction of the same file.
+///
+/// Pages without a supported image are skipped; a PDF without any is an error.
+pub fn extract_pdf_pages(
+    pdf_path: &Path,
+    cache_dir: &Path,
+) -> Result<Vec<PdfPageImage>, PdfImageError> {
+    let bytes = fs::read(pdf_path)?;
+    let hash = format!("{:x}", Sha256::digest(&bytes));
+    let manifest_path = cache_dir.join(format!("{}.json", hash));
+
+    if let Some(pages) = read_manifest(&manifest_path) {
+        return Ok(pages);
+    }
+
+    let document = Document::load_mem(&bytes)?;
+    fs::create_dir_all(cache_dir)?;
+
+    let mut pages = Vec::new();
+    for (page, page_id) in document.get_pages() {
+        let Some(image) = page_image(&document, page_id) else {
+            continue;
+        };
+        let path = cache_dir.join(format!("{}-p{}.{}", hash, page, image.extension()));
+        image.save(&path)?;
+        pages.push(PdfPageImage { page, path });
+    }
+    if pages.is_empty() {
+        return Err(PdfImageError::NoImages);
+    }
+
+    fs::write(&manifest_path, serde_json::to_string_pretty(&pages)?)?;
+    Ok(pages)
+}
+
+/// Pages listed in a manifest, if it exists and all of its images do
+fn read_manifest(path: &Path) -> Option<Vec<PdfPageImage>> {
+    let content = fs::read_to_string(path).ok()?;
+    let pages: Vec<PdfPageImage> = serde_json::from_str(&content).ok()?;
+    pages.iter().all(|p| p.path.exists()).then_some(pages)
+}
+
+/// Follow a reference to its object
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> &'a Object {
+    match object {
+        Object::Reference(id) => document.get_object(*id).unwrap_or(object),
+        _ => object,
+    }
+}
+
+fn get_dict<'a>(document: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Dictionary> {
+    match resolve(document, dict.get(key).ok()?) {
+        Object::Dictionary(d) => Some(d),
+        _ => None,
+    }
+}
+
+fn get_integer(document: &Document, dict: &Dictionary, key: &[u8]) -> Option<i64> {
+    match resolve(document, dict.get(key).ok()?) {
+        Object::Integer(i) => Some(*i),
+        _ => None,
+    }
+}
+
+fn get_bool(document: &Document, dict: &Dictionary, key: &[u8]) -> Option<bool> {
+    match resolve(document, dict.get(key).ok()?) {
+        Object::Boolean(b) => Some(*b),
+        _ => None,
+    }
+}
+
+fn get_name<'a>(document: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a [u8]> {
+    match resolve(document, dict.get(key).ok()?) {
+        Object::Name(name) => Some(name.as_slice()),
+        _ => None,
+    }
+}
+
+/// XObjects of a page; resources may be inherited from the page tree
+fn page_xobjects(document: &Document, page_id: ObjectId) -> Option<&Dictionary> {
+    let mut node = document.get_dictionary(page_id).ok()?;
+    // Bounded walk up the tree, in case of a malformed /Parent loop
+    for _ in 0..32 {
+        if let Some(resources) = get_dict(document, node, b"Resources") {
+            return get_dict(document, resources, b"XObject");
+        }
+        let parent = node.get(b"Parent").ok()?.as_reference().ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// The largest image XObject of a page, decoded if its encoding is supported
+fn page_image(document: &Document, page_id: ObjectId) -> Option<PageImage> {
+    let area = |stream: &Stream| {
+        get_integer(document, &stream.dict, b"Width").unwrap_or(0)
+            * get_integer(document, &stream.dict, b"Height").unwrap_or(0)
+    };
+    page_xobjects(document, page_id)?
+        .iter()
+        .filter_map(|(_, object)| match resolve(document, object) {
+            Object::Stream(stream) => Some(stream),
+            _ => None,
+        })
+        .filter(|stream| get_name(document, &stream.dict, b"Subtype") == Some(b"Image".as_slice()))
+        .max_by_key(|stream| area(stream))
+        .and_then(|stream| decode_image(document, stream))
+}
+
+/// Filter names of a stream, in decoding order
+fn filters<'a>(document: &'a Document, dict: &'a Dictionary) -> Vec<&'a [u8]> {
+    match dict.get(b"Filter").ok().map(|f| resolve(document, f)) {
+        Some(Object::Name(name)) => vec![name.as_slice()],
+        Some(Object::Array(names)) => names
+            .iter()
+            .filter_map(|n| match resolve(document, n) {
+                Object::Name(name) => Some(name.as_slice()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn decode_image(document: &Document, stream: &Stream) -> Option<PageImage> {
+    match filters(document, &stream.dict).as_slice() {
+        [b"DCTDecode"] => Some(PageImage::Jpeg(stream.content.clone())),
+        [b"CCITTFaxDecode"] => decode_ccitt(document, stream),
+        [] => decode_bitmap(document, stream, stream.content.clone()),
+        [b"FlateDecode"] => decode_bitmap(document, stream, stream.decompressed_content().ok()?),
+        _ => None,
+    }
+}
+
+/// `/Decode [1 0]`: sample value 1 is black
+fn decode_inverted(document: &Document, dict: &Dictionary) -> bool {
+    match dict.get(b"Decode").ok().map(|d| resolve(document, d)) {
+        Some(Object::Array(range)) => match range.first() {
+            Some(Object::Integer(first)) => *first == 1,
+            Some(Object::Real(first)) => *first > 0.5,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Decode a CCITT Group 4 bitmap (the encoding of black-and-white scans)
+fn decode_ccitt(document: &Document, stream: &Stream) -> Option<PageImage> {
+    let dict = &stream.dict;
+    let params = get_dict(document, dict, b"DecodeParms");
+    let param = |key: &[u8]| params.and_then(|p| get_integer(document, p, key));
+
+    // K < 0 is Group 4; Group 3 (K >= 0) is rare in scans and not supported
+    if param(b"K").unwrap_or(0) >= 0 {
+        return None;
+    }
+    let width = u16::try_from(param(b"Columns").unwrap_or(1728)).ok()?;
+    let height = param(b"Rows")
+        .or_else(|| get_integer(document, dict, b"Height"))
+        .and_then(|h| u16::try_from(h).ok());
+    let black_is_1 = params
+        .and_then(|p| get_bool(document, p, b"BlackIs1"))
+        .unwrap_or(false);
+    let inverted = decode_inverted(document, dict);
+
+    let mut pixels = Vec::new();
+    let mut rows = 0u32;
+    fax::decoder::decode_g4(stream.content.iter().copied(), width, height, |transitions| {
+        pixels.extend(fax::decoder::pels(transitions, width).map(|color| {
+            // The sample is 1 for black runs only with BlackIs1; sample 1 is
+            // white unless the image has an inverted /Decode
+            let sample_one = matches!(color, fax::Color::Black) == black_is_1;
+            if sample_one != inverted {
+                255u8
+            } else {
+                0u8
+            }
+        }));
+        rows += 1;
+    });
+    if rows == 0 {
+        return None;
+    }
+    image::GrayImage::from_raw(u32::from(width), rows, pixels).map(PageImage::Gray)
+}
+
+/// Decode an 8-bit gray or RGB bitmap
+fn decode_bitmap(document: &Document, stream: &Stream, mut data: Vec<u8>) -> Option<PageImage> {
+    let dict = &stream.dict;
+    if get_integer(document, dict, b"BitsPerComponent")? != 8 {
+        return None;
+    }
+    let width = u32::try_from(get_integer(document, dict, b"Width")?).ok()?;
+    let height = u32::try_from(get_integer(document, dict, b"Height")?).ok()?;
+
+    let components = match resolve(document, dict.get(b"ColorSpace").ok()?) {
+        Object::Name(name) if name.as_slice() == b"DeviceGray" => 1,
+        Object::Name(name) if name.as_slice() == b"DeviceRGB" => 3,
+        // [/ICCBased <profile stream>]: component count from /N
+        Object::Array(space) => match (space.first(), space.get(1).map(|p| resolve(document, p))) {
+            (Some(Object::Name(name)), Some(Object::Stream(profile)))
+                if name.as_slice() == b"ICCBased" =>
+            {
+                get_integer(document, &profile.dict, b"N")?
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let len = (width as usize) * (height as usize) * (components as usize);
+    if data.len() < len {
+        return None;
+    }
+    data.truncate(len);
+    match components {
+        1 => image::GrayImage::from_raw(width, height, data).map(PageImage::Gray),
+        3 => image::RgbImage::from_raw(width, height, data).map(PageImage::Rgb),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    /// PDF with one image XObject per page
+    fn write_pdf(path: &Path, images: Vec<Stream>) {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let mut kids = Vec::new();
+        for image in images {
+            let image_id = document.add_object(image);
+            let page_id = document.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+            });
+            kids.push(Object::from(page_id));
+        }
+        let count = kids.len() as i64;
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => count }),
+        );
+        let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        document.trailer.set("Root", catalog_id);
+        document.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_extracts_jpeg_and_bitmap_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let pdf = dir.path().join("車検証.pdf");
+        let jpeg = b"\xff\xd8\xff\xe0jpeg-data".to_vec();
+        write_pdf(
+            &pdf,
+            vec![
+                Stream::new(
+                    dictionary! {
+                        "Subtype" => "Image", "Width" => 2, "Height" => 1,
+                        "BitsPerComponent" => 8, "ColorSpace" => "DeviceRGB",
+                        "Filter" => "DCTDecode",
+                    },
+                    jpeg.clone(),
+                ),
+                Stream::new(
+                    dictionary! {
+                        "Subtype" => "Image", "Width" => 2, "Height" => 2,
+                        "BitsPerComponent" => 8, "ColorSpace" => "DeviceGray",
+                    },
+                    vec![0, 255, 255, 0],
+                ),
+            ],
+        );
+
+        let cache = dir.path().join("pdf-pages");
+        let pages = extract_pdf_pages(&pdf, &cache).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].page, 1);
+        assert_eq!(fs::read(&pages[0].path).unwrap(), jpeg);
+        assert!(pages[1].path.extension().is_some_and(|e| e == "png"));
+        let gray = image::open(&pages[1].path).unwrap().to_luma8();
+        assert_eq!(gray.get_pixel(1, 0).0, [255]);
+
+        // Same PDF again: served from the manifest; a missing page re-extracts
+        assert_eq!(extract_pdf_pages(&pdf, &cache).unwrap(), pages);
+        fs::remove_file(&pages[1].path).unwrap();
+        assert_eq!(extract_pdf_pages(&pdf, &cache).unwrap(), pages);
+        assert!(pages[1].path.exists());
+
+        let empty = dir.path().join("empty.pdf");
+        write_pdf(&empty, Vec::new());
+        assert!(matches!(extract_pdf_pages(&empty, &cache), Err(PdfImageError::NoImages)));
+    }
+
+    #[test]
+    fn test_decodes_ccitt_polarity() {
+        // 8x2 Group 4 bitmap: a white row, then 4 black and 4 white pels
+        // (V0 | H W0 B4, V0 | EOFB)
+        let g4 = vec![0x93, 0x57, 0x00, 0x10, 0x01];
+        let decode = |black_is_1: bool, decode: Option<[i64; 2]>| {
+            let mut dict = dictionary! {
+                "Subtype" => "Image", "Width" => 8, "Height" => 2,
+                "BitsPerComponent" => 1, "Filter" => "CCITTFaxDecode",
+                "DecodeParms" => dictionary! {
+                    "K" => -1, "Columns" => 8, "Rows" => 2, "BlackIs1" => black_is_1,
+                },
+            };
+            if let Some([d0, d1]) = decode {
+                dict.set("Decode", vec![Object::from(d0), Object::from(d1)]);
+            }
+            let stream = Stream::new(dict, g4.clone());
+            match decode_ccitt(&Document::with_version("1.5"), &stream) {
+                Some(PageImage::Gray(image)) => image,
+                _ => panic!("CCITT image not decoded"),
+            }
+        };
+
+        let gray = decode(false, None);
+        assert_eq!(gray.dimensions(), (8, 2));
+        assert_eq!(gray.get_pixel(0, 0).0, [255]);
+        assert_eq!(gray.get_pixel(0, 1).0, [0]);
+        assert_eq!(gray.get_pixel(5, 1).0, [255]);
+
+        // BlackIs1 makes black pels sample 1, which the default /Decode shows white
+        assert_eq!(decode(true, None).get_pixel(0, 1).0, [255]);
+        assert_eq!(decode(true, Some([1, 0])).get_pixel(0, 1).0, [0]);
+        assert_eq!(decode(true, Some([1, 0])).get_pixel(5, 1).0, [255]);
+        assert_eq!(decode(false, Some([1, 0])).get_pixel(0, 1).0, [255]);
+    }
+}
@@ -8,13 +8,15 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
 use chrono::Utc;
 use sha2::{Digest, Sha256};
 
 use tonsuu_domain::repository::AnalysisHistoryRepository;
+use tonsuu_store::schema::{read_store_file, update_store_file, StoreKind};
+use tonsuu_store::Recovery;
 use tonsuu_types::{CacheError, Error, EstimationResult, HistoryEntry, Result};
 
 /// File-based implementation of AnalysisHistoryRepository
@@ -23,28 +25,28 @@ use tonsuu_types::{CacheError, Error, EstimationResult, HistoryEntry, Result};
 pub struct FileAnalysisHistoryRepository {
     store_path: PathBuf,
     entries: RefCell<HashMap<String, HistoryEntry>>,
+    recovery: RefCell<Option<Recovery>>,
 }
 
 impl FileAnalysisHistoryRepository {
     /// Create or load a history repository
     pub fn open(store_dir: PathBuf) -> Result<Self> {
         fs::create_dir_all(&store_dir)?;
-        let store_path = store_dir.join("history.json");
-
-        let entries = if store_path.exists() {
-            let file = File::open(&store_path)?;
-            let reader = BufReader::new(file);
-            serde_json::from_reader(reader).unwrap_or_default()
-        } else {
-            HashMap::new()
-        };
+        let store_path = store_dir.join(StoreKind::History.file_name());
+        let (entries, recovery) = read_store_file(&store_path, StoreKind::History)?;
 
         Ok(Self {
             store_path,
             entries: RefCell::new(entries),
+            recovery: RefCell::new(recovery),
         })
     }
 
+    /// Set when the history had to be salvaged from a damaged file
+    pub fn recovery(&self) -> Option<Recovery> {
+        self.recovery.borrow().clone()
+    }
+
     /// Compute hash for an image file
     pub fn hash_image(image_path: &Path) -> Result<String> {
         let file = File::open(image_path)?;
@@ -55,12 +57,19 @@ impl FileAnalysisHistoryRepository {
         Ok(format!("{:x}", hash))
     }
 
-    /// Save store to disk
-    fn persist(&self) -> Result<()> {
-        let file = File::create(&self.store_path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &*self.entries.borrow())?;
-        Ok(())
+    /// Apply a mutation to the latest on-disk state and save it, under the
+    /// same lock `Store` takes, so concurrent writers never lose entries
+    fn update<R>(
+        &self,
+        f: impl FnOnce(&mut HashMap<String, HistoryEntry>) -> Result<R>,
+    ) -> Result<R> {
+        let (entries, result, recovery) =
+            update_store_file(&self.store_path, StoreKind::History, f)?;
+        *self.entries.borrow_mut() = entries;
+        if recovery.is_some() {
+            *self.recovery.borrow_mut() = recovery;
+        }
+        Ok(result)
     }
 
     /// Add or update an analysis result
@@ -88,10 +97,13 @@ impl FileAnalysisHistoryRepository {
             feedback_at: None,
             notes: None,
             thumbnail_base64,
+            source_images: Vec::new(),
         };
 
-        self.entries.borrow_mut().insert(hash.clone(), entry);
-        self.persist()?;
+        self.update(|entries| {
+            entries.insert(hash.clone(), entry);
+            Ok(())
+        })?;
         Ok(hash)
     }
 
@@ -115,8 +127,14 @@ impl FileAnalysisHistoryRepository {
     ) -> Result<()> {
         let hash = Self::hash_image(image_path)?;
 
-        let mut entries = self.entries.borrow_mut();
-        if let Some(entry) = entries.get_mut(&hash) {
+        self.update(|entries| {
+            let Some(entry) = entries.get_mut(&hash) else {
+                return Err(CacheError::IoError(format!(
+                    "No analysis found for image: {}",
+                    image_path.display()
+                ))
+                .into());
+            };
             entry.actual_tonnage = Some(actual_tonnage);
             entry.feedback_at = Some(Utc::now());
             if let Some(cap) = max_capacity {
@@ -125,16 +143,8 @@ impl FileAnalysisHistoryRepository {
             if notes.is_some() {
                 entry.notes = notes;
             }
-            drop(entries);
-            self.persist()?;
             Ok(())
-        } else {
-            Err(CacheError::IoError(format!(
-                "No analysis found for image: {}",
-                image_path.display()
-            ))
-            .into())
-        }
+        })
     }
 
     /// Get entry by image path
@@ -158,10 +168,10 @@ impl FileAnalysisHistoryRepository {
 
 impl AnalysisHistoryRepository for FileAnalysisHistoryRepository {
     fn save(&self, result: &HistoryEntry) -> std::result::Result<(), Error> {
-        self.entries
-            .borrow_mut()
-            .insert(result.image_hash.clone(), result.clone());
-        self.persist()
+        self.update(|entries| {
+            entries.insert(result.image_hash.clone(), result.clone());
+            Ok(())
+        })
     }
 
     fn find_by_id(&self, id: &str) -> std::result::Result<Option<HistoryEntry>, Error> {
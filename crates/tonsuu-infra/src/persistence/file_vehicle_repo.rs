@@ -7,12 +7,13 @@
 
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::fs;
 use std::path::PathBuf;
 
 use tonsuu_domain::repository::VehicleRepository;
-use tonsuu_types::{Error, RegisteredVehicle, Result, TruckClass};
+use tonsuu_store::schema::{read_store_file, update_store_file, StoreKind};
+use tonsuu_store::Recovery;
+use tonsuu_types::{Error, LicensePlate, RegisteredVehicle, Result, TruckClass};
 
 /// File-based implementation of VehicleRepository
 ///
@@ -20,51 +21,52 @@ use tonsuu_types::{Error, RegisteredVehicle, Result, TruckClass};
 pub struct FileVehicleRepository {
     store_path: PathBuf,
     vehicles: RefCell<HashMap<String, RegisteredVehicle>>,
+    recovery: RefCell<Option<Recovery>>,
 }
 
 impl FileVehicleRepository {
     /// Create or load a vehicle repository
     pub fn open(store_dir: PathBuf) -> Result<Self> {
         fs::create_dir_all(&store_dir)?;
-        let store_path = store_dir.join("vehicles.json");
-
-        let vehicles = if store_path.exists() {
-            let file = File::open(&store_path)?;
-            let reader = BufReader::new(file);
-            serde_json::from_reader(reader).unwrap_or_default()
-        } else {
-            HashMap::new()
-        };
+        let store_path = store_dir.join(StoreKind::Vehicles.file_name());
+        let (vehicles, recovery) = read_store_file(&store_path, StoreKind::Vehicles)?;
 
         Ok(Self {
             store_path,
             vehicles: RefCell::new(vehicles),
+            recovery: RefCell::new(recovery),
         })
     }
 
-    /// Save store to disk
-    fn persist(&self) -> Result<()> {
-        let file = File::create(&self.store_path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &*self.vehicles.borrow())?;
-        Ok(())
+    /// Set when the vehicles had to be salvaged from a damaged file
+    pub fn recovery(&self) -> Option<Recovery> {
+        self.recovery.borrow().clone()
+    }
+
+    /// Apply a mutation to the latest on-disk state and save it, under the
+    /// same lock `VehicleStore` takes, so concurrent writers never lose vehicles
+    fn update<R>(&self, f: impl FnOnce(&mut HashMap<String, RegisteredVehicle>) -> R) -> Result<R> {
+        let (vehicles, result, recovery) =
+            update_store_file(&self.store_path, StoreKind::Vehicles, |vehicles| Ok(f(vehicles)))?;
+        *self.vehicles.borrow_mut() = vehicles;
+        if recovery.is_some() {
+            *self.recovery.borrow_mut() = recovery;
+        }
+        Ok(result)
     }
 
     /// Add a new vehicle and return its ID
     pub fn add_vehicle(&self, vehicle: RegisteredVehicle) -> Result<String> {
         let id = vehicle.id.clone();
-        self.vehicles.borrow_mut().insert(id.clone(), vehicle);
-        self.persist()?;
+        self.update(|vehicles| {
+            vehicles.insert(vehicle.id.clone(), vehicle);
+        })?;
         Ok(id)
     }
 
     /// Remove a vehicle by ID
     pub fn remove_vehicle(&self, id: &str) -> Result<bool> {
-        let removed = self.vehicles.borrow_mut().remove(id).is_some();
-        if removed {
-            self.persist()?;
-        }
-        Ok(removed)
+        self.update(|vehicles| vehicles.remove(id).is_some())
     }
 
     /// Get a vehicle by ID
@@ -89,34 +91,45 @@ impl FileVehicleRepository {
 
     /// Update a vehicle
     pub fn update_vehicle(&self, vehicle: RegisteredVehicle) -> Result<bool> {
-        let mut vehicles = self.vehicles.borrow_mut();
-        if vehicles.contains_key(&vehicle.id) {
+        self.update(|vehicles| {
+            if !vehicles.contains_key(&vehicle.id) {
+                return false;
+            }
             vehicles.insert(vehicle.id.clone(), vehicle);
-            drop(vehicles);
-            self.persist()?;
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+            true
+        })
     }
 }
 
 impl VehicleRepository for FileVehicleRepository {
     fn save(&self, vehicle: &RegisteredVehicle) -> std::result::Result<(), Error> {
-        let mut vehicles = self.vehicles.borrow_mut();
-        vehicles.insert(vehicle.id.clone(), vehicle.clone());
-        drop(vehicles);
-        self.persist()
+        self.update(|vehicles| {
+            vehicles.insert(vehicle.id.clone(), vehicle.clone());
+        })
     }
 
     fn find_by_plate(&self, plate: &str) -> std::result::Result<Option<RegisteredVehicle>, Error> {
-        let result = self
-            .vehicles
-            .borrow()
+        let vehicles = self.vehicles.borrow();
+        let exact = vehicles
             .values()
-            .find(|v| v.license_plate.as_ref().map(|p| p == plate).unwrap_or(false))
-            .cloned();
-        Ok(result)
+            .find(|v| v.license_plate.as_ref().map(|p| p == plate).unwrap_or(false));
+        if let Some(vehicle) = exact {
+            return Ok(Some(vehicle.clone()));
+        }
+
+        // Same plate written differently (spacing, full-width digits, separators)
+        let Some(parsed) = LicensePlate::parse(plate) else {
+            return Ok(None);
+        };
+        Ok(vehicles
+            .values()
+            .find(|v| {
+                v.license_plate
+                    .as_deref()
+                    .and_then(LicensePlate::parse)
+                    .is_some_and(|p| p == parsed)
+            })
+            .cloned())
     }
 
     fn find_all(&self) -> std::result::Result<Vec<RegisteredVehicle>, Error> {
@@ -125,3 +138,22 @@ impl VehicleRepository for FileVehicleRepository {
         Ok(vehicles)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writers_keep_each_others_vehicles() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = FileVehicleRepository::open(dir.path().to_path_buf()).unwrap();
+        let second = FileVehicleRepository::open(dir.path().to_path_buf()).unwrap();
+
+        first.add_vehicle(RegisteredVehicle::new("1号".to_string(), 4.0)).unwrap();
+        second.add_vehicle(RegisteredVehicle::new("2号".to_string(), 9.8)).unwrap();
+
+        assert_eq!(second.count(), 2);
+        let reopened = FileVehicleRepository::open(dir.path().to_path_buf()).unwrap();
+        assert_eq!(reopened.count(), 2);
+    }
+}
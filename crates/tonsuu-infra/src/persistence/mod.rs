@@ -1,11 +1,17 @@
 //! Persistence implementations
 //!
-//! This module provides file-based implementations of the repository traits.
+//! This module provides file-based and SQLite implementations of the repository traits.
 
 mod file_analysis_history_repo;
 mod file_vehicle_master_repo;
 mod file_vehicle_repo;
 mod file_weighing_slip_repo;
+mod sqlite_analysis_history_repo;
+mod sqlite_db;
+mod sqlite_storage;
+mod sqlite_vehicle_master_repo;
+mod sqlite_vehicle_repo;
+mod sqlite_weighing_slip_repo;
 
 #[allow(unused_imports)]
 pub use file_analysis_history_repo::FileAnalysisHistoryRepository;
@@ -15,3 +21,9 @@ pub use file_vehicle_master_repo::FileVehicleMasterRepository;
 pub use file_vehicle_repo::FileVehicleRepository;
 #[allow(unused_imports)]
 pub use file_weighing_slip_repo::FileWeighingSlipRepository;
+pub use sqlite_analysis_history_repo::SqliteAnalysisHistoryRepository;
+pub use sqlite_db::{migrate_json_to_sqlite, SqliteMigrationReport, SQLITE_DB_FILE};
+pub use sqlite_storage::{SqliteRecord, SqliteStorage};
+pub use sqlite_vehicle_master_repo::SqliteVehicleMasterRepository;
+pub use sqlite_vehicle_repo::SqliteVehicleRepository;
+pub use sqlite_weighing_slip_repo::SqliteWeighingSlipRepository;
@@ -0,0 +1,132 @@
+//! SQLite-based analysis history repository implementation
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use tonsuu_domain::repository::AnalysisHistoryRepository;
+use tonsuu_types::{Error, HistoryEntry, Result};
+
+use super::sqlite_db::{db_err, decode_history, insert_history, open_connection, plate_key};
+
+/// SQLite implementation of AnalysisHistoryRepository
+///
+/// Entries are indexed by image hash (primary key), analysis time and plate
+/// (verbatim and normalized).
+pub struct SqliteAnalysisHistoryRepository {
+    conn: Connection,
+}
+
+impl SqliteAnalysisHistoryRepository {
+    /// Open (or create) the history table in the given database file
+    pub fn open(db_path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: open_connection(db_path)?,
+        })
+    }
+
+    /// Entries analyzed from the given license plate, however it was
+    /// spaced or punctuated (newest first)
+    pub fn find_by_plate(&self, plate: &str) -> Result<Vec<HistoryEntry>> {
+        self.query(
+            "SELECT data, thumbnail_base64 FROM history
+             WHERE plate_key = ?1 ORDER BY analyzed_at DESC",
+            params![plate_key(plate)],
+        )
+    }
+
+    /// Number of stored entries
+    pub fn count(&self) -> Result<usize> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))
+            .map_err(db_err)?;
+        Ok(count as usize)
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_err)?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .map_err(db_err)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (data, thumbnail) = row.map_err(db_err)?;
+            entries.push(decode_history(&data, thumbnail)?);
+        }
+        Ok(entries)
+    }
+}
+
+impl AnalysisHistoryRepository for SqliteAnalysisHistoryRepository {
+    fn save(&self, result: &HistoryEntry) -> std::result::Result<(), Error> {
+        insert_history(&self.conn, result)
+    }
+
+    fn find_by_id(&self, id: &str) -> std::result::Result<Option<HistoryEntry>, Error> {
+        let row: Option<(String, Option<String>)> = self
+            .conn
+            .query_row(
+                "SELECT data, thumbnail_base64 FROM history WHERE image_hash = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(db_err)?;
+        row.map(|(data, thumbnail)| decode_history(&data, thumbnail))
+            .transpose()
+    }
+
+    fn find_all(&self) -> std::result::Result<Vec<HistoryEntry>, Error> {
+        self.query(
+            "SELECT data, thumbnail_base64 FROM history ORDER BY analyzed_at DESC",
+            [],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::TempDir;
+    use tonsuu_types::EstimationResult;
+
+    fn entry(hash: &str) -> HistoryEntry {
+        HistoryEntry {
+            image_path: format!("{}.jpg", hash),
+            image_hash: hash.to_string(),
+            estimation: EstimationResult {
+                license_plate: Some("1122".to_string()),
+                ..Default::default()
+            },
+            actual_tonnage: None,
+            max_capacity: None,
+            analyzed_at: Utc::now(),
+            feedback_at: None,
+            notes: None,
+            thumbnail_base64: Some("thumb".to_string()),
+            source_images: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_save_and_find() {
+        let dir = TempDir::new().unwrap();
+        let repo = SqliteAnalysisHistoryRepository::open(&dir.path().join("t.db")).unwrap();
+
+        repo.save(&entry("a")).unwrap();
+        repo.save(&entry("b")).unwrap();
+        repo.save(&entry("a")).unwrap();
+
+        assert_eq!(repo.count().unwrap(), 2);
+        let found = repo.find_by_id("a").unwrap().unwrap();
+        assert_eq!(found.thumbnail_base64.as_deref(), Some("thumb"));
+        assert!(repo.find_by_id("zzz").unwrap().is_none());
+        assert_eq!(repo.find_by_plate("1122").unwrap().len(), 2);
+        assert_eq!(repo.find_by_plate("11-22").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,525 @@
+//! Shared SQLite connection setup, schema and JSON → SQLite migration
+//!
+//! All four SQLite repositories live in one database file (`tonsuu.db` in the
+//! store directory). Each row keeps the full record as JSON in `data` plus the
+//! indexed lookup columns (hash, plate, date, site), so adding a serde field
+//! to a type does not require a schema change.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use tonsuu_store::{Store, StoreKind, VehicleStore};
+use tonsuu_types::{Error, HistoryEntry, LicensePlate, RegisteredVehicle, Result};
+
+/// Database file name inside the store directory
+pub const SQLITE_DB_FILE: &str = "tonsuu.db";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS json_imports (
+    store  TEXT NOT NULL,
+    key    TEXT NOT NULL,
+    digest TEXT NOT NULL,
+    PRIMARY KEY (store, key)
+);
+
+CREATE TABLE IF NOT EXISTS history (
+    image_hash       TEXT PRIMARY KEY,
+    image_path       TEXT NOT NULL,
+    analyzed_at      TEXT NOT NULL,
+    license_plate    TEXT,
+    plate_key        TEXT,
+    thumbnail_base64 TEXT,
+    data             TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_history_analyzed_at ON history(analyzed_at);
+CREATE INDEX IF NOT EXISTS idx_history_plate ON history(license_plate);
+
+CREATE TABLE IF NOT EXISTS vehicles (
+    id            TEXT PRIMARY KEY,
+    name          TEXT NOT NULL,
+    license_plate TEXT,
+    plate_key     TEXT,
+    data          TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_vehicles_plate ON vehicles(license_plate);
+CREATE INDEX IF NOT EXISTS idx_vehicles_plate_key ON vehicles(plate_key);
+
+CREATE TABLE IF NOT EXISTS weighing_slips (
+    slip_number    TEXT PRIMARY KEY,
+    date           TEXT,
+    site_name      TEXT,
+    vehicle_number TEXT NOT NULL,
+    is_overloaded  INTEGER NOT NULL,
+    data           TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_slips_date ON weighing_slips(date);
+CREATE INDEX IF NOT EXISTS idx_slips_site ON weighing_slips(site_name);
+CREATE INDEX IF NOT EXISTS idx_slips_vehicle ON weighing_slips(vehicle_number);
+CREATE INDEX IF NOT EXISTS idx_slips_overloaded ON weighing_slips(is_overloaded);
+
+CREATE TABLE IF NOT EXISTS vehicle_master (
+    vehicle_number TEXT PRIMARY KEY,
+    data           TEXT NOT NULL
+);
+";
+
+/// Convert a rusqlite error into the shared error type
+pub(crate) fn db_err(e: rusqlite::Error) -> Error {
+    Error::Database(e.to_string())
+}
+
+/// Open (or create) the database and ensure the schema exists
+pub(crate) fn open_connection(db_path: &Path) -> Result<Connection> {
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = Connection::open(db_path).map_err(db_err)?;
+    // journal_mode returns the resulting mode as a row, so use the checked variant
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+        .map_err(db_err)?;
+    conn.busy_timeout(std::time::Duration::from_secs(10))
+        .map_err(db_err)?;
+    conn.execute_batch(SCHEMA).map_err(db_err)?;
+    upgrade_schema(&mut conn)?;
+    Ok(conn)
+}
+
+/// Add what `SCHEMA` gained since a database was created
+fn upgrade_schema(conn: &mut Connection) -> Result<()> {
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(db_err)?;
+    let has_history_plate_key = tx
+        .prepare("SELECT 1 FROM pragma_table_info('history') WHERE name = 'plate_key'")
+        .and_then(|mut stmt| stmt.exists([]))
+        .map_err(db_err)?;
+    if !has_history_plate_key {
+        tx.execute_batch("ALTER TABLE history ADD COLUMN plate_key TEXT")
+            .map_err(db_err)?;
+        let plates = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT image_hash, license_plate FROM history WHERE license_plate IS NOT NULL",
+                )
+                .map_err(db_err)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(db_err)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)?
+        };
+        for (hash, plate) in plates {
+            tx.execute(
+                "UPDATE history SET plate_key = ?1 WHERE image_hash = ?2",
+                params![plate_key(&plate), hash],
+            )
+            .map_err(db_err)?;
+        }
+    }
+    tx.execute_batch("CREATE INDEX IF NOT EXISTS idx_history_plate_key ON history(plate_key)")
+        .map_err(db_err)?;
+    tx.commit().map_err(db_err)
+}
+
+/// Normalized plate key for lookups (see `LicensePlate::key`)
+pub(crate) fn plate_key(plate: &str) -> String {
+    LicensePlate::parse(plate)
+        .map(|p| p.key())
+        .unwrap_or_else(|| plate.chars().filter(|c| !c.is_whitespace()).collect())
+}
+
+/// Insert or replace a history row. The thumbnail is kept in its own column;
+/// an entry without one (e.g. read by `load_history`) keeps the stored one.
+pub(crate) fn insert_history(conn: &Connection, entry: &HistoryEntry) -> Result<()> {
+    let mut stored = entry.clone();
+    let thumbnail = stored.thumbnail_base64.take();
+    let data = serde_json::to_string(&stored)?;
+    conn.execute(
+        "INSERT INTO history
+             (image_hash, image_path, analyzed_at, license_plate, plate_key, thumbnail_base64, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(image_hash) DO UPDATE SET
+             image_path = excluded.image_path,
+             analyzed_at = excluded.analyzed_at,
+             license_plate = excluded.license_plate,
+             plate_key = excluded.plate_key,
+             thumbnail_base64 = COALESCE(excluded.thumbnail_base64, thumbnail_base64),
+             data = excluded.data",
+        params![
+            entry.image_hash,
+            entry.image_path,
+            entry.analyzed_at.to_rfc3339(),
+            entry.estimation.license_plate,
+            entry.estimation.license_plate.as_deref().map(plate_key),
+            thumbnail,
+            data
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+pub(crate) fn decode_history(data: &str, thumbnail: Option<String>) -> Result<HistoryEntry> {
+    let mut entry: HistoryEntry = serde_json::from_str(data)?;
+    entry.thumbnail_base64 = thumbnail;
+    Ok(entry)
+}
+
+/// All history rows without their thumbnails (see `load_thumbnail`), keyed
+/// by image hash
+pub(crate) fn load_history(conn: &Connection) -> Result<HashMap<String, HistoryEntry>> {
+    let mut stmt = conn.prepare("SELECT data FROM history").map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_err)?;
+
+    let mut entries = HashMap::new();
+    for row in rows {
+        let entry = decode_history(&row.map_err(db_err)?, None)?;
+        entries.insert(entry.image_hash.clone(), entry);
+    }
+    Ok(entries)
+}
+
+/// One history row without its thumbnail
+pub(crate) fn load_history_entry(conn: &Connection, hash: &str) -> Result<Option<HistoryEntry>> {
+    let data: Option<String> = conn
+        .query_row(
+            "SELECT data FROM history WHERE image_hash = ?1",
+            params![hash],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(db_err)?;
+    data.map(|data| decode_history(&data, None)).transpose()
+}
+
+/// Thumbnail of one history row
+pub(crate) fn load_thumbnail(conn: &Connection, hash: &str) -> Result<Option<String>> {
+    let thumbnail: Option<Option<String>> = conn
+        .query_row(
+            "SELECT thumbnail_base64 FROM history WHERE image_hash = ?1",
+            params![hash],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(db_err)?;
+    Ok(thumbnail.flatten())
+}
+
+/// All vehicle rows, keyed by vehicle id
+pub(crate) fn load_vehicles(conn: &Connection) -> Result<HashMap<String, RegisteredVehicle>> {
+    let mut stmt = conn.prepare("SELECT data FROM vehicles").map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_err)?;
+
+    let mut vehicles = HashMap::new();
+    for row in rows {
+        let vehicle: RegisteredVehicle = serde_json::from_str(&row.map_err(db_err)?)?;
+        vehicles.insert(vehicle.id.clone(), vehicle);
+    }
+    Ok(vehicles)
+}
+
+/// One vehicle row
+pub(crate) fn load_vehicle(conn: &Connection, id: &str) -> Result<Option<RegisteredVehicle>> {
+    let data: Option<String> = conn
+        .query_row(
+            "SELECT data FROM vehicles WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(db_err)?;
+    data.map(|data| serde_json::from_str(&data).map_err(Into::into))
+        .transpose()
+}
+
+pub(crate) fn insert_vehicle(conn: &Connection, vehicle: &RegisteredVehicle) -> Result<()> {
+    let data = serde_json::to_string(vehicle)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO vehicles (id, name, license_plate, plate_key, data)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            vehicle.id,
+            vehicle.name,
+            vehicle.license_plate,
+            vehicle.license_plate.as_deref().map(plate_key),
+            data
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+/// Result of importing the JSON stores into SQLite
+#[derive(Debug, Clone, Default)]
+pub struct SqliteMigrationReport {
+    /// History entries written to the database by this import
+    pub history_count: usize,
+    /// Vehicles written to the database by this import
+    pub vehicle_count: usize,
+    /// False when there was nothing new to import
+    pub migrated: bool,
+}
+
+/// Modification time and size of the JSON store files, to skip unchanged ones
+fn json_stamp(store_dir: &Path) -> String {
+    [StoreKind::History, StoreKind::Vehicles]
+        .iter()
+        .map(
+            |kind| match std::fs::metadata(store_dir.join(kind.file_name())) {
+                Ok(meta) => {
+                    let modified = meta
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|d| d.as_nanos())
+                        .unwrap_or_default();
+                    format!("{}:{}", modified, meta.len())
+                }
+                Err(_) => "-".to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn record_digest<T: Serialize>(record: &T) -> Result<String> {
+    // Through Value so map fields serialize in a stable (sorted) order
+    let json = serde_json::to_string(&serde_json::to_value(record)?)?;
+    Ok(format!("{:x}", Sha256::digest(json.as_bytes())))
+}
+
+/// Import the records of one JSON store that changed since the last import.
+///
+/// A record is written when it is missing from the database, or when its JSON
+/// differs from what was imported last time. Records unchanged in JSON keep
+/// their database version, so edits made through SQLite are not overwritten.
+fn import_changed<T: Serialize>(
+    conn: &Connection,
+    store: &str,
+    records: &[(&String, &T)],
+    exists: impl Fn(&str) -> Result<bool>,
+    insert: impl Fn(&T) -> Result<()>,
+) -> Result<usize> {
+    let mut imported = 0;
+    for (key, record) in records {
+        let digest = record_digest(record)?;
+        let previous: Option<String> = conn
+            .query_row(
+                "SELECT digest FROM json_imports WHERE store = ?1 AND key = ?2",
+                params![store, key.as_str()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_err)?;
+        let changed = match previous {
+            Some(previous) => previous != digest,
+            None => !exists(key.as_str())?,
+        };
+        if changed {
+            insert(record)?;
+            imported += 1;
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO json_imports (store, key, digest) VALUES (?1, ?2, ?3)",
+            params![store, key.as_str(), digest],
+        )
+        .map_err(db_err)?;
+    }
+    Ok(imported)
+}
+
+fn row_exists(conn: &Connection, sql: &str, key: &str) -> Result<bool> {
+    conn.query_row(sql, params![key], |_| Ok(()))
+        .optional()
+        .map(|row| row.is_some())
+        .map_err(db_err)
+}
+
+/// Import history.json and vehicles.json from `store_dir` into the database.
+///
+/// The first call copies everything. Later calls pick up entries added or
+/// changed in the JSON files since the previous import (e.g. by a process
+/// still on the JSON backend), and return immediately when neither file has
+/// been modified. Deletions in JSON are not propagated. The JSON files are
+/// left untouched.
+pub fn migrate_json_to_sqlite(store_dir: &Path, db_path: &Path) -> Result<SqliteMigrationReport> {
+    let mut conn = open_connection(db_path)?;
+
+    // Taken before reading the files, so a write racing this import changes
+    // the stamp and is picked up next time
+    let stamp = json_stamp(store_dir);
+    let previous: Option<String> = conn
+        .query_row(
+            "SELECT value FROM meta WHERE key = 'json_stamp'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(db_err)?;
+    if previous.as_deref() == Some(stamp.as_str()) {
+        return Ok(SqliteMigrationReport::default());
+    }
+
+    let store = Store::open(store_dir.to_path_buf())?;
+    let vehicles = VehicleStore::open(store_dir.to_path_buf())?;
+    let history: Vec<_> = store
+        .all_entries()
+        .into_iter()
+        .map(|entry| (&entry.image_hash, entry))
+        .collect();
+    let vehicle_records: Vec<_> = vehicles
+        .all_vehicles()
+        .into_iter()
+        .map(|vehicle| (&vehicle.id, vehicle))
+        .collect();
+
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(db_err)?;
+    let history_count = import_changed(
+        &tx,
+        "history",
+        &history,
+        |key| row_exists(&tx, "SELECT 1 FROM history WHERE image_hash = ?1", key),
+        |entry| insert_history(&tx, entry),
+    )?;
+    let vehicle_count = import_changed(
+        &tx,
+        "vehicles",
+        &vehicle_records,
+        |key| row_exists(&tx, "SELECT 1 FROM vehicles WHERE id = ?1", key),
+        |vehicle| insert_vehicle(&tx, vehicle),
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('json_stamp', ?1)",
+        params![stamp],
+    )
+    .map_err(db_err)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('migrated_from_json', ?1)",
+        params![chrono::Utc::now().to_rfc3339()],
+    )
+    .map_err(db_err)?;
+    tx.commit().map_err(db_err)?;
+
+    Ok(SqliteMigrationReport {
+        history_count,
+        vehicle_count,
+        migrated: history_count > 0 || vehicle_count > 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tonsuu_types::EstimationResult;
+
+    #[test]
+    fn test_migrate_json_once() {
+        let dir = TempDir::new().unwrap();
+        let image = dir.path().join("truck.jpg");
+        std::fs::write(&image, b"image").unwrap();
+
+        let mut store = Store::open(dir.path().to_path_buf()).unwrap();
+        store
+            .add_analysis(&image, EstimationResult::default())
+            .unwrap();
+        let mut vehicles = VehicleStore::open(dir.path().to_path_buf()).unwrap();
+        vehicles
+            .add_vehicle(RegisteredVehicle::new("日野".to_string(), 10.0))
+            .unwrap();
+
+        let db_path = dir.path().join(SQLITE_DB_FILE);
+        let report = migrate_json_to_sqlite(dir.path(), &db_path).unwrap();
+        assert!(report.migrated);
+        assert_eq!(report.history_count, 1);
+        assert_eq!(report.vehicle_count, 1);
+
+        let again = migrate_json_to_sqlite(dir.path(), &db_path).unwrap();
+        assert!(!again.migrated);
+    }
+
+    #[test]
+    fn test_later_json_writes_reach_database() {
+        let dir = TempDir::new().unwrap();
+        let first = dir.path().join("first.jpg");
+        let second = dir.path().join("second.jpg");
+        std::fs::write(&first, b"first").unwrap();
+        std::fs::write(&second, b"second").unwrap();
+
+        let mut store = Store::open(dir.path().to_path_buf()).unwrap();
+        store
+            .add_analysis(&first, EstimationResult::default())
+            .unwrap();
+        let mut vehicles = VehicleStore::open(dir.path().to_path_buf()).unwrap();
+        let id = vehicles
+            .add_vehicle(RegisteredVehicle::new("日野".to_string(), 10.0))
+            .unwrap();
+
+        let db_path = dir.path().join(SQLITE_DB_FILE);
+        migrate_json_to_sqlite(dir.path(), &db_path).unwrap();
+
+        // Edited through SQLite, unchanged in JSON: the database keeps its version
+        let conn = open_connection(&db_path).unwrap();
+        let mut renamed = vehicles.get_vehicle(&id).unwrap().clone();
+        renamed.name = "日野 プロフィア".to_string();
+        insert_vehicle(&conn, &renamed).unwrap();
+
+        // Written to JSON after the first import (e.g. by a JSON-backend process)
+        store
+            .add_analysis(&second, EstimationResult::default())
+            .unwrap();
+
+        let report = migrate_json_to_sqlite(dir.path(), &db_path).unwrap();
+        assert!(report.migrated);
+        assert_eq!(report.history_count, 1);
+        assert_eq!(report.vehicle_count, 0);
+        assert_eq!(load_history(&conn).unwrap().len(), 2);
+        assert_eq!(load_vehicles(&conn).unwrap()[&id].name, "日野 プロフィア");
+    }
+
+    #[test]
+    fn test_history_without_plate_key_is_upgraded() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join(SQLITE_DB_FILE);
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE history (
+                     image_hash       TEXT PRIMARY KEY,
+                     image_path       TEXT NOT NULL,
+                     analyzed_at      TEXT NOT NULL,
+                     license_plate    TEXT,
+                     thumbnail_base64 TEXT,
+                     data             TEXT NOT NULL
+                 );
+                 INSERT INTO history VALUES ('a', 'a.jpg', '', '熊本 130 ら 11-22', NULL, '{}');",
+            )
+            .unwrap();
+
+        let conn = open_connection(&db_path).unwrap();
+        let key: String = conn
+            .query_row(
+                "SELECT plate_key FROM history WHERE image_hash = 'a'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(key, plate_key("熊本130ら1122"));
+    }
+}
@@ -0,0 +1,214 @@
+//! SQLite storage backend for `Store` and `VehicleStore`
+//!
+//! Lets the stores used throughout the app (analysis, batch, GUI) persist to
+//! the same database as the SQLite repositories when `storage_backend = "sqlite"`.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, TransactionBehavior};
+
+use tonsuu_store::{EntryEdit, EntryStorage};
+use tonsuu_types::{Error, HistoryEntry, RegisteredVehicle, Result};
+
+use super::sqlite_db::{
+    db_err, insert_history, insert_vehicle, load_history, load_history_entry, load_thumbnail,
+    load_vehicle, load_vehicles, open_connection,
+};
+
+/// A store entry type with a table in the database
+pub trait SqliteRecord: Sized {
+    /// Table holding the records
+    const TABLE: &'static str;
+    /// Primary key column, holding the store map key
+    const KEY: &'static str;
+
+    /// All rows, keyed like the store map
+    fn load_all(conn: &Connection) -> Result<HashMap<String, Self>>;
+    /// The row with the given key
+    fn load(conn: &Connection, key: &str) -> Result<Option<Self>>;
+    /// Insert or replace the row for this record
+    fn save(&self, conn: &Connection) -> Result<()>;
+    /// Thumbnail left out by `load_all`/`load`, if the table has one
+    fn load_thumbnail(_conn: &Connection, _key: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+impl SqliteRecord for HistoryEntry {
+    const TABLE: &'static str = "history";
+    const KEY: &'static str = "image_hash";
+
+    fn load_all(conn: &Connection) -> Result<HashMap<String, Self>> {
+        load_history(conn)
+    }
+
+    fn load(conn: &Connection, key: &str) -> Result<Option<Self>> {
+        load_history_entry(conn, key)
+    }
+
+    fn save(&self, conn: &Connection) -> Result<()> {
+        insert_history(conn, self)
+    }
+
+    fn load_thumbnail(conn: &Connection, key: &str) -> Result<Option<String>> {
+        load_thumbnail(conn, key)
+    }
+}
+
+impl SqliteRecord for RegisteredVehicle {
+    const TABLE: &'static str = "vehicles";
+    const KEY: &'static str = "id";
+
+    fn load_all(conn: &Connection) -> Result<HashMap<String, Self>> {
+        load_vehicles(conn)
+    }
+
+    fn load(conn: &Connection, key: &str) -> Result<Option<Self>> {
+        load_vehicle(conn, key)
+    }
+
+    fn save(&self, conn: &Connection) -> Result<()> {
+        insert_vehicle(conn, self)
+    }
+}
+
+/// `EntryStorage` over one table of the SQLite database
+///
+/// Each update reads, writes or deletes only the row under its key, in an
+/// immediate (write-locked) transaction. History thumbnails stay out of
+/// `load` and are read per entry.
+pub struct SqliteStorage<T> {
+    conn: Mutex<Connection>,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<T: SqliteRecord> SqliteStorage<T> {
+    /// Open (or create) the database file
+    pub fn open(db_path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: Mutex::new(open_connection(db_path)?),
+            _record: PhantomData,
+        })
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| Error::Database("SQLite connection lock poisoned".to_string()))
+    }
+}
+
+impl<T: SqliteRecord> EntryStorage<T> for SqliteStorage<T> {
+    fn load(&self) -> Result<HashMap<String, T>> {
+        let conn = self.conn()?;
+        T::load_all(&conn)
+    }
+
+    fn update_entry(&self, key: &str, f: &mut EntryEdit<'_, T>) -> Result<Option<T>> {
+        let mut conn = self.conn()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_err)?;
+
+        let mut slot = T::load(&tx, key)?;
+        f(&mut slot)?;
+        match &slot {
+            Some(record) => record.save(&tx)?,
+            None => {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE {} = ?1", T::TABLE, T::KEY),
+                    params![key],
+                )
+                .map_err(db_err)?;
+            }
+        }
+        tx.commit().map_err(db_err)?;
+        Ok(slot)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.conn()?
+            .execute(&format!("DELETE FROM {}", T::TABLE), [])
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    fn load_thumbnail(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.conn()?;
+        T::load_thumbnail(&conn, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tonsuu_store::{Store, VehicleStore};
+    use tonsuu_types::EstimationResult;
+
+    #[test]
+    fn test_vehicle_store_on_sqlite() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("tonsuu.db");
+        let open = || {
+            VehicleStore::with_storage(Box::new(
+                SqliteStorage::<RegisteredVehicle>::open(&db_path).unwrap(),
+            ))
+            .unwrap()
+        };
+
+        let mut first = open();
+        let mut second = open();
+        let a = first
+            .add_vehicle(RegisteredVehicle::new("日野".to_string(), 10.0))
+            .unwrap();
+        second
+            .add_vehicle(RegisteredVehicle::new("いすゞ".to_string(), 4.0))
+            .unwrap();
+        assert_eq!(open().count(), 2);
+
+        // The row is removed even though this store never loaded it
+        assert!(second.remove_vehicle(&a).unwrap());
+        assert_eq!(open().count(), 1);
+        // JSON files are not involved
+        assert!(!dir.path().join("vehicles.json").exists());
+    }
+
+    #[test]
+    fn test_history_thumbnails_are_read_on_demand() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("tonsuu.db");
+        let image = dir.path().join("truck.jpg");
+        std::fs::write(&image, b"image").unwrap();
+        let open = || {
+            Store::with_storage(Box::new(
+                SqliteStorage::<HistoryEntry>::open(&db_path).unwrap(),
+            ))
+            .unwrap()
+        };
+
+        let hash = open()
+            .add_analysis_with_capacity(
+                &image,
+                EstimationResult::default(),
+                Some(4.0),
+                Some("dGh1bWI=".to_string()),
+            )
+            .unwrap();
+
+        let mut store = open();
+        assert!(store.get_by_hash(&hash).unwrap().thumbnail_base64.is_none());
+        assert_eq!(store.thumbnail(&hash).unwrap().as_deref(), Some("dGh1bWI="));
+
+        // Rewriting the row from the loaded entry keeps the thumbnail
+        store.add_feedback(&image, 3.9, None).unwrap();
+        assert_eq!(store.get_by_hash(&hash).unwrap().actual_tonnage, Some(3.9));
+        assert_eq!(
+            open().thumbnail(&hash).unwrap().as_deref(),
+            Some("dGh1bWI=")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! SQLite-based implementation of VehicleMasterRepository
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use tonsuu_domain::model::VehicleMaster;
+use tonsuu_domain::repository::VehicleMasterRepository;
+use tonsuu_types::{Error, Result};
+
+use super::sqlite_db::{db_err, open_connection};
+
+/// SQLite VehicleMaster repository (keyed by vehicle number)
+pub struct SqliteVehicleMasterRepository {
+    conn: Connection,
+}
+
+impl SqliteVehicleMasterRepository {
+    /// Open (or create) the vehicle_master table in the given database file
+    pub fn open(db_path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: open_connection(db_path)?,
+        })
+    }
+
+    /// Insert or replace master entries; returns the count written
+    pub fn import(&self, vehicles: &[VehicleMaster]) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction().map_err(db_err)?;
+        for vehicle in vehicles {
+            tx.execute(
+                "INSERT OR REPLACE INTO vehicle_master (vehicle_number, data) VALUES (?1, ?2)",
+                params![vehicle.vehicle_number, serde_json::to_string(vehicle)?],
+            )
+            .map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)?;
+        Ok(vehicles.len())
+    }
+
+    /// True until a master has been imported
+    pub fn is_empty(&self) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT NOT EXISTS (SELECT 1 FROM vehicle_master)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(db_err)
+    }
+}
+
+impl VehicleMasterRepository for SqliteVehicleMasterRepository {
+    fn find_all(&self) -> std::result::Result<Vec<VehicleMaster>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT data FROM vehicle_master ORDER BY vehicle_number")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_err)?;
+
+        let mut vehicles = Vec::new();
+        for row in rows {
+            vehicles.push(serde_json::from_str(&row.map_err(db_err)?)?);
+        }
+        Ok(vehicles)
+    }
+
+    fn find_by_number(
+        &self,
+        vehicle_number: &str,
+    ) -> std::result::Result<Option<VehicleMaster>, Error> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM vehicle_master WHERE vehicle_number = ?1",
+                params![vehicle_number],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_err)?;
+        data.map(|d| serde_json::from_str(&d).map_err(Error::from))
+            .transpose()
+    }
+}
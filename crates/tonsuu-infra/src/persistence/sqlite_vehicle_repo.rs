@@ -0,0 +1,106 @@
+//! SQLite-based registered vehicle repository implementation
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use tonsuu_domain::repository::VehicleRepository;
+use tonsuu_types::{Error, RegisteredVehicle, Result};
+
+use super::sqlite_db::{db_err, insert_vehicle, open_connection, plate_key};
+
+/// SQLite implementation of VehicleRepository
+///
+/// Plates are indexed both verbatim and normalized (`LicensePlate::key`).
+pub struct SqliteVehicleRepository {
+    conn: Connection,
+}
+
+impl SqliteVehicleRepository {
+    /// Open (or create) the vehicles table in the given database file
+    pub fn open(db_path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: open_connection(db_path)?,
+        })
+    }
+
+    /// Remove a vehicle by ID
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let n = self
+            .conn
+            .execute("DELETE FROM vehicles WHERE id = ?1", params![id])
+            .map_err(db_err)?;
+        Ok(n > 0)
+    }
+
+    fn find_one(&self, sql: &str, key: &str) -> Result<Option<RegisteredVehicle>> {
+        let data: Option<String> = self
+            .conn
+            .query_row(sql, params![key], |row| row.get(0))
+            .optional()
+            .map_err(db_err)?;
+        data.map(|d| serde_json::from_str(&d).map_err(Error::from))
+            .transpose()
+    }
+}
+
+impl VehicleRepository for SqliteVehicleRepository {
+    fn save(&self, vehicle: &RegisteredVehicle) -> std::result::Result<(), Error> {
+        insert_vehicle(&self.conn, vehicle)
+    }
+
+    fn find_by_plate(&self, plate: &str) -> std::result::Result<Option<RegisteredVehicle>, Error> {
+        if let Some(v) = self.find_one(
+            "SELECT data FROM vehicles WHERE license_plate = ?1 LIMIT 1",
+            plate,
+        )? {
+            return Ok(Some(v));
+        }
+        self.find_one(
+            "SELECT data FROM vehicles WHERE plate_key = ?1 LIMIT 1",
+            &plate_key(plate),
+        )
+    }
+
+    fn find_all(&self) -> std::result::Result<Vec<RegisteredVehicle>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT data FROM vehicles ORDER BY name")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_err)?;
+
+        let mut vehicles = Vec::new();
+        for row in rows {
+            vehicles.push(serde_json::from_str(&row.map_err(db_err)?)?);
+        }
+        Ok(vehicles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_by_plate_normalized() {
+        let dir = TempDir::new().unwrap();
+        let repo = SqliteVehicleRepository::open(&dir.path().join("t.db")).unwrap();
+
+        let vehicle = RegisteredVehicle::new("日野".to_string(), 10.0)
+            .with_license_plate("品川 100 あ 12-34".to_string());
+        repo.save(&vehicle).unwrap();
+
+        assert!(repo.find_by_plate("品川 100 あ 12-34").unwrap().is_some());
+        assert!(repo.find_by_plate("品川100あ1234").unwrap().is_some());
+        assert!(repo
+            .find_by_plate("品川 １００ あ １２－３４")
+            .unwrap()
+            .is_some());
+        assert!(repo.find_by_plate("9999").unwrap().is_none());
+        assert_eq!(repo.find_all().unwrap().len(), 1);
+        assert!(repo.remove(&vehicle.id).unwrap());
+    }
+}
@@ -0,0 +1,158 @@
+//! SQLite-based implementation of WeighingSlipRepository
+//!
+//! Slips are imported from CSV (see `csv_loader`) and then queried through
+//! indexes on date, site, vehicle number and overload flag.
+
+use std::path::Path;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection};
+
+use tonsuu_domain::model::WeighingSlip;
+use tonsuu_domain::repository::WeighingSlipRepository;
+use tonsuu_types::{Error, Result};
+
+use super::sqlite_db::{db_err, open_connection};
+
+/// SQLite WeighingSlip repository
+pub struct SqliteWeighingSlipRepository {
+    conn: Connection,
+}
+
+impl SqliteWeighingSlipRepository {
+    /// Open (or create) the weighing_slips table in the given database file
+    pub fn open(db_path: &Path) -> Result<Self> {
+        Ok(Self {
+            conn: open_connection(db_path)?,
+        })
+    }
+
+    /// Insert or replace slips (keyed by slip number); returns the count written
+    pub fn import(&self, slips: &[WeighingSlip]) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction().map_err(db_err)?;
+        for slip in slips {
+            tx.execute(
+                "INSERT OR REPLACE INTO weighing_slips
+                     (slip_number, date, site_name, vehicle_number, is_overloaded, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    slip.slip_number,
+                    slip.date.map(|d| d.format("%Y-%m-%d").to_string()),
+                    slip.site_name,
+                    slip.vehicle_number,
+                    slip.is_overloaded,
+                    serde_json::to_string(slip)?
+                ],
+            )
+            .map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)?;
+        Ok(slips.len())
+    }
+
+    /// True until slips have been imported
+    pub fn is_empty(&self) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT NOT EXISTS (SELECT 1 FROM weighing_slips)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(db_err)
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<WeighingSlip>> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_err)?;
+        let rows = stmt
+            .query_map(params, |row| row.get::<_, String>(0))
+            .map_err(db_err)?;
+
+        let mut slips = Vec::new();
+        for row in rows {
+            slips.push(serde_json::from_str(&row.map_err(db_err)?)?);
+        }
+        Ok(slips)
+    }
+}
+
+impl WeighingSlipRepository for SqliteWeighingSlipRepository {
+    fn find_all(&self) -> std::result::Result<Vec<WeighingSlip>, Error> {
+        self.query(
+            "SELECT data FROM weighing_slips ORDER BY date, slip_number",
+            [],
+        )
+    }
+
+    fn find_by_date(&self, date: NaiveDate) -> std::result::Result<Vec<WeighingSlip>, Error> {
+        self.query(
+            "SELECT data FROM weighing_slips WHERE date = ?1 ORDER BY slip_number",
+            params![date.format("%Y-%m-%d").to_string()],
+        )
+    }
+
+    fn find_by_site(&self, site_name: &str) -> std::result::Result<Vec<WeighingSlip>, Error> {
+        // Same semantics as the CSV repository: substring match on the site name
+        self.query(
+            "SELECT data FROM weighing_slips
+             WHERE site_name = ?1 OR instr(site_name, ?1) > 0
+             ORDER BY date, slip_number",
+            params![site_name],
+        )
+    }
+
+    fn find_by_vehicle(
+        &self,
+        vehicle_number: &str,
+    ) -> std::result::Result<Vec<WeighingSlip>, Error> {
+        self.query(
+            "SELECT data FROM weighing_slips WHERE vehicle_number = ?1 ORDER BY date, slip_number",
+            params![vehicle_number],
+        )
+    }
+
+    fn find_overloaded(&self) -> std::result::Result<Vec<WeighingSlip>, Error> {
+        self.query(
+            "SELECT data FROM weighing_slips WHERE is_overloaded = 1 ORDER BY date, slip_number",
+            [],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn slip(number: &str, vehicle: &str, overloaded: bool) -> WeighingSlip {
+        WeighingSlip {
+            slip_number: number.to_string(),
+            date: NaiveDate::from_ymd_opt(2025, 11, 28),
+            material_type: Some("ASガラ".to_string()),
+            weight_tons: 4.0,
+            cumulative_tons: None,
+            delivery_count: None,
+            vehicle_number: vehicle.to_string(),
+            transport_company: None,
+            site_name: Some("長嶺南6丁目".to_string()),
+            max_capacity: Some(3.75),
+            is_overloaded: overloaded,
+        }
+    }
+
+    #[test]
+    fn test_import_and_query() {
+        let dir = TempDir::new().unwrap();
+        let repo = SqliteWeighingSlipRepository::open(&dir.path().join("t.db")).unwrap();
+        assert!(repo.is_empty().unwrap());
+        repo.import(&[slip("S001", "1122", true), slip("S002", "1111", false)])
+            .unwrap();
+
+        assert!(!repo.is_empty().unwrap());
+        assert_eq!(repo.find_all().unwrap().len(), 2);
+        assert_eq!(repo.find_overloaded().unwrap().len(), 1);
+        assert_eq!(repo.find_by_vehicle("1111").unwrap().len(), 1);
+        assert_eq!(repo.find_by_site("長嶺").unwrap().len(), 2);
+        let date = NaiveDate::from_ymd_opt(2025, 11, 28).unwrap();
+        assert_eq!(repo.find_by_date(date).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,289 @@
+//! CSV import of registered vehicles, including their cargo bed dimensions
+//!
+//! The first row is a header; columns are matched by name, so any subset and
+//! order works (e.g. a plate column plus the bed columns to add dimensions to
+//! vehicles that are already registered). A unit suffix such as "荷台長(m)"
+//! is ignored. UTF-8 (with or without BOM) and CP932 files are accepted.
+//!
+//! | Field              | Header names                        |
+//! |--------------------|-------------------------------------|
+//! | license plate      | license_plate, plate, ナンバー, 車両番号 |
+//! | name               | name, 車両名, 車名                   |
+//! | max capacity (t)   | max_capacity, capacity, 最大積載量, 積載量 |
+//! | company            | company, 運送会社                    |
+//! | bed length (m)     | bed_length, 荷台長                   |
+//! | bed width (m)      | bed_width, 荷台幅                    |
+//! | bed height (m)     | bed_height, 荷台高                   |
+//! | side wall (m)      | side_wall_height, 側板高, アオリ高    |
+//! | body type          | body_type, 架装 (標準/深ダンプ/ロング) |
+
+use std::path::Path;
+
+use encoding_rs::SHIFT_JIS;
+
+use crate::csv_loader::CsvLoaderError;
+use tonsuu_store::VehicleStore;
+use tonsuu_types::{BodyType, RegisteredVehicle};
+
+/// One row of a vehicle CSV; empty cells are None
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VehicleCsvRow {
+    /// 1-based line number in the file (header is line 1)
+    pub row: usize,
+    pub license_plate: Option<String>,
+    pub name: Option<String>,
+    pub max_capacity: Option<f64>,
+    pub company: Option<String>,
+    pub bed_length: Option<f64>,
+    pub bed_width: Option<f64>,
+    pub bed_height: Option<f64>,
+    pub side_wall_height: Option<f64>,
+    pub body_type: Option<BodyType>,
+}
+
+/// Outcome of importing vehicle rows into the store
+#[derive(Debug, Default)]
+pub struct VehicleImportResult {
+    pub added: usize,
+    pub updated: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    LicensePlate,
+    Name,
+    MaxCapacity,
+    Company,
+    BedLength,
+    BedWidth,
+    BedHeight,
+    SideWallHeight,
+    BodyType,
+}
+
+impl Column {
+    fn from_header(header: &str) -> Option<Self> {
+        let name = header
+            .split(['(', '（', '['])
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        match name.as_str() {
+            "license_plate" | "plate" | "ナンバー" | "車両番号" => Some(Column::LicensePlate),
+            "name" | "車両名" | "車名" => Some(Column::Name),
+            "max_capacity" | "capacity" | "最大積載量" | "積載量" => Some(Column::MaxCapacity),
+            "company" | "運送会社" => Some(Column::Company),
+            "bed_length" | "荷台長" | "荷台長さ" => Some(Column::BedLength),
+            "bed_width" | "荷台幅" => Some(Column::BedWidth),
+            "bed_height" | "荷台高" | "荷台高さ" => Some(Column::BedHeight),
+            "side_wall_height" | "側板高" | "アオリ高" | "あおり高" => Some(Column::SideWallHeight),
+            "body_type" | "架装" | "ボディ" => Some(Column::BodyType),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Column::LicensePlate => "license_plate",
+            Column::Name => "name",
+            Column::MaxCapacity => "max_capacity",
+            Column::Company => "company",
+            Column::BedLength => "bed_length",
+            Column::BedWidth => "bed_width",
+            Column::BedHeight => "bed_height",
+            Column::SideWallHeight => "side_wall_height",
+            Column::BodyType => "body_type",
+        }
+    }
+}
+
+/// Load vehicle rows from a CSV file with a header row
+pub fn load_vehicle_csv(path: &Path) -> Result<Vec<VehicleCsvRow>, CsvLoaderError> {
+    let bytes = std::fs::read(path)?;
+    let text = match std::str::from_utf8(&bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => SHIFT_JIS.decode(&bytes).0.into_owned(),
+    };
+    parse_vehicle_csv(&text)
+}
+
+/// Parse vehicle rows from CSV text with a header row
+pub fn parse_vehicle_csv(text: &str) -> Result<Vec<VehicleCsvRow>, CsvLoaderError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+
+    let columns: Vec<Option<Column>> = reader.headers()?.iter().map(Column::from_header).collect();
+    if !columns
+        .iter()
+        .any(|c| matches!(c, Some(Column::LicensePlate) | Some(Column::Name)))
+    {
+        return Err(CsvLoaderError::MissingColumn("ナンバー or 車両名".to_string()));
+    }
+
+    let mut rows = Vec::new();
+    for (row_idx, record) in reader.records().enumerate() {
+        let record = record?;
+        let mut row = VehicleCsvRow {
+            row: row_idx + 2,
+            ..Default::default()
+        };
+        for (column, value) in columns.iter().zip(record.iter()) {
+            let Some(column) = column else {
+                continue;
+            };
+            if value.is_empty() {
+                continue;
+            }
+            let line = row.row;
+            let number = || {
+                value.parse::<f64>().ok().filter(|v| *v > 0.0).ok_or_else(|| {
+                    CsvLoaderError::InvalidNumber {
+                        row: line,
+                        column: column.name().to_string(),
+                        value: value.to_string(),
+                    }
+                })
+            };
+            match column {
+                Column::LicensePlate => row.license_plate = Some(value.to_string()),
+                Column::Name => row.name = Some(value.to_string()),
+                Column::Company => row.company = Some(value.to_string()),
+                Column::MaxCapacity => row.max_capacity = Some(number()?),
+                Column::BedLength => row.bed_length = Some(number()?),
+                Column::BedWidth => row.bed_width = Some(number()?),
+                Column::BedHeight => row.bed_height = Some(number()?),
+                Column::SideWallHeight => row.side_wall_height = Some(number()?),
+                Column::BodyType => {
+                    row.body_type = Some(BodyType::from_name(value).ok_or_else(|| {
+                        CsvLoaderError::InvalidValue {
+                            row: line,
+                            column: column.name().to_string(),
+                            value: value.to_string(),
+                        }
+                    })?)
+                }
+            }
+        }
+        if row.license_plate.is_some() || row.name.is_some() {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+/// Copy the non-empty cells of a row onto a vehicle (the plate is only
+/// used to find the vehicle, so a registered plate keeps its spelling)
+fn apply_row(vehicle: &mut RegisteredVehicle, row: &VehicleCsvRow) {
+    if let Some(ref name) = row.name {
+        vehicle.name = name.clone();
+    }
+    if let Some(capacity) = row.max_capacity {
+        vehicle.max_capacity = capacity;
+    }
+    if row.company.is_some() {
+        vehicle.company = row.company.clone();
+    }
+    vehicle.bed_length = row.bed_length.or(vehicle.bed_length);
+    vehicle.bed_width = row.bed_width.or(vehicle.bed_width);
+    vehicle.bed_height = row.bed_height.or(vehicle.bed_height);
+    vehicle.side_wall_height = row.side_wall_height.or(vehicle.side_wall_height);
+    vehicle.body_type = row.body_type.or(vehicle.body_type);
+}
+
+/// Add or update registered vehicles from CSV rows.
+///
+/// A row whose plate matches a registered vehicle updates that vehicle with
+/// its non-empty cells. Other rows are registered as new vehicles, which
+/// needs a name and max capacity.
+pub fn import_vehicle_rows(
+    vehicle_store: &mut VehicleStore,
+    rows: &[VehicleCsvRow],
+) -> VehicleImportResult {
+    let mut result = VehicleImportResult::default();
+
+    for row in rows {
+        let existing = row
+            .license_plate
+            .as_deref()
+            .and_then(|plate| vehicle_store.get_by_license_plate(plate))
+            .cloned();
+
+        let outcome = match existing {
+            Some(mut vehicle) => {
+                apply_row(&mut vehicle, row);
+                vehicle_store.update_vehicle(vehicle).map(|_| result.updated += 1)
+            }
+            None => {
+                let (Some(name), Some(capacity)) = (row.name.clone(), row.max_capacity) else {
+                    result.errors.push(format!(
+                        "{}行目: 未登録の車両には車両名と最大積載量が必要です",
+                        row.row
+                    ));
+                    continue;
+                };
+                let mut vehicle = RegisteredVehicle::new(name, capacity);
+                vehicle.license_plate = row.license_plate.clone();
+                apply_row(&mut vehicle, row);
+                vehicle_store.add_vehicle(vehicle).map(|_| result.added += 1)
+            }
+        };
+        if let Err(e) = outcome {
+            result.errors.push(format!("{}行目: {}", row.row, e));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bed_columns() {
+        let csv = "ナンバー,車両名,最大積載量(t),荷台長(m),荷台幅(m),荷台高(m),側板高(m),架装\n\
+                   熊本 100 あ 1234,増トン1号,6.5,4.30,2.10,0.40,0.60,深ダンプ\n\
+                   熊本 100 あ 5678,,,,,,,\n";
+        let rows = parse_vehicle_csv(csv).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].max_capacity, Some(6.5));
+        assert_eq!(rows[0].bed_length, Some(4.30));
+        assert_eq!(rows[0].side_wall_height, Some(0.60));
+        assert_eq!(rows[0].body_type, Some(BodyType::Deep));
+        assert_eq!(rows[1].license_plate.as_deref(), Some("熊本 100 あ 5678"));
+        assert_eq!(rows[1].bed_length, None);
+
+        let bad = parse_vehicle_csv("plate,bed_width\nA,wide\n").unwrap_err();
+        assert!(matches!(bad, CsvLoaderError::InvalidNumber { row: 2, .. }));
+        assert!(parse_vehicle_csv("荷台長\n4.3\n").is_err());
+    }
+
+    #[test]
+    fn test_import_updates_by_plate() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VehicleStore::open(dir.path().to_path_buf()).unwrap();
+        let registered = RegisteredVehicle::new("増トン1号".to_string(), 6.5)
+            .with_license_plate("熊本 100 あ 1234".to_string());
+        let id = store.add_vehicle(registered).unwrap();
+
+        let csv = "plate,name,max_capacity,bed_length,bed_width\n\
+                   熊本100あ1234,,,4.3,2.1\n\
+                   熊本 100 あ 9999,10t2号,9.8,,\n\
+                   熊本 100 あ 0000,,,4.0,\n";
+        let rows = parse_vehicle_csv(csv).unwrap();
+        let result = import_vehicle_rows(&mut store, &rows);
+
+        assert_eq!((result.added, result.updated), (1, 1));
+        assert_eq!(result.errors.len(), 1);
+        let updated = store.get_vehicle(&id).unwrap();
+        assert_eq!(updated.name, "増トン1号");
+        assert_eq!(updated.bed_length, Some(4.3));
+        assert_eq!(updated.bed_width, Some(2.1));
+        assert_eq!(store.count(), 2);
+    }
+}
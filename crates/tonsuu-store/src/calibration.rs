@@ -0,0 +1,495 @@
+//! Per-group tonnage calibration learned from weighed loads
+//!
+//! Estimates for one truck type and material tend to be off in the same
+//! direction (see `AccuracyStats::by_truck_type`). `CalibrationModel::fit`
+//! learns a correction from raw AI tonnage to weighed tonnage for every truck
+//! type × material with enough ground truth, plus a per-truck-type and an
+//! overall fallback. The model is saved as `calibration.json` in the store
+//! directory.
+//!
+//! Two methods are supported:
+//! - `Linear`: least-squares `actual = scale * estimated + offset`
+//! - `Isotonic`: monotone step function (pool-adjacent-violators),
+//!   interpolated between steps and shifted by the end residual outside the
+//!   fitted range
+//!
+//! `cross_validate` reports k-fold MAE/RMSE before and after calibration, so a
+//! model can be judged on loads it was not fitted on.
+
+use crate::persist::{self, Recovery};
+use crate::schema::StoreKind;
+use crate::AccuracySample;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tonsuu_types::Result;
+
+/// Fewest ground-truth samples a group needs before a correction is fitted
+pub const MIN_CALIBRATION_SAMPLES: usize = 5;
+
+/// Default number of cross-validation folds
+pub const DEFAULT_CV_FOLDS: usize = 5;
+
+/// Group key part meaning "any truck type" / "any material"
+const ANY: &str = "*";
+
+/// How the correction is fitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationMethod {
+    /// Scale and offset
+    #[default]
+    Linear,
+    /// Monotone step function
+    Isotonic,
+}
+
+impl CalibrationMethod {
+    /// Parse a CLI name ("linear", "isotonic")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "linear" => Some(CalibrationMethod::Linear),
+            "isotonic" => Some(CalibrationMethod::Isotonic),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CalibrationMethod::Linear => "linear",
+            CalibrationMethod::Isotonic => "isotonic",
+        }
+    }
+}
+
+/// Fitted mapping from raw to calibrated tonnage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum CalibrationFit {
+    Linear { scale: f64, offset: f64 },
+    /// Steps as `[estimated, actual]`, ordered by estimate
+    Isotonic { points: Vec<[f64; 2]> },
+}
+
+impl CalibrationFit {
+    /// Fit `(estimated, actual)` pairs with `method`
+    pub fn fit(samples: &[&AccuracySample], method: CalibrationMethod) -> Self {
+        let mut pairs: Vec<(f64, f64)> = samples.iter().map(|s| (s.estimated, s.actual)).collect();
+        match method {
+            CalibrationMethod::Linear => fit_linear(&pairs),
+            CalibrationMethod::Isotonic => {
+                pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+                CalibrationFit::Isotonic {
+                    points: fit_isotonic(&pairs),
+                }
+            }
+        }
+    }
+
+    pub fn method(&self) -> CalibrationMethod {
+        match self {
+            CalibrationFit::Linear { .. } => CalibrationMethod::Linear,
+            CalibrationFit::Isotonic { .. } => CalibrationMethod::Isotonic,
+        }
+    }
+
+    /// Calibrated tonnage for a raw estimate (never negative)
+    pub fn apply(&self, tonnage: f64) -> f64 {
+        let calibrated = match self {
+            CalibrationFit::Linear { scale, offset } => scale * tonnage + offset,
+            CalibrationFit::Isotonic { points } => interpolate(points, tonnage),
+        };
+        calibrated.max(0.0)
+    }
+}
+
+fn fit_linear(pairs: &[(f64, f64)]) -> CalibrationFit {
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = pairs.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = pairs.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+
+    // All estimates equal, or a flat/inverted trend (noise in a small group):
+    // only the bias can be learned. A non-positive scale would make heavier
+    // loads calibrate lighter.
+    let scale = if sxx > 1e-9 { sxy / sxx } else { 1.0 };
+    let scale = if scale > 0.0 { scale } else { 1.0 };
+    CalibrationFit::Linear {
+        scale,
+        offset: mean_y - scale * mean_x,
+    }
+}
+
+/// Pool-adjacent-violators on pairs sorted by estimate
+fn fit_isotonic(sorted: &[(f64, f64)]) -> Vec<[f64; 2]> {
+    // (sum of estimates, sum of actuals, count) per block
+    let mut blocks: Vec<(f64, f64, f64)> = Vec::new();
+    for &(x, y) in sorted {
+        blocks.push((x, y, 1.0));
+        while blocks.len() >= 2 {
+            let (sx, sy, n) = blocks[blocks.len() - 1];
+            let (px, py, pn) = blocks[blocks.len() - 2];
+            if py / pn <= sy / n {
+                break;
+            }
+            blocks.pop();
+            *blocks.last_mut().unwrap() = (px + sx, py + sy, pn + n);
+        }
+    }
+    blocks.iter().map(|(sx, sy, n)| [sx / n, sy / n]).collect()
+}
+
+fn interpolate(points: &[[f64; 2]], x: f64) -> f64 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return x;
+    };
+    if x <= first[0] {
+        return x + (first[1] - first[0]);
+    }
+    if x >= last[0] {
+        return x + (last[1] - last[0]);
+    }
+    points
+        .windows(2)
+        .find(|w| x <= w[1][0])
+        .map(|w| {
+            let dx = w[1][0] - w[0][0];
+            if dx <= 1e-12 {
+                w[1][1]
+            } else {
+                w[0][1] + (x - w[0][0]) / dx * (w[1][1] - w[0][1])
+            }
+        })
+        .unwrap_or(x)
+}
+
+/// Correction for one truck type × material group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationEntry {
+    /// Truck type, or "*" for the overall fallback
+    pub truck_type: String,
+    /// Material type, or "*" for the per-truck-type fallback
+    pub material_type: String,
+    pub fit: CalibrationFit,
+    pub sample_count: usize,
+    pub fitted_at: DateTime<Utc>,
+}
+
+impl CalibrationEntry {
+    /// Display label ("4t / As殻", "4t / (all materials)", "(all)")
+    pub fn group_label(&self) -> String {
+        match (self.truck_type.as_str(), self.material_type.as_str()) {
+            (ANY, _) => "(all)".to_string(),
+            (truck, ANY) => format!("{} / (all materials)", truck),
+            (truck, material) => format!("{} / {}", truck, material),
+        }
+    }
+}
+
+fn group_key(truck_type: &str, material_type: &str) -> String {
+    format!("{}|{}", truck_type, material_type)
+}
+
+/// Calibration corrections keyed by truck type × material
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationModel {
+    entries: HashMap<String, CalibrationEntry>,
+    recovery: Option<Recovery>,
+}
+
+impl CalibrationModel {
+    /// Fit a correction for every group with at least `MIN_CALIBRATION_SAMPLES`
+    /// samples. `estimated` must be the raw (uncalibrated) AI tonnage.
+    pub fn fit(samples: &[AccuracySample], method: CalibrationMethod) -> Self {
+        let mut groups: HashMap<(&str, &str), Vec<&AccuracySample>> = HashMap::new();
+        for sample in samples {
+            let truck = sample.truck_type.as_str();
+            for key in [(truck, sample.material_type.as_str()), (truck, ANY), (ANY, ANY)] {
+                groups.entry(key).or_default().push(sample);
+            }
+        }
+
+        let fitted_at = Utc::now();
+        let entries = groups
+            .into_iter()
+            .filter(|(_, group)| group.len() >= MIN_CALIBRATION_SAMPLES)
+            .map(|((truck, material), group)| {
+                let entry = CalibrationEntry {
+                    truck_type: truck.to_string(),
+                    material_type: material.to_string(),
+                    fit: CalibrationFit::fit(&group, method),
+                    sample_count: group.len(),
+                    fitted_at,
+                };
+                (group_key(truck, material), entry)
+            })
+            .collect();
+
+        Self {
+            entries,
+            recovery: None,
+        }
+    }
+
+    /// Most specific correction for a group: same truck type and material,
+    /// then same truck type, then overall
+    pub fn lookup(&self, truck_type: &str, material_type: &str) -> Option<&CalibrationEntry> {
+        [
+            group_key(truck_type, material_type),
+            group_key(truck_type, ANY),
+            group_key(ANY, ANY),
+        ]
+        .iter()
+        .find_map(|key| self.entries.get(key))
+    }
+
+    /// Calibrated tonnage, or None if no group applies
+    pub fn calibrate(&self, truck_type: &str, material_type: &str, tonnage: f64) -> Option<f64> {
+        self.lookup(truck_type, material_type)
+            .map(|entry| entry.fit.apply(tonnage))
+    }
+
+    /// Entries ordered by truck type and material, fallbacks last
+    pub fn entries(&self) -> Vec<&CalibrationEntry> {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_by_key(|e| {
+            (
+                e.truck_type == ANY,
+                e.truck_type.clone(),
+                e.material_type == ANY,
+                e.material_type.clone(),
+            )
+        });
+        entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Load `calibration.json` from the store directory (empty if missing)
+    pub fn load(store_dir: &Path) -> Result<Self> {
+        let path = store_dir.join(StoreKind::Calibration.file_name());
+        let (entries, recovery) = persist::load_map(&path, StoreKind::Calibration)?;
+        Ok(Self { entries, recovery })
+    }
+
+    /// Set when `calibration.json` was damaged and only partly loaded
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
+    }
+
+    /// Replace `calibration.json` in the store directory
+    pub fn save(&self, store_dir: &Path) -> Result<()> {
+        fs::create_dir_all(store_dir)?;
+        let path = store_dir.join(StoreKind::Calibration.file_name());
+        let _lock = persist::lock(&path)?;
+        persist::write_map(&path, StoreKind::Calibration, &self.entries)
+    }
+}
+
+/// Mean absolute error and RMSE of a set of estimates
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorMetrics {
+    pub sample_count: usize,
+    pub mae: f64,
+    pub rmse: f64,
+}
+
+impl ErrorMetrics {
+    fn from_errors(errors: &[f64]) -> Self {
+        if errors.is_empty() {
+            return Self::default();
+        }
+        let n = errors.len() as f64;
+        Self {
+            sample_count: errors.len(),
+            mae: errors.iter().map(|e| e.abs()).sum::<f64>() / n,
+            rmse: (errors.iter().map(|e| e.powi(2)).sum::<f64>() / n).sqrt(),
+        }
+    }
+}
+
+/// Before/after errors of one group in a cross-validation run
+#[derive(Debug, Clone)]
+pub struct GroupReport {
+    /// "4t / As殻"
+    pub label: String,
+    pub before: ErrorMetrics,
+    pub after: ErrorMetrics,
+}
+
+/// Cross-validated effect of calibration
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationReport {
+    pub folds: usize,
+    /// Raw AI tonnage against the weighed tonnage
+    pub before: ErrorMetrics,
+    /// Out-of-fold calibrated tonnage against the weighed tonnage
+    pub after: ErrorMetrics,
+    /// The same comparison per truck type × material
+    pub groups: Vec<GroupReport>,
+}
+
+/// k-fold cross-validation: every sample is calibrated by a model fitted
+/// without it. Samples no group covers keep their raw estimate.
+pub fn cross_validate(
+    samples: &[AccuracySample],
+    method: CalibrationMethod,
+    folds: usize,
+) -> CalibrationReport {
+    if samples.is_empty() {
+        return CalibrationReport::default();
+    }
+    let folds = folds.clamp(2, samples.len().max(2));
+
+    let mut calibrated: Vec<f64> = samples.iter().map(|s| s.estimated).collect();
+    for fold in 0..folds {
+        let train: Vec<AccuracySample> = samples
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % folds != fold)
+            .map(|(_, s)| s.clone())
+            .collect();
+        let model = CalibrationModel::fit(&train, method);
+        for (i, sample) in samples.iter().enumerate().filter(|(i, _)| i % folds == fold) {
+            if let Some(value) =
+                model.calibrate(&sample.truck_type, &sample.material_type, sample.estimated)
+            {
+                calibrated[i] = value;
+            }
+        }
+    }
+
+    let metrics = |indices: &[usize]| {
+        let before: Vec<f64> = indices.iter().map(|&i| samples[i].error()).collect();
+        let after: Vec<f64> = indices
+            .iter()
+            .map(|&i| calibrated[i] - samples[i].actual)
+            .collect();
+        (ErrorMetrics::from_errors(&before), ErrorMetrics::from_errors(&after))
+    };
+
+    let mut by_group: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, sample) in samples.iter().enumerate() {
+        by_group
+            .entry(format!("{} / {}", sample.truck_type, sample.material_type))
+            .or_default()
+            .push(i);
+    }
+    let mut groups: Vec<GroupReport> = by_group
+        .into_iter()
+        .map(|(label, indices)| {
+            let (before, after) = metrics(&indices);
+            GroupReport { label, before, after }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.label.cmp(&b.label));
+
+    let all: Vec<usize> = (0..samples.len()).collect();
+    let (before, after) = metrics(&all);
+    CalibrationReport {
+        folds,
+        before,
+        after,
+        groups,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(estimated: f64, actual: f64, truck_type: &str, material_type: &str) -> AccuracySample {
+        AccuracySample {
+            estimated,
+            actual,
+            truck_type: truck_type.to_string(),
+            material_type: material_type.to_string(),
+            used_references: false,
+        }
+    }
+
+    /// 4t/As殻 loads estimated 10% high plus 0.2t
+    fn biased_samples() -> Vec<AccuracySample> {
+        [2.5, 3.0, 3.4, 3.8, 4.1, 4.5]
+            .iter()
+            .map(|&actual| sample(actual * 1.1 + 0.2, actual, "4t", "As殻"))
+            .collect()
+    }
+
+    #[test]
+    fn test_linear_fit_and_fallbacks() {
+        let mut samples = biased_samples();
+        samples.push(sample(9.0, 9.5, "10t", "土砂"));
+        let model = CalibrationModel::fit(&samples, CalibrationMethod::Linear);
+
+        let calibrated = model.calibrate("4t", "As殻", 4.0 * 1.1 + 0.2).unwrap();
+        assert!((calibrated - 4.0).abs() < 1e-9);
+
+        // Unknown material falls back to the truck type; unknown truck type to overall
+        assert_eq!(model.lookup("4t", "Co殻").unwrap().material_type, "*");
+        assert_eq!(model.lookup("10t", "土砂").unwrap().truck_type, "*");
+        assert_eq!(model.entries().last().unwrap().group_label(), "(all)");
+    }
+
+    #[test]
+    fn test_inverted_trend_falls_back_to_offset() {
+        let fit = fit_linear(&[(3.0, 4.0), (4.0, 3.8), (5.0, 3.6)]);
+        let CalibrationFit::Linear { scale, offset } = fit else {
+            panic!("expected a linear fit");
+        };
+        assert_eq!(scale, 1.0);
+        assert!((offset - (3.8 - 4.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_isotonic_is_monotone() {
+        let pairs = [(1.0, 1.2), (2.0, 2.5), (3.0, 2.1), (4.0, 3.9)];
+        let points = fit_isotonic(&pairs);
+        // 2.5 and 2.1 violate monotonicity and are pooled
+        assert_eq!(points.len(), 3);
+        assert!((points[1][1] - 2.3).abs() < 1e-9);
+        assert!(points.windows(2).all(|w| w[0][1] <= w[1][1]));
+
+        let fit = CalibrationFit::Isotonic { points };
+        assert!((fit.apply(5.0) - 4.9).abs() < 1e-9);
+        assert!((fit.apply(1.75) - (1.2 + 0.5 * 1.1)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cross_validation_reduces_error() {
+        let samples: Vec<AccuracySample> = biased_samples()
+            .into_iter()
+            .chain(biased_samples().into_iter().map(|mut s| {
+                s.estimated += 0.05;
+                s
+            }))
+            .collect();
+        let report = cross_validate(&samples, CalibrationMethod::Linear, DEFAULT_CV_FOLDS);
+        assert_eq!(report.folds, 5);
+        assert_eq!(report.before.sample_count, 12);
+        assert!(report.after.mae < report.before.mae / 2.0);
+        assert!(report.after.rmse < report.before.rmse);
+        assert_eq!(report.groups.len(), 1);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+
+        let model = CalibrationModel::fit(&biased_samples(), CalibrationMethod::Isotonic);
+        model.save(dir).unwrap();
+        let loaded = CalibrationModel::load(dir).unwrap();
+        assert_eq!(loaded.entries().len(), model.entries().len());
+        let entry = loaded.lookup("4t", "As殻").unwrap();
+        assert_eq!(entry.fit.method(), CalibrationMethod::Isotonic);
+        assert_eq!(entry.sample_count, 6);
+        assert!((entry.fit.apply(3.5) - model.calibrate("4t", "As殻", 3.5).unwrap()).abs() < 1e-9);
+        assert!(CalibrationModel::load(&dir.join("missing")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+//! Prediction intervals for tonnage estimates
+//!
+//! The P10/P90 bounds combine two sources of uncertainty: the spread of the
+//! ensemble samples for this image, and how far past estimates for the same
+//! truck type and material were from the weighed tonnage. Both are treated as
+//! normal errors and added in quadrature; the historical mean error (bias) shifts
+//! the interval centre, unless the estimate was already corrected by the
+//! calibration model (`raw_tonnage` is set), which removes that bias itself.
+
+use crate::{AccuracySample, AccuracyStats};
+use tonsuu_types::{EstimationResult, TonnageInterval};
+
+/// z-score of the 90th percentile of the standard normal distribution
+const Z_P90: f64 = 1.2816;
+
+/// Fewest ground-truth samples a group needs before its errors are used
+const MIN_HISTORY_SAMPLES: usize = 5;
+
+/// Relative standard error assumed when there is not enough history
+const DEFAULT_RELATIVE_ERROR: f64 = 0.15;
+
+impl AccuracyStats {
+    /// Samples for the most specific group with enough history:
+    /// same truck type and material, then same truck type, then everything.
+    fn history_for(&self, truck_type: &str, material_type: &str) -> Vec<&AccuracySample> {
+        let groups: [&dyn Fn(&AccuracySample) -> bool; 3] = [
+            &|s| s.truck_type == truck_type && s.material_type == material_type,
+            &|s| s.truck_type == truck_type,
+            &|_| true,
+        ];
+        for in_group in groups {
+            let samples: Vec<&AccuracySample> =
+                self.samples.iter().filter(|s| in_group(s)).collect();
+            if samples.len() >= MIN_HISTORY_SAMPLES {
+                return samples;
+            }
+        }
+        Vec::new()
+    }
+
+    /// P10/P90 tonnage bounds for an estimate
+    pub fn prediction_interval(&self, estimation: &EstimationResult) -> TonnageInterval {
+        let tonnage = estimation.estimated_tonnage;
+        let history = self.history_for(&estimation.truck_type, &estimation.material_type);
+
+        let (bias, history_sigma) = if history.is_empty() {
+            (0.0, DEFAULT_RELATIVE_ERROR * tonnage)
+        } else {
+            let n = history.len() as f64;
+            let mean = history.iter().map(|s| s.error()).sum::<f64>() / n;
+            let var = history
+                .iter()
+                .map(|s| (s.error() - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1.0);
+            (mean, var.sqrt())
+        };
+
+        let ensemble_sigma = estimation.tonnage_std_dev.unwrap_or(0.0);
+        let sigma = (history_sigma.powi(2) + ensemble_sigma.powi(2)).sqrt();
+        // A calibrated estimate has had its systematic error removed already
+        let center = if estimation.raw_tonnage.is_some() {
+            tonnage
+        } else {
+            tonnage - bias
+        };
+
+        TonnageInterval {
+            p10: (center - Z_P90 * sigma).max(0.0),
+            p90: center + Z_P90 * sigma,
+            history_samples: history.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(estimated: f64, actual: f64, truck_type: &str, material_type: &str) -> AccuracySample {
+        AccuracySample {
+            estimated,
+            actual,
+            truck_type: truck_type.to_string(),
+            material_type: material_type.to_string(),
+            used_references: false,
+        }
+    }
+
+    fn estimate(tonnage: f64, std_dev: Option<f64>) -> EstimationResult {
+        EstimationResult {
+            truck_type: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            estimated_tonnage: tonnage,
+            tonnage_std_dev: std_dev,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_spread_without_history() {
+        let interval = AccuracyStats::default().prediction_interval(&estimate(4.0, None));
+        assert_eq!(interval.history_samples, 0);
+        assert!((interval.p90 - (4.0 + Z_P90 * 0.6)).abs() < 1e-9);
+        assert!((interval.p10 - (4.0 - Z_P90 * 0.6)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_history_bias_and_ensemble_spread() {
+        // 4t/As殻 estimates run 0.5t high with ±0.1t scatter
+        let samples: Vec<AccuracySample> = [0.4, 0.5, 0.6, 0.5, 0.4, 0.6]
+            .iter()
+            .map(|e| sample(4.0 + e, 4.0, "4t", "As殻"))
+            .chain([sample(10.0, 5.0, "10t", "土砂")])
+            .collect();
+        let stats = AccuracyStats::from_samples(samples);
+
+        let narrow = stats.prediction_interval(&estimate(4.5, None));
+        assert_eq!(narrow.history_samples, 6);
+        assert!(narrow.p10 < 4.0 && narrow.p90 > 4.0);
+        assert!(narrow.p90 < 4.2);
+
+        let wide = stats.prediction_interval(&estimate(4.5, Some(0.5)));
+        assert!(wide.p90 - wide.p10 > narrow.p90 - narrow.p10);
+    }
+
+    #[test]
+    fn test_calibrated_estimate_is_not_shifted_again() {
+        let samples: Vec<AccuracySample> = [0.4, 0.5, 0.6, 0.5, 0.4, 0.6]
+            .iter()
+            .map(|e| sample(4.0 + e, 4.0, "4t", "As殻"))
+            .collect();
+        let stats = AccuracyStats::from_samples(samples);
+
+        // Raw AI value 4.5t, calibrated down to 4.0t
+        let mut calibrated = estimate(4.0, None);
+        calibrated.raw_tonnage = Some(4.5);
+        let interval = stats.prediction_interval(&calibrated);
+        assert!(((interval.p10 + interval.p90) / 2.0 - 4.0).abs() < 1e-9);
+
+        let uncalibrated = stats.prediction_interval(&estimate(4.5, None));
+        assert!(((uncalibrated.p10 + uncalibrated.p90) / 2.0 - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_falls_back_to_truck_type_group() {
+        let samples: Vec<AccuracySample> = (0..5)
+            .map(|i| sample(4.0 + i as f64 * 0.1, 4.0, "4t", "土砂"))
+            .collect();
+        let stats = AccuracyStats::from_samples(samples);
+        assert_eq!(stats.prediction_interval(&estimate(4.0, None)).history_samples, 5);
+    }
+
+    #[test]
+    fn test_possibly_overloaded() {
+        let mut result = estimate(3.8, None);
+        assert!(!result.possibly_overloaded(4.0));
+        result.tonnage_interval = Some(AccuracyStats::default().prediction_interval(&result));
+        assert!(result.possibly_overloaded(4.0));
+        assert!(!result.possibly_overloaded(5.0));
+    }
+}
@@ -3,18 +3,30 @@
 //! This module provides the store functionality for analysis history.
 //! For new code using the Repository Pattern, see the `infrastructure` module.
 
+mod calibration;
+mod interval;
+mod persist;
+pub mod schema;
+mod storage;
 pub mod vehicles;
 
+pub use calibration::{
+    cross_validate, CalibrationEntry, CalibrationFit, CalibrationMethod, CalibrationModel,
+    CalibrationReport, ErrorMetrics, GroupReport, DEFAULT_CV_FOLDS, MIN_CALIBRATION_SAMPLES,
+};
+pub use persist::Recovery;
+pub use schema::StoreKind;
+pub use storage::{EntryEdit, EntryStorage, JsonStorage};
 pub use vehicles::VehicleStore;
 pub use tonsuu_types::HistoryEntry;
 
 use tonsuu_types::{CacheError, Result};
-use tonsuu_types::{EstimationResult, LoadGrade, TruckClass};
+use tonsuu_types::{EstimationResult, ImageView, LoadGrade, SourceImage, TruckClass};
 use chrono::Utc;
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::fs::File;
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
 
@@ -36,6 +48,8 @@ pub struct AccuracySample {
     pub actual: f64,
     pub truck_type: String,
     pub material_type: String,
+    /// Graded reference images were attached to the prompt
+    pub used_references: bool,
 }
 
 impl AccuracySample {
@@ -98,12 +112,16 @@ impl AccuracyStats {
         }
     }
 
-    /// Group by truck type
+    /// Group by truck type; names of a known class ("4tダンプ", "4トン")
+    /// are grouped under the class id
     pub fn by_truck_type(&self) -> HashMap<String, AccuracyStats> {
         let mut groups: HashMap<String, Vec<AccuracySample>> = HashMap::new();
         for sample in &self.samples {
+            let key = TruckClass::from_name(&sample.truck_type)
+                .map(|class| class.to_string())
+                .unwrap_or_else(|| sample.truck_type.clone());
             groups
-                .entry(sample.truck_type.clone())
+                .entry(key)
                 .or_default()
                 .push(sample.clone());
         }
@@ -127,29 +145,57 @@ impl AccuracyStats {
             .map(|(k, v)| (k, Self::from_samples(v)))
             .collect()
     }
+
+    /// Group by whether reference images were attached ("with references" / "without references")
+    pub fn by_references(&self) -> HashMap<String, AccuracyStats> {
+        let mut groups: HashMap<String, Vec<AccuracySample>> = HashMap::new();
+        for sample in &self.samples {
+            let key = if sample.used_references {
+                "with references"
+            } else {
+                "without references"
+            };
+            groups.entry(key.to_string()).or_default().push(sample.clone());
+        }
+        groups
+            .into_iter()
+            .map(|(k, v)| (k, Self::from_samples(v)))
+            .collect()
+    }
 }
 
 /// Persistent store for history entries
 pub struct Store {
-    store_path: PathBuf,
+    storage: Box<dyn EntryStorage<HistoryEntry>>,
     entries: HashMap<String, HistoryEntry>,
+    recovery: Option<Recovery>,
 }
 
 impl Store {
     /// Create or load a store
+    ///
+    /// A corrupt or truncated history.json is copied aside and the readable
+    /// entries are recovered (see `persist::load_map`); [`Store::recovery`]
+    /// reports it. Older schema versions are upgraded; a file from a newer
+    /// version is an error.
     pub fn open(store_dir: PathBuf) -> Result<Self> {
-        fs::create_dir_all(&store_dir)?;
-        let store_path = store_dir.join("history.json");
+        Self::with_storage(Box::new(JsonStorage::new(&store_dir, StoreKind::History)?))
+    }
 
-        let entries = if store_path.exists() {
-            let file = File::open(&store_path)?;
-            let reader = BufReader::new(file);
-            serde_json::from_reader(reader).unwrap_or_default()
-        } else {
-            HashMap::new()
-        };
+    /// Load a store from another storage backend (e.g. SQLite)
+    pub fn with_storage(storage: Box<dyn EntryStorage<HistoryEntry>>) -> Result<Self> {
+        let entries = storage.load()?;
+        let recovery = storage.take_recovery();
+        Ok(Self {
+            storage,
+            entries,
+            recovery,
+        })
+    }
 
-        Ok(Self { store_path, entries })
+    /// Set when the history had to be salvaged from a damaged file
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
     }
 
     /// Compute hash for an image file
@@ -162,12 +208,44 @@ impl Store {
         Ok(format!("{:x}", hash))
     }
 
-    /// Save store to disk
-    fn save(&self) -> Result<()> {
-        let file = File::create(&self.store_path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &self.entries)?;
-        Ok(())
+    /// Combined key of several photos of one load.
+    ///
+    /// Order-independent: the per-image hashes are sorted before hashing. A
+    /// single image keeps its plain hash, so single-image entries are unchanged.
+    pub fn combined_hash(image_hashes: &[String]) -> String {
+        if let [single] = image_hashes {
+            return single.clone();
+        }
+        let mut sorted: Vec<&str> = image_hashes.iter().map(String::as_str).collect();
+        sorted.sort_unstable();
+        let mut hasher = Sha256::new();
+        for hash in sorted {
+            hasher.update(hash.as_bytes());
+            hasher.update(b"\0");
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Apply an edit to the latest persisted entry under `key` and save it.
+    ///
+    /// The storage serializes writers and re-reads the entry first, so
+    /// changes made to it by other workers/processes since `open` are kept;
+    /// other entries are not rewritten (for JSON: cross-process lock,
+    /// re-read, temp file + rename).
+    fn update_entry<R>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut Option<HistoryEntry>) -> Result<R>,
+    ) -> Result<R> {
+        let (saved, result) = storage::update_entry_once(self.storage.as_ref(), key, f)?;
+        match saved {
+            Some(entry) => self.entries.insert(key.to_string(), entry),
+            None => self.entries.remove(key),
+        };
+        if let Some(recovery) = self.storage.take_recovery() {
+            self.recovery = Some(recovery);
+        }
+        Ok(result)
     }
 
     /// Add or update an analysis result
@@ -199,10 +277,69 @@ impl Store {
             feedback_at: None,
             notes: None,
             thumbnail_base64,
+            source_images: Vec::new(),
         };
 
-        self.entries.insert(hash.clone(), entry);
-        self.save()?;
+        self.update_entry(&hash, |slot| {
+            *slot = Some(entry);
+            Ok(())
+        })?;
+        Ok(hash)
+    }
+
+    /// Add a multi-image analysis as one entry keyed by the combined hash.
+    ///
+    /// `image_path` is the first (primary) image, which `thumbnail_base64`
+    /// should show; every image is listed in `source_images`.
+    pub fn add_analysis_for_images(
+        &mut self,
+        images: &[(PathBuf, ImageView)],
+        estimation: EstimationResult,
+        max_capacity: Option<f64>,
+        thumbnail_base64: Option<String>,
+    ) -> Result<String> {
+        let Some((first, _)) = images.first() else {
+            return Err(CacheError::IoError("No images given".to_string()).into());
+        };
+        if images.len() == 1 {
+            return self.add_analysis_with_capacity(
+                first,
+                estimation,
+                max_capacity,
+                thumbnail_base64,
+            );
+        }
+
+        let source_images = images
+            .iter()
+            .map(|(path, view)| {
+                Ok(SourceImage {
+                    path: path.display().to_string(),
+                    hash: Self::hash_image(path)?,
+                    view: *view,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let hashes: Vec<String> = source_images.iter().map(|s| s.hash.clone()).collect();
+        let hash = Self::combined_hash(&hashes);
+
+        let entry = HistoryEntry {
+            image_path: first.display().to_string(),
+            image_hash: hash.clone(),
+            estimation,
+            actual_tonnage: None,
+            max_capacity,
+            analyzed_at: Utc::now(),
+            feedback_at: None,
+            notes: None,
+            thumbnail_base64,
+            source_images,
+        };
+
+        self.update_entry(&hash, |slot| {
+            *slot = Some(entry);
+            Ok(())
+        })?;
         Ok(hash)
     }
 
@@ -226,24 +363,38 @@ impl Store {
     ) -> Result<()> {
         let hash = Self::hash_image(image_path)?;
 
-        if let Some(entry) = self.entries.get_mut(&hash) {
-            entry.actual_tonnage = Some(actual_tonnage);
-            entry.feedback_at = Some(Utc::now());
-            if let Some(cap) = max_capacity {
-                entry.max_capacity = Some(cap);
-            }
-            if notes.is_some() {
-                entry.notes = notes;
-            }
-            self.save()?;
-            Ok(())
+        // Fall back to the latest multi-image entry that includes this photo;
+        // a hash unknown here may still have been added by another writer
+        let key = if self.entries.contains_key(&hash) {
+            hash.clone()
         } else {
-            Err(CacheError::IoError(format!(
-                "No analysis found for image: {}",
-                image_path.display()
-            ))
-            .into())
-        }
+            self.entries
+                .values()
+                .filter(|e| e.source_images.iter().any(|s| s.hash == hash))
+                .max_by_key(|e| e.analyzed_at)
+                .map(|e| e.image_hash.clone())
+                .unwrap_or_else(|| hash.clone())
+        };
+
+        self.update_entry(&key, |slot| {
+            if let Some(entry) = slot {
+                entry.actual_tonnage = Some(actual_tonnage);
+                entry.feedback_at = Some(Utc::now());
+                if let Some(cap) = max_capacity {
+                    entry.max_capacity = Some(cap);
+                }
+                if notes.is_some() {
+                    entry.notes = notes;
+                }
+                Ok(())
+            } else {
+                Err(CacheError::IoError(format!(
+                    "No analysis found for image: {}",
+                    image_path.display()
+                ))
+                .into())
+            }
+        })
     }
 
     /// Get entries with both actual_tonnage and max_capacity (judged items)
@@ -255,19 +406,32 @@ impl Store {
     }
 
     /// Select graded stock items by truck class
-    /// Returns one representative item per load grade for the given truck class
-    pub fn select_stock_by_grade(&self, target_class: TruckClass) -> Vec<GradedHistoryEntry> {
+    /// Returns one representative item per load grade for the given truck class.
+    /// Entries keyed by, or made from, a photo in `exclude_hashes` (the load
+    /// being analyzed) are left out so its own weighing is never a reference.
+    pub fn select_stock_by_grade(
+        &self,
+        target_class: &TruckClass,
+        exclude_hashes: &[String],
+    ) -> Vec<GradedHistoryEntry> {
         let judged_items = self.get_judged_items();
 
-        // Filter by same truck class
+        // Filter by same truck class, skipping the load being analyzed
         let same_class_items: Vec<_> = judged_items
             .into_iter()
             .filter(|entry| {
                 entry
                     .max_capacity
-                    .map(|cap| TruckClass::from_capacity(cap) == target_class)
+                    .map(|cap| TruckClass::from_capacity(cap) == *target_class)
                     .unwrap_or(false)
             })
+            .filter(|entry| {
+                !exclude_hashes.contains(&entry.image_hash)
+                    && !entry
+                        .source_images
+                        .iter()
+                        .any(|source| exclude_hashes.contains(&source.hash))
+            })
             .collect();
 
         // Add grade information to each item
@@ -307,7 +471,13 @@ impl Store {
             items_in_grade.sort_by(|a, b| b.entry.analyzed_at.cmp(&a.entry.analyzed_at));
 
             if let Some(latest) = items_in_grade.first() {
-                result.push(latest.clone());
+                let mut latest = latest.clone();
+                // Backends that read thumbnails on demand fill in the picked ones
+                if latest.entry.thumbnail_base64.is_none() {
+                    latest.entry.thumbnail_base64 =
+                        self.thumbnail(&latest.entry.image_hash).ok().flatten();
+                }
+                result.push(latest);
             }
         }
 
@@ -320,15 +490,30 @@ impl Store {
         Ok(self.entries.get(&hash))
     }
 
+    /// Thumbnail of an entry; backends that leave thumbnails out of `open`
+    /// (SQLite) read it on demand
+    pub fn thumbnail(&self, hash: &str) -> Result<Option<String>> {
+        match self.entries.get(hash) {
+            Some(entry) if entry.thumbnail_base64.is_some() => Ok(entry.thumbnail_base64.clone()),
+            Some(_) => self.storage.load_thumbnail(hash),
+            None => Ok(None),
+        }
+    }
+
     /// Add a pre-built history entry (for import)
     /// Returns true if the entry was added, false if it already exists
     pub fn add_entry(&mut self, entry: HistoryEntry) -> Result<bool> {
         if self.entries.contains_key(&entry.image_hash) {
             return Ok(false);
         }
-        self.entries.insert(entry.image_hash.clone(), entry);
-        self.save()?;
-        Ok(true)
+        let key = entry.image_hash.clone();
+        self.update_entry(&key, |slot| {
+            if slot.is_some() {
+                return Ok(false);
+            }
+            *slot = Some(entry);
+            Ok(true)
+        })
     }
 
     /// Check if entry with given hash exists
@@ -373,8 +558,9 @@ impl Store {
     /// Clear all entries (for refresh import)
     #[allow(dead_code)]
     pub fn clear(&mut self) -> Result<()> {
+        self.storage.clear()?;
         self.entries.clear();
-        self.save()
+        Ok(())
     }
 
     /// Calculate accuracy statistics
@@ -388,10 +574,137 @@ impl Store {
                     actual,
                     truck_type: e.estimation.truck_type.clone(),
                     material_type: e.estimation.material_type.clone(),
+                    used_references: !e.estimation.reference_hashes.is_empty(),
                 })
             })
             .collect();
 
         AccuracyStats::from_samples(entries)
     }
+
+    /// Ground-truth samples for fitting a calibration: the raw (uncalibrated)
+    /// AI tonnage against the weighed tonnage, ordered by image hash
+    pub fn calibration_samples(&self) -> Vec<AccuracySample> {
+        let mut entries: Vec<&HistoryEntry> = self.entries_with_feedback();
+        entries.sort_by(|a, b| a.image_hash.cmp(&b.image_hash));
+        entries
+            .into_iter()
+            .filter_map(|e| {
+                e.actual_tonnage.map(|actual| AccuracySample {
+                    estimated: e.estimation.raw_tonnage.unwrap_or(e.estimation.estimated_tonnage),
+                    actual,
+                    truck_type: e.estimation.truck_type.clone(),
+                    material_type: e.estimation.material_type.clone(),
+                    used_references: !e.estimation.reference_hashes.is_empty(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_multi_image_entry_and_feedback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let rear = dir.join("rear.jpg");
+        let side = dir.join("side.jpg");
+        fs::write(&rear, b"rear view").unwrap();
+        fs::write(&side, b"side view").unwrap();
+
+        let mut store = Store::open(dir.to_path_buf()).unwrap();
+        let images = vec![(rear.clone(), ImageView::Rear), (side.clone(), ImageView::Side)];
+        let key = store
+            .add_analysis_for_images(
+                &images,
+                EstimationResult::default(),
+                Some(4.0),
+                Some("dGh1bWI=".to_string()),
+            )
+            .unwrap();
+
+        let hashes = vec![Store::hash_image(&side).unwrap(), Store::hash_image(&rear).unwrap()];
+        assert_eq!(key, Store::combined_hash(&hashes));
+        let entry = store.get_by_hash(&key).unwrap();
+        assert_eq!(entry.source_images.len(), 2);
+        assert_eq!(entry.source_images[1].view, ImageView::Side);
+        assert_eq!(entry.thumbnail_base64.as_deref(), Some("dGh1bWI="));
+
+        // Feedback given for either photo lands on the fused entry
+        store.add_feedback(&side, 3.8, None).unwrap();
+        assert_eq!(store.get_by_hash(&key).unwrap().actual_tonnage, Some(3.8));
+    }
+
+    #[test]
+    fn test_stock_excludes_the_analyzed_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let mut store = Store::open(dir.to_path_buf()).unwrap();
+        let mut add = |name: &str, actual: f64| {
+            let photo = dir.join(name);
+            fs::write(&photo, name.as_bytes()).unwrap();
+            store
+                .add_analysis_with_capacity(&photo, EstimationResult::default(), Some(4.0), None)
+                .unwrap();
+            store.add_feedback(&photo, actual, None).unwrap();
+            Store::hash_image(&photo).unwrap()
+        };
+        let own = add("own.jpg", 3.8);
+        let other = add("other.jpg", 3.9);
+
+        let class = TruckClass::from_capacity(4.0);
+        let stock = store.select_stock_by_grade(&class, &[]);
+        assert_eq!(stock.len(), 1);
+
+        // The next latest entry of the grade takes the analyzed photo's place
+        let stock = store.select_stock_by_grade(&class, std::slice::from_ref(&own));
+        assert_eq!(stock.len(), 1);
+        assert_eq!(stock[0].entry.image_hash, other);
+
+        // A fused entry made from the photo is left out as well
+        let side = dir.join("side.jpg");
+        fs::write(&side, b"side view").unwrap();
+        let images = vec![(dir.join("own.jpg"), ImageView::Rear), (side.clone(), ImageView::Side)];
+        store
+            .add_analysis_for_images(&images, EstimationResult::default(), Some(4.0), None)
+            .unwrap();
+        store.add_feedback(&side, 4.0, None).unwrap();
+        let stock = store.select_stock_by_grade(&class, std::slice::from_ref(&own));
+        assert_eq!(stock.len(), 1);
+        assert_eq!(stock[0].entry.image_hash, other);
+    }
+
+    #[test]
+    fn test_stores_on_one_directory_keep_each_others_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let first_photo = dir.join("first.jpg");
+        let second_photo = dir.join("second.jpg");
+        fs::write(&first_photo, b"first").unwrap();
+        fs::write(&second_photo, b"second").unwrap();
+
+        let mut first = Store::open(dir.to_path_buf()).unwrap();
+        let mut second = Store::open(dir.to_path_buf()).unwrap();
+        first
+            .add_analysis(&first_photo, EstimationResult::default())
+            .unwrap();
+        second
+            .add_analysis(&second_photo, EstimationResult::default())
+            .unwrap();
+
+        // Feedback reaches an entry this store has not loaded
+        second.add_feedback(&first_photo, 3.5, None).unwrap();
+        assert_eq!(second.feedback_count(), 1);
+
+        let reopened = Store::open(dir.to_path_buf()).unwrap();
+        assert_eq!(reopened.count(), 2);
+        assert_eq!(reopened.feedback_count(), 1);
+        let unanalyzed = dir.join("unanalyzed.jpg");
+        fs::write(&unanalyzed, b"unanalyzed").unwrap();
+        assert!(second.add_feedback(&unanalyzed, 1.0, None).is_err());
+    }
 }
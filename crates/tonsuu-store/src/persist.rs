@@ -0,0 +1,364 @@
+//! Crash- and concurrency-safe persistence for the JSON store files
+//!
+//! - Writes go to a temp file in the same directory and are renamed over the
+//!   original, so a crash never leaves a half-written `history.json`.
+//! - An advisory lock on a sidecar `<file>.lock` serializes writers across
+//!   threads and processes (CLI batch workers, GUI).
+//! - Every mutation re-reads the file under the lock and applies the change
+//!   to the latest contents, so concurrent writers never drop each other's entries.
+//! - A corrupt or truncated file is copied aside (`<file>.corrupt-<hash>`,
+//!   once per distinct content) and as many entries as possible are salvaged
+//!   instead of starting empty.
+//! - Files are versioned (see `schema`); older files are upgraded on load.
+
+use crate::schema::{self, Envelope, StoreDocument, StoreKind};
+use tonsuu_types::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A damaged store file that was read by salvaging its readable entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// The damaged store file
+    pub path: PathBuf,
+    /// Number of entries that could be recovered
+    pub recovered: usize,
+    /// Copy of the damaged file (`<file>.corrupt-<hash>`)
+    pub backup: PathBuf,
+}
+
+/// Held while a store file is being read-modify-written; released on drop
+pub(crate) struct StoreLock {
+    file: File,
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Take an exclusive advisory lock for `path` (blocks until available)
+pub(crate) fn lock(path: &Path) -> Result<StoreLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sidecar_path(path, ".lock"))?;
+    file.lock()?;
+    Ok(StoreLock { file })
+}
+
+/// Write `value` as pretty JSON via temp file + rename
+pub(crate) fn write_atomic<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let tmp_path = sidecar_path(path, &format!(".tmp.{}", std::process::id()));
+    {
+        let file = File::create(&tmp_path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, value)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Write `entries` wrapped in the current versioned envelope
+pub(crate) fn write_map<T: Serialize>(
+    path: &Path,
+    kind: StoreKind,
+    entries: &HashMap<String, T>,
+) -> Result<()> {
+    let envelope = Envelope {
+        schema: kind.schema_name(),
+        version: kind.current_version(),
+        entries,
+    };
+    write_atomic(path, &envelope)
+}
+
+/// Load a `{ key: entry }` map from a store file, upgrading and recovering.
+///
+/// Missing or empty file → empty map. Older versions are migrated in memory
+/// (the original is kept as `<file>.v<old>.bak`). If parsing fails or entries
+/// no longer match the schema, the file is copied to `<file>.corrupt-<hash>`
+/// and the salvageable entries are returned together with a [`Recovery`] for
+/// the caller to report. The copy is named after the content hash, so
+/// re-reading the same damaged file (every `update` does) does not add copies.
+/// A file from a newer version is an error.
+pub(crate) fn load_map<T: DeserializeOwned>(
+    path: &Path,
+    kind: StoreKind,
+) -> Result<(HashMap<String, T>, Option<Recovery>)> {
+    if !path.exists() {
+        return Ok((HashMap::new(), None));
+    }
+
+    let content = fs::read_to_string(path)?;
+    if content.trim().is_empty() {
+        return Ok((HashMap::new(), None));
+    }
+
+    let (doc, damaged) = match serde_json::from_str::<Value>(&content) {
+        Ok(value) => (StoreDocument::from_value(value, kind)?, false),
+        Err(_) => (salvage_document(&content, kind), true),
+    };
+
+    let from_version = doc.version;
+    let (entries, steps) = schema::migrate(kind, doc)?;
+    if !steps.is_empty() && !damaged {
+        let backup = sidecar_path(path, &format!(".v{}.bak", from_version));
+        if !backup.exists() {
+            fs::copy(path, &backup)?;
+        }
+    }
+
+    let total = entries.len();
+    let map: HashMap<String, T> = entries
+        .into_iter()
+        .filter_map(|(key, value)| serde_json::from_value(value).ok().map(|v| (key, v)))
+        .collect();
+
+    let recovery = if damaged || map.len() < total {
+        let digest = format!("{:x}", Sha256::digest(content.as_bytes()));
+        let backup = sidecar_path(path, &format!(".corrupt-{}", &digest[..16]));
+        if !backup.exists() {
+            fs::copy(path, &backup)?;
+        }
+        Some(Recovery {
+            path: path.to_path_buf(),
+            recovered: map.len(),
+            backup,
+        })
+    } else {
+        None
+    };
+
+    Ok((map, recovery))
+}
+
+/// Recover the version and complete entries of a store file that is not
+/// valid JSON (e.g. truncated by a crash mid-write).
+///
+/// Versioned files keep `entries` last, so the header still parses and only
+/// trailing entries are lost.
+pub(crate) fn salvage_document(content: &str, kind: StoreKind) -> StoreDocument {
+    if let Some(start) = envelope_entries_start(content) {
+        let header = format!("{}{{}}}}", &content[..start]);
+        if let Ok(doc) = serde_json::from_str::<Value>(&header)
+            .map_err(tonsuu_types::Error::from)
+            .and_then(|value| StoreDocument::from_value(value, kind))
+        {
+            return StoreDocument {
+                version: doc.version,
+                entries: parse_truncated_map(&content[start..]),
+            };
+        }
+    }
+
+    StoreDocument {
+        version: 0,
+        entries: parse_truncated_map(content),
+    }
+}
+
+/// Byte offset of the `{` opening the envelope's `entries` object
+fn envelope_entries_start(content: &str) -> Option<usize> {
+    let key = content.find("\"entries\"")?;
+    let rest = &content[key + "\"entries\"".len()..];
+    let after_colon = rest.trim_start().strip_prefix(':')?;
+    let value = after_colon.trim_start();
+    if value.starts_with('{') {
+        Some(content.len() - value.len())
+    } else {
+        None
+    }
+}
+
+/// Parse the longest prefix of a JSON object that ends on an entry boundary
+fn parse_truncated_map(content: &str) -> Map<String, Value> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    // Exclusive end offsets just after a complete top-level entry
+    let mut boundaries = Vec::new();
+
+    for (i, c) in content.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 1 {
+                    boundaries.push(i + 1);
+                }
+            }
+            ',' if depth == 1 => boundaries.push(i),
+            _ => {}
+        }
+    }
+
+    for &end in boundaries.iter().rev() {
+        let candidate = format!("{}}}", &content[..end]);
+        if let Ok(map) = serde_json::from_str(&candidate) {
+            return map;
+        }
+    }
+
+    Map::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_salvage_truncated_map() {
+        let content = r#"{
+  "a": {"n": 1, "s": "x}"},
+  "b": {"n": 2, "s": "y"},
+  "c": {"n": 3, "s"#;
+        let map = salvage_document(content, StoreKind::History).entries;
+        assert_eq!(map.len(), 2);
+        assert!(map.contains_key("a"));
+        assert!(map.contains_key("b"));
+    }
+
+    #[test]
+    fn test_salvage_truncated_envelope() {
+        let content = r#"{
+  "schema": "vehicles",
+  "version": 1,
+  "entries": {
+    "a": {"n": 1},
+    "b": {"n": 2"#;
+        let doc = salvage_document(content, StoreKind::Vehicles);
+        assert_eq!(doc.version, 1);
+        assert_eq!(doc.entries.len(), 1);
+        assert!(doc.entries.contains_key("a"));
+    }
+
+    #[test]
+    fn test_legacy_file_is_upgraded_with_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("history.json");
+        fs::write(&path, r#"{"a": 1, "b": 2}"#).unwrap();
+
+        let (map, recovery): (HashMap<String, u32>, _) =
+            load_map(&path, StoreKind::History).unwrap();
+        assert_eq!(map.len(), 2);
+        assert!(recovery.is_none());
+        assert!(dir.join("history.json.v0.bak").exists());
+
+        write_map(&path, StoreKind::History, &map).unwrap();
+        let written: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["version"], StoreKind::History.current_version());
+        assert_eq!(written["entries"]["b"], 2);
+    }
+
+    #[test]
+    fn test_load_map_keeps_corrupt_copy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("history.json");
+        fs::write(&path, r#"{"a": 1, "b": 2, "c": 3"#).unwrap();
+
+        let (map, recovery): (HashMap<String, u32>, _) =
+            load_map(&path, StoreKind::History).unwrap();
+        assert_eq!(map.len(), 2);
+        let recovery = recovery.unwrap();
+        assert_eq!(recovery.recovered, 2);
+        assert!(recovery.backup.exists());
+
+        let backups = fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().contains(".corrupt-"))
+            .count();
+        assert_eq!(backups, 1);
+    }
+
+    #[test]
+    fn test_corrupt_copy_is_kept_once_per_content() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("history.json");
+        // One entry no longer matches the entry type
+        fs::write(&path, r#"{"a": 1, "b": "not a number"}"#).unwrap();
+
+        let count_backups = || {
+            fs::read_dir(dir)
+                .unwrap()
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().contains(".corrupt-"))
+                .count()
+        };
+        for _ in 0..3 {
+            let (map, recovery): (HashMap<String, u32>, _) =
+                load_map(&path, StoreKind::History).unwrap();
+            assert_eq!(map.len(), 1);
+            assert!(recovery.unwrap().backup.exists());
+        }
+        assert_eq!(count_backups(), 1);
+
+        // Different damaged content gets its own copy
+        fs::write(&path, r#"{"a": 1, "c": "still not a number"}"#).unwrap();
+        let (_, recovery): (HashMap<String, u32>, _) = load_map(&path, StoreKind::History).unwrap();
+        assert!(recovery.is_some());
+        assert_eq!(count_backups(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = Arc::new(dir.join("history.json"));
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = Arc::clone(&path);
+                thread::spawn(move || {
+                    let _lock = lock(&path).unwrap();
+                    let (mut map, _): (HashMap<String, u32>, _) =
+                        load_map(&path, StoreKind::History).unwrap();
+                    map.insert(format!("k{}", i), i);
+                    write_map(&path, StoreKind::History, &map).unwrap();
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let (map, _): (HashMap<String, u32>, _) = load_map(&path, StoreKind::History).unwrap();
+        assert_eq!(map.len(), 8);
+    }
+}
@@ -0,0 +1,477 @@
+//! Versioned on-disk format for history.json, vehicles.json and calibration.json
+//!
+//! Store files are wrapped in an envelope:
+//!
+//! ```json
+//! { "schema": "history", "version": 1, "entries": { "<key>": { ... } } }
+//! ```
+//!
+//! Files written before versioning (a bare `{ key: entry }` map) are version 0.
+//! On load, entries are upgraded through an ordered migration chain that works
+//! on raw JSON values, so fields the current types don't know about survive
+//! until the entry is deserialized. The first load of an old file keeps a copy
+//! at `<file>.v<old>.bak`; the upgraded format is written on the next save.
+//! Files written by a newer version of the tool are refused instead of being
+//! rewritten with fields dropped.
+
+use crate::calibration::CalibrationEntry;
+use crate::persist::{self, Recovery};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tonsuu_types::{Error, HistoryEntry, RegisteredVehicle, Result};
+
+/// Which store file a document belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    History,
+    Vehicles,
+    Calibration,
+}
+
+impl StoreKind {
+    /// File name inside the store directory
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            StoreKind::History => "history.json",
+            StoreKind::Vehicles => "vehicles.json",
+            StoreKind::Calibration => "calibration.json",
+        }
+    }
+
+    /// Value of the envelope's `schema` field
+    pub fn schema_name(&self) -> &'static str {
+        match self {
+            StoreKind::History => "history",
+            StoreKind::Vehicles => "vehicles",
+            StoreKind::Calibration => "calibration",
+        }
+    }
+
+    /// Ordered migration chain (`migrations()[n].from == n`)
+    pub fn migrations(&self) -> &'static [Migration] {
+        match self {
+            StoreKind::History => HISTORY_MIGRATIONS,
+            StoreKind::Vehicles => VEHICLE_MIGRATIONS,
+            StoreKind::Calibration => CALIBRATION_MIGRATIONS,
+        }
+    }
+
+    /// Version written by this build
+    pub fn current_version(&self) -> u32 {
+        self.migrations().len() as u32
+    }
+
+    /// Check that an entry deserializes into the current type for this store
+    fn check_entry(&self, value: &Value) -> std::result::Result<(), String> {
+        let result = match self {
+            StoreKind::History => serde_json::from_value::<HistoryEntry>(value.clone()).map(|_| ()),
+            StoreKind::Vehicles => {
+                serde_json::from_value::<RegisteredVehicle>(value.clone()).map(|_| ())
+            }
+            StoreKind::Calibration => {
+                serde_json::from_value::<CalibrationEntry>(value.clone()).map(|_| ())
+            }
+        };
+        result.map_err(|e| e.to_string())
+    }
+}
+
+/// One step of the migration chain (`from` → `from + 1`)
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    /// Upgrade entries in place; returns a human-readable list of changes
+    apply: fn(&mut Map<String, Value>) -> Vec<String>,
+}
+
+const HISTORY_MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "wrap in versioned envelope; write defaulted fields explicitly",
+        apply: history_v0_to_v1,
+    },
+    Migration {
+        from: 1,
+        description: "add `source_images` for multi-image analyses",
+        apply: history_v1_to_v2,
+    },
+];
+
+const VEHICLE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "wrap in versioned envelope; write defaulted fields explicitly",
+        apply: vehicles_v0_to_v1,
+    },
+    Migration {
+        from: 1,
+        description: "add bed dimensions and `body_type`",
+        apply: vehicles_v1_to_v2,
+    },
+    Migration {
+        from: 2,
+        description: "add vehicle inspection certificate (車検証) fields",
+        apply: vehicles_v2_to_v3,
+    },
+];
+
+const CALIBRATION_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "wrap in versioned envelope",
+    apply: calibration_v0_to_v1,
+}];
+
+fn history_v0_to_v1(entries: &mut Map<String, Value>) -> Vec<String> {
+    fill_missing_fields(
+        entries,
+        &[
+            "actual_tonnage",
+            "max_capacity",
+            "feedback_at",
+            "notes",
+            "thumbnail_base64",
+        ],
+    )
+}
+
+/// `source_images` defaults to empty (single-image entry); nothing to fill.
+/// The bump keeps older builds from loading and rewriting multi-image entries
+/// without their photo list.
+fn history_v1_to_v2(_entries: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+fn vehicles_v0_to_v1(entries: &mut Map<String, Value>) -> Vec<String> {
+    fill_missing_fields(
+        entries,
+        &[
+            "license_plate",
+            "company",
+            "image_path",
+            "thumbnail_base64",
+            "notes",
+        ],
+    )
+}
+
+/// The bed dimensions and `body_type` are omitted when unset; nothing to fill.
+/// The bump keeps older builds from rewriting vehicles without them.
+fn vehicles_v1_to_v2(_entries: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// Model code, weights, dimensions and registration dates from the 車検証 are
+/// omitted when unset; nothing to fill.
+fn vehicles_v2_to_v3(_entries: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// calibration.json has always been written with the envelope; nothing to fill
+fn calibration_v0_to_v1(_entries: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// Insert `null` for optional fields that older versions omitted
+fn fill_missing_fields(entries: &mut Map<String, Value>, fields: &[&str]) -> Vec<String> {
+    let mut counts = vec![0usize; fields.len()];
+    for entry in entries.values_mut() {
+        if let Some(obj) = entry.as_object_mut() {
+            for (i, field) in fields.iter().enumerate() {
+                if !obj.contains_key(*field) {
+                    obj.insert((*field).to_string(), Value::Null);
+                    counts[i] += 1;
+                }
+            }
+        }
+    }
+
+    fields
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(field, count)| format!("add `{}` = null to {} entries", field, count))
+        .collect()
+}
+
+/// A store file split into its version and raw entries
+#[derive(Debug, Clone, Default)]
+pub struct StoreDocument {
+    pub version: u32,
+    pub entries: Map<String, Value>,
+}
+
+/// Serialized form of a current-version store file
+#[derive(Serialize)]
+pub(crate) struct Envelope<'a, T: Serialize> {
+    pub schema: &'static str,
+    pub version: u32,
+    /// Kept last so a truncated file only loses trailing entries
+    pub entries: &'a T,
+}
+
+impl StoreDocument {
+    /// Interpret a parsed JSON file (versioned envelope or legacy bare map)
+    pub fn from_value(value: Value, kind: StoreKind) -> Result<Self> {
+        let Value::Object(mut obj) = value else {
+            return Err(Error::Schema(format!(
+                "{}: expected a JSON object",
+                kind.file_name()
+            )));
+        };
+
+        let is_envelope = obj.get("schema").is_some_and(Value::is_string)
+            && obj.get("entries").is_some_and(Value::is_object);
+        if !is_envelope {
+            return Ok(Self {
+                version: 0,
+                entries: obj,
+            });
+        }
+
+        let schema = obj.get("schema").and_then(Value::as_str).unwrap_or_default();
+        if schema != kind.schema_name() {
+            return Err(Error::Schema(format!(
+                "{}: contains '{}' data, expected '{}'",
+                kind.file_name(),
+                schema,
+                kind.schema_name()
+            )));
+        }
+        let version = obj
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| Error::Schema(format!("{}: missing version", kind.file_name())))?
+            as u32;
+        let entries = match obj.remove("entries") {
+            Some(Value::Object(entries)) => entries,
+            _ => Map::new(),
+        };
+
+        Ok(Self { version, entries })
+    }
+}
+
+/// Report of one applied (or pending) migration step
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStep {
+    pub from: u32,
+    pub to: u32,
+    pub description: &'static str,
+    pub changes: Vec<String>,
+}
+
+/// Upgrade a document to the current version.
+///
+/// Fails if the document was written by a newer version of the tool.
+pub fn migrate(kind: StoreKind, doc: StoreDocument) -> Result<(Map<String, Value>, Vec<MigrationStep>)> {
+    let current = kind.current_version();
+    if doc.version > current {
+        return Err(Error::Schema(format!(
+            "{} is schema version {}, but this build supports up to {}. Please update tonsuu-checker",
+            kind.file_name(),
+            doc.version,
+            current
+        )));
+    }
+
+    let mut entries = doc.entries;
+    let steps = kind.migrations()[doc.version as usize..]
+        .iter()
+        .map(|migration| MigrationStep {
+            from: migration.from,
+            to: migration.from + 1,
+            description: migration.description,
+            changes: (migration.apply)(&mut entries),
+        })
+        .collect();
+
+    Ok((entries, steps))
+}
+
+/// What loading a store file would do, without modifying anything
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaReport {
+    pub file: String,
+    pub exists: bool,
+    /// Version found on disk (None if the file does not exist)
+    pub version: Option<u32>,
+    pub current_version: u32,
+    pub entry_count: usize,
+    /// File is not valid JSON; only the salvageable prefix would be kept
+    pub damaged: bool,
+    /// File was written by a newer version; it will not be loaded or rewritten
+    pub newer_than_supported: bool,
+    pub steps: Vec<MigrationStep>,
+    /// Entries that would be dropped because they don't match the current types
+    pub unreadable_entries: Vec<String>,
+}
+
+impl SchemaReport {
+    /// Loading this file would not change or drop anything
+    pub fn is_clean(&self) -> bool {
+        !self.damaged
+            && !self.newer_than_supported
+            && self.steps.is_empty()
+            && self.unreadable_entries.is_empty()
+    }
+}
+
+/// Inspect the store file of `kind` in `store_dir` (dry run)
+pub fn inspect(store_dir: &Path, kind: StoreKind) -> Result<SchemaReport> {
+    let path = store_dir.join(kind.file_name());
+    let mut report = SchemaReport {
+        file: path.display().to_string(),
+        exists: path.exists(),
+        version: None,
+        current_version: kind.current_version(),
+        entry_count: 0,
+        damaged: false,
+        newer_than_supported: false,
+        steps: Vec::new(),
+        unreadable_entries: Vec::new(),
+    };
+    if !report.exists {
+        return Ok(report);
+    }
+
+    let content = fs::read_to_string(&path)?;
+    if content.trim().is_empty() {
+        report.version = Some(0);
+        return Ok(report);
+    }
+
+    let doc = match serde_json::from_str::<Value>(&content) {
+        Ok(value) => StoreDocument::from_value(value, kind)?,
+        Err(_) => {
+            report.damaged = true;
+            persist::salvage_document(&content, kind)
+        }
+    };
+    report.version = Some(doc.version);
+    report.entry_count = doc.entries.len();
+
+    if doc.version > report.current_version {
+        report.newer_than_supported = true;
+        return Ok(report);
+    }
+
+    let (entries, steps) = migrate(kind, doc)?;
+    report.steps = steps;
+    report.unreadable_entries = entries
+        .iter()
+        .filter_map(|(key, value)| {
+            kind.check_entry(value)
+                .err()
+                .map(|e| format!("{}: {}", key, e))
+        })
+        .collect();
+
+    Ok(report)
+}
+
+/// Read a store file of any supported version into the current types.
+///
+/// Also returns the [`Recovery`] if the file was damaged and only partly read.
+pub fn read_store_file<T: DeserializeOwned>(
+    path: &Path,
+    kind: StoreKind,
+) -> Result<(HashMap<String, T>, Option<Recovery>)> {
+    persist::load_map(path, kind)
+}
+
+/// Write entries in the current versioned format (atomic)
+pub fn write_store_file<T: Serialize>(
+    path: &Path,
+    kind: StoreKind,
+    entries: &HashMap<String, T>,
+) -> Result<()> {
+    persist::write_map(path, kind, entries)
+}
+
+/// Apply a mutation to the latest contents of a store file and save it.
+///
+/// Same scheme as `Store`/`VehicleStore`: takes the cross-process lock,
+/// re-reads the file so entries written by other writers are kept, applies
+/// `f` and writes atomically. Returns the saved entries with `f`'s result,
+/// and the [`Recovery`] if the file had to be salvaged first.
+pub fn update_store_file<T, R>(
+    path: &Path,
+    kind: StoreKind,
+    f: impl FnOnce(&mut HashMap<String, T>) -> Result<R>,
+) -> Result<(HashMap<String, T>, R, Option<Recovery>)>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let _lock = persist::lock(path)?;
+    let (mut entries, recovery) = persist::load_map(path, kind)?;
+    let result = f(&mut entries)?;
+    persist::write_map(path, kind, &entries)?;
+    Ok((entries, result, recovery))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_legacy_map_is_version_zero() {
+        let value = json!({ "abc": { "image_path": "a.jpg" } });
+        let doc = StoreDocument::from_value(value, StoreKind::History).unwrap();
+        assert_eq!(doc.version, 0);
+        assert_eq!(doc.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_envelope_schema_mismatch_is_rejected() {
+        let value = json!({ "schema": "vehicles", "version": 1, "entries": {} });
+        assert!(StoreDocument::from_value(value, StoreKind::History).is_err());
+    }
+
+    #[test]
+    fn test_migrate_fills_fields_and_keeps_unknown() {
+        let doc = StoreDocument {
+            version: 0,
+            entries: json!({ "v1": { "id": "v1", "future_field": 42 } })
+                .as_object()
+                .cloned()
+                .unwrap(),
+        };
+        let (entries, steps) = migrate(StoreKind::Vehicles, doc).unwrap();
+        assert_eq!(steps.len(), StoreKind::Vehicles.current_version() as usize);
+        assert_eq!((steps[0].from, steps[0].to), (0, 1));
+        assert_eq!(steps.last().unwrap().to, StoreKind::Vehicles.current_version());
+        assert!(!steps[0].changes.is_empty());
+        assert_eq!(entries["v1"]["future_field"], 42);
+        assert!(entries["v1"]["company"].is_null());
+    }
+
+    #[test]
+    fn test_history_v1_upgrades_to_v2() {
+        let doc = StoreDocument {
+            version: 1,
+            entries: json!({ "abc": { "image_path": "a.jpg", "notes": null } })
+                .as_object()
+                .cloned()
+                .unwrap(),
+        };
+        let (entries, steps) = migrate(StoreKind::History, doc).unwrap();
+        assert_eq!(StoreKind::History.current_version(), 2);
+        assert_eq!(steps.len(), 1);
+        assert_eq!((steps[0].from, steps[0].to), (1, 2));
+        assert!(entries["abc"].get("source_images").is_none());
+    }
+
+    #[test]
+    fn test_newer_version_is_refused() {
+        let doc = StoreDocument {
+            version: StoreKind::History.current_version() + 1,
+            entries: Map::new(),
+        };
+        assert!(migrate(StoreKind::History, doc).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Storage backends behind `Store` and `VehicleStore`
+//!
+//! The stores keep their entries in memory and persist every mutation through
+//! an [`EntryStorage`], one entry at a time. The JSON files in the store
+//! directory are the default backend ([`JsonStorage`]); tonsuu-infra provides
+//! a SQLite one.
+
+use crate::persist::{self, Recovery};
+use crate::schema::{self, StoreKind};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tonsuu_types::Result;
+
+/// Edit of one entry applied by [`EntryStorage::update_entry`]: `None` when
+/// there is no entry under the key; leaving `None` removes it
+pub type EntryEdit<'a, T> = dyn FnMut(&mut Option<T>) -> Result<()> + 'a;
+
+/// Where a store's entries are persisted
+pub trait EntryStorage<T>: Send + Sync {
+    /// Read all entries. Backends may leave the thumbnail out of history
+    /// entries and return it from [`EntryStorage::load_thumbnail`] instead.
+    fn load(&self) -> Result<HashMap<String, T>>;
+
+    /// Apply `f` to the latest persisted entry under `key` and save what it
+    /// leaves there, without touching other entries.
+    ///
+    /// Must be serialized against other writers (threads and processes).
+    /// Returns the saved entry (`None` if there is none now).
+    fn update_entry(&self, key: &str, f: &mut EntryEdit<'_, T>) -> Result<Option<T>>;
+
+    /// Remove every entry
+    fn clear(&self) -> Result<()>;
+
+    /// Thumbnail of the entry under `key`, for backends whose `load` leaves
+    /// it out. The default is `None`: the loaded entries are complete.
+    fn load_thumbnail(&self, _key: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Take the [`Recovery`] of the last `load`/`update_entry` that had to
+    /// salvage damaged data, if any. Backends that cannot be partly read
+    /// return `None`.
+    fn take_recovery(&self) -> Option<Recovery> {
+        None
+    }
+}
+
+/// Versioned JSON file in the store directory (see `persist`)
+pub struct JsonStorage {
+    path: PathBuf,
+    kind: StoreKind,
+    recovery: Mutex<Option<Recovery>>,
+}
+
+impl JsonStorage {
+    /// Storage for `kind` in `store_dir`, creating the directory if needed
+    pub fn new(store_dir: &Path, kind: StoreKind) -> Result<Self> {
+        fs::create_dir_all(store_dir)?;
+        Ok(Self {
+            path: store_dir.join(kind.file_name()),
+            kind,
+            recovery: Mutex::new(None),
+        })
+    }
+
+    fn record(&self, recovery: Option<Recovery>) {
+        if recovery.is_some() {
+            *self.recovery.lock().unwrap_or_else(|e| e.into_inner()) = recovery;
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Clone> EntryStorage<T> for JsonStorage {
+    fn load(&self) -> Result<HashMap<String, T>> {
+        let (entries, recovery) = persist::load_map(&self.path, self.kind)?;
+        self.record(recovery);
+        Ok(entries)
+    }
+
+    fn update_entry(&self, key: &str, f: &mut EntryEdit<'_, T>) -> Result<Option<T>> {
+        let (_, saved, recovery) = schema::update_store_file(
+            &self.path,
+            self.kind,
+            |entries: &mut HashMap<String, T>| {
+                let mut slot = entries.remove(key);
+                f(&mut slot)?;
+                if let Some(entry) = &slot {
+                    entries.insert(key.to_string(), entry.clone());
+                }
+                Ok(slot)
+            },
+        )?;
+        self.record(recovery);
+        Ok(saved)
+    }
+
+    fn clear(&self) -> Result<()> {
+        let (_, (), recovery) = schema::update_store_file(
+            &self.path,
+            self.kind,
+            |entries: &mut HashMap<String, T>| {
+                entries.clear();
+                Ok(())
+            },
+        )?;
+        self.record(recovery);
+        Ok(())
+    }
+
+    fn take_recovery(&self) -> Option<Recovery> {
+        self.recovery.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+/// Run a one-shot edit through [`EntryStorage::update_entry`], returning the
+/// saved entry and the edit's result
+pub(crate) fn update_entry_once<T, R>(
+    storage: &dyn EntryStorage<T>,
+    key: &str,
+    f: impl FnOnce(&mut Option<T>) -> Result<R>,
+) -> Result<(Option<T>, R)> {
+    let mut f = Some(f);
+    let mut result = None;
+    let saved = storage.update_entry(key, &mut |slot| {
+        let f = f.take().expect("EntryStorage::update_entry must call the edit once");
+        result = Some(f(slot)?);
+        Ok(())
+    })?;
+    let result = result.expect("EntryStorage::update_entry must call the edit once");
+    Ok((saved, result))
+}
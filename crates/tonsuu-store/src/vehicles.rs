@@ -1,59 +1,73 @@
 //! Vehicle store for registered vehicles
 
+use crate::schema::StoreKind;
+use crate::storage::{self, EntryStorage, JsonStorage};
+use crate::Recovery;
+use chrono::NaiveDate;
 use tonsuu_types::Result;
-use tonsuu_types::{RegisteredVehicle, TruckClass};
+use tonsuu_types::{LicensePlate, RegisteredVehicle, TruckClass};
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
 use std::path::PathBuf;
 
 /// Persistent store for registered vehicles
 pub struct VehicleStore {
-    store_path: PathBuf,
+    storage: Box<dyn EntryStorage<RegisteredVehicle>>,
     vehicles: HashMap<String, RegisteredVehicle>,
+    recovery: Option<Recovery>,
 }
 
 impl VehicleStore {
     /// Create or load a vehicle store
     pub fn open(store_dir: PathBuf) -> Result<Self> {
-        fs::create_dir_all(&store_dir)?;
-        let store_path = store_dir.join("vehicles.json");
-
-        let vehicles = if store_path.exists() {
-            let file = File::open(&store_path)?;
-            let reader = BufReader::new(file);
-            serde_json::from_reader(reader).unwrap_or_default()
-        } else {
-            HashMap::new()
-        };
+        Self::with_storage(Box::new(JsonStorage::new(&store_dir, StoreKind::Vehicles)?))
+    }
+
+    /// Load a vehicle store from another storage backend (e.g. SQLite)
+    pub fn with_storage(storage: Box<dyn EntryStorage<RegisteredVehicle>>) -> Result<Self> {
+        let vehicles = storage.load()?;
+        let recovery = storage.take_recovery();
+        Ok(Self {
+            storage,
+            vehicles,
+            recovery,
+        })
+    }
 
-        Ok(Self { store_path, vehicles })
+    /// Set when the vehicles had to be salvaged from a damaged file
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
     }
 
-    /// Save store to disk
-    fn save(&self) -> Result<()> {
-        let file = File::create(&self.store_path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &self.vehicles)?;
-        Ok(())
+    /// Apply an edit to the latest persisted vehicle under `id` and save it
+    /// (same scheme as `Store`)
+    fn update_entry<R>(
+        &mut self,
+        id: &str,
+        f: impl FnOnce(&mut Option<RegisteredVehicle>) -> R,
+    ) -> Result<R> {
+        let (saved, result) =
+            storage::update_entry_once(self.storage.as_ref(), id, |slot| Ok(f(slot)))?;
+        match saved {
+            Some(vehicle) => self.vehicles.insert(id.to_string(), vehicle),
+            None => self.vehicles.remove(id),
+        };
+        if let Some(recovery) = self.storage.take_recovery() {
+            self.recovery = Some(recovery);
+        }
+        Ok(result)
     }
 
     /// Add a new vehicle
     pub fn add_vehicle(&mut self, vehicle: RegisteredVehicle) -> Result<String> {
         let id = vehicle.id.clone();
-        self.vehicles.insert(id.clone(), vehicle);
-        self.save()?;
+        self.update_entry(&id, |slot| *slot = Some(vehicle))?;
         Ok(id)
     }
 
     /// Remove a vehicle by ID
     #[allow(dead_code)]
     pub fn remove_vehicle(&mut self, id: &str) -> Result<bool> {
-        let removed = self.vehicles.remove(id).is_some();
-        if removed {
-            self.save()?;
-        }
-        Ok(removed)
+        self.update_entry(id, |slot| slot.take().is_some())
     }
 
     /// Get a vehicle by ID
@@ -62,13 +76,24 @@ impl VehicleStore {
         self.vehicles.get(id)
     }
 
-    /// Find vehicle by license plate
+    /// Find vehicle by license plate (verbatim, then as the same parsed plate)
     pub fn get_by_license_plate(&self, plate: &str) -> Option<&RegisteredVehicle> {
-        self.vehicles.values().find(|v| {
+        let exact = self.vehicles.values().find(|v| {
             v.license_plate
                 .as_ref()
                 .map(|p| p == plate)
                 .unwrap_or(false)
+        });
+        if exact.is_some() {
+            return exact;
+        }
+
+        let parsed = LicensePlate::parse(plate)?;
+        self.vehicles.values().find(|v| {
+            v.license_plate
+                .as_deref()
+                .and_then(LicensePlate::parse)
+                .is_some_and(|p| p == parsed)
         })
     }
 
@@ -88,6 +113,19 @@ impl VehicleStore {
             .collect()
     }
 
+    /// Vehicles whose 車検証 expires within `days` of `today` (expired ones
+    /// included), with the days left, soonest first
+    pub fn expiring_within(&self, today: NaiveDate, days: i64) -> Vec<(&RegisteredVehicle, i64)> {
+        let mut expiring: Vec<_> = self
+            .vehicles
+            .values()
+            .filter_map(|v| v.days_until_inspection_expiry(today).map(|left| (v, left)))
+            .filter(|(_, left)| *left <= days)
+            .collect();
+        expiring.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.name.cmp(&b.0.name)));
+        expiring
+    }
+
     /// Get total vehicle count
     pub fn count(&self) -> usize {
         self.vehicles.len()
@@ -96,12 +134,14 @@ impl VehicleStore {
     /// Update a vehicle
     #[allow(dead_code)]
     pub fn update_vehicle(&mut self, vehicle: RegisteredVehicle) -> Result<bool> {
-        if self.vehicles.contains_key(&vehicle.id) {
-            self.vehicles.insert(vehicle.id.clone(), vehicle);
-            self.save()?;
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        let id = vehicle.id.clone();
+        self.update_entry(&id, |slot| {
+            if slot.is_some() {
+                *slot = Some(vehicle);
+                true
+            } else {
+                false
+            }
+        })
     }
 }
@@ -67,6 +67,12 @@ pub enum Error {
     #[error("Excel export error: {0}")]
     Excel(String),
 
+    #[error("Database error: {0}")]
+    Database(String),
+
+    #[error("Store schema error: {0}")]
+    Schema(String),
+
     #[allow(dead_code)]
     #[error("No target detected in image")]
     NoTargetDetected,
@@ -1,9 +1,13 @@
 //! Core types for tonnage estimation
 
 mod error;
+mod plate;
+mod truck_class;
 mod types;
 
 pub use error::*;
+pub use plate::*;
+pub use truck_class::*;
 pub use types::*;
 
 use clap::ValueEnum;
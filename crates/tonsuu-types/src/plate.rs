@@ -0,0 +1,336 @@
+//! Japanese license plate parsing and matching
+//!
+//! A plate such as "熊本 130 ら 11-22" has four parts: region (熊本),
+//! classification number (130), kana (ら) and serial number (1122).
+//! Registered vehicles, weighing slips and OCR output write plates
+//! differently (full-width digits, `・` padding, missing region, ...), so all
+//! comparisons go through [`LicensePlate`] instead of raw strings.
+//!
+//! OCR marks characters it could not read with `?`. A `?` matches any single
+//! character in every part, and a part made only of `?` is treated as absent.
+
+use std::fmt;
+
+/// Parsed license plate; absent parts are `None`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LicensePlate {
+    /// Region name (e.g., "熊本")
+    pub region: Option<String>,
+    /// Classification number (e.g., "130", may contain letters such as "30A")
+    pub class_number: Option<String>,
+    /// Hiragana character (katakana OCR output is folded to hiragana)
+    pub kana: Option<char>,
+    /// Serial number digits without padding or separators (e.g., "1122", "12"),
+    /// with `?` for unread digits
+    pub serial: String,
+}
+
+/// Placeholder OCR uses for an unread character
+const WILDCARD: char = '?';
+
+/// Longest classification number ("130", "30A")
+const MAX_CLASS_NUMBER_LEN: usize = 3;
+
+/// Score of a match where only the serial number could be compared
+pub const SERIAL_ONLY_SCORE: f64 = 0.4;
+
+impl LicensePlate {
+    /// Parse a plate string; returns `None` if no serial number is found.
+    ///
+    /// Accepts full-width digits/letters, ASCII and full-width spaces,
+    /// `-`/`－`/`ー` separators and `・` padding. Partial plates ("1122",
+    /// "130 ら 1122") parse with the missing parts left as `None`, and so do
+    /// parts OCR could not read at all ("?? 130 ? 11-22").
+    pub fn parse(input: &str) -> Option<Self> {
+        let chars: Vec<char> = input
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(to_half_width)
+            .collect();
+
+        // Serial: trailing run of digits, separators and padding. A `?` only
+        // belongs to it while fewer than 4 serial characters were seen; one
+        // before that is the kana or class number.
+        let mut end = chars.len();
+        let mut serial_len = 0;
+        while end > 0 {
+            let c = chars[end - 1];
+            if c.is_ascii_digit() || (c == WILDCARD && serial_len < 4) {
+                serial_len += 1;
+            } else if !is_separator(c) {
+                break;
+            }
+            end -= 1;
+        }
+        let mut serial: String = chars[end..]
+            .iter()
+            .filter(|c| c.is_ascii_digit() || **c == WILDCARD)
+            .collect();
+        if serial.is_empty() {
+            return None;
+        }
+
+        // "130-1122" without kana: the leading digits are (the end of) the
+        // class number
+        let mut class_tail = String::new();
+        if serial.len() > 4 {
+            let split = serial.len() - 4;
+            class_tail = serial[..split].to_string();
+            serial = serial[split..].to_string();
+        }
+
+        // Kana directly before the serial
+        let mut kana = None;
+        if end > 0 && class_tail.is_empty() {
+            let c = chars[end - 1];
+            if c == WILDCARD {
+                end -= 1;
+            } else if let Some(h) = to_hiragana(c) {
+                kana = Some(h);
+                end -= 1;
+            }
+        }
+
+        // Classification number: ASCII alphanumerics (or `?`) before the kana
+        let class_end = end;
+        while end > 0 {
+            let c = chars[end - 1];
+            let len = class_end - end + class_tail.len();
+            if !(c.is_ascii_alphanumeric() || (c == WILDCARD && len < MAX_CLASS_NUMBER_LEN)) {
+                break;
+            }
+            end -= 1;
+        }
+        let class_number = chars[end..class_end].iter().collect::<String>() + &class_tail;
+
+        let region: String = chars[..end].iter().filter(|c| !is_separator(**c)).collect();
+
+        Some(Self {
+            region: known(region),
+            class_number: known(class_number),
+            kana,
+            serial,
+        })
+    }
+
+    /// Compact normalized form used as a lookup key (e.g., "熊本130ら1122")
+    pub fn key(&self) -> String {
+        let mut key = String::new();
+        if let Some(ref region) = self.region {
+            key.push_str(region);
+        }
+        if let Some(ref class_number) = self.class_number {
+            key.push_str(class_number);
+        }
+        if let Some(kana) = self.kana {
+            key.push(kana);
+        }
+        key.push_str(&self.serial);
+        key
+    }
+
+    /// Last 4 digits of the serial number, if it has 4 digits and all were read
+    pub fn last4(&self) -> Option<&str> {
+        (self.serial.len() == 4 && self.serial.chars().all(|c| c.is_ascii_digit()))
+            .then_some(self.serial.as_str())
+    }
+
+    /// How well two plates agree, from 0.0 to 1.0.
+    ///
+    /// The serial numbers must be equal, and parts present on both sides must
+    /// not conflict; otherwise the score is 0.0. A `?` matches any character.
+    /// Each part present on both sides adds to the score, so 1.0 means every
+    /// part was compared and matched, and [`SERIAL_ONLY_SCORE`] means only the
+    /// serial was comparable.
+    pub fn match_score(&self, other: &LicensePlate) -> f64 {
+        if !wildcard_eq(&self.serial, &other.serial) {
+            return 0.0;
+        }
+
+        // Serial counts double; region, class number and kana count once each
+        let mut points = 2;
+        let parts = [
+            compare_text(self.region.as_deref(), other.region.as_deref()),
+            compare_text(self.class_number.as_deref(), other.class_number.as_deref()),
+            compare_part(self.kana, other.kana),
+        ];
+        for part in parts {
+            match part {
+                Some(true) => points += 1,
+                Some(false) => return 0.0,
+                None => {}
+            }
+        }
+        points as f64 / 5.0
+    }
+}
+
+impl fmt::Display for LicensePlate {
+    /// Canonical form, e.g. "熊本 130 ら 11-22" or "・・12"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let serial = if self.serial.len() == 4 {
+            format!("{}-{}", &self.serial[..2], &self.serial[2..])
+        } else {
+            format!("{}{}", "・".repeat(4usize.saturating_sub(self.serial.len())), self.serial)
+        };
+
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.region.clone());
+        parts.extend(self.class_number.clone());
+        parts.extend(self.kana.map(String::from));
+        parts.push(serial);
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// `Some(equal)` when both sides have the part, `None` otherwise
+fn compare_part<T: PartialEq>(a: Option<T>, b: Option<T>) -> Option<bool> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a == b),
+        _ => None,
+    }
+}
+
+/// Like [`compare_part`], with `?` matching any character
+fn compare_text(a: Option<&str>, b: Option<&str>) -> Option<bool> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(wildcard_eq(a, b)),
+        _ => None,
+    }
+}
+
+/// Equal length and every character equal or `?` on either side; a text made
+/// only of `?` matches anything
+fn wildcard_eq(a: &str, b: &str) -> bool {
+    let all_wild = |s: &str| s.chars().all(|c| c == WILDCARD);
+    if all_wild(a) || all_wild(b) {
+        return true;
+    }
+    a.chars().count() == b.chars().count()
+        && a.chars()
+            .zip(b.chars())
+            .all(|(x, y)| x == y || x == WILDCARD || y == WILDCARD)
+}
+
+/// `None` for a part that is empty or was not read at all
+fn known(part: String) -> Option<String> {
+    Some(part).filter(|p| p.chars().any(|c| c != WILDCARD))
+}
+
+/// Fold full-width ASCII (digits, letters, `－`, `？`) to half-width; letters uppercased
+fn to_half_width(c: char) -> char {
+    let c = match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    };
+    c.to_ascii_uppercase()
+}
+
+/// Separators and padding that carry no information
+fn is_separator(c: char) -> bool {
+    matches!(
+        c,
+        '-' | 'ー' | '‐' | '−' | '―' | '─' | '・' | '･' | '.' | '·' | '•'
+    )
+}
+
+/// Hiragana as-is, katakana folded to hiragana, anything else `None`
+fn to_hiragana(c: char) -> Option<char> {
+    match c {
+        '\u{3041}'..='\u{3096}' => Some(c),
+        '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_plate() {
+        let plate = LicensePlate::parse("熊本 130 ら 11-22").unwrap();
+        assert_eq!(plate.region.as_deref(), Some("熊本"));
+        assert_eq!(plate.class_number.as_deref(), Some("130"));
+        assert_eq!(plate.kana, Some('ら'));
+        assert_eq!(plate.serial, "1122");
+        assert_eq!(plate.to_string(), "熊本 130 ら 11-22");
+    }
+
+    #[test]
+    fn test_parse_variants_are_equal() {
+        let canonical = LicensePlate::parse("熊本 130 ら 1122").unwrap();
+        for variant in ["熊本130ら11-22", "熊本　１３０　ら　１１－２２", "熊本 130 ラ 1122"] {
+            assert_eq!(LicensePlate::parse(variant).unwrap(), canonical, "{}", variant);
+        }
+    }
+
+    #[test]
+    fn test_parse_padding_and_partial() {
+        let padded = LicensePlate::parse("つくば 500 あ ・・12").unwrap();
+        assert_eq!(padded.region.as_deref(), Some("つくば"));
+        assert_eq!(padded.serial, "12");
+        assert_eq!(padded.to_string(), "つくば 500 あ ・・12");
+        assert!(padded.last4().is_none());
+
+        let serial_only = LicensePlate::parse("1122").unwrap();
+        assert!(serial_only.region.is_none() && serial_only.kana.is_none());
+        assert_eq!(serial_only.last4(), Some("1122"));
+
+        let no_kana = LicensePlate::parse("130-1122").unwrap();
+        assert_eq!(no_kana.class_number.as_deref(), Some("130"));
+        assert_eq!(no_kana.serial, "1122");
+
+        assert!(LicensePlate::parse("熊本").is_none());
+        assert!(LicensePlate::parse("").is_none());
+    }
+
+    #[test]
+    fn test_match_score() {
+        let full = LicensePlate::parse("熊本 130 ら 1122").unwrap();
+        let same = LicensePlate::parse("熊本130ら11-22").unwrap();
+        let no_region = LicensePlate::parse("130 ら 1122").unwrap();
+        let serial = LicensePlate::parse("1122").unwrap();
+        let other_region = LicensePlate::parse("福岡 130 ら 1122").unwrap();
+        let other_serial = LicensePlate::parse("熊本 130 ら 1123").unwrap();
+
+        assert_eq!(full.match_score(&same), 1.0);
+        assert!(full.match_score(&no_region) > SERIAL_ONLY_SCORE);
+        assert!(full.match_score(&no_region) < 1.0);
+        assert_eq!(full.match_score(&serial), SERIAL_ONLY_SCORE);
+        assert_eq!(full.match_score(&other_region), 0.0);
+        assert_eq!(full.match_score(&other_serial), 0.0);
+    }
+
+    #[test]
+    fn test_wildcards_in_every_part() {
+        let full = LicensePlate::parse("熊本 130 ら 11-22").unwrap();
+
+        let partial = LicensePlate::parse("熊本 1?0 ら 11-22").unwrap();
+        assert_eq!(partial.class_number.as_deref(), Some("1?0"));
+        assert_eq!(partial.kana, Some('ら'));
+        assert_eq!(full.match_score(&partial), 1.0);
+        assert_eq!(partial.match_score(&full), 1.0);
+
+        let unread = LicensePlate::parse("？？ １３０ ？ １？－２２").unwrap();
+        assert!(unread.region.is_none() && unread.kana.is_none());
+        assert_eq!(unread.class_number.as_deref(), Some("130"));
+        assert_eq!(unread.serial, "1?22");
+        assert!(unread.last4().is_none());
+        assert!(full.match_score(&unread) > SERIAL_ONLY_SCORE);
+
+        let region = LicensePlate::parse("熊? 130 ら 1122").unwrap();
+        assert_eq!(region.region.as_deref(), Some("熊?"));
+        assert_eq!(region.class_number.as_deref(), Some("130"));
+        assert_eq!(full.match_score(&region), 1.0);
+
+        let no_kana = LicensePlate::parse("1?0-1122").unwrap();
+        assert_eq!(no_kana.class_number.as_deref(), Some("1?0"));
+        assert_eq!(no_kana.serial, "1122");
+
+        let conflicting = LicensePlate::parse("熊本 1?1 ら 11-22").unwrap();
+        assert_eq!(full.match_score(&conflicting), 0.0);
+        let other_serial = LicensePlate::parse("熊本 130 ら 1?-23").unwrap();
+        assert_eq!(full.match_score(&other_serial), 0.0);
+    }
+}
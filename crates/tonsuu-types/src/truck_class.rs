@@ -0,0 +1,395 @@
+//! Truck classes (2t, 4t, 増トン, ...) and the capacity range of each
+//!
+//! The classes are defined in `config/trucks.toml`. The app reads that file
+//! and calls [`install_truck_classes`] at startup; until then the shipped
+//! file, embedded at build time, is used, so library code and tests work
+//! without a config directory. Capacity ranges are half-open and may not
+//! overlap; vehicles whose capacity is in no range are classed as
+//! [`TruckClass::UNKNOWN`].
+//!
+//! The box-overlay pipeline in tonsuu-core only has bed specs for
+//! [`CORE_TRUCK_CLASSES`]; every other class names the one it is analyzed as
+//! with `core_class`, and its estimates are scaled from that class's bed to
+//! its own with [`TruckClassRegistry::core_bed_scale`].
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// `config/trucks.toml` as shipped, the source of the built-in classes
+const BUILTIN_TRUCKS_TOML: &str = include_str!("../../../config/trucks.toml");
+
+/// Classes with a bed spec in tonsuu-core's prompt-spec.json
+pub const CORE_TRUCK_CLASSES: &[&str] = &["2t", "4t", "増トン", "10t"];
+
+/// Truck class, identified by its id in `trucks.toml` (e.g. "4t")
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TruckClass(Cow<'static, str>);
+
+impl TruckClass {
+    /// Vehicles whose capacity matches no configured class
+    pub const UNKNOWN: TruckClass = TruckClass(Cow::Borrowed("不明"));
+
+    /// Class with the given id (not checked against the registry)
+    pub fn new(id: impl Into<String>) -> Self {
+        TruckClass(Cow::Owned(id.into()))
+    }
+
+    /// Determine truck class from max capacity
+    pub fn from_capacity(max_capacity: f64) -> Self {
+        truck_classes().classify(max_capacity)
+    }
+
+    /// Resolve a class id or alias (e.g. "4トン" → 4t)
+    pub fn from_name(name: &str) -> Option<Self> {
+        truck_classes().resolve(name)
+    }
+
+    /// All configured classes, in config order
+    pub fn all() -> Vec<Self> {
+        truck_classes()
+            .classes()
+            .iter()
+            .map(|c| Self::new(c.id.clone()))
+            .collect()
+    }
+
+    /// Get display label (the class id)
+    pub fn label(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == Self::UNKNOWN
+    }
+}
+
+impl fmt::Display for TruckClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// One class of the registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruckClassDef {
+    pub id: String,
+    /// Other names of the class (e.g. "4トン", "4tダンプ")
+    pub aliases: Vec<String>,
+    /// Max capacity range (t) of vehicles in this class, including the lower
+    /// end but not the upper. `None` means the class is only chosen by name.
+    pub capacity_range: Option<(f64, f64)>,
+    /// Class of [`CORE_TRUCK_CLASSES`] the box-overlay pipeline analyzes this
+    /// class as (`None`: the id itself)
+    pub core_class: Option<String>,
+    /// Floor area (m²) of the class's bed, `None` when no bed size is configured
+    pub bed_area: Option<f64>,
+}
+
+impl TruckClassDef {
+    pub fn new(id: &str, min_capacity: f64, max_capacity: f64) -> Self {
+        Self {
+            id: id.to_string(),
+            aliases: Vec::new(),
+            capacity_range: Some((min_capacity, max_capacity)),
+            core_class: None,
+            bed_area: None,
+        }
+    }
+
+    /// Class id passed to tonsuu-core
+    pub fn core_class(&self) -> &str {
+        self.core_class.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// Class fields of a `[[trucks]]` entry (the bed specs are read by the app)
+#[derive(Deserialize)]
+struct TruckClassEntry {
+    id: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    capacity_range: Option<[f64; 2]>,
+    #[serde(default)]
+    core_class: Option<String>,
+    #[serde(default)]
+    bed_length: Option<f64>,
+    #[serde(default)]
+    bed_width: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct TrucksFile {
+    trucks: Vec<TruckClassEntry>,
+}
+
+/// Truck classes in config order
+#[derive(Debug, Clone, Default)]
+pub struct TruckClassRegistry {
+    classes: Vec<TruckClassDef>,
+}
+
+impl TruckClassRegistry {
+    pub fn new(classes: Vec<TruckClassDef>) -> Self {
+        Self { classes }
+    }
+
+    /// Classes used when no `trucks.toml` has been installed
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN_TRUCKS_TOML).expect("built-in trucks.toml is invalid")
+    }
+
+    /// Read the classes of a `trucks.toml`.
+    ///
+    /// Rejects empty or overlapping capacity ranges and classes the
+    /// box-overlay pipeline has no spec for.
+    pub fn from_toml(content: &str) -> std::result::Result<Self, String> {
+        let file: TrucksFile =
+            toml::from_str(content).map_err(|e| format!("Failed to parse trucks.toml: {}", e))?;
+
+        let mut classes: Vec<TruckClassDef> = Vec::new();
+        for entry in file.trucks {
+            if let Some([min, max]) = entry.capacity_range {
+                if min >= max {
+                    return Err(format!(
+                        "Invalid capacity_range for {}: {} >= {}",
+                        entry.id, min, max
+                    ));
+                }
+                let overlapping = classes.iter().find(|c| {
+                    c.capacity_range
+                        .is_some_and(|(other_min, other_max)| min < other_max && other_min < max)
+                });
+                if let Some(other) = overlapping {
+                    return Err(format!(
+                        "capacity_range of {} overlaps that of {}",
+                        entry.id, other.id
+                    ));
+                }
+            }
+            let class = TruckClassDef {
+                id: entry.id,
+                aliases: entry.aliases,
+                capacity_range: entry.capacity_range.map(|[min, max]| (min, max)),
+                core_class: entry.core_class,
+                bed_area: entry
+                    .bed_length
+                    .zip(entry.bed_width)
+                    .map(|(length, width)| length * width),
+            };
+            if !CORE_TRUCK_CLASSES.contains(&class.core_class()) {
+                return Err(format!(
+                    "Truck class {} has no box-overlay spec; set core_class to one of {}",
+                    class.id,
+                    CORE_TRUCK_CLASSES.join(", ")
+                ));
+            }
+            classes.push(class);
+        }
+        Ok(Self::new(classes))
+    }
+
+    pub fn classes(&self) -> &[TruckClassDef] {
+        &self.classes
+    }
+
+    /// Class whose capacity range contains `max_capacity`.
+    ///
+    /// A capacity on the boundary of two ranges belongs to the class it is
+    /// the lower end of.
+    pub fn classify(&self, max_capacity: f64) -> TruckClass {
+        self.classes
+            .iter()
+            .find(|c| {
+                c.capacity_range
+                    .is_some_and(|(min, max)| max_capacity >= min && max_capacity < max)
+            })
+            .map(|c| TruckClass::new(c.id.clone()))
+            .unwrap_or(TruckClass::UNKNOWN)
+    }
+
+    /// Resolve a class id or alias; falls back to a case-insensitive match
+    pub fn resolve(&self, name: &str) -> Option<TruckClass> {
+        let trimmed = name.trim();
+        let exact = self
+            .classes
+            .iter()
+            .find(|c| c.id == trimmed || c.aliases.iter().any(|a| a == trimmed));
+        let found = exact.or_else(|| {
+            let lower_input = trimmed.to_lowercase();
+            self.classes.iter().find(|c| {
+                c.id.to_lowercase() == lower_input
+                    || c.aliases.iter().any(|a| a.to_lowercase() == lower_input)
+            })
+        });
+        found.map(|c| TruckClass::new(c.id.clone()))
+    }
+
+    /// Class id tonsuu-core analyzes `id` as (unknown ids are passed through)
+    pub fn core_class<'a>(&'a self, id: &'a str) -> &'a str {
+        self.classes
+            .iter()
+            .find(|c| c.id == id)
+            .map_or(id, TruckClassDef::core_class)
+    }
+
+    /// Ratio of the bed area of class `id` to that of the class tonsuu-core
+    /// analyzes it as.
+    ///
+    /// Volume and tonnage computed on the core class bed are multiplied by it
+    /// to get the class's own. 1.0 for core classes, unknown ids and classes
+    /// without a configured bed size.
+    pub fn core_bed_scale(&self, id: &str) -> f64 {
+        let bed_area = |id: &str| {
+            self.classes
+                .iter()
+                .find(|c| c.id == id)
+                .and_then(|c| c.bed_area)
+                .filter(|area| *area > 0.0)
+        };
+        let core = self.core_class(id);
+        if core == id {
+            return 1.0;
+        }
+        match (bed_area(id), bed_area(core)) {
+            (Some(own), Some(core)) => own / core,
+            _ => 1.0,
+        }
+    }
+}
+
+static TRUCK_CLASSES: OnceLock<TruckClassRegistry> = OnceLock::new();
+
+/// Install the configured truck classes.
+///
+/// Returns `false` if classes were already installed or already used (the
+/// registry cannot change while the process runs); callers should report
+/// that the configured classes were not applied.
+pub fn install_truck_classes(registry: TruckClassRegistry) -> bool {
+    TRUCK_CLASSES.set(registry).is_ok()
+}
+
+/// The installed truck classes, or the built-in ones
+pub fn truck_classes() -> &'static TruckClassRegistry {
+    TRUCK_CLASSES.get_or_init(TruckClassRegistry::builtin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_by_capacity_range() {
+        let registry = TruckClassRegistry::new(vec![
+            TruckClassDef::new("2t", 1.5, 2.5),
+            TruckClassDef::new("3t", 2.5, 3.5),
+            TruckClassDef::new("11t+", 11.0, 40.0),
+        ]);
+        assert_eq!(registry.classify(2.0).label(), "2t");
+        assert_eq!(registry.classify(2.5).label(), "3t");
+        assert_eq!(registry.classify(3.0).label(), "3t");
+        assert_eq!(registry.classify(18.0).label(), "11t+");
+        assert!(registry.classify(6.0).is_unknown());
+        assert!(registry.classify(3.5).is_unknown());
+    }
+
+    #[test]
+    fn test_invalid_capacity_ranges_are_rejected() {
+        let class = |id: &str, range: &str| {
+            format!(
+                "[[trucks]]\nid = \"{}\"\ncapacity_range = {}\ncore_class = \"10t\"\n",
+                id, range
+            )
+        };
+        assert!(TruckClassRegistry::from_toml(&class("a", "[3.0, 3.0]")).is_err());
+        assert!(TruckClassRegistry::from_toml(&class("a", "[4.0, 3.0]")).is_err());
+
+        let adjacent = class("a", "[2.5, 3.5]") + &class("b", "[3.5, 5.0]");
+        assert!(TruckClassRegistry::from_toml(&adjacent).is_ok());
+        let overlapping = class("a", "[2.5, 3.5]") + &class("b", "[3.0, 5.0]");
+        assert!(TruckClassRegistry::from_toml(&overlapping).is_err());
+    }
+
+    #[test]
+    fn test_resolve_alias() {
+        let mut four = TruckClassDef::new("4t", 3.0, 4.5);
+        four.aliases = vec!["4トン".to_string(), "4tダンプ".to_string()];
+        let registry = TruckClassRegistry::new(vec![four]);
+        assert_eq!(registry.resolve(" 4トン "), Some(TruckClass::new("4t")));
+        assert_eq!(registry.resolve("4T"), Some(TruckClass::new("4t")));
+        assert_eq!(registry.resolve("8t"), None);
+    }
+
+    #[test]
+    fn test_builtin_matches_trucks_toml() {
+        let registry = TruckClassRegistry::builtin();
+        assert_eq!(registry.classes().len(), 7);
+        assert_eq!(registry.classify(4.2).label(), "4t");
+        assert_eq!(registry.classify(2.75).label(), "3t");
+        assert_eq!(registry.classify(8.5).label(), "8t");
+        assert_eq!(registry.classify(15.0).label(), "11t+");
+        assert_eq!(registry.classify(4.8).label(), "4t");
+        assert!(registry.classify(1.0).is_unknown());
+        assert!(registry.classify(40.0).is_unknown());
+
+        // A nominal capacity is in its own class
+        for (capacity, id) in [
+            (2.0, "2t"),
+            (3.0, "3t"),
+            (4.0, "4t"),
+            (8.0, "8t"),
+            (10.0, "10t"),
+        ] {
+            assert_eq!(registry.classify(capacity).label(), id, "{}t", capacity);
+        }
+
+        // No gaps between the lowest and highest class
+        let mut capacity = 1.5;
+        while capacity < 40.0 {
+            assert!(!registry.classify(capacity).is_unknown(), "{}t", capacity);
+            capacity += 0.05;
+        }
+        assert_eq!(registry.resolve("4トン"), Some(TruckClass::new("4t")));
+
+        assert_eq!(registry.core_class("3t"), "2t");
+        assert_eq!(registry.core_class("8t"), "10t");
+        assert_eq!(registry.core_class("11t+"), "10t");
+        assert_eq!(registry.core_class("増トン"), "増トン");
+    }
+
+    #[test]
+    fn test_core_bed_scale() {
+        let registry = TruckClassRegistry::builtin();
+        // 11t+ bed 7.5 x 2.3 on the 10t bed 5.3 x 2.3
+        assert!((registry.core_bed_scale("11t+") - 7.5 / 5.3).abs() < 1e-9);
+        // 3t bed 3.1 x 1.8 on the 2t bed 3.0 x 1.6
+        assert!((registry.core_bed_scale("3t") - (3.1 * 1.8) / (3.0 * 1.6)).abs() < 1e-9);
+        assert_eq!(registry.core_bed_scale("10t"), 1.0);
+        assert_eq!(registry.core_bed_scale("99t"), 1.0);
+
+        // No bed size configured: left unscaled
+        let toml = "[[trucks]]\nid = \"12t\"\ncore_class = \"10t\"\n";
+        let registry = TruckClassRegistry::from_toml(toml).unwrap();
+        assert_eq!(registry.core_bed_scale("12t"), 1.0);
+    }
+
+    #[test]
+    fn test_class_without_core_spec_is_rejected() {
+        let toml = "[[trucks]]\nid = \"12t\"\ncapacity_range = [11.0, 13.0]\n";
+        assert!(TruckClassRegistry::from_toml(toml).is_err());
+        let mapped = format!("{}core_class = \"10t\"\n", toml);
+        let registry = TruckClassRegistry::from_toml(&mapped).unwrap();
+        assert_eq!(registry.core_class("12t"), "10t");
+    }
+
+    #[test]
+    fn test_serializes_as_id() {
+        let json = serde_json::to_string(&TruckClass::new("増トン")).unwrap();
+        assert_eq!(json, "\"増トン\"");
+        let back: TruckClass = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.label(), "増トン");
+    }
+}
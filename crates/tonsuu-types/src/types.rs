@@ -1,7 +1,8 @@
 //! Core types for tonnage estimation
 
 use serde::{Deserialize, Deserializer, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::TruckClass;
 
 /// Deserialize null as default value
 fn null_to_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
@@ -12,45 +13,6 @@ where
     Option::deserialize(deserializer).map(|opt| opt.unwrap_or_default())
 }
 
-/// Truck class based on max capacity
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum TruckClass {
-    /// 2t class (1.5-2.5t)
-    TwoTon,
-    /// 4t class (3.0-4.5t)
-    FourTon,
-    /// 増トン (5.0-8.0t)
-    IncreasedTon,
-    /// 10t class (9.0-12.0t)
-    TenTon,
-    /// Unknown
-    Unknown,
-}
-
-impl TruckClass {
-    /// Determine truck class from max capacity
-    pub fn from_capacity(max_capacity: f64) -> Self {
-        match max_capacity {
-            c if c >= 1.5 && c <= 2.5 => TruckClass::TwoTon,
-            c if c >= 3.0 && c <= 4.5 => TruckClass::FourTon,
-            c if c >= 5.0 && c <= 8.0 => TruckClass::IncreasedTon,
-            c if c >= 9.0 && c <= 12.0 => TruckClass::TenTon,
-            _ => TruckClass::Unknown,
-        }
-    }
-
-    /// Get display label in Japanese
-    pub fn label(&self) -> &'static str {
-        match self {
-            TruckClass::TwoTon => "2t",
-            TruckClass::FourTon => "4t",
-            TruckClass::IncreasedTon => "増トン",
-            TruckClass::TenTon => "10t",
-            TruckClass::Unknown => "不明",
-        }
-    }
-}
-
 /// Registered vehicle information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisteredVehicle {
@@ -75,6 +37,45 @@ pub struct RegisteredVehicle {
     /// Notes/memo
     #[serde(default)]
     pub notes: Option<String>,
+    /// Inner cargo bed length in meters (overrides the class spec)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bed_length: Option<f64>,
+    /// Inner cargo bed width in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bed_width: Option<f64>,
+    /// Inner cargo bed height (tailgate) in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bed_height: Option<f64>,
+    /// Side wall height in meters, when raised above the tailgate (深ダンプ)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side_wall_height: Option<f64>,
+    /// Body type of the bed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_type: Option<BodyType>,
+    /// Model code from the 車検証 (型式, e.g. "2PG-FW1AHG")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_code: Option<String>,
+    /// Curb weight in tonnes (車両重量)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vehicle_weight: Option<f64>,
+    /// Gross vehicle weight in tonnes (車両総重量)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gross_vehicle_weight: Option<f64>,
+    /// Overall length in meters (長さ)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vehicle_length: Option<f64>,
+    /// Overall width in meters (幅)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vehicle_width: Option<f64>,
+    /// Overall height in meters (高さ)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vehicle_height: Option<f64>,
+    /// First registration year and month, "YYYY-MM" (初度登録年月)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_registration: Option<String>,
+    /// Last day the 車検証 is valid (有効期間の満了する日)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inspection_expiry: Option<NaiveDate>,
     /// When registered
     pub registered_at: chrono::DateTime<chrono::Utc>,
 }
@@ -90,6 +91,19 @@ impl RegisteredVehicle {
             image_path: None,
             thumbnail_base64: None,
             notes: None,
+            bed_length: None,
+            bed_width: None,
+            bed_height: None,
+            side_wall_height: None,
+            body_type: None,
+            model_code: None,
+            vehicle_weight: None,
+            gross_vehicle_weight: None,
+            vehicle_length: None,
+            vehicle_width: None,
+            vehicle_height: None,
+            first_registration: None,
+            inspection_expiry: None,
             registered_at: chrono::Utc::now(),
         }
     }
@@ -105,17 +119,113 @@ impl RegisteredVehicle {
         self
     }
 
+    pub fn with_bed(mut self, length: f64, width: f64, height: f64) -> Self {
+        self.bed_length = Some(length);
+        self.bed_width = Some(width);
+        self.bed_height = Some(height);
+        self
+    }
+
     pub fn truck_class(&self) -> TruckClass {
         TruckClass::from_capacity(self.max_capacity)
     }
+
+    /// Any bed dimension is registered for this vehicle
+    pub fn has_bed_dimensions(&self) -> bool {
+        self.bed_length.is_some()
+            || self.bed_width.is_some()
+            || self.bed_height.is_some()
+            || self.side_wall_height.is_some()
+    }
+
+    /// Height the load is held in by: the side walls if given, else the tailgate
+    pub fn wall_height(&self) -> Option<f64> {
+        self.side_wall_height.or(self.bed_height)
+    }
+
+    /// The vehicle's company contains `filter` (the `--company` filter of
+    /// every command); vehicles without a company never match
+    pub fn company_matches(&self, filter: &str) -> bool {
+        self.company.as_ref().is_some_and(|c| c.contains(filter))
+    }
+
+    /// Days from `today` until the 車検証 expires (0 on the last valid day,
+    /// negative once expired); None if no expiry date is recorded
+    pub fn days_until_inspection_expiry(&self, today: NaiveDate) -> Option<i64> {
+        self.inspection_expiry
+            .map(|expiry| (expiry - today).num_days())
+    }
+}
+
+/// Body type of a dump truck bed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyType {
+    /// Standard dump body
+    #[default]
+    Standard,
+    /// Deep body with raised side walls (深ダンプ)
+    Deep,
+    /// Extended (long) body
+    Long,
+}
+
+impl BodyType {
+    pub const ALL: [BodyType; 3] = [BodyType::Standard, BodyType::Deep, BodyType::Long];
+
+    /// Parse a body type name ("standard", "deep", "long"; also 標準/深ダンプ/ロング)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "standard" | "標準" | "普通" => Some(BodyType::Standard),
+            "deep" | "深ダンプ" | "深" => Some(BodyType::Deep),
+            "long" | "ロング" | "ロングボディ" => Some(BodyType::Long),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BodyType::Standard => "standard",
+            BodyType::Deep => "deep",
+            BodyType::Long => "long",
+        }
+    }
+
+    /// Japanese label for display
+    pub fn label(&self) -> &'static str {
+        match self {
+            BodyType::Standard => "標準",
+            BodyType::Deep => "深ダンプ",
+            BodyType::Long => "ロング",
+        }
+    }
+}
+
+/// Plausibility finding on an estimate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EstimationWarning {
+    /// Parameter the finding is about (camelCase, e.g. "height", "estimatedVolumeM3")
+    pub field: String,
+    /// Description shown to the user
+    pub message: String,
+    /// Value before clamping (None if the value was only flagged)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clamped_from: Option<f64>,
 }
 
 /// Material breakdown in mixed loads
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MaterialBreakdown {
     pub material: String,
+    /// Share of the load volume (%)
     pub percentage: f64,
+    /// Density (t/m³); may be omitted in the karte, where it is looked up by material
+    #[serde(default)]
     pub density: f64,
+    /// This material's part of the estimated tonnage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tonnage: Option<f64>,
 }
 
 /// Karte input for analysis (known values; null means "estimate")
@@ -144,6 +254,77 @@ pub struct KarteInput {
     pub estimated_tonnage: Option<f64>,
     #[serde(default)]
     pub confidence_score: Option<f64>,
+    /// Known material mix (e.g. 70% As殻 / 30% Co殻)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material_breakdown: Option<Vec<MaterialBreakdown>>,
+}
+
+/// Where the license plate reading came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlateSource {
+    /// Entered by the operator (`--plate`)
+    Manual,
+    /// Located by the local YOLO detector, crop read by API OCR
+    Yolo,
+    /// Full image read by API OCR
+    Api,
+}
+
+impl PlateSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlateSource::Manual => "manual",
+            PlateSource::Yolo => "yolo",
+            PlateSource::Api => "api",
+        }
+    }
+}
+
+/// Key values of one ensemble sample, kept for spread reporting
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsembleSample {
+    pub estimated_tonnage: f64,
+    pub estimated_volume_m3: f64,
+    #[serde(default)]
+    pub height: Option<f64>,
+    #[serde(default)]
+    pub fill_ratio_l: Option<f64>,
+    #[serde(default)]
+    pub fill_ratio_w: Option<f64>,
+    #[serde(default)]
+    pub packing_density: Option<f64>,
+    pub confidence_score: f64,
+    /// Excluded from aggregation as an outlier
+    #[serde(default)]
+    pub rejected: bool,
+    /// Wall time of this sample's AI calls (ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<u64>,
+}
+
+/// Preprocessing applied to photos before they were sent to the AI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePreprocessing {
+    /// Longest edge the photos were downsampled to (px)
+    pub max_long_edge: u32,
+    /// JPEG quality of the re-encoded photos (1-100)
+    pub jpeg_quality: u8,
+}
+
+/// P10/P90 bounds of the tonnage estimate
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TonnageInterval {
+    /// 10th percentile (t)
+    pub p10: f64,
+    /// 90th percentile (t)
+    pub p90: f64,
+    /// Ground-truth samples the historical error was taken from (0 = default spread)
+    #[serde(default)]
+    pub history_samples: usize,
 }
 
 /// AI estimation result from image analysis
@@ -162,6 +343,14 @@ pub struct EstimationResult {
     #[serde(default)]
     pub license_plate: Option<String>,
 
+    /// How `license_plate` was obtained
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plate_source: Option<PlateSource>,
+
+    /// Confidence of the plate reading (0.0 - 1.0; None for manual entry)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plate_confidence: Option<f32>,
+
     /// Material type: "土砂", "As殻", "Co殻", "開粒度As殻"
     #[serde(default, deserialize_with = "null_to_default")]
     pub material_type: String,
@@ -194,6 +383,10 @@ pub struct EstimationResult {
     #[serde(default, deserialize_with = "null_to_default")]
     pub estimated_tonnage: f64,
 
+    /// AI tonnage before calibration (`estimated_tonnage` holds the calibrated value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_tonnage: Option<f64>,
+
     /// Confidence score (0.0 - 1.0)
     #[serde(default, deserialize_with = "null_to_default")]
     pub confidence_score: f64,
@@ -209,6 +402,30 @@ pub struct EstimationResult {
     /// Number of ensemble samples used
     #[serde(default)]
     pub ensemble_count: Option<u32>,
+
+    /// Per-sample values of an ensemble run (empty for single-sample results)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ensemble_samples: Vec<EnsembleSample>,
+
+    /// Standard deviation of tonnage across the accepted samples
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tonnage_std_dev: Option<f64>,
+
+    /// P10/P90 tonnage bounds (ensemble spread + historical error)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tonnage_interval: Option<TonnageInterval>,
+
+    /// History hashes of the graded reference images attached to the prompt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reference_hashes: Vec<String>,
+
+    /// Physical plausibility findings (out-of-range parameters, clamped values)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<EstimationWarning>,
+
+    /// How the photos were preprocessed before upload (None = sent as is)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preprocessing: Option<ImagePreprocessing>,
 }
 
 impl Default for EstimationResult {
@@ -217,6 +434,8 @@ impl Default for EstimationResult {
             is_target_detected: false,
             truck_type: String::new(),
             license_plate: None,
+            plate_source: None,
+            plate_confidence: None,
             material_type: String::new(),
             height: None,
             packing_density: None,
@@ -225,14 +444,29 @@ impl Default for EstimationResult {
             fill_ratio_z: None,
             estimated_volume_m3: 0.0,
             estimated_tonnage: 0.0,
+            raw_tonnage: None,
             confidence_score: 0.0,
             reasoning: String::new(),
             material_breakdown: Vec::new(),
             ensemble_count: None,
+            ensemble_samples: Vec::new(),
+            tonnage_std_dev: None,
+            tonnage_interval: None,
+            reference_hashes: Vec::new(),
+            warnings: Vec::new(),
+            preprocessing: None,
         }
     }
 }
 
+impl EstimationResult {
+    /// The upper tonnage bound exceeds `max_capacity`
+    pub fn possibly_overloaded(&self, max_capacity: f64) -> bool {
+        self.tonnage_interval
+            .is_some_and(|interval| max_capacity > 0.0 && interval.p90 > max_capacity)
+    }
+}
+
 /// Load grade classification
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LoadGrade {
@@ -293,6 +527,9 @@ pub struct AnalysisEntry {
     pub grade: Option<LoadGrade>,
     /// Actual tonnage (if known)
     pub actual_tonnage: Option<f64>,
+    /// Max capacity of the matched vehicle (if any)
+    #[serde(default)]
+    pub max_capacity: Option<f64>,
 }
 
 /// Batch analysis results
@@ -312,6 +549,51 @@ pub struct BatchResults {
     pub completed_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Camera position of one photo of a load
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageView {
+    /// Behind the truck, looking at the tailgate
+    Rear,
+    /// Beside the truck; shows the load height above the side gate
+    Side,
+    /// From above; shows how far the load fills the bed
+    Top,
+    #[default]
+    Unspecified,
+}
+
+impl ImageView {
+    /// Parse a view name ("rear", "side", "top"; also 後方/側面/上方)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "rear" | "back" | "後方" | "後ろ" => Some(ImageView::Rear),
+            "side" | "側面" | "横" => Some(ImageView::Side),
+            "top" | "above" | "上方" | "上" => Some(ImageView::Top),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImageView::Rear => "rear",
+            ImageView::Side => "side",
+            ImageView::Top => "top",
+            ImageView::Unspecified => "unspecified",
+        }
+    }
+}
+
+/// One photo of a multi-image analysis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceImage {
+    pub path: String,
+    /// SHA256 hash of the image file
+    pub hash: String,
+    #[serde(default)]
+    pub view: ImageView,
+}
+
 /// Stored analysis history entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
@@ -338,6 +620,10 @@ pub struct HistoryEntry {
     /// Base64 encoded thumbnail for reference (optional)
     #[serde(default)]
     pub thumbnail_base64: Option<String>,
+    /// Every photo of a multi-image analysis (empty for single-image entries,
+    /// whose `image_hash` is the hash of `image_path`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub source_images: Vec<SourceImage>,
 }
 
 #[cfg(test)]
@@ -352,4 +638,32 @@ mod tests {
         assert_eq!(result.fill_ratio_w, Some(0.75));
         assert_eq!(result.fill_ratio_z, Some(0.9));
     }
+
+    #[test]
+    fn test_days_until_inspection_expiry() {
+        let mut vehicle = RegisteredVehicle::new("日野 プロフィア".to_string(), 10.0);
+        let today = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        assert_eq!(vehicle.days_until_inspection_expiry(today), None);
+
+        vehicle.inspection_expiry = NaiveDate::from_ymd_opt(2025, 3, 31);
+        assert_eq!(vehicle.days_until_inspection_expiry(today), Some(30));
+        let later = NaiveDate::from_ymd_opt(2025, 4, 2).unwrap();
+        assert_eq!(vehicle.days_until_inspection_expiry(later), Some(-2));
+
+        // Old records without the field still load
+        let json = serde_json::to_string(&RegisteredVehicle::new("いすゞ ギガ".to_string(), 10.0)).unwrap();
+        assert!(!json.contains("inspection_expiry"));
+        let back: RegisteredVehicle = serde_json::from_str(&json).unwrap();
+        assert!(back.inspection_expiry.is_none());
+    }
+
+    #[test]
+    fn test_company_matches_substring() {
+        let mut vehicle = RegisteredVehicle::new("日野 プロフィア".to_string(), 10.0);
+        assert!(!vehicle.company_matches("松尾"));
+        vehicle.company = Some("松尾運搬".to_string());
+        assert!(vehicle.company_matches("松尾"));
+        assert!(vehicle.company_matches("松尾運搬"));
+        assert!(!vehicle.company_matches("田中"));
+    }
 }
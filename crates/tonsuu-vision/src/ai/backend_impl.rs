@@ -2,16 +2,31 @@
 //!
 //! Uses file paths directly to avoid redundant read→write round-trips.
 //! The `images` parameter from AiBackend::send_prompt is ignored;
-//! instead, the original file paths are passed directly to cli-ai-analyzer.
+//! instead, the file paths are passed directly to cli-ai-analyzer.
+//! With preprocessing enabled these are the preprocessed copies, while
+//! fixture keys are still computed from the original files.
+//!
+//! Also provides record/replay backends so the pipeline can be exercised
+//! offline: `RecordingAiBackend` writes every prompt+image-hash → raw response
+//! pair to a fixture directory, and `ReplayAiBackend` serves them back.
+//! Fixtures are keyed by backend, model and ensemble sample as well, so a
+//! recording made with one model never replays for another and parallel
+//! samples of one analysis are stored side by side.
 
 use cli_ai_analyzer::{analyze, AnalyzeOptions};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tonsuu_core::pipeline::{AiBackend, PipelineError};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// AiBackend implementation that uses cli-ai-analyzer CLI tools.
 ///
-/// Holds the original image file paths so they can be passed directly
-/// to the AI backend without copying data through temp files.
+/// Holds the image file paths (originals or preprocessed copies) so they can
+/// be passed directly to the AI backend without copying data through temp
+/// files.
 pub struct CliAiBackend {
     pub options: AnalyzeOptions,
     pub image_paths: Vec<PathBuf>,
@@ -23,3 +38,306 @@ impl AiBackend for CliAiBackend {
             .map_err(|e| PipelineError::AiError(e.to_string()))
     }
 }
+
+/// Fixture mode selected via `--backend record:<dir>` / `--backend replay:<dir>`
+/// (optionally `record:<backend>:<dir>` / `replay:<backend>:<dir>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Call the live backend and save every response to the directory
+    Record(PathBuf),
+    /// Serve responses from the directory without any network access
+    Replay(PathBuf),
+}
+
+/// One recorded prompt → response pair (`<key>.json` in the fixture directory)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureRecord {
+    pub key: String,
+    #[serde(default)]
+    pub backend: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub sample: usize,
+    pub prompt: String,
+    pub image_hashes: Vec<String>,
+    pub response: String,
+}
+
+/// Which backend, model and ensemble sample a fixture belongs to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FixtureScope {
+    /// "gemini" / "claude" / "codex"
+    pub backend: String,
+    pub model: Option<String>,
+    /// Ensemble sample index (0 for single requests)
+    pub sample: usize,
+}
+
+/// Compute the fixture key for a prompt and the SHA256 hashes of its images.
+///
+/// Images are identified by content, so fixtures stay valid when the
+/// dataset is moved to another machine or directory.
+pub fn fixture_key(scope: &FixtureScope, prompt: &str, image_hashes: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(scope.backend.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(scope.model.as_deref().unwrap_or("default").as_bytes());
+    hasher.update(format!("\0{}\0", scope.sample).as_bytes());
+    hasher.update(prompt.as_bytes());
+    for hash in image_hashes {
+        hasher.update(b"\0");
+        hasher.update(hash.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn hash_image_files(image_paths: &[PathBuf]) -> Result<Vec<String>, PipelineError> {
+    image_paths
+        .iter()
+        .map(|path| {
+            let file = File::open(path).map_err(|e| {
+                PipelineError::AiError(format!("Failed to open {}: {}", path.display(), e))
+            })?;
+            let mut reader = BufReader::new(file);
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut reader, &mut hasher).map_err(|e| {
+                PipelineError::AiError(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            Ok(format!("{:x}", hasher.finalize()))
+        })
+        .collect()
+}
+
+fn fixture_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.json", key))
+}
+
+/// Write a fixture via a uniquely named temp file + rename, so a concurrent
+/// replay never reads a partial record and two recordings of the same key
+/// never share a temp file
+fn write_fixture(dir: &Path, record: &FixtureRecord) -> Result<(), PipelineError> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    fs::create_dir_all(dir)
+        .map_err(|e| PipelineError::AiError(format!("Failed to create fixture dir: {}", e)))?;
+    let json = serde_json::to_string_pretty(record)
+        .map_err(|e| PipelineError::AiError(format!("Failed to serialize fixture: {}", e)))?;
+    let path = fixture_path(dir, &record.key);
+    let tmp_path = path.with_extension(format!(
+        "json.tmp.{}.{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp_path, json)
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            PipelineError::AiError(format!("Failed to write fixture: {}", e))
+        })
+}
+
+/// Wraps another backend and records every response to a fixture directory.
+pub struct RecordingAiBackend<B: AiBackend> {
+    pub inner: B,
+    pub fixture_dir: PathBuf,
+    pub image_paths: Vec<PathBuf>,
+    pub scope: FixtureScope,
+}
+
+impl<B: AiBackend> AiBackend for RecordingAiBackend<B> {
+    fn send_prompt(&self, prompt: &str, images: &[Vec<u8>]) -> Result<String, PipelineError> {
+        let response = self.inner.send_prompt(prompt, images)?;
+
+        let image_hashes = hash_image_files(&self.image_paths)?;
+        let record = FixtureRecord {
+            key: fixture_key(&self.scope, prompt, &image_hashes),
+            backend: self.scope.backend.clone(),
+            model: self.scope.model.clone(),
+            sample: self.scope.sample,
+            prompt: prompt.to_string(),
+            image_hashes,
+            response: response.clone(),
+        };
+        write_fixture(&self.fixture_dir, &record)?;
+
+        Ok(response)
+    }
+}
+
+/// Serves previously recorded responses; never touches the network.
+pub struct ReplayAiBackend {
+    pub fixture_dir: PathBuf,
+    pub image_paths: Vec<PathBuf>,
+    pub scope: FixtureScope,
+}
+
+impl AiBackend for ReplayAiBackend {
+    fn send_prompt(&self, prompt: &str, _images: &[Vec<u8>]) -> Result<String, PipelineError> {
+        let image_hashes = hash_image_files(&self.image_paths)?;
+        let key = fixture_key(&self.scope, prompt, &image_hashes);
+        let path = fixture_path(&self.fixture_dir, &key);
+
+        let content = fs::read_to_string(&path).map_err(|_| {
+            PipelineError::AiError(format!(
+                "No recorded response for key {} in {} (record it first with --backend record:<dir>)",
+                key,
+                self.fixture_dir.display()
+            ))
+        })?;
+        let record: FixtureRecord = serde_json::from_str(&content).map_err(|e| {
+            PipelineError::AiError(format!("Corrupted fixture {}: {}", path.display(), e))
+        })?;
+
+        Ok(record.response)
+    }
+}
+
+/// Backend selected from `AnalyzerConfig` (live, recording or replaying)
+pub enum AnalyzerBackend {
+    Cli(CliAiBackend),
+    Record(RecordingAiBackend<CliAiBackend>),
+    Replay(ReplayAiBackend),
+}
+
+impl AnalyzerBackend {
+    /// `image_paths` are the original photos (fixture keys hash these);
+    /// `upload_paths` are the files actually sent to the live backend.
+    pub fn new(
+        options: AnalyzeOptions,
+        image_paths: Vec<PathBuf>,
+        upload_paths: Vec<PathBuf>,
+        fixture: Option<&FixtureMode>,
+        scope: FixtureScope,
+    ) -> Self {
+        match fixture {
+            None => AnalyzerBackend::Cli(CliAiBackend {
+                options,
+                image_paths: upload_paths,
+            }),
+            Some(FixtureMode::Record(dir)) => AnalyzerBackend::Record(RecordingAiBackend {
+                inner: CliAiBackend {
+                    options,
+                    image_paths: upload_paths,
+                },
+                fixture_dir: dir.clone(),
+                image_paths,
+                scope,
+            }),
+            Some(FixtureMode::Replay(dir)) => AnalyzerBackend::Replay(ReplayAiBackend {
+                fixture_dir: dir.clone(),
+                image_paths,
+                scope,
+            }),
+        }
+    }
+}
+
+impl AiBackend for AnalyzerBackend {
+    fn send_prompt(&self, prompt: &str, images: &[Vec<u8>]) -> Result<String, PipelineError> {
+        match self {
+            AnalyzerBackend::Cli(b) => b.send_prompt(prompt, images),
+            AnalyzerBackend::Record(b) => b.send_prompt(prompt, images),
+            AnalyzerBackend::Replay(b) => b.send_prompt(prompt, images),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedBackend(&'static str);
+
+    impl AiBackend for FixedBackend {
+        fn send_prompt(&self, _prompt: &str, _images: &[Vec<u8>]) -> Result<String, PipelineError> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn scope(model: Option<&str>, sample: usize) -> FixtureScope {
+        FixtureScope {
+            backend: "gemini".to_string(),
+            model: model.map(str::to_string),
+            sample,
+        }
+    }
+
+    #[test]
+    fn test_fixture_key_depends_on_prompt_and_images() {
+        let base = scope(None, 0);
+        let a = fixture_key(&base, "prompt", &["abc".to_string()]);
+        assert_eq!(a, fixture_key(&base, "prompt", &["abc".to_string()]));
+        assert_ne!(a, fixture_key(&base, "prompt2", &["abc".to_string()]));
+        assert_ne!(a, fixture_key(&base, "prompt", &["abd".to_string()]));
+        assert_ne!(a, fixture_key(&scope(Some("gemini-2.5-pro"), 0), "prompt", &["abc".to_string()]));
+        assert_ne!(a, fixture_key(&scope(None, 1), "prompt", &["abc".to_string()]));
+        let claude = FixtureScope {
+            backend: "claude".to_string(),
+            ..base
+        };
+        assert_ne!(a, fixture_key(&claude, "prompt", &["abc".to_string()]));
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let image = dir.join("truck.jpg");
+        fs::write(&image, b"fake image bytes").unwrap();
+        let fixtures = dir.join("fixtures");
+
+        // Two ensemble samples of the same request are recorded separately
+        for (sample, response) in [(0, "{\"height\": 0.4}"), (1, "{\"height\": 0.5}")] {
+            let recorder = RecordingAiBackend {
+                inner: FixedBackend(response),
+                fixture_dir: fixtures.clone(),
+                image_paths: vec![image.clone()],
+                scope: scope(None, sample),
+            };
+            assert_eq!(recorder.send_prompt("p", &[]).unwrap(), response);
+        }
+
+        let replay = |sample| ReplayAiBackend {
+            fixture_dir: fixtures.clone(),
+            image_paths: vec![image.clone()],
+            scope: scope(None, sample),
+        };
+        assert_eq!(replay(0).send_prompt("p", &[]).unwrap(), "{\"height\": 0.4}");
+        assert_eq!(replay(1).send_prompt("p", &[]).unwrap(), "{\"height\": 0.5}");
+        assert!(replay(0).send_prompt("other prompt", &[]).is_err());
+        assert!(replay(2).send_prompt("p", &[]).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_recordings_of_one_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let image = dir.join("truck.jpg");
+        fs::write(&image, b"fake image bytes").unwrap();
+        let fixtures = dir.join("fixtures");
+
+        // The same photo recorded twice at once (e.g. listed twice in a batch)
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let recorder = RecordingAiBackend {
+                        inner: FixedBackend("{\"height\": 0.4}"),
+                        fixture_dir: fixtures.clone(),
+                        image_paths: vec![image.clone()],
+                        scope: scope(None, 0),
+                    };
+                    recorder.send_prompt("p", &[]).unwrap();
+                });
+            }
+        });
+
+        let files: Vec<String> = fs::read_dir(&fixtures)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(files.len(), 1, "{:?}", files);
+        assert!(files[0].ends_with(".json"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Per-backend concurrency limit for AI calls
+//!
+//! Ensemble samples are dispatched on their own threads, and batch analysis
+//! runs several images at once. Every backend built by `AnalyzerConfig` takes
+//! a permit from a process-wide limiter for its backend name before calling
+//! out, so the number of in-flight requests per backend stays bounded no
+//! matter how many workers and samples are running.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use tonsuu_core::pipeline::{AiBackend, PipelineError};
+
+/// Default number of concurrent requests per backend
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: usize,
+    in_flight: usize,
+}
+
+/// Counting semaphore whose limit can be changed while in use
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+/// Held while a request is in flight; released on drop
+pub struct Permit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                limit: limit.max(1),
+                in_flight: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Change the limit; requests already in flight are not interrupted
+    pub fn set_limit(&self, limit: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.limit = limit.max(1);
+        self.released.notify_all();
+    }
+
+    /// Block until fewer than `limit` requests are in flight
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.in_flight >= state.limit {
+            state = self.released.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.in_flight += 1;
+        Permit { limiter: self }
+    }
+
+    /// Requests currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).in_flight
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.in_flight = state.in_flight.saturating_sub(1);
+        self.limiter.released.notify_one();
+    }
+}
+
+/// Shared limiter for a backend name, created on first use.
+///
+/// Only an explicit `limit` (from the app config) changes it; callers that
+/// did not configure one (None) get the current limit, so building a default
+/// `AnalyzerConfig` somewhere never resets the configured limit.
+pub fn backend_limiter(backend_name: &str, limit: Option<usize>) -> Arc<ConcurrencyLimiter> {
+    static LIMITERS: OnceLock<Mutex<HashMap<String, Arc<ConcurrencyLimiter>>>> = OnceLock::new();
+
+    let mut limiters = LIMITERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let limiter = limiters.entry(backend_name.to_string()).or_insert_with(|| {
+        Arc::new(ConcurrencyLimiter::new(limit.unwrap_or(DEFAULT_MAX_CONCURRENCY)))
+    });
+    if let Some(limit) = limit {
+        limiter.set_limit(limit);
+    }
+    Arc::clone(limiter)
+}
+
+/// Backend wrapper that holds a limiter permit for the duration of each call
+pub struct LimitedBackend<B: AiBackend> {
+    pub inner: B,
+    pub limiter: Arc<ConcurrencyLimiter>,
+}
+
+impl<B: AiBackend> AiBackend for LimitedBackend<B> {
+    fn send_prompt(&self, prompt: &str, images: &[Vec<u8>]) -> Result<String, PipelineError> {
+        let _permit = self.limiter.acquire();
+        self.inner.send_prompt(prompt, images)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_limit_caps_in_flight_requests() {
+        let limiter = ConcurrencyLimiter::new(2);
+        let peak = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    let _permit = limiter.acquire();
+                    peak.fetch_max(limiter.in_flight(), Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                });
+            }
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_backend_limiter_is_shared_per_name() {
+        let a = backend_limiter("test-shared", Some(1));
+        let b = backend_limiter("test-shared", None);
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &backend_limiter("test-other", Some(1))));
+
+        // An explicit limit changes the shared limiter
+        let _first = a.acquire();
+        assert_eq!(a.in_flight(), 1);
+        backend_limiter("test-shared", Some(2));
+        let _second = b.acquire();
+        assert_eq!(a.in_flight(), 2);
+    }
+
+    #[test]
+    fn test_unconfigured_limiter_keeps_limit() {
+        let configured = backend_limiter("test-keep", Some(1));
+        // A default AnalyzerConfig elsewhere must not reset the limit
+        backend_limiter("test-keep", None);
+        assert_eq!(configured.state.lock().unwrap().limit, 1);
+
+        assert_eq!(
+            backend_limiter("test-fresh", None).state.lock().unwrap().limit,
+            DEFAULT_MAX_CONCURRENCY
+        );
+    }
+}
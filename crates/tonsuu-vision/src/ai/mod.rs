@@ -1,4 +1,5 @@
 //! AI-related modules for vision analysis
 
 pub mod backend_impl;
+pub mod limiter;
 pub mod prompts;
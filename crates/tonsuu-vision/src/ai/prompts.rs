@@ -11,8 +11,26 @@
 //! - Japanese for domain-specific terms (アスファルト殻, コンクリート殻, 土砂,
 //!   後板, ヒンジ, ダンプ)
 
+use sha2::{Digest, Sha256};
 use std::sync::LazyLock;
 use tonsuu_core::spec::SPEC;
+use tonsuu_types::{ImageView, TruckClassRegistry};
+
+/// Raw prompt-spec.json embedded at build time (SSOT shared with tonsuu-core)
+const PROMPT_SPEC_JSON: &str = include_str!("../../../../../tonsuu-core/prompt-spec.json");
+
+/// Version tag of the embedded prompt-spec.json: `<version>+<content hash>`.
+///
+/// The content hash makes edits without a version bump still change the tag,
+/// so cached results built from an older prompt are never reused.
+pub static PROMPT_SPEC_VERSION: LazyLock<String> = LazyLock::new(|| {
+    let version = serde_json::from_str::<serde_json::Value>(PROMPT_SPEC_JSON)
+        .ok()
+        .and_then(|v| v["version"].as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| "unknown".to_string());
+    let hash = format!("{:x}", Sha256::digest(PROMPT_SPEC_JSON.as_bytes()));
+    format!("{}+{}", version, &hash[..8])
+});
 
 // ============================================================================
 // Multi-param prompt section from prompt-spec.json
@@ -28,9 +46,8 @@ struct MultiParamPrompt {
 /// Parse the multiParamPrompt section from the raw embedded JSON.
 /// This is needed because v2.1.0 moved prompt strings out of the top-level spec.
 static MULTI_PARAM: LazyLock<MultiParamPrompt> = LazyLock::new(|| {
-    let raw: serde_json::Value = serde_json::from_str(
-        include_str!("../../../../../tonsuu-core/prompt-spec.json")
-    ).expect("Failed to parse prompt-spec.json");
+    let raw: serde_json::Value = serde_json::from_str(PROMPT_SPEC_JSON)
+        .expect("Failed to parse prompt-spec.json");
 
     let mp = &raw["multiParamPrompt"];
     MultiParamPrompt {
@@ -130,6 +147,7 @@ pub static VOLUME_ESTIMATION_PROMPT: LazyLock<String> =
     LazyLock::new(build_volume_estimation_prompt);
 
 /// Graded reference item for prompt building (used by staged analysis)
+#[derive(Debug, Clone)]
 pub struct GradedReferenceItem {
     pub grade_name: String,
     pub actual_tonnage: f64,
@@ -304,6 +322,113 @@ pub fn build_staged_analysis_prompt(
     prompt
 }
 
+/// Build the follow-up prompt for a response that failed JSON validation.
+///
+/// Lists the problems and echoes the previous response (truncated) so the
+/// AI can correct its own output instead of re-estimating from scratch.
+pub fn build_json_repair_prompt(previous_response: &str, problems: &[String]) -> String {
+    let previous: String = previous_response.chars().take(2000).collect();
+    let problem_list: String = problems.iter().map(|p| format!("- {}\n", p)).collect();
+    format!(
+        "Your previous response could not be used:\n{}\n\
+         Previous response:\n{}\n\n\
+         Output ONLY one corrected JSON object with the same schema \
+         (isTargetDetected, truckType, materialType, height, fillRatioL, fillRatioW, \
+         fillRatioZ, packingDensity, confidenceScore, reasoning, licensePlate). \
+         Numbers must be JSON numbers, not strings. No text outside the JSON.",
+        problem_list, previous
+    )
+}
+
+/// Append the material names the AI may report as `materialType`.
+///
+/// The list comes from the material catalog, so custom materials are offered
+/// without a prompt change.
+pub fn with_material_choices_note(prompt: &str, materials: &[String]) -> String {
+    if materials.is_empty() {
+        return prompt.to_string();
+    }
+    format!(
+        "{}\n\nmaterialType must be one of: {} (use the main material for a mixed load).",
+        prompt,
+        materials.join(", ")
+    )
+}
+
+/// Append the truck classes the AI may report as `truckType`, with the max
+/// load range of each (from trucks.toml).
+pub fn with_truck_classes_note(prompt: &str, classes: &TruckClassRegistry) -> String {
+    let list: Vec<String> = classes
+        .classes()
+        .iter()
+        .map(|c| match c.capacity_range {
+            Some((min, max)) => format!("{} ({:.1}-{:.1}t max load)", c.id, min, max),
+            None => c.id.clone(),
+        })
+        .collect();
+    if list.is_empty() {
+        return prompt.to_string();
+    }
+    format!("{}\ntruckType must be one of: {}.", prompt, list.join(", "))
+}
+
+/// Append labels for graded reference images attached after the photos being analyzed.
+///
+/// `target_count` is the number of photos of the load to estimate; reference
+/// `i` is attached as image `target_count + i + 1`.
+pub fn with_reference_images_note(
+    prompt: &str,
+    target_count: usize,
+    references: &[GradedReferenceItem],
+) -> String {
+    if references.is_empty() {
+        return prompt.to_string();
+    }
+    let mut note = format!(
+        "\n\nThe last {} attached images are REFERENCE photos of other, already weighed loads \
+         (calibration only - do NOT estimate them and do NOT copy their values):\n",
+        references.len()
+    );
+    for (i, item) in references.iter().enumerate() {
+        note.push_str(&format!(
+            "- Image {}: grade {}, actual {:.2}t / max {:.1}t\n",
+            target_count + i + 1,
+            item.grade_name,
+            item.actual_tonnage,
+            item.max_capacity,
+        ));
+    }
+    let targets = if target_count > 1 {
+        format!("images 1-{}", target_count)
+    } else {
+        "image 1".to_string()
+    };
+    note.push_str(&format!("Your estimate is for {} only.", targets));
+    format!("{}{}", prompt, note)
+}
+
+/// Prefix a prompt with the camera position of each attached photo.
+///
+/// Used when several photos of the same load are sent in one request.
+pub fn with_multi_view_note(prompt: &str, views: &[ImageView]) -> String {
+    let list: String = views
+        .iter()
+        .enumerate()
+        .map(|(i, view)| match view {
+            ImageView::Unspecified => format!("- Image {}: unknown angle\n", i + 1),
+            _ => format!("- Image {}: {} view\n", i + 1, view.label()),
+        })
+        .collect();
+    format!(
+        "Images 1-{} show the SAME truck and load from different angles:\n{}\
+         Estimate ONE result for this load. Prefer the side view for the load height \
+         and the top view for fill ratios; use the other images to cross-check.\n\n{}",
+        views.len(),
+        list,
+        prompt
+    )
+}
+
 
 // ============================================================================
 // Tests
@@ -466,6 +591,33 @@ mod tests {
         assert!(prompt.contains("(full load)"));
         assert!(prompt.contains("do NOT copy these values"));
         assert!(prompt.contains("4.0t"));
+
+        let with_images = with_reference_images_note("base", 2, &refs);
+        assert!(with_images.starts_with("base"));
+        assert!(with_images.contains("- Image 3: grade A, actual 3.50t"));
+        assert!(with_images.contains("- Image 4: grade C, actual 1.50t"));
+        assert!(with_images.contains("images 1-2 only"));
+        assert_eq!(with_reference_images_note("base", 1, &[]), "base");
+    }
+
+    #[test]
+    fn test_material_choices_note() {
+        let materials = vec!["As殻".to_string(), "砕石".to_string()];
+        let prompt = with_material_choices_note("base", &materials);
+        assert!(prompt.starts_with("base"));
+        assert!(prompt.contains("materialType must be one of: As殻, 砕石"));
+        assert_eq!(with_material_choices_note("base", &[]), "base");
+    }
+
+    #[test]
+    fn test_truck_classes_note() {
+        let registry = TruckClassRegistry::new(vec![
+            tonsuu_types::TruckClassDef::new("3t", 2.5, 3.5),
+            tonsuu_types::TruckClassDef::new("11t+", 11.0, 40.0),
+        ]);
+        let prompt = with_truck_classes_note("base", &registry);
+        assert!(prompt.contains("truckType must be one of: 3t (2.5-3.5t max load), 11t+ (11.0-40.0t max load)."));
+        assert_eq!(with_truck_classes_note("base", &TruckClassRegistry::default()), "base");
     }
 
 
@@ -1,26 +1,364 @@
 //! Cache for analysis results
+//!
+//! Entries are keyed by the image hash *and* every input that influences the
+//! result (backend, model, pipeline, hints, prompt-spec version, ensemble
+//! count), so switching any of them never returns a stale estimate.
+//!
+//! File layout: `<image_hash>-<inputs_hash>.json` holding a [`CacheEntry`].
+//! Legacy flat `<image_hash>.json` entries (raw `EstimationResult`, no inputs)
+//! are never read; they are reported by `stats` and removed by `clear`/`prune`.
+//!
+//! Eviction: a [`CachePolicy`] bounds entry age (TTL from creation) and total
+//! size; when over the size cap, least-recently-hit entries are removed first.
+//! A hit is recorded at most once per [`HIT_RECORD_INTERVAL_MINUTES`], so
+//! repeated reads of a hot entry do not rewrite it.
+//!
+//! Entries are written via temp file + rename, so concurrent readers (batch
+//! workers) never see a partial entry. `prune` still leaves unreadable files
+//! and temp files alone for [`UNREADABLE_GRACE_MINUTES`] in case a writer is
+//! mid-rename; older leftover temp files are removed.
+//!
+//! Plate readings are cached separately under `plates/`, keyed by the plate
+//! image hash and the reader settings, so a repeated analysis does not run
+//! YOLO / API OCR again. `clear` and `prune` cover them too.
 
 use tonsuu_types::Result;
 use tonsuu_types::EstimationResult;
+use tonsuu_store::Store;
+use crate::PlateReading;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::{self, BufReader};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Unreadable entries and temp files younger than this are not pruned
+pub const UNREADABLE_GRACE_MINUTES: i64 = 10;
+
+/// A cache hit is written back only when the entry's last use is older than this
+pub const HIT_RECORD_INTERVAL_MINUTES: i64 = 60;
+
+/// Which analysis pipeline produced a cached result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineKind {
+    /// Box-overlay geometry + fill pipeline (tonsuu-core)
+    BoxOverlay,
+    /// Legacy staged / karte prompt
+    Staged,
+}
+
+impl PipelineKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PipelineKind::BoxOverlay => "box_overlay",
+            PipelineKind::Staged => "staged",
+        }
+    }
+}
+
+/// Analysis inputs that are part of the cache key (besides the image hash)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheKeyInputs {
+    pub backend: String,
+    pub model: Option<String>,
+    pub pipeline: PipelineKind,
+    pub truck_class: Option<String>,
+    pub material_type: Option<String>,
+    /// Short hash of the karte JSON (karte content itself is not stored)
+    pub karte_hash: Option<String>,
+    /// Short hash of the material catalog and truck specs the result was
+    /// computed with (from materials.toml / trucks.toml)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<String>,
+    pub prompt_version: String,
+    pub ensemble_count: u32,
+    /// Ensemble aggregator (None for single-sample runs, where it has no effect)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregator: Option<String>,
+    /// Max reference images attached to a staged prompt (None when off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub references: Option<usize>,
+    /// Fusion mode and views of a multi-image analysis (e.g. "per_view:rear,side")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fusion: Option<String>,
+    /// Bed dimensions of the matched vehicle the result was rescaled to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bed: Option<String>,
+    /// Upload preprocessing (e.g. "1600/q85"; None when originals are sent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preprocess: Option<String>,
+}
+
+impl CacheKeyInputs {
+    /// Stable short hash of the inputs (used in the file name)
+    fn hash(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        short_hash(&json)
+    }
+
+    /// Human-readable configuration label for statistics
+    pub fn label(&self) -> String {
+        let mut parts = vec![
+            format!("{}/{}", self.backend, self.model.as_deref().unwrap_or("default")),
+            self.pipeline.label().to_string(),
+        ];
+        if let Some(ref tc) = self.truck_class {
+            parts.push(tc.clone());
+        }
+        if let Some(ref m) = self.material_type {
+            parts.push(m.clone());
+        }
+        if self.karte_hash.is_some() {
+            parts.push("karte".to_string());
+        }
+        parts.push(format!("x{}", self.ensemble_count));
+        if let Some(ref aggregator) = self.aggregator {
+            parts.push(aggregator.clone());
+        }
+        if let Some(references) = self.references {
+            parts.push(format!("{} refs", references));
+        }
+        if let Some(ref fusion) = self.fusion {
+            parts.push(fusion.clone());
+        }
+        if let Some(ref bed) = self.bed {
+            parts.push(format!("bed {}", bed));
+        }
+        if let Some(ref preprocess) = self.preprocess {
+            parts.push(format!("pre {}", preprocess));
+        }
+        parts.push(format!("spec {}", self.prompt_version));
+        parts.join(" ")
+    }
+}
+
+/// Cached result with the inputs that produced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub image_hash: String,
+    pub inputs: CacheKeyInputs,
+    pub created_at: DateTime<Utc>,
+    /// Last time this entry was returned by `get` (None = never hit)
+    #[serde(default)]
+    pub last_hit_at: Option<DateTime<Utc>>,
+    pub result: EstimationResult,
+}
+
+impl CacheEntry {
+    /// Time used for LRU ordering
+    pub fn last_used(&self) -> DateTime<Utc> {
+        self.last_hit_at.unwrap_or(self.created_at)
+    }
+}
+
+/// Eviction policy (None = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Entries older than this (since creation) are expired
+    pub max_age: Option<Duration>,
+    /// Total size cap; least-recently-hit entries are evicted first
+    pub max_bytes: Option<u64>,
+}
+
+impl CachePolicy {
+    fn is_expired(&self, created_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.max_age.is_some_and(|age| now - created_at > age)
+    }
+}
+
+/// Why an entry was (or would be) removed by `prune`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneReason {
+    /// Older than `max_age`
+    Expired,
+    /// Evicted (LRU) to get under `max_bytes`
+    OverSize,
+    /// Legacy flat entry without inputs
+    Legacy,
+    /// Not a readable cache entry
+    Unreadable,
+    /// Temp file left behind by an interrupted write
+    StaleTemp,
+}
+
+impl PruneReason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PruneReason::Expired => "expired",
+            PruneReason::OverSize => "lru",
+            PruneReason::Legacy => "legacy",
+            PruneReason::Unreadable => "unreadable",
+            PruneReason::StaleTemp => "temp",
+        }
+    }
+}
+
+/// One entry selected by `prune`
+#[derive(Debug, Clone)]
+pub struct PrunedEntry {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub last_used: Option<DateTime<Utc>>,
+    pub reason: PruneReason,
+}
+
+/// Result of `prune`
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    pub removed: Vec<PrunedEntry>,
+    pub kept_count: usize,
+    pub kept_bytes: u64,
+    pub dry_run: bool,
+}
+
+impl PruneReport {
+    pub fn removed_bytes(&self) -> u64 {
+        self.removed.iter().map(|e| e.size_bytes).sum()
+    }
+
+    pub fn display(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.removed {
+            let name = entry
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let last_used = entry
+                .last_used
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string());
+            out.push_str(&format!(
+                "  {:<10} {:>9.2} KB  {}  {}\n",
+                entry.reason.label(),
+                entry.size_bytes as f64 / 1024.0,
+                last_used,
+                name
+            ));
+        }
+        out.push_str(&format!(
+            "{} {} entries ({:.2} KB); kept {} entries ({:.2} KB)",
+            if self.dry_run { "Would remove" } else { "Removed" },
+            self.removed.len(),
+            self.removed_bytes() as f64 / 1024.0,
+            self.kept_count,
+            self.kept_bytes as f64 / 1024.0
+        ));
+        out
+    }
+}
+
+/// Parse a duration such as `30d`, `12h`, `2w` or `90m` (bare number = days)
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: i64 = num.parse().ok()?;
+    match unit.trim().to_lowercase().as_str() {
+        "" | "d" | "day" | "days" => Some(Duration::days(n)),
+        "h" | "hour" | "hours" => Some(Duration::hours(n)),
+        "m" | "min" | "mins" => Some(Duration::minutes(n)),
+        "w" | "week" | "weeks" => Some(Duration::weeks(n)),
+        _ => None,
+    }
+}
+
+/// Parse a size such as `500MB`, `2GB`, `100k` (bare number = bytes)
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: f64 = num.parse().ok()?;
+    let mult: f64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((n * mult) as u64)
+}
+
+/// Cached plate reading (`reading: None` = no readable plate)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlateCacheEntry {
+    image_hash: String,
+    settings: String,
+    created_at: DateTime<Utc>,
+    reading: Option<PlateReading>,
+}
+
+/// First 16 hex chars of the SHA256 of a string
+pub fn short_hash(s: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(s.as_bytes()));
+    hash[..16].to_string()
+}
+
+/// Write a cache entry via a uniquely named temp file + rename
+fn write_entry<T: Serialize>(path: &Path, entry: &T) -> Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let content = serde_json::to_string_pretty(entry)?;
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(
+        ".tmp.{}.{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, content)?;
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Whether a file was modified within the last `minutes`
+fn modified_within(path: &Path, minutes: i64) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_none_or(|age| (age.as_secs() as i64) < minutes * 60)
+}
 
 /// Cache manager for analysis results
 pub struct Cache {
     cache_dir: PathBuf,
+    policy: CachePolicy,
 }
 
 impl Cache {
     /// Create a new cache manager
     pub fn new(cache_dir: PathBuf) -> Result<Self> {
         fs::create_dir_all(&cache_dir)?;
-        Ok(Self { cache_dir })
+        Ok(Self {
+            cache_dir,
+            policy: CachePolicy::default(),
+        })
+    }
+
+    /// Apply an eviction policy (TTL is enforced on `get`, size on `prune`)
+    pub fn with_policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The configured eviction policy
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
     }
 
-    /// Get cache key for an image file (streaming hash for memory efficiency)
-    fn cache_key(image_path: &Path) -> Result<String> {
+    /// Hash an image file (streaming hash for memory efficiency)
+    fn image_hash(image_path: &Path) -> Result<String> {
         let file = File::open(image_path)?;
         let mut reader = BufReader::new(file);
         let mut hasher = Sha256::new();
@@ -29,59 +367,294 @@ impl Cache {
         Ok(format!("{:x}", hash))
     }
 
-    /// Get cached result for an image
-    pub fn get(&self, image_path: &Path) -> Result<Option<EstimationResult>> {
-        let key = Self::cache_key(image_path)?;
-        let cache_path = self.cache_dir.join(format!("{}.json", key));
+    /// Hash of one or more images; several photos share one combined key
+    fn images_hash(image_paths: &[PathBuf]) -> Result<String> {
+        let hashes = image_paths
+            .iter()
+            .map(|path| Self::image_hash(path))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Store::combined_hash(&hashes))
+    }
+
+    fn entry_path(&self, image_hash: &str, inputs: &CacheKeyInputs) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}-{}.json", image_hash, inputs.hash()))
+    }
+
+    /// Get cached result for an image analyzed with the given inputs
+    pub fn get(&self, image_path: &Path, inputs: &CacheKeyInputs) -> Result<Option<EstimationResult>> {
+        self.get_images(&[image_path.to_path_buf()], inputs)
+    }
+
+    /// Get cached result for a set of images analyzed together
+    pub fn get_images(
+        &self,
+        image_paths: &[PathBuf],
+        inputs: &CacheKeyInputs,
+    ) -> Result<Option<EstimationResult>> {
+        let image_hash = Self::images_hash(image_paths)?;
+        let cache_path = self.entry_path(&image_hash, inputs);
 
         if !cache_path.exists() {
             return Ok(None);
         }
 
         let content = fs::read_to_string(&cache_path)?;
-        let result: EstimationResult = serde_json::from_str(&content)?;
-        Ok(Some(result))
+        let mut entry: CacheEntry = match serde_json::from_str(&content) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(None),
+        };
+
+        // Guard against hash collisions of the short inputs hash
+        if entry.image_hash != image_hash || &entry.inputs != inputs {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        if self.policy.is_expired(entry.created_at, now) {
+            let _ = fs::remove_file(&cache_path);
+            return Ok(None);
+        }
+
+        // Record the hit for LRU eviction (best effort); a recent last use is
+        // precise enough, so most hits stay read-only
+        if now - entry.last_used() >= Duration::minutes(HIT_RECORD_INTERVAL_MINUTES) {
+            entry.last_hit_at = Some(now);
+            let _ = write_entry(&cache_path, &entry);
+        }
+
+        Ok(Some(entry.result))
     }
 
     /// Store result in cache
-    pub fn set(&self, image_path: &Path, result: &EstimationResult) -> Result<()> {
-        let key = Self::cache_key(image_path)?;
-        let cache_path = self.cache_dir.join(format!("{}.json", key));
+    pub fn set(&self, image_path: &Path, inputs: &CacheKeyInputs, result: &EstimationResult) -> Result<()> {
+        self.set_images(&[image_path.to_path_buf()], inputs, result)
+    }
+
+    /// Store the result of a set of images analyzed together
+    pub fn set_images(
+        &self,
+        image_paths: &[PathBuf],
+        inputs: &CacheKeyInputs,
+        result: &EstimationResult,
+    ) -> Result<()> {
+        let image_hash = Self::images_hash(image_paths)?;
+        let cache_path = self.entry_path(&image_hash, inputs);
+
+        let entry = CacheEntry {
+            image_hash,
+            inputs: inputs.clone(),
+            created_at: Utc::now(),
+            last_hit_at: None,
+            result: result.clone(),
+        };
+
+        write_entry(&cache_path, &entry)
+    }
+
+    fn plate_path(&self, image_hash: &str, settings: &str) -> PathBuf {
+        self.cache_dir
+            .join("plates")
+            .join(format!("{}-{}.json", image_hash, short_hash(settings)))
+    }
 
-        let content = serde_json::to_string_pretty(result)?;
-        fs::write(&cache_path, content)?;
-        Ok(())
+    /// Cached plate reading for an image read with the given settings.
+    ///
+    /// `Ok(Some(None))` means the image is known to have no readable plate.
+    pub fn get_plate(&self, image_path: &Path, settings: &str) -> Result<Option<Option<PlateReading>>> {
+        let image_hash = Self::image_hash(image_path)?;
+        let path = self.plate_path(&image_hash, settings);
+        let now = Utc::now();
+        Ok(fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str::<PlateCacheEntry>(&c).ok())
+            .filter(|e| e.image_hash == image_hash && e.settings == settings)
+            .filter(|e| !self.policy.is_expired(e.created_at, now))
+            .map(|e| e.reading))
     }
 
-    /// Clear all cached results
+    /// Store a plate reading (or the absence of one) for an image
+    pub fn set_plate(&self, image_path: &Path, settings: &str, reading: Option<&PlateReading>) -> Result<()> {
+        let image_hash = Self::image_hash(image_path)?;
+        let path = self.plate_path(&image_hash, settings);
+        fs::create_dir_all(self.cache_dir.join("plates"))?;
+        let entry = PlateCacheEntry {
+            image_hash,
+            settings: settings.to_string(),
+            created_at: Utc::now(),
+            reading: reading.cloned(),
+        };
+        write_entry(&path, &entry)
+    }
+
+    /// Clear all cached results (including legacy entries and plate readings)
     pub fn clear(&self) -> Result<usize> {
         let mut count = 0;
 
-        for entry in fs::read_dir(&self.cache_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map_or(false, |e| e == "json") {
-                fs::remove_file(&path)?;
-                count += 1;
+        for dir in [self.cache_dir.clone(), self.cache_dir.join("plates")] {
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == "json") {
+                    fs::remove_file(&path)?;
+                    count += 1;
+                }
             }
         }
 
         Ok(count)
     }
 
-    /// Get cache statistics
+    /// Remove expired, legacy and (LRU) over-size entries, including plate
+    /// readings under `plates/`.
+    ///
+    /// Unreadable files and leftover temp files are only removed once they are
+    /// older than [`UNREADABLE_GRACE_MINUTES`]. With `dry_run` nothing is
+    /// deleted; the report lists what would be.
+    pub fn prune(&self, policy: &CachePolicy, dry_run: bool) -> Result<PruneReport> {
+        let now = Utc::now();
+        let mut removed = Vec::new();
+        let mut live: Vec<(PathBuf, u64, DateTime<Utc>)> = Vec::new();
+        let plates_dir = self.cache_dir.join("plates");
+
+        for dir in [&self.cache_dir, &plates_dir] {
+            if !dir.is_dir() {
+                continue;
+            }
+            let plates = dir == &plates_dir;
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+
+                if is_temp_file(&path) {
+                    if !modified_within(&path, UNREADABLE_GRACE_MINUTES) {
+                        removed.push(PrunedEntry {
+                            path,
+                            size_bytes: size,
+                            last_used: None,
+                            reason: PruneReason::StaleTemp,
+                        });
+                    }
+                    continue;
+                }
+                if !path.extension().is_some_and(|e| e == "json") {
+                    continue;
+                }
+
+                if !plates && is_legacy_entry(&path) {
+                    removed.push(PrunedEntry {
+                        path,
+                        size_bytes: size,
+                        last_used: None,
+                        reason: PruneReason::Legacy,
+                    });
+                    continue;
+                }
+
+                // (created_at, last_used); plate readings record no hits
+                let parsed = fs::read_to_string(&path).ok().and_then(|c| {
+                    if plates {
+                        serde_json::from_str::<PlateCacheEntry>(&c)
+                            .ok()
+                            .map(|e| (e.created_at, e.created_at))
+                    } else {
+                        serde_json::from_str::<CacheEntry>(&c)
+                            .ok()
+                            .map(|e| (e.created_at, e.last_used()))
+                    }
+                });
+                match parsed {
+                    // Possibly still being written by another process
+                    None if modified_within(&path, UNREADABLE_GRACE_MINUTES) => continue,
+                    None => removed.push(PrunedEntry {
+                        path,
+                        size_bytes: size,
+                        last_used: None,
+                        reason: PruneReason::Unreadable,
+                    }),
+                    Some((created_at, last_used)) if policy.is_expired(created_at, now) => {
+                        removed.push(PrunedEntry {
+                            path,
+                            size_bytes: size,
+                            last_used: Some(last_used),
+                            reason: PruneReason::Expired,
+                        })
+                    }
+                    Some((_, last_used)) => live.push((path, size, last_used)),
+                }
+            }
+        }
+
+        // LRU: evict least-recently-used until under the size cap
+        let mut kept_bytes: u64 = live.iter().map(|(_, size, _)| size).sum();
+        if let Some(max_bytes) = policy.max_bytes {
+            live.sort_by_key(|(_, _, last_used)| *last_used);
+            let mut remaining = Vec::new();
+            for (path, size, last_used) in live {
+                if kept_bytes > max_bytes {
+                    kept_bytes -= size;
+                    removed.push(PrunedEntry {
+                        path,
+                        size_bytes: size,
+                        last_used: Some(last_used),
+                        reason: PruneReason::OverSize,
+                    });
+                } else {
+                    remaining.push((path, size, last_used));
+                }
+            }
+            live = remaining;
+        }
+
+        if !dry_run {
+            for entry in &removed {
+                // Already removed by another prune: nothing left to do
+                match fs::remove_file(&entry.path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(PruneReport {
+            removed,
+            kept_count: live.len(),
+            kept_bytes,
+            dry_run,
+        })
+    }
+
+    /// Get cache statistics, broken down by configuration
     pub fn stats(&self) -> Result<CacheStats> {
         let mut count = 0;
         let mut total_size = 0u64;
+        let mut legacy_count = 0;
+        let mut by_config: BTreeMap<String, ConfigStats> = BTreeMap::new();
 
         for entry in fs::read_dir(&self.cache_dir)? {
             let entry = entry?;
             let path = entry.path();
-            if path.extension().map_or(false, |e| e == "json") {
+            if path.extension().is_some_and(|e| e == "json") {
                 count += 1;
-                if let Ok(metadata) = fs::metadata(&path) {
-                    total_size += metadata.len();
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                total_size += size;
+
+                if is_legacy_entry(&path) {
+                    legacy_count += 1;
+                    continue;
                 }
+
+                let label = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|c| serde_json::from_str::<CacheEntry>(&c).ok())
+                    .map(|e| e.inputs.label())
+                    .unwrap_or_else(|| "(unreadable)".to_string());
+                let stats = by_config.entry(label).or_default();
+                stats.entry_count += 1;
+                stats.total_size_bytes += size;
             }
         }
 
@@ -89,22 +662,49 @@ impl Cache {
             entry_count: count,
             total_size_bytes: total_size,
             cache_dir: self.cache_dir.clone(),
+            legacy_count,
+            by_config,
         })
     }
 }
 
+/// Temp files of `write_entry` are named `<entry>.json.tmp.<pid>.<n>`
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .is_some_and(|s| s.contains(".json.tmp."))
+}
+
+/// Legacy entries are named `<image_hash>.json` (no inputs hash suffix)
+fn is_legacy_entry(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .is_some_and(|s| !s.contains('-'))
+}
+
+/// Per-configuration statistics
+#[derive(Debug, Default, Clone)]
+pub struct ConfigStats {
+    pub entry_count: usize,
+    pub total_size_bytes: u64,
+}
+
 /// Cache statistics
 #[derive(Debug)]
 pub struct CacheStats {
     pub entry_count: usize,
     pub total_size_bytes: u64,
     pub cache_dir: PathBuf,
+    /// Legacy flat entries (ignored; removed by `cache --clear`)
+    pub legacy_count: usize,
+    /// Entry count and size per configuration label
+    pub by_config: BTreeMap<String, ConfigStats>,
 }
 
 impl CacheStats {
     pub fn display(&self) -> String {
         let size_kb = self.total_size_bytes as f64 / 1024.0;
-        format!(
+        let mut out = format!(
             "Cache Statistics\n\
              ================\n\
              Entries:    {}\n\
@@ -113,6 +713,258 @@ impl CacheStats {
             self.entry_count,
             size_kb,
             self.cache_dir.display()
-        )
+        );
+
+        if !self.by_config.is_empty() {
+            out.push_str("\n\nBy configuration:");
+            for (label, stats) in &self.by_config {
+                out.push_str(&format!(
+                    "\n  {:>5}  {:>9.2} KB  {}",
+                    stats.entry_count,
+                    stats.total_size_bytes as f64 / 1024.0,
+                    label
+                ));
+            }
+        }
+
+        if self.legacy_count > 0 {
+            out.push_str(&format!(
+                "\n\nLegacy entries (ignored): {} — remove with `cache --clear`",
+                self.legacy_count
+            ));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs() -> CacheKeyInputs {
+        CacheKeyInputs {
+            backend: "gemini".to_string(),
+            model: None,
+            pipeline: PipelineKind::BoxOverlay,
+            truck_class: Some("4t".to_string()),
+            material_type: Some("As殻".to_string()),
+            karte_hash: None,
+            catalog: None,
+            prompt_version: "test".to_string(),
+            ensemble_count: 1,
+            aggregator: None,
+            references: None,
+            fusion: None,
+            bed: None,
+            preprocess: None,
+        }
+    }
+
+    #[test]
+    fn test_key_includes_inputs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let image = dir.join("truck.jpg");
+        fs::write(&image, b"image").unwrap();
+
+        let result = EstimationResult {
+            estimated_tonnage: 3.2,
+            ..Default::default()
+        };
+        cache.set(&image, &inputs(), &result).unwrap();
+
+        let hit = cache.get(&image, &inputs()).unwrap();
+        assert_eq!(hit.map(|r| r.estimated_tonnage), Some(3.2));
+
+        let mut other = inputs();
+        other.ensemble_count = 3;
+        assert!(cache.get(&image, &other).unwrap().is_none());
+
+        let mut other = inputs();
+        other.backend = "claude".to_string();
+        assert!(cache.get(&image, &other).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_legacy_entries_ignored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let image = dir.join("truck.jpg");
+        fs::write(&image, b"image").unwrap();
+
+        let legacy_name = format!("{}.json", Cache::image_hash(&image).unwrap());
+        let legacy = serde_json::to_string(&EstimationResult::default()).unwrap();
+        fs::write(dir.join("cache").join(legacy_name), legacy).unwrap();
+
+        assert!(cache.get(&image, &inputs()).unwrap().is_none());
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.legacy_count, 1);
+        assert!(stats.by_config.is_empty());
+    }
+
+    #[test]
+    fn test_prune_lru_and_legacy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let old_image = dir.join("old.jpg");
+        let new_image = dir.join("new.jpg");
+        fs::write(&old_image, b"old").unwrap();
+        fs::write(&new_image, b"new").unwrap();
+
+        cache.set(&old_image, &inputs(), &EstimationResult::default()).unwrap();
+        cache.set(&new_image, &inputs(), &EstimationResult::default()).unwrap();
+        // Backdate the old entry so it is the least recently used
+        let old_path = cache.entry_path(&Cache::image_hash(&old_image).unwrap(), &inputs());
+        let mut old_entry: CacheEntry =
+            serde_json::from_str(&fs::read_to_string(&old_path).unwrap()).unwrap();
+        old_entry.created_at = Utc::now() - Duration::days(1);
+        write_entry(&old_path, &old_entry).unwrap();
+        fs::write(dir.join("cache").join(format!("{}.json", "a".repeat(64))), "{}").unwrap();
+
+        let new_path = cache.entry_path(&Cache::image_hash(&new_image).unwrap(), &inputs());
+        let policy = CachePolicy {
+            max_age: None,
+            max_bytes: Some(fs::metadata(&new_path).unwrap().len()),
+        };
+
+        let report = cache.prune(&policy, true).unwrap();
+        assert_eq!(report.removed.len(), 2);
+        assert!(report.removed.iter().any(|e| e.reason == PruneReason::Legacy));
+        assert!(report.removed.iter().all(|e| e.path.exists()), "dry run must not delete");
+
+        cache.prune(&policy, false).unwrap();
+        assert!(cache.get(&old_image, &inputs()).unwrap().is_none());
+        assert!(cache.get(&new_image, &inputs()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_hit_recorded_only_after_interval() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let image = dir.join("truck.jpg");
+        fs::write(&image, b"image").unwrap();
+        let path = cache.entry_path(&Cache::image_hash(&image).unwrap(), &inputs());
+        let read_entry =
+            || serde_json::from_str::<CacheEntry>(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        // A fresh entry is not rewritten by a hit
+        cache.set(&image, &inputs(), &EstimationResult::default()).unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+        assert!(cache.get(&image, &inputs()).unwrap().is_some());
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        assert!(read_entry().last_hit_at.is_none());
+
+        // Last used over an interval ago: the hit is recorded
+        let mut entry = read_entry();
+        entry.created_at = Utc::now() - Duration::minutes(HIT_RECORD_INTERVAL_MINUTES + 1);
+        write_entry(&path, &entry).unwrap();
+        assert!(cache.get(&image, &inputs()).unwrap().is_some());
+        let hit_at = read_entry().last_hit_at.unwrap();
+        assert!(Utc::now() - hit_at < Duration::minutes(1));
+
+        // ...and not again right after
+        let before = fs::read_to_string(&path).unwrap();
+        assert!(cache.get(&image, &inputs()).unwrap().is_some());
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn test_prune_spares_fresh_unreadable_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let partial = dir.join("cache").join(format!("{}-{}.json", "b".repeat(64), "c".repeat(16)));
+        fs::write(&partial, "{\"imageHash\": \"b").unwrap();
+
+        let report = cache.prune(&CachePolicy::default(), false).unwrap();
+        assert!(report.removed.is_empty());
+        assert!(partial.exists());
+
+        let old = SystemTime::now() - std::time::Duration::from_secs(3600);
+        File::options().write(true).open(&partial).unwrap().set_modified(old).unwrap();
+        let report = cache.prune(&CachePolicy::default(), false).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].reason, PruneReason::Unreadable);
+        assert!(!partial.exists());
+    }
+
+    #[test]
+    fn test_plate_readings_cached_by_settings() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let image = dir.join("rear.jpg");
+        fs::write(&image, b"rear").unwrap();
+
+        assert_eq!(cache.get_plate(&image, "gemini").unwrap(), None);
+        let reading = PlateReading {
+            plate: "熊本 130 ら 11-22".to_string(),
+            source: tonsuu_types::PlateSource::Api,
+            confidence: Some(0.9),
+        };
+        cache.set_plate(&image, "gemini", Some(&reading)).unwrap();
+        assert_eq!(cache.get_plate(&image, "gemini").unwrap(), Some(Some(reading)));
+        assert_eq!(cache.get_plate(&image, "claude").unwrap(), None);
+
+        // "No readable plate" is remembered too
+        cache.set_plate(&image, "claude", None).unwrap();
+        assert_eq!(cache.get_plate(&image, "claude").unwrap(), Some(None));
+
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert_eq!(cache.get_plate(&image, "gemini").unwrap(), None);
+    }
+
+    #[test]
+    fn test_prune_plates_and_stale_temp_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let image = dir.join("rear.jpg");
+        fs::write(&image, b"rear").unwrap();
+        cache.set_plate(&image, "gemini", None).unwrap();
+
+        let plates = dir.join("cache").join("plates");
+        let fresh_tmp = plates.join(format!("{}.json.tmp.1.0", "d".repeat(64)));
+        let stale_tmp = dir.join("cache").join(format!("{}.json.tmp.1.1", "e".repeat(64)));
+        fs::write(&fresh_tmp, "{").unwrap();
+        fs::write(&stale_tmp, "{").unwrap();
+        let old = SystemTime::now() - std::time::Duration::from_secs(3600);
+        File::options().write(true).open(&stale_tmp).unwrap().set_modified(old).unwrap();
+
+        let report = cache.prune(&CachePolicy::default(), false).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].reason, PruneReason::StaleTemp);
+        assert!(!stale_tmp.exists());
+        assert!(fresh_tmp.exists());
+        assert_eq!(report.kept_count, 1);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let policy = CachePolicy {
+            max_age: Some(Duration::zero()),
+            max_bytes: None,
+        };
+        let report = cache.prune(&policy, false).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].reason, PruneReason::Expired);
+        assert_eq!(cache.get_plate(&image, "gemini").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_duration_and_size() {
+        assert_eq!(parse_duration("30d"), Some(Duration::days(30)));
+        assert_eq!(parse_duration("12h"), Some(Duration::hours(12)));
+        assert_eq!(parse_duration("2w"), Some(Duration::weeks(2)));
+        assert_eq!(parse_duration("7"), Some(Duration::days(7)));
+        assert_eq!(parse_duration("abc"), None);
+
+        assert_eq!(parse_size("500MB"), Some(500 * 1024 * 1024));
+        assert_eq!(parse_size("1.5k"), Some(1536));
+        assert_eq!(parse_size("2GB"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("10xb"), None);
     }
 }
@@ -0,0 +1,389 @@
+//! Ensemble aggregation with outlier rejection
+//!
+//! Samples whose height, volume or tonnage is far from the ensemble median
+//! (modified z-score on the median absolute deviation) are rejected first.
+//! Every numeric parameter of the remaining samples is then combined with the
+//! selected aggregator, so one run that hallucinated twice the height does not
+//! drag the answer off.
+
+use std::collections::HashMap;
+use std::time::Instant;
+use tonsuu_types::{EnsembleSample, Error, EstimationResult, Result};
+
+/// Modified z-score above which a value is an outlier (Iglewicz & Hoaglin)
+const MAD_Z_THRESHOLD: f64 = 3.5;
+
+/// Scale factor making the MAD consistent with the standard deviation
+const MAD_SCALE: f64 = 1.4826;
+
+/// Spread used when the MAD is zero, relative to the median
+const ZERO_MAD_RELATIVE_SPREAD: f64 = 0.05;
+
+/// Fraction trimmed from each end by `Aggregator::TrimmedMean`
+const TRIM_FRACTION: f64 = 0.2;
+
+/// How accepted ensemble samples are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aggregator {
+    #[default]
+    Median,
+    /// Mean after dropping the lowest and highest 20%
+    TrimmedMean,
+    /// Mean weighted by each sample's confidence score
+    ConfidenceWeighted,
+}
+
+impl Aggregator {
+    /// Parse a config/CLI name ("median", "trimmed_mean", "confidence_weighted")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace('-', "_").as_str() {
+            "median" => Some(Aggregator::Median),
+            "trimmed_mean" | "trimmed" => Some(Aggregator::TrimmedMean),
+            "confidence_weighted" | "weighted" => Some(Aggregator::ConfidenceWeighted),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregator::Median => "median",
+            Aggregator::TrimmedMean => "trimmed_mean",
+            Aggregator::ConfidenceWeighted => "confidence_weighted",
+        }
+    }
+
+    /// Combine `(value, weight)` pairs; weights are only used by `ConfidenceWeighted`
+    fn combine(&self, values: &[(f64, f64)]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        match self {
+            Aggregator::Median => median(&values.iter().map(|(v, _)| *v).collect::<Vec<_>>()),
+            Aggregator::TrimmedMean => {
+                let mut sorted: Vec<f64> = values.iter().map(|(v, _)| *v).collect();
+                sorted.sort_by(f64::total_cmp);
+                let trim = (sorted.len() as f64 * TRIM_FRACTION).floor() as usize;
+                mean(&sorted[trim..sorted.len() - trim])
+            }
+            Aggregator::ConfidenceWeighted => {
+                let total: f64 = values.iter().map(|(_, w)| w.max(0.0)).sum();
+                if total <= 0.0 {
+                    return mean(&values.iter().map(|(v, _)| *v).collect::<Vec<_>>());
+                }
+                Some(values.iter().map(|(v, w)| v * w.max(0.0)).sum::<f64>() / total)
+            }
+        }
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+pub(crate) fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    // Same element twice for odd lengths, the two middle elements for even ones
+    let n = sorted.len();
+    Some((sorted[(n - 1) / 2] + sorted[n / 2]) / 2.0)
+}
+
+/// Sample standard deviation (None for fewer than 2 values)
+fn std_dev(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let m = mean(values)?;
+    let var = values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    Some(var.sqrt())
+}
+
+/// Indices of values that are outliers by the MAD rule
+fn mad_outliers(values: &[(usize, f64)]) -> Vec<usize> {
+    let plain: Vec<f64> = values.iter().map(|(_, v)| *v).collect();
+    let Some(med) = median(&plain) else {
+        return Vec::new();
+    };
+    let deviations: Vec<f64> = plain.iter().map(|v| (v - med).abs()).collect();
+    let mad = median(&deviations).unwrap_or(0.0);
+
+    let mut scale = MAD_SCALE * mad;
+    if scale <= f64::EPSILON {
+        scale = ZERO_MAD_RELATIVE_SPREAD * med.abs();
+    }
+    if scale <= f64::EPSILON {
+        return Vec::new();
+    }
+
+    values
+        .iter()
+        .filter(|(_, v)| (v - med).abs() / scale > MAD_Z_THRESHOLD)
+        .map(|(i, _)| *i)
+        .collect()
+}
+
+/// Mode of strings; ties go to the value seen first
+pub(crate) fn mode_string<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (order, v) in values.enumerate() {
+        counts.entry(v).or_insert((0, order)).0 += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(_, (ca, oa)), (_, (cb, ob))| ca.cmp(cb).then(ob.cmp(oa)))
+        .map(|(value, _)| value.to_string())
+        .unwrap_or_default()
+}
+
+/// Merge ensemble samples into one estimate.
+///
+/// Rejects outliers (MAD rule on height, volume and tonnage), aggregates every
+/// numeric parameter of the accepted samples and records the per-sample
+/// values and the tonnage standard deviation on the result.
+pub fn merge_results(results: &[EstimationResult], aggregator: Aggregator) -> EstimationResult {
+    if results.is_empty() {
+        return EstimationResult::default();
+    }
+    if results.len() == 1 {
+        return results[0].clone();
+    }
+
+    // Outlier rejection: a sample is rejected if any key value is an outlier
+    let mut rejected = vec![false; results.len()];
+    let key_values: [fn(&EstimationResult) -> Option<f64>; 3] = [
+        |r| r.height,
+        |r| Some(r.estimated_volume_m3),
+        |r| Some(r.estimated_tonnage),
+    ];
+    for key in key_values {
+        let values: Vec<(usize, f64)> = results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, r)| key(r).map(|v| (i, v)))
+            .collect();
+        for i in mad_outliers(&values) {
+            rejected[i] = true;
+        }
+    }
+
+    let accepted: Vec<&EstimationResult> = results
+        .iter()
+        .zip(&rejected)
+        .filter(|(_, rejected)| !**rejected)
+        .map(|(r, _)| r)
+        .collect();
+
+    let combine = |get: fn(&EstimationResult) -> Option<f64>| {
+        let values: Vec<(f64, f64)> = accepted
+            .iter()
+            .filter_map(|r| get(r).map(|v| (v, r.confidence_score)))
+            .collect();
+        aggregator.combine(&values)
+    };
+
+    // Base on the accepted sample closest to the aggregated tonnage
+    let tonnage = combine(|r| Some(r.estimated_tonnage)).unwrap_or_default();
+    let base = accepted
+        .iter()
+        .min_by(|a, b| {
+            (a.estimated_tonnage - tonnage)
+                .abs()
+                .total_cmp(&(b.estimated_tonnage - tonnage).abs())
+        })
+        .copied()
+        .unwrap_or(&results[0]);
+
+    let mut merged = base.clone();
+    merged.truck_type = mode_string(accepted.iter().map(|r| r.truck_type.as_str()));
+    merged.material_type = mode_string(accepted.iter().map(|r| r.material_type.as_str()));
+    merged.estimated_tonnage = tonnage;
+    merged.estimated_volume_m3 = combine(|r| Some(r.estimated_volume_m3)).unwrap_or_default();
+    merged.height = combine(|r| r.height);
+    merged.fill_ratio_l = combine(|r| r.fill_ratio_l);
+    merged.fill_ratio_w = combine(|r| r.fill_ratio_w);
+    merged.fill_ratio_z = combine(|r| r.fill_ratio_z);
+    merged.packing_density = combine(|r| r.packing_density);
+    merged.confidence_score = combine(|r| Some(r.confidence_score)).unwrap_or_default();
+    merged.ensemble_count = Some(results.len() as u32);
+    merged.tonnage_std_dev =
+        std_dev(&accepted.iter().map(|r| r.estimated_tonnage).collect::<Vec<_>>());
+    merged.ensemble_samples = results
+        .iter()
+        .zip(&rejected)
+        .map(|(r, rejected)| EnsembleSample {
+            estimated_tonnage: r.estimated_tonnage,
+            estimated_volume_m3: r.estimated_volume_m3,
+            height: r.height,
+            fill_ratio_l: r.fill_ratio_l,
+            fill_ratio_w: r.fill_ratio_w,
+            packing_density: r.packing_density,
+            confidence_score: r.confidence_score,
+            rejected: *rejected,
+            elapsed_ms: None,
+        })
+        .collect();
+    merged.reasoning = format!(
+        "Ensemble {} of {}/{} samples ({} rejected as outliers). {}",
+        aggregator.name(),
+        accepted.len(),
+        results.len(),
+        results.len() - accepted.len(),
+        merged.reasoning
+    );
+
+    merged
+}
+
+/// Run `count` ensemble samples concurrently and merge the ones that succeed.
+///
+/// Each sample runs on its own thread; the backend limiter (see
+/// `ai::limiter`) caps how many AI calls are actually in flight. Failed
+/// samples are dropped and noted in the reasoning; the run only fails when
+/// every sample failed. Per-sample wall times are recorded on
+/// `ensemble_samples` so callers can compare against the total.
+pub fn run_ensemble<F>(count: usize, aggregator: Aggregator, sample: F) -> Result<EstimationResult>
+where
+    F: Fn(usize) -> Result<EstimationResult> + Sync,
+{
+    let count = count.max(1);
+    let runs: Vec<(Result<EstimationResult>, u64)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..count)
+            .map(|i| {
+                let sample = &sample;
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let result = sample(i);
+                    (result, start.elapsed().as_millis() as u64)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    (Err(Error::AnalysisFailed("inference thread panicked".to_string())), 0)
+                })
+            })
+            .collect()
+    });
+
+    let mut results = Vec::new();
+    let mut elapsed = Vec::new();
+    let mut failures = Vec::new();
+    for (i, (result, ms)) in runs.into_iter().enumerate() {
+        match result {
+            Ok(result) => {
+                results.push(result);
+                elapsed.push(ms);
+            }
+            Err(e) => failures.push(format!("#{}: {}", i + 1, e)),
+        }
+    }
+
+    if results.is_empty() {
+        return Err(Error::AnalysisFailed(format!(
+            "All {} inference attempts failed ({})",
+            count,
+            failures.join("; ")
+        )));
+    }
+
+    let mut merged = merge_results(&results, aggregator);
+    for (sample, ms) in merged.ensemble_samples.iter_mut().zip(&elapsed) {
+        sample.elapsed_ms = Some(*ms);
+    }
+    if !failures.is_empty() {
+        merged.reasoning = format!(
+            "{} of {} samples failed ({}). {}",
+            failures.len(),
+            count,
+            failures.join("; "),
+            merged.reasoning
+        );
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(height: f64, tonnage: f64, confidence: f64) -> EstimationResult {
+        EstimationResult {
+            truck_type: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            height: Some(height),
+            estimated_volume_m3: tonnage / 2.5,
+            estimated_tonnage: tonnage,
+            confidence_score: confidence,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hallucinated_sample_is_rejected() {
+        let results = vec![sample(0.40, 3.0, 0.8), sample(0.42, 3.2, 0.8), sample(0.84, 6.4, 0.8)];
+        for aggregator in [
+            Aggregator::Median,
+            Aggregator::TrimmedMean,
+            Aggregator::ConfidenceWeighted,
+        ] {
+            let merged = merge_results(&results, aggregator);
+            assert!((merged.estimated_tonnage - 3.1).abs() < 0.11, "{:?}", aggregator);
+            assert!(merged.height.unwrap() < 0.45);
+            assert_eq!(merged.ensemble_samples.len(), 3);
+            assert!(merged.ensemble_samples[2].rejected);
+            assert_eq!(merged.ensemble_count, Some(3));
+        }
+    }
+
+    #[test]
+    fn test_aggregators() {
+        let values = [(1.0, 0.1), (2.0, 0.1), (3.0, 0.1), (4.0, 0.1), (100.0, 0.1)];
+        assert_eq!(Aggregator::Median.combine(&values), Some(3.0));
+        assert_eq!(Aggregator::TrimmedMean.combine(&values), Some(3.0));
+
+        let weighted = [(1.0, 0.9), (3.0, 0.1)];
+        let w = Aggregator::ConfidenceWeighted.combine(&weighted).unwrap();
+        assert!((w - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_run_ensemble_tolerates_failures() {
+        let merged = run_ensemble(4, Aggregator::Median, |i| {
+            if i == 1 {
+                Err(Error::AnalysisFailed("timeout".to_string()))
+            } else {
+                Ok(sample(0.4, 3.0 + i as f64 * 0.1, 0.8))
+            }
+        })
+        .unwrap();
+        assert_eq!(merged.ensemble_count, Some(3));
+        assert!(merged.ensemble_samples.iter().all(|s| s.elapsed_ms.is_some()));
+        assert!(merged.reasoning.starts_with("1 of 4 samples failed"));
+
+        let all_failed = run_ensemble(2, Aggregator::Median, |_| {
+            Err(Error::AnalysisFailed("timeout".to_string()))
+        });
+        assert!(matches!(all_failed, Err(Error::AnalysisFailed(_))));
+    }
+
+    #[test]
+    fn test_std_dev_and_names() {
+        let merged = merge_results(&[sample(0.4, 3.0, 0.8), sample(0.4, 3.2, 0.8)], Aggregator::Median);
+        assert!((merged.tonnage_std_dev.unwrap() - 0.1414).abs() < 0.001);
+        assert!(merged.ensemble_samples.iter().all(|s| !s.rejected));
+
+        for aggregator in [Aggregator::Median, Aggregator::TrimmedMean, Aggregator::ConfidenceWeighted] {
+            assert_eq!(Aggregator::from_name(aggregator.name()), Some(aggregator));
+        }
+        assert_eq!(Aggregator::from_name("nope"), None);
+    }
+}
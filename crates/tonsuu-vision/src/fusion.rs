@@ -0,0 +1,306 @@
+//! Multi-image analysis of one load (rear, side and top views)
+//!
+//! Two strategies are supported:
+//! - `Joint`: every photo goes to the AI in one request and it returns a
+//!   single estimate.
+//! - `PerView`: each photo is analyzed on its own and the results are
+//!   reconciled. The height comes from the side view and the fill ratios come
+//!   from the top view, since those views show them best. Each view's volume
+//!   and tonnage are then rescaled to the reconciled parameters, so the fused
+//!   estimate keeps the bed, taper and density the pipeline used for the views.
+
+use crate::ai::prompts::with_multi_view_note;
+use crate::ensemble::{median, mode_string};
+use std::path::PathBuf;
+use tonsuu_core::pipeline::{AiBackend, PipelineError};
+use tonsuu_types::{EstimationResult, ImageView};
+
+/// How several photos of one load are analyzed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FusionMode {
+    /// Send all photos in one request
+    #[default]
+    Joint,
+    /// Analyze each photo separately and reconcile the parameters
+    PerView,
+}
+
+impl FusionMode {
+    /// Parse a CLI name ("joint", "per_view")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace('-', "_").as_str() {
+            "joint" => Some(FusionMode::Joint),
+            "per_view" | "perview" => Some(FusionMode::PerView),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FusionMode::Joint => "joint",
+            FusionMode::PerView => "per_view",
+        }
+    }
+}
+
+/// One photo of the load and the camera position it was taken from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewImage {
+    pub path: PathBuf,
+    pub view: ImageView,
+}
+
+impl ViewImage {
+    pub fn new(path: impl Into<PathBuf>, view: ImageView) -> Self {
+        Self {
+            path: path.into(),
+            view,
+        }
+    }
+
+    /// Parse a CLI argument of the form `[view:]path` (e.g. "side:b.jpg").
+    ///
+    /// Only known view names are treated as a prefix, so `C:\img.jpg` stays a path.
+    pub fn parse(arg: &str) -> Self {
+        if let Some((prefix, path)) = arg.split_once(':') {
+            if let Some(view) = ImageView::from_name(prefix) {
+                return Self::new(path, view);
+            }
+        }
+        Self::new(arg, ImageView::Unspecified)
+    }
+}
+
+/// Backend wrapper for joint analysis: every prompt is prefixed with the
+/// camera position of each attached photo
+pub struct MultiViewBackend<B: AiBackend> {
+    pub inner: B,
+    pub views: Vec<ImageView>,
+}
+
+impl<B: AiBackend> AiBackend for MultiViewBackend<B> {
+    fn send_prompt(&self, prompt: &str, images: &[Vec<u8>]) -> Result<String, PipelineError> {
+        if self.views.len() > 1 {
+            self.inner
+                .send_prompt(&with_multi_view_note(prompt, &self.views), images)
+        } else {
+            self.inner.send_prompt(prompt, images)
+        }
+    }
+}
+
+/// Median of a parameter over the views of `preferred`, or over all views if
+/// none of them has it
+fn pick_view(
+    views: &[(ImageView, EstimationResult)],
+    preferred: ImageView,
+    get: fn(&EstimationResult) -> Option<f64>,
+) -> Option<f64> {
+    let from_preferred: Vec<f64> = views
+        .iter()
+        .filter(|(view, _)| *view == preferred)
+        .filter_map(|(_, r)| get(r))
+        .collect();
+    median(&from_preferred)
+        .or_else(|| median(&views.iter().filter_map(|(_, r)| get(r)).collect::<Vec<_>>()))
+}
+
+/// Factor from a view's own parameter to the reconciled one (1.0 if either is missing)
+fn param_ratio(reconciled: Option<f64>, own: Option<f64>) -> f64 {
+    match (reconciled, own) {
+        (Some(reconciled), Some(own)) if own > 0.0 => reconciled / own,
+        _ => 1.0,
+    }
+}
+
+/// Median of `values`, kept within the range of `bounds`
+fn median_within(values: &[f64], bounds: &[f64]) -> f64 {
+    let min = bounds.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = bounds.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    median(values).unwrap_or_default().clamp(min, max)
+}
+
+/// Reconcile per-view estimates of one load into a single result
+pub fn reconcile_views(views: &[(ImageView, EstimationResult)]) -> EstimationResult {
+    let Some((_, first)) = views.first() else {
+        return EstimationResult::default();
+    };
+    if views.len() == 1 {
+        return first.clone();
+    }
+
+    let mut merged = first.clone();
+    merged.is_target_detected = views.iter().any(|(_, r)| r.is_target_detected);
+    merged.truck_type = mode_string(views.iter().map(|(_, r)| r.truck_type.as_str()));
+    merged.material_type = mode_string(views.iter().map(|(_, r)| r.material_type.as_str()));
+    merged.license_plate = views.iter().find_map(|(_, r)| r.license_plate.clone());
+
+    merged.height = pick_view(views, ImageView::Side, |r| r.height);
+    merged.fill_ratio_l = pick_view(views, ImageView::Top, |r| r.fill_ratio_l);
+    merged.fill_ratio_w = pick_view(views, ImageView::Top, |r| r.fill_ratio_w);
+    merged.fill_ratio_z = pick_view(views, ImageView::Top, |r| r.fill_ratio_z);
+    merged.packing_density = median(
+        &views
+            .iter()
+            .filter_map(|(_, r)| r.packing_density)
+            .collect::<Vec<_>>(),
+    );
+    merged.confidence_score =
+        views.iter().map(|(_, r)| r.confidence_score).sum::<f64>() / views.len() as f64;
+
+    merged.ensemble_count = Some(
+        views
+            .iter()
+            .map(|(_, r)| r.ensemble_count.unwrap_or(1))
+            .sum(),
+    );
+    merged.ensemble_samples = views
+        .iter()
+        .flat_map(|(_, r)| r.ensemble_samples.iter().cloned())
+        .collect();
+    merged.tonnage_std_dev = views
+        .iter()
+        .filter_map(|(_, r)| r.tonnage_std_dev)
+        .fold(None, |max: Option<f64>, sd| {
+            Some(max.map_or(sd, |m| m.max(sd)))
+        });
+    merged.tonnage_interval = None;
+
+    let per_view_tonnage: Vec<String> = views
+        .iter()
+        .map(|(view, r)| format!("{} {:.2}t", view.label(), r.estimated_tonnage))
+        .collect();
+
+    // Volume is linear in the height and fill ratios and tonnage also in the
+    // packing density, so each view's result is rescaled to the reconciled
+    // parameters instead of recomputed with another formula. The median stays
+    // within the per-view estimates.
+    let mut volumes = Vec::with_capacity(views.len());
+    let mut tonnages = Vec::with_capacity(views.len());
+    for (_, r) in views {
+        let shape = param_ratio(merged.height, r.height)
+            * param_ratio(merged.fill_ratio_l, r.fill_ratio_l)
+            * param_ratio(merged.fill_ratio_w, r.fill_ratio_w);
+        volumes.push(r.estimated_volume_m3 * shape);
+        tonnages.push(
+            r.estimated_tonnage * shape * param_ratio(merged.packing_density, r.packing_density),
+        );
+    }
+    let own_volumes: Vec<f64> = views.iter().map(|(_, r)| r.estimated_volume_m3).collect();
+    let own_tonnages: Vec<f64> = views.iter().map(|(_, r)| r.estimated_tonnage).collect();
+    merged.estimated_volume_m3 = median_within(&volumes, &own_volumes);
+    merged.estimated_tonnage = median_within(&tonnages, &own_tonnages);
+
+    let per_view_reasoning: Vec<String> = views
+        .iter()
+        .map(|(view, r)| format!("[{}] {}", view.label(), r.reasoning))
+        .collect();
+    merged.reasoning = format!(
+        "Fused {} views ({}); height from side view, fill from top view where available. {}",
+        views.len(),
+        per_view_tonnage.join(", "),
+        per_view_reasoning.join(" ")
+    );
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(height: f64, fill: f64) -> EstimationResult {
+        EstimationResult {
+            is_target_detected: true,
+            truck_type: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            height: Some(height),
+            fill_ratio_l: Some(fill),
+            fill_ratio_w: Some(fill),
+            estimated_volume_m3: 2.0,
+            estimated_tonnage: 3.0,
+            confidence_score: 0.8,
+            ..Default::default()
+        }
+    }
+
+    fn with_result(mut result: EstimationResult, volume: f64, tonnage: f64) -> EstimationResult {
+        result.estimated_volume_m3 = volume;
+        result.estimated_tonnage = tonnage;
+        result
+    }
+
+    #[test]
+    fn test_fused_tonnage_comes_from_the_view_results() {
+        // Same parameters, different per-view results (e.g. another taper):
+        // the views' own volume and tonnage are kept, not recomputed
+        let views = vec![
+            (ImageView::Rear, with_result(view(0.4, 0.8), 2.0, 3.0)),
+            (ImageView::Side, with_result(view(0.4, 0.8), 2.2, 3.3)),
+            (ImageView::Top, with_result(view(0.4, 0.8), 2.6, 3.9)),
+        ];
+        let merged = reconcile_views(&views);
+        assert!((merged.estimated_volume_m3 - 2.2).abs() < 1e-9);
+        assert!((merged.estimated_tonnage - 3.3).abs() < 1e-9);
+
+        // Reconciled parameters rescale the views' results
+        let views = vec![
+            (ImageView::Rear, with_result(view(0.4, 0.8), 2.0, 3.0)),
+            (ImageView::Side, with_result(view(0.5, 0.8), 2.5, 3.75)),
+        ];
+        let merged = reconcile_views(&views);
+        assert_eq!(merged.height, Some(0.5));
+        assert!((merged.estimated_tonnage - 3.75).abs() < 1e-9);
+
+        // Height from one view and fill from another stay within the views' range
+        let views = vec![
+            (ImageView::Side, with_result(view(0.5, 0.6), 2.0, 3.0)),
+            (ImageView::Top, with_result(view(0.3, 0.9), 2.4, 3.6)),
+        ];
+        let merged = reconcile_views(&views);
+        assert!(merged.estimated_tonnage >= 3.0 && merged.estimated_tonnage <= 3.6);
+    }
+
+    #[test]
+    fn test_view_image_parse() {
+        assert_eq!(
+            ViewImage::parse("side:b.jpg"),
+            ViewImage::new("b.jpg", ImageView::Side)
+        );
+        assert_eq!(ViewImage::parse("TOP:c.jpg").view, ImageView::Top);
+        assert_eq!(
+            ViewImage::parse("C:\\photos\\a.jpg"),
+            ViewImage::new("C:\\photos\\a.jpg", ImageView::Unspecified)
+        );
+    }
+
+    #[test]
+    fn test_height_from_side_fill_from_top() {
+        let views = vec![
+            (ImageView::Rear, view(0.30, 0.6)),
+            (ImageView::Side, view(0.45, 0.7)),
+            (ImageView::Top, view(0.20, 0.9)),
+        ];
+        let merged = reconcile_views(&views);
+        assert_eq!(merged.height, Some(0.45));
+        assert_eq!(merged.fill_ratio_l, Some(0.9));
+        assert_eq!(merged.fill_ratio_w, Some(0.9));
+        assert_eq!(merged.ensemble_count, Some(3));
+        assert!(merged.reasoning.starts_with("Fused 3 views"));
+
+        // Without a side view the height is the median of all views
+        let no_side = vec![
+            (ImageView::Rear, view(0.30, 0.6)),
+            (ImageView::Top, view(0.20, 0.9)),
+        ];
+        assert!((reconcile_views(&no_side).height.unwrap() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fusion_mode_names() {
+        for mode in [FusionMode::Joint, FusionMode::PerView] {
+            assert_eq!(FusionMode::from_name(mode.name()), Some(mode));
+        }
+        assert_eq!(FusionMode::from_name("per-view"), Some(FusionMode::PerView));
+    }
+}
@@ -2,7 +2,13 @@
 
 pub mod ai;
 pub mod cache;
+pub mod ensemble;
+pub mod fusion;
 pub mod plate_recognizer;
+pub mod preprocess;
+pub mod references;
+pub mod response;
+pub mod shaken_qr;
 pub mod volume_estimator;
 
 // Re-export main types for convenience
@@ -10,18 +16,37 @@ pub use ai::prompts::{
     build_analysis_prompt,
     build_estimation_prompt,
     build_karte_prompt,
-    build_staged_analysis_prompt, GradedReferenceItem,
+    build_staged_analysis_prompt, with_material_choices_note, with_reference_images_note,
+    with_truck_classes_note, GradedReferenceItem,
+    PROMPT_SPEC_VERSION,
+};
+pub use ai::backend_impl::{AnalyzerBackend, CliAiBackend, FixtureMode, FixtureScope};
+pub use ai::limiter::{LimitedBackend, DEFAULT_MAX_CONCURRENCY};
+pub use cache::{Cache, CacheKeyInputs, CachePolicy, PipelineKind};
+pub use ensemble::{merge_results, run_ensemble, Aggregator};
+pub use fusion::{reconcile_views, FusionMode, MultiViewBackend, ViewImage};
+pub use plate_recognizer::{
+    detect_plate, PlateDetectionConfig, PlateDetectionOutcome, PlateReading,
+};
+pub use preprocess::{ImagePreprocessor, DEFAULT_JPEG_QUALITY, DEFAULT_MAX_LONG_EDGE};
+pub use references::{
+    encode_thumbnail, prepare_reference_images, ReferenceImages, DEFAULT_MAX_REFERENCE_IMAGES,
+};
+pub use response::{
+    extract_json_from_response, parse_estimation_response, parse_estimation_with_repair,
+};
+pub use shaken_qr::{decode_qr_codes, parse_shaken_qr, read_shaken_qr};
+pub use volume_estimator::{
+    analyze_shaken, parse_certificate_date, parse_expiry_date, read_shaken, ShakenResult,
 };
-pub use ai::backend_impl::CliAiBackend;
-pub use cache::Cache;
-#[allow(unused_imports)]
-pub use volume_estimator::analyze_shaken;
 
 use tonsuu_types::{Error, Result};
 use tonsuu_store::{GradedHistoryEntry, Store};
-use tonsuu_types::{EstimationResult, TruckClass};
-use cli_ai_analyzer::{analyze, AnalyzeOptions, Backend, UsageMode};
-use std::path::Path;
+use tonsuu_types::{truck_classes, EstimationResult, ImagePreprocessing, ImageView, TruckClass};
+use cli_ai_analyzer::{AnalyzeOptions, Backend, UsageMode};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tonsuu_core::pipeline::AiBackend;
 
 /// Default taper ratio for multi-param path (which doesn't estimate taper).
 /// Conservative value; box-overlay path estimates it from コボレーン visibility.
@@ -33,6 +58,16 @@ pub struct AnalyzerConfig {
     pub backend: Backend,
     pub model: Option<String>,
     pub usage_mode: UsageMode,
+    /// Record/replay fixture directory (None = live backend only)
+    pub fixture: Option<FixtureMode>,
+    /// Requests in flight per backend, shared by all threads of the process
+    /// (None = keep the backend's current limit, [`DEFAULT_MAX_CONCURRENCY`] at first)
+    pub max_concurrency: Option<usize>,
+    /// Photo preprocessing before upload (None = send the originals)
+    pub preprocess: Option<ImagePreprocessor>,
+    /// Preprocessed copies uploaded in place of the originals (original →
+    /// copy), filled once per analysis by `with_prepared_images`
+    pub prepared: HashMap<PathBuf, PathBuf>,
 }
 
 impl Default for AnalyzerConfig {
@@ -41,17 +76,60 @@ impl Default for AnalyzerConfig {
             backend: Backend::Gemini,
             model: None,
             usage_mode: UsageMode::TimeBasedQuota,
+            fixture: None,
+            max_concurrency: None,
+            preprocess: None,
+            prepared: HashMap::new(),
         }
     }
 }
 
+fn parse_backend_name(name: &str) -> Option<Backend> {
+    match name.to_lowercase().as_str() {
+        "gemini" => Some(Backend::Gemini),
+        "claude" => Some(Backend::Claude),
+        "codex" => Some(Backend::Codex),
+        _ => None,
+    }
+}
+
 impl AnalyzerConfig {
+    /// Set the backend from a CLI/config string.
+    ///
+    /// Besides `gemini` / `claude` / `codex`, accepts:
+    /// - `replay:<dir>` or `replay:<backend>:<dir>` — serve recorded responses
+    ///   from `<dir>` (no network)
+    /// - `record:<dir>` or `record:<backend>:<dir>` — call the live backend and
+    ///   save every response to `<dir>`
+    ///
+    /// Fixtures are keyed by backend and model, so replay with the backend and
+    /// model they were recorded with.
     pub fn with_backend(mut self, backend: &str) -> Self {
-        self.backend = match backend.to_lowercase().as_str() {
-            "claude" => Backend::Claude,
-            "codex" => Backend::Codex,
-            _ => Backend::Gemini,
-        };
+        for (prefix, replay) in [("replay:", true), ("record:", false)] {
+            let Some(rest) = backend.strip_prefix(prefix) else {
+                continue;
+            };
+            let dir = match rest.split_once(':') {
+                Some((name, dir)) => match parse_backend_name(name) {
+                    Some(b) => {
+                        self.backend = b;
+                        dir
+                    }
+                    None => rest,
+                },
+                None => rest,
+            };
+            let dir = PathBuf::from(dir);
+            self.fixture = Some(if replay {
+                FixtureMode::Replay(dir)
+            } else {
+                FixtureMode::Record(dir)
+            });
+            return self;
+        }
+
+        self.backend = parse_backend_name(backend).unwrap_or(Backend::Gemini);
+        self.fixture = None;
         self
     }
 
@@ -60,6 +138,44 @@ impl AnalyzerConfig {
         self
     }
 
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency.max(1));
+        self
+    }
+
+    pub fn with_preprocessing(mut self, preprocessor: ImagePreprocessor) -> Self {
+        self.preprocess = Some(preprocessor);
+        self
+    }
+
+    /// Preprocess the photos of one analysis up front, so every backend
+    /// built from this config (ensemble samples, views, plate OCR) uploads
+    /// the same copies without reading and hashing the photos again.
+    ///
+    /// Does nothing without preprocessing or when replaying (nothing is
+    /// uploaded). A photo that cannot be processed is sent as it is.
+    pub fn with_prepared_images(mut self, image_paths: &[PathBuf]) -> Self {
+        let Some(ref preprocessor) = self.preprocess else {
+            return self;
+        };
+        if matches!(self.fixture, Some(FixtureMode::Replay(_))) {
+            return self;
+        }
+        for path in image_paths {
+            if let Ok(prepared) = preprocessor.prepare(path) {
+                self.prepared.insert(path.clone(), prepared);
+            }
+        }
+        self
+    }
+
+    /// Preprocessing the photos were uploaded with (None if any of them was
+    /// sent as the original, e.g. on fallback or replay)
+    pub fn preprocessing_for(&self, image_paths: &[PathBuf]) -> Option<ImagePreprocessing> {
+        let all_prepared = image_paths.iter().all(|p| self.prepared.contains_key(p));
+        self.preprocess.as_ref().filter(|_| all_prepared).map(|p| p.info())
+    }
+
     pub fn with_usage_mode(mut self, usage_mode: &str) -> Self {
         self.usage_mode = match usage_mode {
             "pay_per_use" => UsageMode::PayPerUse,
@@ -67,6 +183,98 @@ impl AnalyzerConfig {
         };
         self
     }
+
+    /// Backend name for cache keys ("gemini" / "claude" / "codex", or "replay")
+    pub fn backend_name(&self) -> &'static str {
+        if let Some(FixtureMode::Replay(_)) = self.fixture {
+            return "replay";
+        }
+        self.live_backend_name()
+    }
+
+    /// Name of the backend called (or recorded from), even when replaying
+    fn live_backend_name(&self) -> &'static str {
+        match self.backend {
+            Backend::Claude => "claude",
+            Backend::Codex => "codex",
+            _ => "gemini",
+        }
+    }
+
+    /// Build cli-ai-analyzer options for JSON output
+    pub fn analyze_options(&self) -> AnalyzeOptions {
+        let options = if let Some(ref model) = self.model {
+            AnalyzeOptions::with_model(model)
+        } else {
+            AnalyzeOptions::default()
+        };
+        options.with_backend(self.backend).json().with_usage_mode(self.usage_mode)
+    }
+
+    /// Build the AiBackend for the given images (live, recording or replaying).
+    ///
+    /// Calls go through the process-wide limiter for this backend, so at most
+    /// `max_concurrency` requests are in flight across all threads. Photos
+    /// prepared by `with_prepared_images` are sent as their preprocessed
+    /// copies, everything else (plate crops, reference thumbnails) as it is.
+    pub fn ai_backend(&self, image_paths: Vec<PathBuf>) -> LimitedBackend<AnalyzerBackend> {
+        self.sample_backend(image_paths, 0)
+    }
+
+    /// Like `ai_backend`, for one sample of an ensemble; recorded fixtures
+    /// are kept per sample so parallel samples do not overwrite each other.
+    pub fn sample_backend(
+        &self,
+        image_paths: Vec<PathBuf>,
+        sample: usize,
+    ) -> LimitedBackend<AnalyzerBackend> {
+        let upload_paths = image_paths
+            .iter()
+            .map(|path| self.prepared.get(path).unwrap_or(path).clone())
+            .collect();
+        LimitedBackend {
+            inner: AnalyzerBackend::new(
+                self.analyze_options(),
+                image_paths,
+                upload_paths,
+                self.fixture.as_ref(),
+                FixtureScope {
+                    backend: self.live_backend_name().to_string(),
+                    model: self.model.clone(),
+                    sample,
+                },
+            ),
+            limiter: ai::limiter::backend_limiter(self.backend_name(), self.max_concurrency),
+        }
+    }
+
+    /// Build the AiBackend for several photos of one load sent together.
+    ///
+    /// `extra_images` (e.g. reference thumbnails) are attached after the photos.
+    /// `sample` is the ensemble sample index.
+    pub fn multi_view_backend(
+        &self,
+        images: &[ViewImage],
+        extra_images: &[PathBuf],
+        sample: usize,
+    ) -> MultiViewBackend<LimitedBackend<AnalyzerBackend>> {
+        let paths = images
+            .iter()
+            .map(|i| i.path.clone())
+            .chain(extra_images.iter().cloned())
+            .collect();
+        MultiViewBackend {
+            inner: self.sample_backend(paths, sample),
+            views: images.iter().map(|i| i.view).collect(),
+        }
+    }
+}
+
+/// Send a prompt through the configured backend, mapping pipeline errors
+pub fn send_prompt(backend: &impl AiBackend, prompt: &str) -> Result<String> {
+    backend
+        .send_prompt(prompt, &[])
+        .map_err(|e| Error::AnalysisFailed(e.to_string()))
 }
 
 /// Analyze a single image and return estimation result.
@@ -77,29 +285,51 @@ impl AnalyzerConfig {
 pub fn analyze_image(image_path: &Path, config: &AnalyzerConfig) -> Result<EstimationResult> {
     let prompt = build_analysis_prompt();
 
-    let mut options = if let Some(ref model) = config.model {
-        AnalyzeOptions::with_model(model)
-    } else {
-        AnalyzeOptions::default()
-    };
-
-    options = options.with_backend(config.backend).json().with_usage_mode(config.usage_mode);
-
-    let response = analyze(&prompt, &[image_path.to_path_buf()], options)?;
+    let backend = config.ai_backend(vec![image_path.to_path_buf()]);
+    let response = send_prompt(&backend, &prompt)?;
 
-    parse_response(&response)
+    parse_estimation_with_repair(&response, |repair| send_prompt(&backend, repair))
 }
 
 /// Analyze a single image using the box-overlay pipeline (geometry + fill two-stage).
 ///
 /// This is the recommended analysis path, producing more accurate results than
 /// the legacy multi-param single-prompt approach.
+///
+/// Ensemble samples run as independent single-sample pipelines in parallel
+/// (bounded by `config.max_concurrency`) and are merged with `aggregator`;
+/// samples that fail are dropped.
 pub fn analyze_image_box_overlay(
     image_path: &Path,
     config: &AnalyzerConfig,
     truck_class: &str,
     material_type: &str,
     ensemble_count: usize,
+    aggregator: Aggregator,
+    progress: Option<ProgressCallback>,
+) -> Result<EstimationResult> {
+    analyze_images_box_overlay(
+        &[ViewImage::new(image_path, ImageView::Unspecified)],
+        config,
+        truck_class,
+        material_type,
+        ensemble_count,
+        aggregator,
+        progress,
+    )
+}
+
+/// Analyze several photos of one load together with the box-overlay pipeline.
+///
+/// All photos are attached to every request and each prompt names their
+/// views (joint fusion). Use `reconcile_views` for per-view fusion.
+pub fn analyze_images_box_overlay(
+    images: &[ViewImage],
+    config: &AnalyzerConfig,
+    truck_class: &str,
+    material_type: &str,
+    ensemble_count: usize,
+    aggregator: Aggregator,
     progress: Option<ProgressCallback>,
 ) -> Result<EstimationResult> {
     let notify = |msg: &str| {
@@ -110,46 +340,45 @@ pub fn analyze_image_box_overlay(
 
     notify("Box-overlay解析を準備中...");
 
-    let mut options = if let Some(ref model) = config.model {
-        AnalyzeOptions::with_model(model)
-    } else {
-        AnalyzeOptions::default()
-    };
-    options = options.with_backend(config.backend).json().with_usage_mode(config.usage_mode);
-
-    let backend = CliAiBackend {
-        options,
-        image_paths: vec![image_path.to_path_buf()],
-    };
-
+    let (core_class, bed_scale) = core_truck_class(truck_class);
     let pipeline_config = tonsuu_core::BoxOverlayConfig {
-        truck_class: truck_class.to_string(),
+        truck_class: core_class,
         material_type: material_type.to_string(),
-        ensemble_count,
+        ensemble_count: 1,
     };
 
-    notify("AI推論実行中...");
-
-    let result = tonsuu_core::analyze_box_overlay(&backend, &[], &pipeline_config)
-        .map_err(|e| Error::AnalysisFailed(e.to_string()))?;
-
-    notify("結果を変換中...");
-
-    // Convert pipeline result to EstimationResult for backward compatibility
-    let mut estimation = EstimationResult::default();
-    estimation.truck_type = truck_class.to_string();
-    estimation.material_type = result.material_type.clone();
-    estimation.height = Some(result.height_m);
-    estimation.fill_ratio_l = Some(result.fill_ratio_l);
-    estimation.fill_ratio_w = Some(result.fill_ratio_w);
-    estimation.packing_density = Some(result.packing_density);
-    estimation.estimated_volume_m3 = result.volume;
-    estimation.estimated_tonnage = result.tonnage;
-    let success_rate = result.geometry_runs.iter().filter(|r| r.parsed.is_some()).count() as f64
-        / result.geometry_runs.len().max(1) as f64;
-    estimation.confidence_score = 0.6 + 0.3 * success_rate; // 0.6~0.9 based on ensemble success
-    estimation.reasoning = result.reasoning;
-    estimation.ensemble_count = Some(ensemble_count as u32);
+    notify(&format!("AI推論実行中... ({}件並列)", ensemble_count.max(1)));
+
+    let estimation = run_ensemble(ensemble_count, aggregator, |sample| {
+        let backend = config.multi_view_backend(images, &[], sample);
+        let result = tonsuu_core::analyze_box_overlay(&backend, &[], &pipeline_config)
+            .map_err(|e| Error::AnalysisFailed(e.to_string()))?;
+
+        // Convert pipeline result to EstimationResult for backward compatibility
+        let mut estimation = EstimationResult::default();
+        estimation.truck_type = truck_class.to_string();
+        estimation.material_type = result.material_type.clone();
+        estimation.height = Some(result.height_m);
+        estimation.fill_ratio_l = Some(result.fill_ratio_l);
+        estimation.fill_ratio_w = Some(result.fill_ratio_w);
+        estimation.packing_density = Some(result.packing_density);
+        estimation.estimated_volume_m3 = result.volume * bed_scale;
+        estimation.estimated_tonnage = result.tonnage * bed_scale;
+        let parsed_runs = result
+            .geometry_runs
+            .iter()
+            .filter(|r| r.parsed.is_some())
+            .count();
+        let success_rate = parsed_runs as f64 / result.geometry_runs.len().max(1) as f64;
+        // 0.6~0.9 based on geometry parse success
+        estimation.confidence_score = 0.6 + 0.3 * success_rate;
+        estimation.reasoning = result.reasoning;
+        estimation.ensemble_count = Some(1);
+
+        Ok(estimation)
+    })?;
+
+    notify("結果を統合中...");
 
     Ok(estimation)
 }
@@ -162,7 +391,13 @@ pub struct StagedAnalysisOptions {
     pub ensemble_count: u32,
     pub truck_type_hint: Option<String>,
     pub material_type: Option<String>,
+    /// Material names the AI must choose from (empty = no restriction)
+    pub material_choices: Vec<String>,
     pub karte_json: Option<String>,
+    /// How ensemble samples are combined
+    pub aggregator: Aggregator,
+    /// Max graded reference thumbnails attached to the prompt (0 = none)
+    pub max_reference_images: usize,
 }
 
 impl Default for StagedAnalysisOptions {
@@ -172,7 +407,10 @@ impl Default for StagedAnalysisOptions {
             ensemble_count: 1,
             truck_type_hint: None,
             material_type: None,
+            material_choices: Vec::new(),
             karte_json: None,
+            aggregator: Aggregator::default(),
+            max_reference_images: 0,
         }
     }
 }
@@ -202,11 +440,29 @@ impl StagedAnalysisOptions {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn with_material_choices(mut self, choices: Vec<String>) -> Self {
+        self.material_choices = choices;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_karte_json(mut self, karte_json: String) -> Self {
         self.karte_json = Some(karte_json);
         self
     }
+
+    #[allow(dead_code)]
+    pub fn with_aggregator(mut self, aggregator: Aggregator) -> Self {
+        self.aggregator = aggregator;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_reference_images(mut self, max: usize) -> Self {
+        self.max_reference_images = max;
+        self
+    }
 }
 
 /// Staged analysis progress callback
@@ -219,6 +475,28 @@ pub fn analyze_image_staged(
     options: &StagedAnalysisOptions,
     store: &Store,
     progress: Option<ProgressCallback>,
+) -> Result<EstimationResult> {
+    analyze_images_staged(
+        &[ViewImage::new(image_path, ImageView::Unspecified)],
+        config,
+        options,
+        store,
+        progress,
+    )
+}
+
+/// Analyze several photos of one load in one request (joint fusion) using
+/// the staged approach.
+///
+/// With `options.max_reference_images` > 0, thumbnails of graded history
+/// entries are attached as extra images and their history hashes are recorded
+/// in `reference_hashes` of the result.
+pub fn analyze_images_staged(
+    images: &[ViewImage],
+    config: &AnalyzerConfig,
+    options: &StagedAnalysisOptions,
+    store: &Store,
+    progress: Option<ProgressCallback>,
 ) -> Result<EstimationResult> {
     let notify = |msg: &str| {
         if let Some(ref cb) = progress {
@@ -227,64 +505,84 @@ pub fn analyze_image_staged(
     };
 
     let mut graded_stock: Vec<GradedHistoryEntry> = Vec::new();
-    let mut _detected_class = TruckClass::Unknown;
-    let mut results: Vec<EstimationResult> = Vec::new();
     let target_count = options.ensemble_count.max(1) as usize;
 
-    if let Some(truck_class) = options.truck_class {
-        _detected_class = truck_class;
-        if _detected_class != TruckClass::Unknown {
-            notify(&format!("{}クラスの実測データを取得中...", _detected_class.label()));
-            graded_stock = store.select_stock_by_grade(_detected_class);
-            if !graded_stock.is_empty() {
-                notify(&format!("実測データ {}件を参照", graded_stock.len()));
-            }
+    if let Some(truck_class) = options.truck_class.as_ref().filter(|tc| !tc.is_unknown()) {
+        notify(&format!("{}クラスの実測データを取得中...", truck_class.label()));
+        graded_stock = store.select_stock_by_grade(truck_class, &load_hashes(images));
+        if !graded_stock.is_empty() {
+            notify(&format!("実測データ {}件を参照", graded_stock.len()));
         }
     }
 
-    for iteration in 0..target_count {
-        notify(&format!("推論 {}/{} 実行中...", iteration + 1, target_count));
-
-        let prompt = if let Some(karte_json) = &options.karte_json {
-            build_karte_prompt(karte_json)
-                .map_err(|e| Error::AnalysisFailed(format!("Invalid karte JSON: {}", e)))?
-        } else if let (Some(truck_type), Some(material_type)) = (&options.truck_type_hint, &options.material_type) {
-            build_estimation_prompt(truck_type, material_type)
-        } else if !graded_stock.is_empty() {
-            let references: Vec<GradedReferenceItem> = graded_stock
-                .iter()
-                .map(|g| GradedReferenceItem {
-                    grade_name: g.grade.label().to_string(),
-                    actual_tonnage: g.entry.actual_tonnage.unwrap_or(0.0),
-                    max_capacity: g.entry.max_capacity.unwrap_or(0.0),
-                    load_ratio: g.load_ratio,
-                    memo: g.entry.notes.clone(),
-                })
-                .collect();
-            build_staged_analysis_prompt(None, &references)
-        } else {
-            build_staged_analysis_prompt(None, &[])
-        };
+    let prompt = if let Some(karte_json) = &options.karte_json {
+        build_karte_prompt(karte_json)
+            .map_err(|e| Error::AnalysisFailed(format!("Invalid karte JSON: {}", e)))?
+    } else if let (Some(truck_type), Some(material_type)) =
+        (&options.truck_type_hint, &options.material_type)
+    {
+        build_estimation_prompt(truck_type, material_type)
+    } else if !graded_stock.is_empty() {
+        let references: Vec<GradedReferenceItem> = graded_stock
+            .iter()
+            .map(|g| GradedReferenceItem {
+                grade_name: g.grade.label().to_string(),
+                actual_tonnage: g.entry.actual_tonnage.unwrap_or(0.0),
+                max_capacity: g.entry.max_capacity.unwrap_or(0.0),
+                load_ratio: g.load_ratio / 100.0,
+                memo: g.entry.notes.clone(),
+            })
+            .collect();
+        build_staged_analysis_prompt(None, &references)
+    } else {
+        build_staged_analysis_prompt(None, &[])
+    };
 
-        let mut ai_options = if let Some(ref model) = config.model {
-            AnalyzeOptions::with_model(model)
-        } else {
-            AnalyzeOptions::default()
-        };
-        ai_options = ai_options.with_backend(config.backend).json().with_usage_mode(config.usage_mode);
+    let prompt = with_truck_classes_note(
+        &with_material_choices_note(&prompt, &options.material_choices),
+        truck_classes(),
+    );
 
-        let response = analyze(&prompt, &[image_path.to_path_buf()], ai_options)?;
-        let result = parse_response(&response)?;
+    // Reference thumbnails are attached after the photos being analyzed
+    let references = if options.max_reference_images > 0 && !graded_stock.is_empty() {
+        prepare_reference_images(&graded_stock, options.max_reference_images)?
+    } else {
+        ReferenceImages::default()
+    };
+    let prompt = if references.is_empty() {
+        prompt
+    } else {
+        notify(&format!("参照画像 {}枚を添付", references.images.len()));
+        with_reference_images_note(&prompt, images.len(), &references.items())
+    };
+    let reference_paths = references.paths();
 
-        results.push(result);
-    }
+    notify(&format!("推論 {}件を並列実行中...", target_count));
 
-    if results.is_empty() {
-        return Err(Error::AnalysisFailed("All inference attempts failed".to_string()));
-    }
+    // Samples run concurrently; a sample whose response stays invalid after
+    // the repair re-ask is dropped instead of failing the whole analysis
+    let mut merged = run_ensemble(target_count, options.aggregator, |sample| {
+        let backend = config.multi_view_backend(images, &reference_paths, sample);
+        let response = send_prompt(&backend, &prompt)?;
+        parse_estimation_with_repair(&response, |repair| send_prompt(&backend, repair))
+    })?;
+    merged.reference_hashes = references.hashes();
 
     notify("結果を統合中...");
-    Ok(merge_results(&results))
+    Ok(merged)
+}
+
+/// History keys of the load being analyzed: each photo's hash and, for
+/// several photos, their combined hash. Photos that cannot be read are skipped.
+fn load_hashes(images: &[ViewImage]) -> Vec<String> {
+    let mut hashes: Vec<String> = images
+        .iter()
+        .filter_map(|image| Store::hash_image(&image.path).ok())
+        .collect();
+    if hashes.len() > 1 {
+        hashes.push(Store::combined_hash(&hashes));
+    }
+    hashes
 }
 
 /// Analyze with staged approach (ensemble version)
@@ -298,30 +596,6 @@ pub fn analyze_image_staged_ensemble(
     analyze_image_staged(image_path, config, options, store, None)
 }
 
-/// Parse AI response into EstimationResult
-fn parse_response(response: &str) -> Result<EstimationResult> {
-    let json_str = extract_json_from_response(response);
-
-    let mut result: EstimationResult = match serde_json::from_str(&json_str) {
-        Ok(parsed) => parsed,
-        Err(e) => {
-            let truncated: String = response.chars().take(500).collect();
-            let mut fallback = EstimationResult::default();
-            fallback.reasoning = format!(
-                "[parse_error] {} | raw: {}",
-                e, truncated
-            );
-            return Ok(fallback);
-        }
-    };
-
-    if result.estimated_volume_m3 == 0.0 || result.estimated_tonnage == 0.0 {
-        calculate_volume_and_tonnage(&mut result);
-    }
-
-    Ok(result)
-}
-
 /// Calculate volume and tonnage from estimated parameters using shared-core.
 ///
 /// Maps multi-param AI output (fillRatioL/W/Z) to box-overlay CoreParams.
@@ -354,100 +628,34 @@ pub fn calculate_volume_and_tonnage(result: &mut EstimationResult) {
             .unwrap_or("")
             .trim()
             .to_string();
-        if cls.is_empty() { None } else { Some(cls) }
+        if cls.is_empty() { None } else { Some(core_truck_class(&cls)) }
     };
 
-    let calc = tonsuu_core::calculate_tonnage(&params, truck_class.as_deref());
-    result.estimated_volume_m3 = calc.volume;
-    result.estimated_tonnage = calc.tonnage;
-}
-
-/// Extract JSON from response (handles markdown code blocks)
-pub fn extract_json_from_response(response: &str) -> String {
-    let response = response.trim();
-
-    if response.starts_with("```json") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(7) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    if response.starts_with("```") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(3) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
-
-    if let Some(start) = response.find('{') {
-        if let Some(end) = response.rfind('}') {
-            if start < end {
-                return response[start..=end].to_string();
-            }
-        }
-    }
-
-    response.to_string()
-}
-
-
-/// Merge multiple estimation results (ensemble voting)
-fn merge_results(results: &[EstimationResult]) -> EstimationResult {
-    if results.is_empty() {
-        return EstimationResult::default();
-    }
-
-    if results.len() == 1 {
-        return results[0].clone();
-    }
-
-    let avg_volume: f64 = results.iter().map(|r| r.estimated_volume_m3).sum::<f64>()
-        / results.len() as f64;
-    let avg_tonnage: f64 =
-        results.iter().map(|r| r.estimated_tonnage).sum::<f64>() / results.len() as f64;
-    let avg_confidence: f64 =
-        results.iter().map(|r| r.confidence_score).sum::<f64>() / results.len() as f64;
-
-    let truck_type = mode_string(results.iter().map(|r| r.truck_type.clone()).collect());
-    let material_type = mode_string(results.iter().map(|r| r.material_type.clone()).collect());
-
-    let mut merged = results[0].clone();
-    merged.truck_type = truck_type;
-    merged.material_type = material_type;
-    merged.estimated_volume_m3 = avg_volume;
-    merged.estimated_tonnage = avg_tonnage;
-    merged.confidence_score = avg_confidence;
-    merged.ensemble_count = Some(results.len() as u32);
-    merged.reasoning = format!(
-        "Ensemble average of {} samples. {}",
-        results.len(),
-        merged.reasoning
+    let calc = tonsuu_core::calculate_tonnage(
+        &params,
+        truck_class.as_ref().map(|(core_class, _)| core_class.as_str()),
     );
-
-    merged
+    let bed_scale = truck_class.map_or(1.0, |(_, scale)| scale);
+    result.estimated_volume_m3 = calc.volume * bed_scale;
+    result.estimated_tonnage = calc.tonnage * bed_scale;
 }
 
-/// Get mode (most common) of strings
-fn mode_string(values: Vec<String>) -> String {
-    use std::collections::HashMap;
-
-    let mut counts: HashMap<String, usize> = HashMap::new();
-    for v in values.iter() {
-        *counts.entry(v.clone()).or_insert(0) += 1;
-    }
-
-    counts
-        .into_iter()
-        .max_by_key(|(_, count)| *count)
-        .map(|(value, _)| value)
-        .unwrap_or_default()
+/// Class tonsuu-core computes `truck_type` as, and the factor from that
+/// class's bed to the bed of `truck_type`.
+///
+/// Classes without a core spec (3t, 8t, 11t+) are analyzed on the bed of
+/// their `core_class`; multiplying the volume and tonnage by the factor puts
+/// the estimate on the class's own bed. Aliases are resolved first; unknown
+/// names are passed through unscaled.
+pub fn core_truck_class(truck_type: &str) -> (String, f64) {
+    let classes = truck_classes();
+    let id = classes
+        .resolve(truck_type)
+        .map_or_else(|| truck_type.to_string(), |c| c.label().to_string());
+    (classes.core_class(&id).to_string(), classes.core_bed_scale(&id))
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -469,4 +677,100 @@ mod tests {
         let response = "Here is the result: {\"test\": 123} end";
         assert_eq!(extract_json_from_response(response), "{\"test\": 123}");
     }
+
+    #[test]
+    fn test_with_backend_fixture_modes() {
+        let config = AnalyzerConfig::default().with_backend("replay:fixtures/ai");
+        assert_eq!(config.fixture, Some(FixtureMode::Replay(PathBuf::from("fixtures/ai"))));
+        assert_eq!(config.backend_name(), "replay");
+
+        let config = AnalyzerConfig::default().with_backend("replay:codex:fixtures/ai");
+        assert!(matches!(config.backend, Backend::Codex));
+        assert_eq!(config.fixture, Some(FixtureMode::Replay(PathBuf::from("fixtures/ai"))));
+
+        let config = AnalyzerConfig::default().with_backend("record:claude:fixtures/ai");
+        assert!(matches!(config.backend, Backend::Claude));
+        assert_eq!(config.fixture, Some(FixtureMode::Record(PathBuf::from("fixtures/ai"))));
+
+        let config = AnalyzerConfig::default().with_backend("record:C:\\fixtures");
+        assert_eq!(config.fixture, Some(FixtureMode::Record(PathBuf::from("C:\\fixtures"))));
+
+        let config = AnalyzerConfig::default().with_backend("codex");
+        assert!(matches!(config.backend, Backend::Codex));
+        assert!(config.fixture.is_none());
+    }
+
+    #[test]
+    fn test_prepared_images() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let photo = dir.join("truck.png");
+        image::RgbImage::new(40, 30).save(&photo).unwrap();
+        let missing = dir.join("missing.jpg");
+        let paths = [photo.clone(), missing.clone()];
+
+        let preprocessed = AnalyzerConfig::default()
+            .with_preprocessing(ImagePreprocessor::new(dir.join("preprocessed")));
+        let config = preprocessed.clone().with_prepared_images(&paths);
+        assert_eq!(config.prepared.len(), 1);
+        assert!(config.prepared[&photo].starts_with(dir.join("preprocessed")));
+        assert!(!config.prepared.contains_key(&missing));
+        assert!(config.preprocessing_for(&[photo.clone()]).is_some());
+        assert!(config.preprocessing_for(&paths).is_none());
+
+        // Replay uploads nothing, so nothing is processed
+        let replay = preprocessed
+            .with_backend("replay:fixtures/ai")
+            .with_prepared_images(&paths);
+        assert!(replay.prepared.is_empty());
+        assert!(replay.preprocessing_for(&[photo]).is_none());
+    }
+
+    #[test]
+    fn test_load_hashes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let rear = tmp.path().join("rear.jpg");
+        let side = tmp.path().join("side.jpg");
+        std::fs::write(&rear, b"rear view").unwrap();
+        std::fs::write(&side, b"side view").unwrap();
+
+        let single = load_hashes(&[ViewImage::new(&rear, ImageView::Rear)]);
+        assert_eq!(single, vec![Store::hash_image(&rear).unwrap()]);
+
+        let both = load_hashes(&[
+            ViewImage::new(&rear, ImageView::Rear),
+            ViewImage::new(&side, ImageView::Side),
+        ]);
+        assert_eq!(both.len(), 3);
+        assert_eq!(both[2], Store::combined_hash(&both[..2]));
+    }
+
+    #[test]
+    fn test_class_without_core_spec_uses_own_bed() {
+        let estimate = |truck_type: &str| {
+            let mut result = EstimationResult {
+                truck_type: truck_type.to_string(),
+                material_type: "土砂".to_string(),
+                height: Some(0.4),
+                fill_ratio_l: Some(0.8),
+                fill_ratio_w: Some(0.6),
+                packing_density: Some(0.8),
+                ..Default::default()
+            };
+            calculate_volume_and_tonnage(&mut result);
+            result
+        };
+        let ten = estimate("10t");
+        let trailer = estimate("11t+");
+        assert!(ten.estimated_tonnage > 0.0);
+        assert!(trailer.estimated_tonnage > ten.estimated_tonnage);
+        let scale = trailer.estimated_volume_m3 / ten.estimated_volume_m3;
+        assert!((scale - 7.5 / 5.3).abs() < 1e-6);
+        // Aliases are scaled like the class id
+        let alias = estimate("トレーラー");
+        assert!((alias.estimated_tonnage - trailer.estimated_tonnage).abs() < 1e-9);
+
+        assert_eq!(core_truck_class("11t+").0, "10t");
+        assert_eq!(core_truck_class("4t"), ("4t".to_string(), 1.0));
+    }
 }
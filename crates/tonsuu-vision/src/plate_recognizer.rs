@@ -1,10 +1,14 @@
-//! Local license plate detection using YOLO (detection only, no OCR).
+//! License plate detection and reading.
+//!
+//! Local YOLO detection only locates the plate (no OCR); the crop, or the
+//! whole image when local detection is unavailable, is read by API OCR.
 
-use tonsuu_types::Result;
-use crate::extract_json_from_response;
-use serde::Deserialize;
+use tonsuu_types::{LicensePlate, PlateSource, Result};
+use crate::{extract_json_from_response, send_prompt, AnalyzerConfig};
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Instant;
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
@@ -26,7 +30,6 @@ pub struct PlateDetectionConfig {
 
 /// Detect license plate using YOLO and return cropped image path.
 /// Returns Ok(Some(crop_path)) on success, Ok(None) on failure or disabled.
-#[allow(dead_code)]
 pub fn detect_plate_yolo(
     image_path: &Path,
     config: &PlateDetectionConfig,
@@ -136,7 +139,201 @@ pub fn detect_plate_yolo(
 }
 
 /// Clean up temporary crop file
-#[allow(dead_code)]
 pub fn cleanup_crop(crop_path: &Path) {
     let _ = std::fs::remove_file(crop_path);
 }
+
+/// Result from API plate OCR
+#[derive(Debug, Deserialize)]
+struct PlateOcrResult {
+    plate: Option<String>,
+    confidence: Option<f32>,
+}
+
+/// A license plate reading and where it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlateReading {
+    pub plate: String,
+    pub source: PlateSource,
+    /// None for manual entry
+    pub confidence: Option<f32>,
+}
+
+/// Outcome of automatic plate detection, with per-stage timings
+#[derive(Debug, Clone, Default)]
+pub struct PlateDetectionOutcome {
+    pub reading: Option<PlateReading>,
+    pub yolo_ms: Option<u64>,
+    pub api_ms: Option<u64>,
+    /// An OCR call errored, so "no reading" may be transient
+    pub failed: bool,
+}
+
+/// Build a simple OCR prompt for a plate image (crop or full frame)
+pub fn build_plate_ocr_prompt(registered_plates: &[String]) -> String {
+    let mut prompt = String::from(
+r#"この画像は日本の自動車ナンバープレートです。プレートに書かれている文字を正確に読み取ってください。
+
+【読み取り手順】
+1. 地名（例: 熊本、福岡、東京）
+2. 分類番号3桁（例: 130, 101, 500）
+3. ひらがな1文字（例: ら, あ, さ）
+4. 一連番号4桁（例: 1122, 5678）← ハイフンがある場合は除去して4桁で
+
+【重要】
+- 見えた文字のみを記載すること
+- 推測・創作は禁止
+- 読み取れない部分は「?」で表記
+
+"#);
+
+    // Add registered vehicles for matching hint
+    if !registered_plates.is_empty() {
+        prompt.push_str("【登録車両リスト（参考）】以下のナンバーが登録されています:\n");
+        for plate in registered_plates {
+            prompt.push_str(&format!("- {}\n", plate));
+        }
+        prompt.push_str("\n読み取った結果がリストにあればそのまま返す。なければ読み取った通りに返す。\n\n");
+    }
+
+    prompt.push_str(r#"以下のJSON形式で回答:
+{"plate": "読み取ったナンバー全体", "confidence": 0.0-1.0}
+
+読み取れない場合: {"plate": null, "confidence": 0.0}"#);
+
+    prompt
+}
+
+/// Parse the OCR response; unreadable plates (null / only "?") yield None
+fn parse_plate_ocr_response(response: &str) -> Option<(String, f32)> {
+    let json_str = extract_json_from_response(response);
+    let parsed: PlateOcrResult = serde_json::from_str(&json_str).ok()?;
+    let plate = parsed.plate?.trim().to_string();
+    if plate.chars().all(|c| c == '?' || c == '？' || c.is_whitespace()) {
+        return None;
+    }
+    // Canonical spelling when fully read; partial reads ("?") are kept as-is
+    let plate = if plate.contains(['?', '？']) {
+        plate
+    } else {
+        LicensePlate::parse(&plate).map_or(plate, |p| p.to_string())
+    };
+    Some((plate, parsed.confidence.unwrap_or(0.0).clamp(0.0, 1.0)))
+}
+
+/// Read the plate text in `image_path` via the configured AI backend
+pub fn read_plate_api(
+    image_path: &Path,
+    config: &AnalyzerConfig,
+    registered_plates: &[String],
+) -> Result<Option<(String, f32)>> {
+    let backend = config.ai_backend(vec![image_path.to_path_buf()]);
+    let response = send_prompt(&backend, &build_plate_ocr_prompt(registered_plates))?;
+    Ok(parse_plate_ocr_response(&response))
+}
+
+/// Detect and read the license plate.
+///
+/// Runs local YOLO detection when enabled and reads the crop with API OCR.
+/// If that yields nothing and `fallback_api` is set, the whole image is read
+/// by API OCR instead. OCR failures are reported (verbose) but not fatal.
+pub fn detect_plate(
+    image_path: &Path,
+    detection: &PlateDetectionConfig,
+    fallback_api: bool,
+    analyzer: &AnalyzerConfig,
+    registered_plates: &[String],
+    verbose: bool,
+) -> PlateDetectionOutcome {
+    let mut outcome = PlateDetectionOutcome::default();
+
+    if detection.enabled {
+        let start = Instant::now();
+        let detected = detect_plate_yolo(image_path, detection, verbose);
+        outcome.yolo_ms = Some(start.elapsed().as_millis() as u64);
+
+        if let Ok(Some((crop_path, det_conf))) = detected {
+            let start = Instant::now();
+            let read = read_plate_api(&crop_path, analyzer, registered_plates);
+            outcome.api_ms = Some(start.elapsed().as_millis() as u64);
+            cleanup_crop(&crop_path);
+
+            match read {
+                Ok(Some((plate, ocr_conf))) => {
+                    outcome.reading = Some(PlateReading {
+                        plate,
+                        source: PlateSource::Yolo,
+                        confidence: Some(det_conf.min(ocr_conf)),
+                    });
+                    return outcome;
+                }
+                Ok(None) => {
+                    if verbose {
+                        eprintln!("Plate OCR: crop unreadable");
+                    }
+                }
+                Err(e) => {
+                    outcome.failed = true;
+                    if verbose {
+                        eprintln!("Plate OCR failed: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    if !fallback_api {
+        return outcome;
+    }
+
+    let start = Instant::now();
+    let read = read_plate_api(image_path, analyzer, registered_plates);
+    outcome.api_ms = Some(outcome.api_ms.unwrap_or(0) + start.elapsed().as_millis() as u64);
+
+    match read {
+        Ok(Some((plate, conf))) => {
+            outcome.reading = Some(PlateReading {
+                plate,
+                source: PlateSource::Api,
+                confidence: Some(conf),
+            });
+        }
+        Ok(None) => {
+            if verbose {
+                eprintln!("Plate OCR: no readable plate");
+            }
+        }
+        Err(e) => {
+            outcome.failed = true;
+            if verbose {
+                eprintln!("Plate OCR failed: {}", e);
+            }
+        }
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plate_ocr_response() {
+        let parsed = parse_plate_ocr_response(r#"{"plate": "熊本 130 ら 1122", "confidence": 0.9}"#);
+        assert_eq!(parsed, Some(("熊本 130 ら 11-22".to_string(), 0.9)));
+        let parsed = parse_plate_ocr_response(r#"{"plate": "熊本　１３０　ら　１１２２", "confidence": 0.9}"#);
+        assert_eq!(parsed, Some(("熊本 130 ら 11-22".to_string(), 0.9)));
+
+        assert_eq!(parse_plate_ocr_response(r#"{"plate": null, "confidence": 0.0}"#), None);
+        assert_eq!(parse_plate_ocr_response(r#"{"plate": "????", "confidence": 0.1}"#), None);
+        assert_eq!(parse_plate_ocr_response("not json"), None);
+    }
+
+    #[test]
+    fn test_ocr_prompt_lists_registered_plates() {
+        let prompt = build_plate_ocr_prompt(&["熊本 130 ら 1122".to_string()]);
+        assert!(prompt.contains("- 熊本 130 ら 1122"));
+        assert!(!build_plate_ocr_prompt(&[]).contains("登録車両リスト"));
+    }
+}
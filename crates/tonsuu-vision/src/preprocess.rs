@@ -0,0 +1,205 @@
+//! Photo preprocessing before upload
+//!
+//! Phone photos are often 4000×3000 and several MB, and rotated shots only
+//! carry their orientation in EXIF, which the backends do not apply. Before a
+//! photo is sent it is turned upright, downsampled to a maximum long edge and
+//! re-encoded as JPEG, which also drops all metadata.
+//!
+//! Processed files are cached by the SHA256 of the original bytes and the
+//! settings (`<hash>-<edge>-q<quality>.jpg`), so each photo is processed once.
+//! Analysis cache, history and fixture keys keep hashing the original file.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use exif::{In, Tag};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use sha2::{Digest, Sha256};
+use tonsuu_types::{ImagePreprocessing, Result};
+
+/// Default longest edge of uploaded photos (px)
+pub const DEFAULT_MAX_LONG_EDGE: u32 = 1600;
+
+/// Default JPEG quality of uploaded photos
+pub const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+/// Preprocessing settings and the directory the processed files go to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImagePreprocessor {
+    /// Longest edge after downsampling (px); smaller photos are not enlarged
+    pub max_long_edge: u32,
+    /// JPEG quality (1-100)
+    pub jpeg_quality: u8,
+    pub cache_dir: PathBuf,
+}
+
+impl ImagePreprocessor {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self {
+            max_long_edge: DEFAULT_MAX_LONG_EDGE,
+            jpeg_quality: DEFAULT_JPEG_QUALITY,
+            cache_dir,
+        }
+    }
+
+    pub fn with_max_long_edge(mut self, max_long_edge: u32) -> Self {
+        self.max_long_edge = max_long_edge.max(1);
+        self
+    }
+
+    pub fn with_jpeg_quality(mut self, jpeg_quality: u8) -> Self {
+        self.jpeg_quality = jpeg_quality.clamp(1, 100);
+        self
+    }
+
+    /// Settings as recorded in the estimation result
+    pub fn info(&self) -> ImagePreprocessing {
+        ImagePreprocessing {
+            max_long_edge: self.max_long_edge,
+            jpeg_quality: self.jpeg_quality,
+        }
+    }
+
+    /// Path of the preprocessed copy of a photo, created on first use
+    pub fn prepare(&self, image_path: &Path) -> Result<PathBuf> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let bytes = fs::read(image_path)?;
+        let hash = format!("{:x}", Sha256::digest(&bytes));
+        let output = self.cache_dir.join(format!(
+            "{}-{}-q{}.jpg",
+            hash, self.max_long_edge, self.jpeg_quality
+        ));
+        if output.exists() {
+            return Ok(output);
+        }
+
+        let image = image::load_from_memory(&bytes)?;
+        let image = apply_orientation(image, exif_orientation(&bytes));
+        let image = if image.width().max(image.height()) > self.max_long_edge {
+            image.resize(self.max_long_edge, self.max_long_edge, FilterType::Lanczos3)
+        } else {
+            image
+        };
+
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, self.jpeg_quality).encode_image(&image.to_rgb8())?;
+
+        // Written under a unique temporary name so a concurrent reader never
+        // sees a partial file and workers processing identical photos do not
+        // share one
+        fs::create_dir_all(&self.cache_dir)?;
+        let partial = output.with_extension(format!(
+            "{}.{}.part",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&partial, &encoded)?;
+        if let Err(e) = fs::rename(&partial, &output) {
+            let _ = fs::remove_file(&partial);
+            // Another worker put the same content in place first
+            if !output.exists() {
+                return Err(e.into());
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// EXIF orientation tag (1 = upright, also when there is no EXIF)
+fn exif_orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| exif.get_field(Tag::Orientation, In::PRIMARY)?.value.get_uint(0))
+        .unwrap_or(1)
+}
+
+/// Turn an image upright according to its EXIF orientation
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn test_orientation_transforms() {
+        // 3x2 image with a marked top-left pixel
+        let mut source = RgbImage::new(3, 2);
+        source.put_pixel(0, 0, Rgb([255, 0, 0]));
+        let source = DynamicImage::ImageRgb8(source);
+
+        let rotated = apply_orientation(source.clone(), 6);
+        assert_eq!(rotated.dimensions(), (2, 3));
+        assert_eq!(rotated.get_pixel(1, 0).0[0], 255);
+
+        let transposed = apply_orientation(source.clone(), 5);
+        assert_eq!(transposed.get_pixel(0, 0).0[0], 255);
+        assert_eq!(apply_orientation(source, 1).get_pixel(0, 0).0[0], 255);
+    }
+
+    #[test]
+    fn test_prepare_downsamples_and_caches() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let photo = dir.join("truck.png");
+        RgbImage::new(400, 300).save(&photo).unwrap();
+
+        let preprocessor = ImagePreprocessor::new(dir.join("preprocessed"))
+            .with_max_long_edge(200)
+            .with_jpeg_quality(70);
+        let output = preprocessor.prepare(&photo).unwrap();
+        assert!(output.extension().is_some_and(|e| e == "jpg"));
+        assert_eq!(image::open(&output).unwrap().dimensions(), (200, 150));
+        assert_eq!(preprocessor.prepare(&photo).unwrap(), output);
+
+        assert!(preprocessor.prepare(&dir.join("missing.jpg")).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_prepare_of_identical_photos() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        // Byte-identical photos map to the same output file
+        let photos: Vec<PathBuf> = (0..4)
+            .map(|i| {
+                let photo = dir.join(format!("truck-{}.png", i));
+                RgbImage::new(400, 300).save(&photo).unwrap();
+                photo
+            })
+            .collect();
+        let preprocessor = ImagePreprocessor::new(dir.join("preprocessed")).with_max_long_edge(200);
+
+        let outputs: Vec<PathBuf> = std::thread::scope(|scope| {
+            let handles: Vec<_> = photos
+                .iter()
+                .map(|photo| scope.spawn(|| preprocessor.prepare(photo).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+
+        let leftovers = fs::read_dir(dir.join("preprocessed"))
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".part"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
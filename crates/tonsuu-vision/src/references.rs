@@ -0,0 +1,227 @@
+//! Graded reference thumbnails attached to the prompt as few-shot examples
+//!
+//! `Store::select_stock_by_grade` picks one weighed load per grade for the
+//! truck class, never the load being analyzed itself. Their stored thumbnails are decoded into a temporary directory
+//! so the backend can attach them after the photos being analyzed; the
+//! directory is removed when the `ReferenceImages` value is dropped.
+//! `encode_thumbnail` makes the thumbnail stored with each new history entry.
+
+use crate::ai::prompts::GradedReferenceItem;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::codecs::jpeg::JpegEncoder;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tonsuu_store::GradedHistoryEntry;
+use tonsuu_types::Result;
+
+/// Default cap on reference images per request
+pub const DEFAULT_MAX_REFERENCE_IMAGES: usize = 3;
+
+/// Longest edge of the history thumbnails made by `encode_thumbnail` (px)
+pub const THUMBNAIL_LONG_EDGE: u32 = 512;
+
+/// Downscaled JPEG of a photo, base64-encoded for `HistoryEntry::thumbnail_base64`.
+///
+/// None if the photo cannot be read.
+pub fn encode_thumbnail(image_path: &Path) -> Option<String> {
+    let image = image::open(image_path).ok()?;
+    let thumbnail = image.thumbnail(THUMBNAIL_LONG_EDGE, THUMBNAIL_LONG_EDGE);
+    let mut encoded = Vec::new();
+    JpegEncoder::new_with_quality(&mut encoded, 80)
+        .encode_image(&thumbnail.to_rgb8())
+        .ok()?;
+    Some(STANDARD.encode(encoded))
+}
+
+/// One reference thumbnail written to disk
+#[derive(Debug, Clone)]
+pub struct ReferenceImage {
+    /// History key (image hash) of the reference entry
+    pub hash: String,
+    pub path: PathBuf,
+    pub item: GradedReferenceItem,
+}
+
+/// Reference thumbnails for one analysis; the files are deleted on drop
+#[derive(Debug, Default)]
+pub struct ReferenceImages {
+    dir: Option<PathBuf>,
+    pub images: Vec<ReferenceImage>,
+}
+
+impl ReferenceImages {
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.images.iter().map(|r| r.path.clone()).collect()
+    }
+
+    pub fn hashes(&self) -> Vec<String> {
+        self.images.iter().map(|r| r.hash.clone()).collect()
+    }
+
+    pub fn items(&self) -> Vec<GradedReferenceItem> {
+        self.images.iter().map(|r| r.item.clone()).collect()
+    }
+}
+
+impl Drop for ReferenceImages {
+    fn drop(&mut self) {
+        if let Some(ref dir) = self.dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// File extension for image bytes, from their magic number
+fn image_extension(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        "png"
+    } else if bytes.starts_with(b"GIF8") {
+        "gif"
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "webp"
+    } else {
+        "jpg"
+    }
+}
+
+/// Pick up to `max` items spread evenly over `items` (which are ordered by grade)
+fn spread_pick<T>(items: Vec<T>, max: usize) -> Vec<T> {
+    let len = items.len();
+    if len <= max {
+        return items;
+    }
+    if max <= 1 {
+        return items.into_iter().skip(len / 2).take(max).collect();
+    }
+    let picked: Vec<usize> = (0..max).map(|i| i * (len - 1) / (max - 1)).collect();
+    items
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| picked.contains(i))
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// Decode the thumbnails of graded entries into a temporary directory.
+///
+/// Entries without a (decodable) thumbnail are skipped. At most `max` images
+/// are kept, spread across the grades.
+pub fn prepare_reference_images(
+    graded: &[GradedHistoryEntry],
+    max: usize,
+) -> Result<ReferenceImages> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let decoded: Vec<(&GradedHistoryEntry, Vec<u8>)> = graded
+        .iter()
+        .filter_map(|g| {
+            let thumbnail = g.entry.thumbnail_base64.as_deref()?;
+            let bytes = STANDARD.decode(thumbnail.trim()).ok()?;
+            (!bytes.is_empty()).then_some((g, bytes))
+        })
+        .collect();
+    let selected = spread_pick(decoded, max);
+    if selected.is_empty() {
+        return Ok(ReferenceImages::default());
+    }
+
+    let dir = std::env::temp_dir().join(format!(
+        "tonsuu-references-{}-{}",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir)?;
+    let mut references = ReferenceImages {
+        dir: Some(dir.clone()),
+        images: Vec::new(),
+    };
+
+    for (i, (g, bytes)) in selected.into_iter().enumerate() {
+        let path = dir.join(format!("reference-{}.{}", i + 1, image_extension(&bytes)));
+        std::fs::write(&path, &bytes)?;
+        references.images.push(ReferenceImage {
+            hash: g.entry.image_hash.clone(),
+            path,
+            item: GradedReferenceItem {
+                grade_name: g.grade.label().to_string(),
+                actual_tonnage: g.entry.actual_tonnage.unwrap_or(0.0),
+                max_capacity: g.entry.max_capacity.unwrap_or(0.0),
+                // GradedHistoryEntry holds a percentage; prompts take a fraction
+                load_ratio: g.load_ratio / 100.0,
+                memo: g.entry.notes.clone(),
+            },
+        });
+    }
+
+    Ok(references)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tonsuu_types::{EstimationResult, HistoryEntry, LoadGrade};
+
+    fn graded(hash: &str, thumbnail: Option<&[u8]>, grade: LoadGrade) -> GradedHistoryEntry {
+        GradedHistoryEntry {
+            entry: HistoryEntry {
+                image_path: format!("{}.jpg", hash),
+                image_hash: hash.to_string(),
+                estimation: EstimationResult::default(),
+                actual_tonnage: Some(3.8),
+                max_capacity: Some(4.0),
+                analyzed_at: Utc::now(),
+                feedback_at: None,
+                notes: None,
+                thumbnail_base64: thumbnail.map(|b| STANDARD.encode(b)),
+                source_images: Vec::new(),
+            },
+            grade,
+            load_ratio: 95.0,
+        }
+    }
+
+    #[test]
+    fn test_prepare_skips_missing_thumbnails_and_caps() {
+        let png = [0x89, b'P', b'N', b'G', 0, 1, 2];
+        let stock = vec![
+            graded("a", Some(b"jpegdata"), LoadGrade::TooLight),
+            graded("b", None, LoadGrade::Light),
+            graded("c", Some(b"jpegdata"), LoadGrade::JustRight),
+            graded("d", Some(&png), LoadGrade::Marginal),
+            graded("e", Some(b"jpegdata"), LoadGrade::Overloaded),
+        ];
+
+        let references = prepare_reference_images(&stock, 2).unwrap();
+        assert_eq!(references.hashes(), vec!["a", "e"]);
+        assert!(references.paths().iter().all(|p| p.exists()));
+
+        let all = prepare_reference_images(&stock, 10).unwrap();
+        assert_eq!(all.hashes(), vec!["a", "c", "d", "e"]);
+        assert!(all.images[2].path.extension().is_some_and(|e| e == "png"));
+
+        let dir = all.images[0].path.parent().unwrap().to_path_buf();
+        drop(all);
+        assert!(!dir.exists());
+
+        assert!(prepare_reference_images(&stock, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encode_thumbnail() {
+        let tmp = tempfile::tempdir().unwrap();
+        let photo = tmp.path().join("truck.png");
+        image::RgbImage::new(1024, 768).save(&photo).unwrap();
+
+        let encoded = encode_thumbnail(&photo).unwrap();
+        let bytes = STANDARD.decode(encoded).unwrap();
+        let thumbnail = image::load_from_memory(&bytes).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (512, 384));
+
+        assert!(encode_thumbnail(&tmp.path().join("missing.jpg")).is_none());
+    }
+}
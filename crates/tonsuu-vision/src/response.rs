@@ -0,0 +1,292 @@
+//! AI response parsing: JSON extraction, validation and repair re-ask
+//!
+//! Responses may wrap the JSON in prose or code fences, contain more than one
+//! object (an echoed template plus the answer), or have braces inside the
+//! reasoning text. A balanced-brace scanner collects every top-level object and
+//! the one that looks most like an `EstimationResult` is validated. A response
+//! that fails validation gets one repair prompt; if that also fails, the
+//! analysis fails instead of storing an empty estimate.
+
+use crate::ai::prompts::build_json_repair_prompt;
+use crate::calculate_volume_and_tonnage;
+use serde_json::{Map, Value};
+use tonsuu_types::{Error, EstimationResult, Result};
+
+/// Keys of the estimation schema, as the AI returns them (camelCase)
+const ESTIMATION_KEYS: &[&str] = &[
+    "isTargetDetected",
+    "truckType",
+    "materialType",
+    "height",
+    "fillRatioL",
+    "fillRatioW",
+    "fillRatioZ",
+    "packingDensity",
+    "confidenceScore",
+    "reasoning",
+    "licensePlate",
+    "estimatedVolumeM3",
+    "estimatedTonnage",
+];
+
+/// String fields that must be present when a target was detected
+const REQUIRED_STRINGS: &[&str] = &["truckType", "materialType"];
+
+/// Allowed range of a numeric field
+struct NumericRule {
+    key: &'static str,
+    min: f64,
+    max: f64,
+    /// Must be present (non-null) when a target was detected
+    required: bool,
+}
+
+const NUMERIC_RULES: &[NumericRule] = &[
+    NumericRule { key: "height", min: 0.05, max: 3.0, required: true },
+    NumericRule { key: "fillRatioL", min: 0.0, max: 1.0, required: false },
+    NumericRule { key: "fillRatioW", min: 0.0, max: 1.0, required: false },
+    NumericRule { key: "fillRatioZ", min: 0.0, max: 1.0, required: false },
+    NumericRule { key: "packingDensity", min: 0.0, max: 1.0, required: false },
+    NumericRule { key: "confidenceScore", min: 0.0, max: 1.0, required: false },
+    NumericRule { key: "estimatedVolumeM3", min: 0.0, max: 30.0, required: false },
+    NumericRule { key: "estimatedTonnage", min: 0.0, max: 40.0, required: false },
+];
+
+/// Top-level `{...}` spans in `text`, in order of appearance.
+///
+/// Braces inside JSON strings are ignored. A stray unmatched `{` in prose
+/// does not hide the objects that follow it.
+pub fn json_object_candidates(text: &str) -> Vec<&str> {
+    // Braces and quotes are ASCII, so byte offsets are always char boundaries
+    let bytes = text.as_bytes();
+    let mut candidates = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let mut depth = 0usize;
+        let mut start = offset;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for (i, &b) in bytes.iter().enumerate().skip(offset) {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if b == b'\\' {
+                    escaped = true;
+                } else if b == b'"' {
+                    in_string = false;
+                }
+                continue;
+            }
+            match b {
+                b'"' if depth > 0 => in_string = true,
+                b'{' => {
+                    if depth == 0 {
+                        start = i;
+                    }
+                    depth += 1;
+                }
+                b'}' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        candidates.push(&text[start..=i]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if depth == 0 {
+            return candidates;
+        }
+        // Unclosed object: rescan after its opening brace
+        offset = start + 1;
+    }
+}
+
+/// Extract the JSON object from an AI response (handles code fences and prose).
+///
+/// Returns the first top-level object that parses as JSON, or the trimmed
+/// response so the caller's parse error shows the raw text.
+pub fn extract_json_from_response(response: &str) -> String {
+    json_object_candidates(response)
+        .into_iter()
+        .find(|candidate| serde_json::from_str::<Value>(candidate).is_ok())
+        .unwrap_or_else(|| response.trim())
+        .to_string()
+}
+
+/// The parsed object with the most estimation keys (the later one on ties)
+fn find_estimation_object(response: &str) -> Option<Map<String, Value>> {
+    json_object_candidates(response)
+        .into_iter()
+        .filter_map(|candidate| match serde_json::from_str(candidate) {
+            Ok(Value::Object(obj)) => Some(obj),
+            _ => None,
+        })
+        .map(|obj| {
+            let matched = ESTIMATION_KEYS.iter().filter(|k| obj.contains_key(**k)).count();
+            (matched, obj)
+        })
+        .filter(|(matched, _)| *matched > 0)
+        .max_by_key(|(matched, _)| *matched)
+        .map(|(_, obj)| obj)
+}
+
+/// Check required fields and numeric ranges of an estimation object.
+///
+/// Returns the problems found (empty if valid). When no target was detected
+/// only `isTargetDetected` itself is checked.
+pub fn validate_estimation_json(obj: &Map<String, Value>) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let detected = match obj.get("isTargetDetected") {
+        Some(Value::Bool(detected)) => *detected,
+        Some(other) => {
+            problems.push(format!("isTargetDetected must be true or false, got {}", other));
+            true
+        }
+        None => {
+            problems.push("isTargetDetected is missing".to_string());
+            true
+        }
+    };
+    if !detected {
+        return problems;
+    }
+
+    for key in REQUIRED_STRINGS {
+        match obj.get(*key) {
+            Some(Value::String(_)) => {}
+            None | Some(Value::Null) => problems.push(format!("{} is missing", key)),
+            Some(other) => problems.push(format!("{} must be a string, got {}", key, other)),
+        }
+    }
+
+    for rule in NUMERIC_RULES {
+        match obj.get(rule.key) {
+            None | Some(Value::Null) => {
+                if rule.required {
+                    problems.push(format!("{} is missing", rule.key));
+                }
+            }
+            Some(Value::Number(n)) => {
+                let value = n.as_f64().unwrap_or(f64::NAN);
+                if !(rule.min..=rule.max).contains(&value) {
+                    problems.push(format!(
+                        "{} = {} is outside {}..={}",
+                        rule.key, value, rule.min, rule.max
+                    ));
+                }
+            }
+            Some(other) => problems.push(format!("{} must be a number, got {}", rule.key, other)),
+        }
+    }
+
+    problems
+}
+
+/// Parse and validate an estimation response; returns the problems on failure
+pub fn parse_estimation_response(
+    response: &str,
+) -> std::result::Result<EstimationResult, Vec<String>> {
+    let Some(obj) = find_estimation_object(response) else {
+        return Err(vec!["no JSON object with estimation fields found".to_string()]);
+    };
+
+    let problems = validate_estimation_json(&obj);
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    let mut result: EstimationResult =
+        serde_json::from_value(Value::Object(obj)).map_err(|e| vec![e.to_string()])?;
+
+    if result.estimated_volume_m3 == 0.0 || result.estimated_tonnage == 0.0 {
+        calculate_volume_and_tonnage(&mut result);
+    }
+
+    Ok(result)
+}
+
+/// Parse an estimation response, asking the backend once to fix invalid JSON.
+///
+/// `resend` sends a follow-up prompt to the same backend and images. If the
+/// repaired response is still invalid the analysis fails with
+/// `Error::AnalysisFailed`, so nothing gets cached or stored in history.
+pub fn parse_estimation_with_repair<F>(response: &str, resend: F) -> Result<EstimationResult>
+where
+    F: FnOnce(&str) -> Result<String>,
+{
+    let problems = match parse_estimation_response(response) {
+        Ok(result) => return Ok(result),
+        Err(problems) => problems,
+    };
+
+    let repaired = resend(&build_json_repair_prompt(response, &problems))?;
+    parse_estimation_response(&repaired).map_err(|problems| {
+        let truncated: String = repaired.chars().take(500).collect();
+        Error::AnalysisFailed(format!(
+            "AI response failed validation after a repair attempt: {} | raw: {}",
+            problems.join("; "),
+            truncated
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"{"isTargetDetected": true, "truckType": "4t", "materialType": "As殻", "height": 0.4, "fillRatioL": 0.8, "fillRatioW": 0.7, "packingDensity": 0.8, "confidenceScore": 0.7, "reasoning": "ok"}"#;
+
+    #[test]
+    fn test_candidates_respect_strings_and_nesting() {
+        let text = r#"Reasoning {rough guess} then {"a": "brace } in string", "b": {"c": 1}} done"#;
+        let candidates = json_object_candidates(text);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[1], r#"{"a": "brace } in string", "b": {"c": 1}}"#);
+
+        let stray = r#"note: { unclosed, answer {"x": 1}"#;
+        assert_eq!(json_object_candidates(stray), vec![r#"{"x": 1}"#]);
+
+        let many_unclosed = format!("{}{}", "{ ".repeat(5000), r#"{"x": 1}"#);
+        assert_eq!(json_object_candidates(&many_unclosed), vec![r#"{"x": 1}"#]);
+    }
+
+    #[test]
+    fn test_picks_estimation_shaped_object() {
+        let response = format!("Example: {{\"foo\": 1}}\nAnswer:\n```json\n{}\n```", VALID);
+        let result = parse_estimation_response(&response).unwrap();
+        assert_eq!(result.truck_type, "4t");
+        assert!(result.estimated_tonnage > 0.0);
+    }
+
+    #[test]
+    fn test_validation_reports_problems() {
+        let response = r#"{"isTargetDetected": true, "truckType": "4t", "height": "0.4", "fillRatioL": 1.5}"#;
+        let problems = parse_estimation_response(response).unwrap_err();
+        assert!(problems.iter().any(|p| p.contains("materialType")));
+        assert!(problems.iter().any(|p| p.contains("height must be a number")));
+        assert!(problems.iter().any(|p| p.contains("fillRatioL")));
+
+        let not_detected = r#"{"isTargetDetected": false, "reasoning": "no truck"}"#;
+        assert!(!parse_estimation_response(not_detected).unwrap().is_target_detected);
+    }
+
+    #[test]
+    fn test_repair_reask_once_then_fail() {
+        let mut asked = 0;
+        let result = parse_estimation_with_repair("not json", |prompt| {
+            asked += 1;
+            assert!(prompt.contains("no JSON object"));
+            Ok(VALID.to_string())
+        });
+        assert!(result.is_ok());
+        assert_eq!(asked, 1);
+
+        let result = parse_estimation_with_repair("not json", |_| Ok("still not json".to_string()));
+        assert!(matches!(result, Err(Error::AnalysisFailed(_))));
+    }
+}
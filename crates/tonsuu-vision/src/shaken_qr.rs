@@ -0,0 +1,212 @@
+//! Local decoding of the QR codes printed on 車検証
+//!
+//! 車検証 (and the 記録事項 sheet of the 2023 electronic certificate) carry
+//! MLIT QR codes whose fields are separated by `/`. Long records are split
+//! over several symbols (structured append), so the symbols are read left to
+//! right, top to bottom and concatenated before the fields are split.
+//!
+//! The codes hold the registration number, the expiry date (有効期間の満了する
+//! 日), the first registration month (初度登録年月) and the model code (型式),
+//! written as consecutive fields `YYMMDD/YYMM/型式` in the current 和暦 era
+//! (newer certificates may use `YYYYMMDD/YYYYMM`). 車名, 最大積載量, weights
+//! and dimensions are not part of the codes.
+
+use std::path::Path;
+
+use chrono::{Datelike, NaiveDate};
+use encoding_rs::SHIFT_JIS;
+use tonsuu_types::LicensePlate;
+
+use crate::volume_estimator::ERAS;
+use crate::ShakenResult;
+
+/// Decode every readable QR code in an image, in reading order.
+///
+/// Payloads that are not UTF-8 are decoded as Shift_JIS. Returns an empty
+/// list if the image cannot be opened or holds no readable code.
+pub fn decode_qr_codes(image_path: &Path) -> Vec<String> {
+    let Ok(image) = image::open(image_path) else {
+        return Vec::new();
+    };
+    let mut prepared = rqrr::PreparedImage::prepare(image.to_luma8());
+
+    // (top, bottom, left, payload) of each decoded symbol
+    let mut symbols: Vec<(i32, i32, i32, String)> = prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| {
+            let mut bytes = Vec::new();
+            grid.decode_to(&mut bytes).ok()?;
+            let text = match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(e) => SHIFT_JIS.decode(e.as_bytes()).0.into_owned(),
+            };
+            let top = grid.bounds.iter().map(|p| p.y).min()?;
+            let bottom = grid.bounds.iter().map(|p| p.y).max()?;
+            let left = grid.bounds.iter().map(|p| p.x).min()?;
+            Some((top, bottom, left, text))
+        })
+        .collect();
+
+    // Rows: a symbol starting above the middle of the row's first symbol is
+    // on the same row
+    symbols.sort_by_key(|s| s.0);
+    let mut rows: Vec<Vec<(i32, i32, i32, String)>> = Vec::new();
+    for symbol in symbols {
+        match rows.last_mut() {
+            Some(row) if symbol.0 < (row[0].0 + row[0].1) / 2 => row.push(symbol),
+            _ => rows.push(vec![symbol]),
+        }
+    }
+    rows.into_iter()
+        .flat_map(|mut row| {
+            row.sort_by_key(|s| s.2);
+            row.into_iter().map(|s| s.3)
+        })
+        .collect()
+}
+
+/// Year of a two-digit 和暦 year, taking the newest era that gives a year no
+/// later than `latest` (the era itself is not written in the codes)
+fn wareki_year(yy: i32, latest: i32) -> Option<i32> {
+    if yy < 1 {
+        return None;
+    }
+    ERAS.iter().enumerate().find_map(|(i, (_, offset))| {
+        // An era ends in the year the next one starts
+        let end = match i {
+            0 => latest,
+            _ => (ERAS[i - 1].1 + 1).min(latest),
+        };
+        let year = offset + yy;
+        (year <= end).then_some(year)
+    })
+}
+
+/// Split a digit field into (year, rest); 和暦 years are resolved with `latest`
+fn split_year(field: &str, wareki_len: usize, latest: i32) -> Option<(i32, &str)> {
+    if field.is_empty() || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if field.len() == wareki_len {
+        Some((wareki_year(field[..2].parse().ok()?, latest)?, &field[2..]))
+    } else if field.len() == wareki_len + 2 {
+        Some((field[..4].parse().ok()?, &field[4..]))
+    } else {
+        None
+    }
+}
+
+/// Expiry date field: `YYMMDD` (和暦) or `YYYYMMDD`
+fn qr_date(field: &str, latest: i32) -> Option<NaiveDate> {
+    let (year, rest) = split_year(field, 6, latest)?;
+    NaiveDate::from_ymd_opt(year, rest[..2].parse().ok()?, rest[2..].parse().ok()?)
+}
+
+/// First registration field: `YYMM` (和暦) or `YYYYMM`, as (year, month)
+fn qr_year_month(field: &str, latest: i32) -> Option<(i32, u32)> {
+    let (year, rest) = split_year(field, 4, latest)?;
+    let month: u32 = rest.parse().ok()?;
+    (1..=12).contains(&month).then_some((year, month))
+}
+
+/// 型式 such as "2PG-FW1AHG": letters before a hyphen (a 車台番号 looks the
+/// same, so the caller also checks the date fields before it)
+fn is_model_code(field: &str) -> bool {
+    let Some((prefix, rest)) = field.split_once('-') else {
+        return false;
+    };
+    !prefix.is_empty()
+        && !rest.is_empty()
+        && prefix.chars().any(|c| c.is_ascii_alphabetic())
+        && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Registration number field: a full plate with region, class number and kana
+fn qr_plate(field: &str) -> Option<String> {
+    let plate = LicensePlate::parse(field)?;
+    (plate.region.is_some() && plate.class_number.is_some() && plate.kana.is_some())
+        .then(|| plate.to_string())
+}
+
+/// Parse the QR payloads of one 車検証 (in reading order).
+///
+/// `today` resolves the 和暦 years. Returns None if neither the registration
+/// number nor the expiry/型式 fields are found. The result has no vehicle
+/// name or max capacity (the codes do not carry them).
+pub fn parse_shaken_qr(payloads: &[String], today: NaiveDate) -> Option<ShakenResult> {
+    let mut result = ShakenResult {
+        from_qr: true,
+        ..Default::default()
+    };
+
+    // The plate sits inside a single symbol
+    result.registration_number = payloads
+        .iter()
+        .flat_map(|p| p.split('/'))
+        .find_map(|field| qr_plate(field.trim()));
+
+    // Expiry, first registration and 型式 are consecutive fields of the
+    // record that may span several symbols
+    let joined = payloads.concat();
+    let fields: Vec<&str> = joined.split('/').map(str::trim).collect();
+    let record = fields.windows(3).find_map(|w| {
+        let expiry = qr_date(w[0], today.year() + 3)?;
+        let first_registration = qr_year_month(w[1], today.year())?;
+        is_model_code(w[2]).then_some((expiry, first_registration, w[2]))
+    });
+    if let Some((expiry, (year, month), model_code)) = record {
+        result.expiry_date = Some(expiry.format("%Y-%m-%d").to_string());
+        result.first_registration = Some(format!("{:04}-{:02}", year, month));
+        result.model_code = Some(model_code.to_string());
+    }
+
+    (result.registration_number.is_some() || result.model_code.is_some()).then_some(result)
+}
+
+/// Read a 車検証 image's QR codes; None if no code could be read or parsed
+pub fn read_shaken_qr(image_path: &Path, today: NaiveDate) -> Option<ShakenResult> {
+    let payloads = decode_qr_codes(image_path);
+    if payloads.is_empty() {
+        return None;
+    }
+    parse_shaken_qr(&payloads, today)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 6, 1).unwrap()
+    }
+
+    #[test]
+    fn test_parse_split_record() {
+        // Record split mid-field over two symbols, plate in its own symbol
+        let payloads = vec![
+            "2/熊本　１３０ら１１２２/2/FW1AHG-10001/A09C/1".to_string(),
+            "2/FW1AHG-10001/15312/0001/0703".to_string(),
+            "31/0104/2PG-FW1AHG/0580/-/0620/0620/1".to_string(),
+        ];
+        let result = parse_shaken_qr(&payloads, today()).unwrap();
+        assert!(result.from_qr);
+        assert_eq!(result.registration_number.as_deref(), Some("熊本 130 ら 11-22"));
+        assert_eq!(result.expiry_date.as_deref(), Some("2025-03-31"));
+        assert_eq!(result.first_registration.as_deref(), Some("2019-04"));
+        assert_eq!(result.model_code.as_deref(), Some("2PG-FW1AHG"));
+        assert_eq!(result.capacity(), None);
+    }
+
+    #[test]
+    fn test_wareki_years() {
+        // 令和7年, 平成29年 (令和29年 would be in the future), 昭和63年
+        assert_eq!(qr_year_month("0704", 2025), Some((2025, 4)));
+        assert_eq!(qr_year_month("2903", 2025), Some((2017, 3)));
+        assert_eq!(qr_year_month("6312", 2025), Some((1988, 12)));
+        assert_eq!(qr_year_month("202504", 2025), Some((2025, 4)));
+        assert_eq!(qr_date("20270228", 2028), NaiveDate::from_ymd_opt(2027, 2, 28));
+        assert_eq!(qr_date("070230", 2028), None);
+        assert!(parse_shaken_qr(&["1/2/3".to_string()], today()).is_none());
+    }
+}
@@ -1,14 +1,14 @@
 //! Vehicle registration certificate (shaken) analyzer and volume estimation
 
-use tonsuu_types::{Error, Result};
-use crate::AnalyzerConfig;
-use cli_ai_analyzer::{analyze, AnalyzeOptions};
+use tonsuu_types::{Error, RegisteredVehicle, Result};
+use crate::shaken_qr::read_shaken_qr;
+use crate::{extract_json_from_response, send_prompt, AnalyzerConfig};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
 /// Result of 車検証 (vehicle registration certificate) analysis
-#[allow(dead_code)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ShakenResult {
     /// Vehicle name (車名), e.g., "日野 プロフィア"
@@ -18,10 +18,145 @@ pub struct ShakenResult {
     /// Registration number (登録番号), optional
     #[serde(default)]
     pub registration_number: Option<String>,
+    /// Model code (型式), e.g., "2PG-FW1AHG"
+    #[serde(default)]
+    pub model_code: Option<String>,
+    /// Curb weight in tonnes (車両重量)
+    #[serde(default)]
+    pub vehicle_weight: Option<f64>,
+    /// Gross vehicle weight in tonnes (車両総重量)
+    #[serde(default)]
+    pub gross_vehicle_weight: Option<f64>,
+    /// Overall length in meters (長さ)
+    #[serde(default)]
+    pub length: Option<f64>,
+    /// Overall width in meters (幅)
+    #[serde(default)]
+    pub width: Option<f64>,
+    /// Overall height in meters (高さ)
+    #[serde(default)]
+    pub height: Option<f64>,
+    /// First registration (初度登録年月), "YYYY-MM" or as printed (e.g. "令和元年5月")
+    #[serde(default)]
+    pub first_registration: Option<String>,
+    /// Expiry date (有効期間の満了する日), "YYYY-MM-DD" or as printed
+    #[serde(default)]
+    pub expiry_date: Option<String>,
+    /// Fields were read from the certificate's QR codes
+    #[serde(skip)]
+    pub from_qr: bool,
+}
+
+impl ShakenResult {
+    /// Max capacity, if it was read (the AI returns 0.0 when it could not)
+    pub fn capacity(&self) -> Option<f64> {
+        Some(self.max_capacity).filter(|c| *c > 0.0)
+    }
+
+    /// Copy the certificate fields onto a registered vehicle.
+    ///
+    /// Only fields that were read are set; unparsable dates are skipped.
+    pub fn apply_to(&self, vehicle: &mut RegisteredVehicle) {
+        let positive = |v: Option<f64>| v.filter(|v| *v > 0.0);
+        if let Some(capacity) = self.capacity() {
+            vehicle.max_capacity = capacity;
+        }
+        if let Some(ref plate) = self.registration_number {
+            vehicle.license_plate = Some(plate.clone());
+        }
+        if let Some(ref model_code) = self.model_code {
+            vehicle.model_code = Some(model_code.clone());
+        }
+        vehicle.vehicle_weight = positive(self.vehicle_weight).or(vehicle.vehicle_weight);
+        vehicle.gross_vehicle_weight =
+            positive(self.gross_vehicle_weight).or(vehicle.gross_vehicle_weight);
+        vehicle.vehicle_length = positive(self.length).or(vehicle.vehicle_length);
+        vehicle.vehicle_width = positive(self.width).or(vehicle.vehicle_width);
+        vehicle.vehicle_height = positive(self.height).or(vehicle.vehicle_height);
+        let first_registration = self
+            .first_registration
+            .as_deref()
+            .and_then(parse_certificate_date);
+        if let Some((year, month, _)) = first_registration {
+            vehicle.first_registration = Some(format!("{:04}-{:02}", year, month));
+        }
+        if let Some(expiry) = self.expiry_date.as_deref().and_then(parse_expiry_date) {
+            vehicle.inspection_expiry = Some(expiry);
+        }
+    }
+
+    /// Replace fields with the ones read from the QR codes, which are exact
+    pub fn overlay_qr(&mut self, qr: &ShakenResult) {
+        if qr.registration_number.is_some() {
+            self.registration_number = qr.registration_number.clone();
+        }
+        if qr.model_code.is_some() {
+            self.model_code = qr.model_code.clone();
+        }
+        if qr.first_registration.is_some() {
+            self.first_registration = qr.first_registration.clone();
+        }
+        if qr.expiry_date.is_some() {
+            self.expiry_date = qr.expiry_date.clone();
+        }
+        self.from_qr = true;
+    }
+
+    /// New registered vehicle from the certificate (`fallback_name` when the
+    /// vehicle name could not be read)
+    pub fn to_vehicle(&self, fallback_name: &str) -> Option<RegisteredVehicle> {
+        let capacity = self.capacity()?;
+        let name = if self.vehicle_name.trim().is_empty() || self.vehicle_name == "不明" {
+            fallback_name.to_string()
+        } else {
+            self.vehicle_name.clone()
+        };
+        let mut vehicle = RegisteredVehicle::new(name, capacity);
+        self.apply_to(&mut vehicle);
+        Some(vehicle)
+    }
+}
+
+/// Start year (minus one) of the Japanese eras used on 車検証
+pub(crate) const ERAS: &[(&str, i32)] = &[("令和", 2018), ("平成", 1988), ("昭和", 1925)];
+
+/// Parse a date as written on a 車検証 into (year, month, day).
+///
+/// Accepts "2025-03-31", "2025/3/31", "2019-05" and 和暦 such as
+/// "令和7年3月31日" or "令和元年5月". The day is None for year-month dates.
+pub fn parse_certificate_date(text: &str) -> Option<(i32, u32, Option<u32>)> {
+    let text = text.trim();
+    let (base, rest) = match ERAS.iter().find(|(era, _)| text.starts_with(era)) {
+        Some((era, offset)) => (Some(*offset), text[era.len()..].replacen("元", "1", 1)),
+        None => (None, text.to_string()),
+    };
+    let numbers: Vec<i32> = rest
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect::<Option<Vec<i32>>>()?;
+    let (year, month, day) = match numbers.as_slice() {
+        [y, m] => (*y, *m, None),
+        [y, m, d] => (*y, *m, Some(*d)),
+        _ => return None,
+    };
+    let year = base.map_or(year, |offset| offset + year);
+    let month = u32::try_from(month).ok().filter(|m| (1..=12).contains(m))?;
+    let day = match day {
+        Some(d) => Some(u32::try_from(d).ok()?),
+        None => None,
+    };
+    NaiveDate::from_ymd_opt(year, month, day.unwrap_or(1))?;
+    Some((year, month, day))
+}
+
+/// Parse an expiry date (有効期間の満了する日); a full date is required
+pub fn parse_expiry_date(text: &str) -> Option<NaiveDate> {
+    let (year, month, day) = parse_certificate_date(text)?;
+    NaiveDate::from_ymd_opt(year, month, day?)
 }
 
 /// Build the prompt for 車検証 analysis
-#[allow(dead_code)]
 fn build_shaken_prompt() -> String {
     r#"あなたは車検証（自動車検査証）を読み取る専門家です。
 提供された車検証の画像から以下の情報を正確に読み取ってください。
@@ -40,6 +175,21 @@ fn build_shaken_prompt() -> String {
    - 例: "品川 100 あ 12-34"
    - 読み取れない場合はnullを返してください
 
+4. **型式**: 「型式」欄の英数字を読み取ってください。
+   - 例: "2PG-FW1AHG", "QKG-FS1EKDA"
+
+5. **車両重量・車両総重量**: それぞれ**トン単位**で返してください（kg記載は1000で割る）。
+   - 例: 車両総重量 24,950kg → 24.95
+
+6. **長さ・幅・高さ**: 車両の外寸を**メートル単位**で返してください（cm記載は100で割る）。
+   - 例: 長さ 1,199cm → 11.99
+
+7. **初度登録年月**: 西暦の "YYYY-MM" 形式で返してください。
+   - 例: 令和元年5月 → "2019-05"
+
+8. **有効期間の満了する日**: 西暦の "YYYY-MM-DD" 形式で返してください。
+   - 例: 令和7年3月31日 → "2025-03-31"
+
 ## 出力形式
 
 以下のJSON形式で出力してください：
@@ -48,7 +198,15 @@ fn build_shaken_prompt() -> String {
 {
   "vehicleName": "車名（メーカー名と車種名）",
   "maxCapacity": 最大積載量（トン単位の数値）,
-  "registrationNumber": "登録番号またはnull"
+  "registrationNumber": "登録番号またはnull",
+  "modelCode": "型式またはnull",
+  "vehicleWeight": 車両重量（トン単位の数値またはnull）,
+  "grossVehicleWeight": 車両総重量（トン単位の数値またはnull）,
+  "length": 長さ（メートル単位の数値またはnull）,
+  "width": 幅（メートル単位の数値またはnull）,
+  "height": 高さ（メートル単位の数値またはnull）,
+  "firstRegistration": "YYYY-MMまたはnull",
+  "expiryDate": "YYYY-MM-DDまたはnull"
 }
 ```
 
@@ -58,66 +216,91 @@ fn build_shaken_prompt() -> String {
 - 数値は必ず数値型で返してください（文字列にしないでください）
 - 最大積載量は必ずトン単位に変換してください
 - 車名が読み取れない場合は「不明」と返してください
-- 最大積載量が読み取れない場合は0.0を返してください"#
+- 最大積載量が読み取れない場合は0.0を返してください
+- その他の項目が読み取れない場合はnullを返してください"#
         .to_string()
 }
 
-/// Extract JSON from AI response (handles markdown code blocks)
-#[allow(dead_code)]
-fn extract_json(response: &str) -> String {
-    let response = response.trim();
-
-    if response.starts_with("```json") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(7) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
+/// Build the prompt for the 車検証 fields its QR codes do not carry
+/// (車名, 最大積載量, weights and dimensions)
+fn build_shaken_supplement_prompt() -> String {
+    r#"あなたは車検証（自動車検査証）を読み取る専門家です。
+提供された車検証の画像から以下の情報を正確に読み取ってください。
+登録番号・型式・日付はQRコードから読み取り済みのため不要です。
 
-    if response.starts_with("```") {
-        if let Some(end) = response.rfind("```") {
-            let start = response.find('\n').unwrap_or(3) + 1;
-            if start < end {
-                return response[start..end].trim().to_string();
-            }
-        }
-    }
+## 読み取る項目
 
-    if let Some(start) = response.find('{') {
-        if let Some(end) = response.rfind('}') {
-            if start < end {
-                return response[start..=end].to_string();
-            }
-        }
-    }
+1. **車名**: 車検証に記載されている「車名」欄を読み取ってください。
+   - 例: "日野 プロフィア", "いすゞ ギガ", "三菱ふそう スーパーグレート", "UDトラックス クオン"
 
-    response.to_string()
-}
+2. **最大積載量**: 車検証に記載されている「最大積載量」を読み取り、**トン単位**で返してください。
+   - 車検証にはkg単位で記載されていることが多いので、その場合は1000で割ってトンに変換してください
+   - 例: 11,500kg → 11.5 (トン)
 
-/// Analyze a 車検証 (vehicle registration certificate) image
-#[allow(dead_code)]
-pub fn analyze_shaken(image_path: &Path, config: &AnalyzerConfig) -> Result<ShakenResult> {
-    let prompt = build_shaken_prompt();
+3. **車両重量・車両総重量**: それぞれ**トン単位**で返してください（kg記載は1000で割る）。
+   - 例: 車両総重量 24,950kg → 24.95
 
-    let mut options = if let Some(ref model) = config.model {
-        AnalyzeOptions::with_model(model)
-    } else {
-        AnalyzeOptions::default()
-    };
+4. **長さ・幅・高さ**: 車両の外寸を**メートル単位**で返してください（cm記載は100で割る）。
+   - 例: 長さ 1,199cm → 11.99
+
+## 出力形式
+
+以下のJSON形式で出力してください：
 
-    options = options.with_backend(config.backend).json();
+```json
+{
+  "vehicleName": "車名（メーカー名と車種名）",
+  "maxCapacity": 最大積載量（トン単位の数値）,
+  "vehicleWeight": 車両重量（トン単位の数値またはnull）,
+  "grossVehicleWeight": 車両総重量（トン単位の数値またはnull）,
+  "length": 長さ（メートル単位の数値またはnull）,
+  "width": 幅（メートル単位の数値またはnull）,
+  "height": 高さ（メートル単位の数値またはnull）
+}
+```
 
-    let response = analyze(&prompt, &[image_path.to_path_buf()], options)?;
+## 注意事項
 
+- 数値は必ず数値型で返してください（文字列にしないでください）
+- 車名が読み取れない場合は「不明」と返してください
+- 最大積載量が読み取れない場合は0.0を返してください
+- その他の項目が読み取れない場合はnullを返してください"#
+        .to_string()
+}
+
+/// Analyze a 車検証 (vehicle registration certificate) image
+pub fn analyze_shaken(image_path: &Path, config: &AnalyzerConfig) -> Result<ShakenResult> {
+    ask_shaken(&build_shaken_prompt(), image_path, config)
+}
+
+/// Send a 車検証 prompt with the image and parse the reply
+fn ask_shaken(prompt: &str, image_path: &Path, config: &AnalyzerConfig) -> Result<ShakenResult> {
+    let backend = config.ai_backend(vec![image_path.to_path_buf()]);
+    let response = send_prompt(&backend, prompt)?;
     parse_shaken_response(&response)
 }
 
+/// Read a 車検証, decoding its QR codes before asking the AI.
+///
+/// The QR codes carry no vehicle name, max capacity, weights or dimensions,
+/// and a vehicle cannot be registered without the name and capacity, so the
+/// AI is still asked for those (with a shorter prompt) and the QR fields are
+/// added to its reading. Without a readable code this is `analyze_shaken`.
+pub fn read_shaken(image_path: &Path, config: &AnalyzerConfig) -> Result<ShakenResult> {
+    let today = chrono::Local::now().date_naive();
+    match read_shaken_qr(image_path, today) {
+        Some(qr) => {
+            let mut result = ask_shaken(&build_shaken_supplement_prompt(), image_path, config)?;
+            result.overlay_qr(&qr);
+            Ok(result)
+        }
+        None => analyze_shaken(image_path, config),
+    }
+}
+
 /// Parse AI response into ShakenResult
-#[allow(dead_code)]
 fn parse_shaken_response(response: &str) -> Result<ShakenResult> {
-    let json_str = extract_json(response);
+    let json_str = extract_json_from_response(response);
 
     let result: ShakenResult = serde_json::from_str(&json_str).map_err(|e| {
         let truncated: String = response.chars().take(500).collect();
@@ -165,5 +348,71 @@ mod tests {
         assert_eq!(result.vehicle_name, "いすゞ ギガ");
         assert!((result.max_capacity - 10.0).abs() < 0.001);
         assert!(result.registration_number.is_none());
+        assert!(result.expiry_date.is_none());
+    }
+
+    #[test]
+    fn test_supplement_reading_with_qr_fields() {
+        let prompt = build_shaken_supplement_prompt();
+        assert!(prompt.contains("maxCapacity"));
+        assert!(!prompt.contains("registrationNumber") && !prompt.contains("expiryDate"));
+
+        let mut result = parse_shaken_response(
+            r#"{"vehicleName": "日野 プロフィア", "maxCapacity": 9.8, "length": 7.65}"#,
+        )
+        .unwrap();
+        let qr = ShakenResult {
+            registration_number: Some("熊本 130 ら 11-22".to_string()),
+            model_code: Some("2PG-FW1AHG".to_string()),
+            expiry_date: Some("2025-03-31".to_string()),
+            from_qr: true,
+            ..Default::default()
+        };
+        result.overlay_qr(&qr);
+        let vehicle = result.to_vehicle("folder").unwrap();
+        assert!((vehicle.max_capacity - 9.8).abs() < 0.001);
+        assert_eq!(vehicle.vehicle_length, Some(7.65));
+        assert_eq!(vehicle.license_plate.as_deref(), Some("熊本 130 ら 11-22"));
+        assert_eq!(vehicle.model_code.as_deref(), Some("2PG-FW1AHG"));
+    }
+
+    #[test]
+    fn test_extended_fields_applied_to_vehicle() {
+        let response = r#"{
+  "vehicleName": "日野 プロフィア",
+  "maxCapacity": 9.8,
+  "registrationNumber": "熊本 100 あ 12-34",
+  "modelCode": "2PG-FW1AHG",
+  "vehicleWeight": 15.03,
+  "grossVehicleWeight": 24.94,
+  "length": 7.65,
+  "width": 2.49,
+  "height": 3.15,
+  "firstRegistration": "令和元年5月",
+  "expiryDate": "令和7年3月31日"
+}"#;
+        let vehicle = parse_shaken_response(response)
+            .unwrap()
+            .to_vehicle("folder")
+            .unwrap();
+        assert_eq!(vehicle.name, "日野 プロフィア");
+        assert_eq!(vehicle.model_code.as_deref(), Some("2PG-FW1AHG"));
+        assert_eq!(vehicle.gross_vehicle_weight, Some(24.94));
+        assert_eq!(vehicle.vehicle_length, Some(7.65));
+        assert_eq!(vehicle.first_registration.as_deref(), Some("2019-05"));
+        assert_eq!(vehicle.inspection_expiry, NaiveDate::from_ymd_opt(2025, 3, 31));
+
+        let unread = ShakenResult::default();
+        assert!(unread.to_vehicle("folder").is_none());
+    }
+
+    #[test]
+    fn test_parse_certificate_date() {
+        assert_eq!(parse_certificate_date("2025-03-31"), Some((2025, 3, Some(31))));
+        assert_eq!(parse_certificate_date("2025/3/31"), Some((2025, 3, Some(31))));
+        assert_eq!(parse_certificate_date("平成31年4月"), Some((2019, 4, None)));
+        assert_eq!(parse_certificate_date("2025-02-30"), None);
+        assert_eq!(parse_expiry_date("2019-05"), None);
+        assert_eq!(parse_expiry_date("令和元年12月1日"), NaiveDate::from_ymd_opt(2019, 12, 1));
     }
 }